@@ -1,11 +1,45 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m000001_create_tables;
+mod m000002_reconciliation;
+mod m000003_imports;
+mod m000004_transaction_sources;
+mod m000005_schedules;
+mod m000006_anomaly_dismissals;
+mod m000007_rules;
+mod m000008_attachments;
+mod m000009_sessions;
+mod m000010_email_tokens;
+mod m000011_two_factor;
+mod m000012_login_lockout;
+mod m000013_api_tokens;
+mod m000014_audit_log;
+mod m000015_data_keys;
+mod m000016_oidc;
+mod m000017_import_holding_changes;
 
 pub struct Migrator;
 
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m000001_create_tables::Migration)]
+        vec![
+            Box::new(m000001_create_tables::Migration),
+            Box::new(m000002_reconciliation::Migration),
+            Box::new(m000003_imports::Migration),
+            Box::new(m000004_transaction_sources::Migration),
+            Box::new(m000005_schedules::Migration),
+            Box::new(m000006_anomaly_dismissals::Migration),
+            Box::new(m000007_rules::Migration),
+            Box::new(m000008_attachments::Migration),
+            Box::new(m000009_sessions::Migration),
+            Box::new(m000010_email_tokens::Migration),
+            Box::new(m000011_two_factor::Migration),
+            Box::new(m000012_login_lockout::Migration),
+            Box::new(m000013_api_tokens::Migration),
+            Box::new(m000014_audit_log::Migration),
+            Box::new(m000015_data_keys::Migration),
+            Box::new(m000016_oidc::Migration),
+            Box::new(m000017_import_holding_changes::Migration),
+        ]
     }
 }
@@ -0,0 +1,129 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // transactions.status: pending -> cleared -> reconciled
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Transactions::Table)
+                    .add_column(
+                        ColumnDef::new(Transactions::Status)
+                            .string()
+                            .not_null()
+                            .default("pending"),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Transactions::Table)
+                    .add_column(ColumnDef::new(Transactions::ReconciliationId).integer().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        // reconciliations (one statement session per account)
+        manager
+            .create_table(
+                Table::create()
+                    .table(Reconciliations::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Reconciliations::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Reconciliations::AccountId).integer().not_null())
+                    .col(ColumnDef::new(Reconciliations::StatementDate).date().not_null())
+                    .col(ColumnDef::new(Reconciliations::OpeningBalance).decimal_len(16, 8).not_null().default("0"))
+                    .col(ColumnDef::new(Reconciliations::EndingBalance).decimal_len(16, 8).not_null())
+                    .col(ColumnDef::new(Reconciliations::Status).string().not_null().default("open"))
+                    .col(
+                        ColumnDef::new(Reconciliations::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(ColumnDef::new(Reconciliations::CompletedAt).date_time().null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_reconciliations_account")
+                            .from(Reconciliations::Table, Reconciliations::AccountId)
+                            .to(Accounts::Table, Accounts::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_reconciliations_account_id")
+                    .table(Reconciliations::Table)
+                    .col(Reconciliations::AccountId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Reconciliations::Table).to_owned())
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Transactions::Table)
+                    .drop_column(Transactions::ReconciliationId)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Transactions::Table)
+                    .drop_column(Transactions::Status)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Accounts {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum Transactions {
+    Table,
+    Status,
+    ReconciliationId,
+}
+
+#[derive(Iden)]
+enum Reconciliations {
+    Table,
+    Id,
+    AccountId,
+    StatementDate,
+    OpeningBalance,
+    EndingBalance,
+    Status,
+    CreatedAt,
+    CompletedAt,
+}
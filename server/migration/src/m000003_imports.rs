@@ -0,0 +1,178 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // import_profiles (saved column mappings)
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImportProfiles::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ImportProfiles::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ImportProfiles::UserId).integer().not_null())
+                    .col(ColumnDef::new(ImportProfiles::Name).string().not_null())
+                    .col(ColumnDef::new(ImportProfiles::Mapping).json().not_null())
+                    .col(
+                        ColumnDef::new(ImportProfiles::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_import_profiles_user")
+                            .from(ImportProfiles::Table, ImportProfiles::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_import_profiles_user_id")
+                    .table(ImportProfiles::Table)
+                    .col(ImportProfiles::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // import_batches (one per committed upload, can be rolled back)
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImportBatches::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ImportBatches::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ImportBatches::UserId).integer().not_null())
+                    .col(ColumnDef::new(ImportBatches::AccountId).integer().not_null())
+                    .col(ColumnDef::new(ImportBatches::Source).string().not_null())
+                    .col(ColumnDef::new(ImportBatches::RowCount).integer().not_null().default(0))
+                    .col(ColumnDef::new(ImportBatches::Status).string().not_null().default("committed"))
+                    .col(
+                        ColumnDef::new(ImportBatches::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_import_batches_account")
+                            .from(ImportBatches::Table, ImportBatches::AccountId)
+                            .to(Accounts::Table, Accounts::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_import_batches_user_id")
+                    .table(ImportBatches::Table)
+                    .col(ImportBatches::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Transactions::Table)
+                    .add_column(ColumnDef::new(Transactions::ImportBatchId).integer().null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_transactions_import_batch_id")
+                    .table(Transactions::Table)
+                    .col(Transactions::ImportBatchId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(Index::drop().name("idx_transactions_import_batch_id").table(Transactions::Table).to_owned())
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Transactions::Table)
+                    .drop_column(Transactions::ImportBatchId)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_table(Table::drop().table(ImportBatches::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(ImportProfiles::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Users {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum Accounts {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum Transactions {
+    Table,
+    ImportBatchId,
+}
+
+#[derive(Iden)]
+enum ImportProfiles {
+    Table,
+    Id,
+    UserId,
+    Name,
+    Mapping,
+    CreatedAt,
+}
+
+#[derive(Iden)]
+enum ImportBatches {
+    Table,
+    Id,
+    UserId,
+    AccountId,
+    Source,
+    RowCount,
+    Status,
+    CreatedAt,
+}
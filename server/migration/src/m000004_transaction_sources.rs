@@ -0,0 +1,67 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite only allows one column per ALTER TABLE
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Transactions::Table)
+                    .add_column(ColumnDef::new(Transactions::Counterparty).string().null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Transactions::Table)
+                    .add_column(ColumnDef::new(Transactions::ExternalId).string().null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Transactions::Table)
+                    .add_column(ColumnDef::new(Transactions::RefundOfId).integer().null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_transactions_account_external_id")
+                    .table(Transactions::Table)
+                    .col(Transactions::AccountId)
+                    .col(Transactions::ExternalId)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(Index::drop().name("idx_transactions_account_external_id").table(Transactions::Table).to_owned())
+            .await?;
+        for col in [Transactions::RefundOfId, Transactions::ExternalId, Transactions::Counterparty] {
+            manager
+                .alter_table(Table::alter().table(Transactions::Table).drop_column(col).to_owned())
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Transactions {
+    Table,
+    AccountId,
+    Counterparty,
+    ExternalId,
+    RefundOfId,
+}
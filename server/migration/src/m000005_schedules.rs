@@ -0,0 +1,197 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // recurring_transactions (salary, rent, subscriptions, ...)
+        manager
+            .create_table(
+                Table::create()
+                    .table(RecurringTransactions::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(RecurringTransactions::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(RecurringTransactions::UserId).integer().not_null())
+                    .col(ColumnDef::new(RecurringTransactions::AccountId).integer().not_null())
+                    .col(ColumnDef::new(RecurringTransactions::TransactionType).string().not_null())
+                    .col(ColumnDef::new(RecurringTransactions::Amount).decimal_len(16, 8).not_null())
+                    .col(ColumnDef::new(RecurringTransactions::Description).string().not_null())
+                    .col(ColumnDef::new(RecurringTransactions::Category).string().null())
+                    .col(ColumnDef::new(RecurringTransactions::Frequency).string().not_null())
+                    .col(ColumnDef::new(RecurringTransactions::Interval).integer().not_null().default(1))
+                    .col(ColumnDef::new(RecurringTransactions::StartDate).date().not_null())
+                    .col(ColumnDef::new(RecurringTransactions::EndDate).date().null())
+                    .col(ColumnDef::new(RecurringTransactions::Active).boolean().not_null().default(true))
+                    .col(
+                        ColumnDef::new(RecurringTransactions::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_recurring_transactions_account")
+                            .from(RecurringTransactions::Table, RecurringTransactions::AccountId)
+                            .to(Accounts::Table, Accounts::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_recurring_transactions_user_id")
+                    .table(RecurringTransactions::Table)
+                    .col(RecurringTransactions::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // investment_plans (scheduled purchases of an asset, funded from an account)
+        manager
+            .create_table(
+                Table::create()
+                    .table(InvestmentPlans::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(InvestmentPlans::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(InvestmentPlans::UserId).integer().not_null())
+                    .col(ColumnDef::new(InvestmentPlans::AccountId).integer().not_null())
+                    .col(ColumnDef::new(InvestmentPlans::Name).string().not_null())
+                    .col(ColumnDef::new(InvestmentPlans::Symbol).string().not_null())
+                    .col(ColumnDef::new(InvestmentPlans::Amount).decimal_len(16, 8).not_null())
+                    .col(ColumnDef::new(InvestmentPlans::Frequency).string().not_null())
+                    .col(ColumnDef::new(InvestmentPlans::Interval).integer().not_null().default(1))
+                    .col(ColumnDef::new(InvestmentPlans::StartDate).date().not_null())
+                    .col(ColumnDef::new(InvestmentPlans::EndDate).date().null())
+                    .col(ColumnDef::new(InvestmentPlans::Active).boolean().not_null().default(true))
+                    .col(
+                        ColumnDef::new(InvestmentPlans::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_investment_plans_account")
+                            .from(InvestmentPlans::Table, InvestmentPlans::AccountId)
+                            .to(Accounts::Table, Accounts::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_investment_plans_user_id")
+                    .table(InvestmentPlans::Table)
+                    .col(InvestmentPlans::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // credit card billing cycle on accounts (SQLite adds one column per statement)
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Accounts::Table)
+                    .add_column(ColumnDef::new(Accounts::StatementDay).integer().null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Accounts::Table)
+                    .add_column(ColumnDef::new(Accounts::PaymentDueDay).integer().null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Accounts::Table)
+                    .add_column(ColumnDef::new(Accounts::PaymentAccountId).integer().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for col in [Accounts::PaymentAccountId, Accounts::PaymentDueDay, Accounts::StatementDay] {
+            manager
+                .alter_table(Table::alter().table(Accounts::Table).drop_column(col).to_owned())
+                .await?;
+        }
+        manager
+            .drop_table(Table::drop().table(InvestmentPlans::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(RecurringTransactions::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Accounts {
+    Table,
+    Id,
+    StatementDay,
+    PaymentDueDay,
+    PaymentAccountId,
+}
+
+#[derive(Iden)]
+enum RecurringTransactions {
+    Table,
+    Id,
+    UserId,
+    AccountId,
+    TransactionType,
+    Amount,
+    Description,
+    Category,
+    Frequency,
+    Interval,
+    StartDate,
+    EndDate,
+    Active,
+    CreatedAt,
+}
+
+#[derive(Iden)]
+enum InvestmentPlans {
+    Table,
+    Id,
+    UserId,
+    AccountId,
+    Name,
+    Symbol,
+    Amount,
+    Frequency,
+    Interval,
+    StartDate,
+    EndDate,
+    Active,
+    CreatedAt,
+}
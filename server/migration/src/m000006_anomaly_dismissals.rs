@@ -0,0 +1,76 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // anomaly_dismissals (anomalies are computed on read; only the user's "not a problem" is stored)
+        manager
+            .create_table(
+                Table::create()
+                    .table(AnomalyDismissals::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AnomalyDismissals::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(AnomalyDismissals::UserId).integer().not_null())
+                    .col(ColumnDef::new(AnomalyDismissals::AnomalyKey).string().not_null())
+                    .col(
+                        ColumnDef::new(AnomalyDismissals::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_anomaly_dismissals_user")
+                            .from(AnomalyDismissals::Table, AnomalyDismissals::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_anomaly_dismissals_user_key")
+                    .table(AnomalyDismissals::Table)
+                    .col(AnomalyDismissals::UserId)
+                    .col(AnomalyDismissals::AnomalyKey)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AnomalyDismissals::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Users {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum AnomalyDismissals {
+    Table,
+    Id,
+    UserId,
+    AnomalyKey,
+    CreatedAt,
+}
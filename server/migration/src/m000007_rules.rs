@@ -0,0 +1,117 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // rules (user-defined categorization, applied on create and import)
+        manager
+            .create_table(
+                Table::create()
+                    .table(Rules::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Rules::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Rules::UserId).integer().not_null())
+                    .col(ColumnDef::new(Rules::Name).string().not_null())
+                    .col(ColumnDef::new(Rules::Priority).integer().not_null().default(0))
+                    .col(ColumnDef::new(Rules::Enabled).boolean().not_null().default(true))
+                    .col(ColumnDef::new(Rules::DescriptionContains).string().null())
+                    .col(ColumnDef::new(Rules::DescriptionRegex).string().null())
+                    .col(ColumnDef::new(Rules::CounterpartyContains).string().null())
+                    .col(ColumnDef::new(Rules::AccountId).integer().null())
+                    .col(ColumnDef::new(Rules::MinAmount).decimal_len(16, 8).null())
+                    .col(ColumnDef::new(Rules::MaxAmount).decimal_len(16, 8).null())
+                    .col(ColumnDef::new(Rules::SetCategory).string().null())
+                    .col(ColumnDef::new(Rules::AddTags).json().null())
+                    .col(ColumnDef::new(Rules::RenameDescription).string().null())
+                    .col(ColumnDef::new(Rules::MarkTransfer).boolean().not_null().default(false))
+                    .col(
+                        ColumnDef::new(Rules::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_rules_user")
+                            .from(Rules::Table, Rules::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_rules_user_id")
+                    .table(Rules::Table)
+                    .col(Rules::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // free-form labels on transactions, a JSON array of strings
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Transactions::Table)
+                    .add_column(ColumnDef::new(Transactions::Tags).json().null())
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(Table::alter().table(Transactions::Table).drop_column(Transactions::Tags).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Rules::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Users {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum Transactions {
+    Table,
+    Tags,
+}
+
+#[derive(Iden)]
+enum Rules {
+    Table,
+    Id,
+    UserId,
+    Name,
+    Priority,
+    Enabled,
+    DescriptionContains,
+    DescriptionRegex,
+    CounterpartyContains,
+    AccountId,
+    MinAmount,
+    MaxAmount,
+    SetCategory,
+    AddTags,
+    RenameDescription,
+    MarkTransfer,
+    CreatedAt,
+}
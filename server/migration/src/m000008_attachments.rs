@@ -0,0 +1,91 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // attachments (receipt and invoice files; the bytes live in attachment storage, keyed by sha256)
+        manager
+            .create_table(
+                Table::create()
+                    .table(Attachments::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Attachments::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Attachments::TransactionId).integer().not_null())
+                    .col(ColumnDef::new(Attachments::FileName).string().not_null())
+                    .col(ColumnDef::new(Attachments::ContentType).string().not_null())
+                    .col(ColumnDef::new(Attachments::Size).big_integer().not_null())
+                    .col(ColumnDef::new(Attachments::Sha256).string_len(64).not_null())
+                    .col(ColumnDef::new(Attachments::HasThumbnail).boolean().not_null().default(false))
+                    .col(
+                        ColumnDef::new(Attachments::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_attachments_transaction")
+                            .from(Attachments::Table, Attachments::TransactionId)
+                            .to(Transactions::Table, Transactions::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_attachments_transaction_id")
+                    .table(Attachments::Table)
+                    .col(Attachments::TransactionId)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_attachments_sha256")
+                    .table(Attachments::Table)
+                    .col(Attachments::Sha256)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Attachments::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Transactions {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum Attachments {
+    Table,
+    Id,
+    TransactionId,
+    FileName,
+    ContentType,
+    Size,
+    Sha256,
+    HasThumbnail,
+    CreatedAt,
+}
@@ -0,0 +1,100 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // users.email_verified_at (null until the user follows a verification or reset email)
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(ColumnDef::new(Users::EmailVerifiedAt).date_time().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        // email_tokens (single-use verification and password reset tokens; only the hash is kept)
+        manager
+            .create_table(
+                Table::create()
+                    .table(EmailTokens::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(EmailTokens::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(EmailTokens::UserId).integer().not_null())
+                    .col(ColumnDef::new(EmailTokens::Purpose).string().not_null())
+                    .col(ColumnDef::new(EmailTokens::TokenHash).string_len(64).not_null().unique_key())
+                    .col(ColumnDef::new(EmailTokens::ExpiresAt).date_time().not_null())
+                    .col(ColumnDef::new(EmailTokens::UsedAt).date_time().null())
+                    .col(
+                        ColumnDef::new(EmailTokens::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_email_tokens_user")
+                            .from(EmailTokens::Table, EmailTokens::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_email_tokens_user_purpose")
+                    .table(EmailTokens::Table)
+                    .col(EmailTokens::UserId)
+                    .col(EmailTokens::Purpose)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(EmailTokens::Table).to_owned())
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_column(Users::EmailVerifiedAt)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Users {
+    Table,
+    Id,
+    EmailVerifiedAt,
+}
+
+#[derive(Iden)]
+enum EmailTokens {
+    Table,
+    Id,
+    UserId,
+    Purpose,
+    TokenHash,
+    ExpiresAt,
+    UsedAt,
+    CreatedAt,
+}
@@ -0,0 +1,145 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // users: TOTP secret (set at setup, active once totp_enabled_at is set) and the last
+        // accepted time step, so a code cannot be replayed. SQLite adds one column per statement.
+        for column in [
+            ColumnDef::new(Users::TotpSecret).string().null().to_owned(),
+            ColumnDef::new(Users::TotpEnabledAt).date_time().null().to_owned(),
+            ColumnDef::new(Users::TotpLastStep).big_integer().null().to_owned(),
+        ] {
+            manager
+                .alter_table(Table::alter().table(Users::Table).add_column(column).to_owned())
+                .await?;
+        }
+
+        // recovery_codes (single-use fallbacks for a lost authenticator; only the hash is kept)
+        manager
+            .create_table(
+                Table::create()
+                    .table(RecoveryCodes::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(RecoveryCodes::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(RecoveryCodes::UserId).integer().not_null())
+                    .col(ColumnDef::new(RecoveryCodes::CodeHash).string_len(64).not_null())
+                    .col(ColumnDef::new(RecoveryCodes::UsedAt).date_time().null())
+                    .col(
+                        ColumnDef::new(RecoveryCodes::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_recovery_codes_user")
+                            .from(RecoveryCodes::Table, RecoveryCodes::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_recovery_codes_user")
+                    .table(RecoveryCodes::Table)
+                    .col(RecoveryCodes::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // mfa_challenges (password accepted, waiting for the second factor)
+        manager
+            .create_table(
+                Table::create()
+                    .table(MfaChallenges::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(MfaChallenges::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(MfaChallenges::UserId).integer().not_null())
+                    .col(ColumnDef::new(MfaChallenges::TokenHash).string_len(64).not_null().unique_key())
+                    .col(ColumnDef::new(MfaChallenges::Attempts).integer().not_null().default(0))
+                    .col(ColumnDef::new(MfaChallenges::ExpiresAt).date_time().not_null())
+                    .col(
+                        ColumnDef::new(MfaChallenges::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_mfa_challenges_user")
+                            .from(MfaChallenges::Table, MfaChallenges::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(MfaChallenges::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(RecoveryCodes::Table).to_owned())
+            .await?;
+        for column in [Users::TotpLastStep, Users::TotpEnabledAt, Users::TotpSecret] {
+            manager
+                .alter_table(Table::alter().table(Users::Table).drop_column(column).to_owned())
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Users {
+    Table,
+    Id,
+    TotpSecret,
+    TotpEnabledAt,
+    TotpLastStep,
+}
+
+#[derive(Iden)]
+enum RecoveryCodes {
+    Table,
+    Id,
+    UserId,
+    CodeHash,
+    UsedAt,
+    CreatedAt,
+}
+
+#[derive(Iden)]
+enum MfaChallenges {
+    Table,
+    Id,
+    UserId,
+    TokenHash,
+    Attempts,
+    ExpiresAt,
+    CreatedAt,
+}
@@ -0,0 +1,36 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // users: consecutive wrong passwords, and the lock they trigger
+        for column in [
+            ColumnDef::new(Users::FailedLogins).integer().not_null().default(0).to_owned(),
+            ColumnDef::new(Users::LockedUntil).date_time().null().to_owned(),
+        ] {
+            manager
+                .alter_table(Table::alter().table(Users::Table).add_column(column).to_owned())
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [Users::LockedUntil, Users::FailedLogins] {
+            manager
+                .alter_table(Table::alter().table(Users::Table).drop_column(column).to_owned())
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Users {
+    Table,
+    FailedLogins,
+    LockedUntil,
+}
@@ -0,0 +1,104 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // audit_log (who changed what; append-only, and kept when the user is deleted, so no
+        // foreign keys)
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuditLog::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AuditLog::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(AuditLog::UserId).integer().not_null())
+                    .col(ColumnDef::new(AuditLog::ActorUserId).integer().null())
+                    .col(ColumnDef::new(AuditLog::Action).string().not_null())
+                    .col(ColumnDef::new(AuditLog::EntityType).string().not_null())
+                    .col(ColumnDef::new(AuditLog::EntityId).integer().not_null())
+                    .col(ColumnDef::new(AuditLog::Before).json().null())
+                    .col(ColumnDef::new(AuditLog::After).json().null())
+                    .col(ColumnDef::new(AuditLog::RequestId).string().null())
+                    .col(ColumnDef::new(AuditLog::Ip).string().null())
+                    .col(
+                        ColumnDef::new(AuditLog::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_audit_log_user")
+                    .table(AuditLog::Table)
+                    .col(AuditLog::UserId)
+                    .col(AuditLog::CreatedAt)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_audit_log_actor")
+                    .table(AuditLog::Table)
+                    .col(AuditLog::ActorUserId)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_audit_log_entity")
+                    .table(AuditLog::Table)
+                    .col(AuditLog::EntityType)
+                    .col(AuditLog::EntityId)
+                    .to_owned(),
+            )
+            .await?;
+        let db = manager.get_connection();
+        for op in ["UPDATE", "DELETE"] {
+            db.execute_unprepared(&format!(
+                "CREATE TRIGGER IF NOT EXISTS audit_log_no_{} BEFORE {} ON audit_log BEGIN SELECT RAISE(ABORT, 'audit_log is append-only'); END",
+                op.to_lowercase(),
+                op
+            ))
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AuditLog::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum AuditLog {
+    Table,
+    Id,
+    UserId,
+    ActorUserId,
+    Action,
+    EntityType,
+    EntityId,
+    Before,
+    After,
+    RequestId,
+    Ip,
+    CreatedAt,
+}
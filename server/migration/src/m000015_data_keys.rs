@@ -0,0 +1,65 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // data_keys (per-user keys for sealed columns, wrapped by the master key). No foreign key:
+        // a deleted user's key must outlive them for the audit log entries it sealed.
+        manager
+            .create_table(
+                Table::create()
+                    .table(DataKeys::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(DataKeys::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(DataKeys::KeyId).string_len(32).not_null().unique_key())
+                    .col(ColumnDef::new(DataKeys::UserId).integer().null())
+                    .col(ColumnDef::new(DataKeys::WrappedKey).string().not_null())
+                    .col(ColumnDef::new(DataKeys::MasterKeyId).string().not_null())
+                    .col(
+                        ColumnDef::new(DataKeys::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_data_keys_user")
+                    .table(DataKeys::Table)
+                    .col(DataKeys::UserId)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(DataKeys::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum DataKeys {
+    Table,
+    Id,
+    KeyId,
+    UserId,
+    WrappedKey,
+    MasterKeyId,
+    CreatedAt,
+}
@@ -0,0 +1,134 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // oidc_logins (sign-ins waiting for the identity provider's redirect back)
+        manager
+            .create_table(
+                Table::create()
+                    .table(OidcLogins::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(OidcLogins::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(OidcLogins::StateHash).string_len(64).not_null().unique_key())
+                    .col(ColumnDef::new(OidcLogins::Provider).string().not_null())
+                    .col(ColumnDef::new(OidcLogins::Nonce).string().not_null())
+                    .col(ColumnDef::new(OidcLogins::CodeVerifier).string().not_null())
+                    .col(ColumnDef::new(OidcLogins::ExpiresAt).date_time().not_null())
+                    .col(
+                        ColumnDef::new(OidcLogins::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // user_identities (identity-provider accounts linked to users)
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserIdentities::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(UserIdentities::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(UserIdentities::UserId).integer().not_null())
+                    .col(ColumnDef::new(UserIdentities::Provider).string().not_null())
+                    .col(ColumnDef::new(UserIdentities::Subject).string().not_null())
+                    .col(ColumnDef::new(UserIdentities::Email).string().null())
+                    .col(
+                        ColumnDef::new(UserIdentities::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(ColumnDef::new(UserIdentities::LastLoginAt).date_time().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_user_identities_user")
+                            .from(UserIdentities::Table, UserIdentities::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_user_identities_subject")
+                    .table(UserIdentities::Table)
+                    .col(UserIdentities::Provider)
+                    .col(UserIdentities::Subject)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_user_identities_user")
+                    .table(UserIdentities::Table)
+                    .col(UserIdentities::UserId)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(UserIdentities::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(OidcLogins::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Users {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum OidcLogins {
+    Table,
+    Id,
+    StateHash,
+    Provider,
+    Nonce,
+    CodeVerifier,
+    ExpiresAt,
+    CreatedAt,
+}
+
+#[derive(Iden)]
+enum UserIdentities {
+    Table,
+    Id,
+    UserId,
+    Provider,
+    Subject,
+    Email,
+    CreatedAt,
+    LastLoginAt,
+}
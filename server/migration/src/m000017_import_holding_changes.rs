@@ -0,0 +1,74 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // import_holding_changes (what a batch did to asset holdings, so a rollback can undo it).
+        // No foreign key to assets: a deleted asset just has nothing left to undo.
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImportHoldingChanges::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ImportHoldingChanges::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ImportHoldingChanges::BatchId).integer().not_null())
+                    .col(ColumnDef::new(ImportHoldingChanges::AssetId).integer().not_null())
+                    .col(ColumnDef::new(ImportHoldingChanges::Units).decimal_len(16, 8).not_null())
+                    .col(ColumnDef::new(ImportHoldingChanges::UnitPrice).decimal_len(16, 8).not_null())
+                    .col(ColumnDef::new(ImportHoldingChanges::CreatedAsset).boolean().not_null().default(false))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_import_holding_changes_batch")
+                            .from(ImportHoldingChanges::Table, ImportHoldingChanges::BatchId)
+                            .to(ImportBatches::Table, ImportBatches::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_import_holding_changes_batch")
+                    .table(ImportHoldingChanges::Table)
+                    .col(ImportHoldingChanges::BatchId)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ImportHoldingChanges::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum ImportBatches {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum ImportHoldingChanges {
+    Table,
+    Id,
+    BatchId,
+    AssetId,
+    Units,
+    UnitPrice,
+    CreatedAsset,
+}
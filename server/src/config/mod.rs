@@ -3,24 +3,140 @@ use std::env;
 #[derive(Debug, Clone)]
 pub struct Config {
     pub database_url: String,
-    pub jwt_secret: String,
+    /// HS256 secret; also still accepted for verification once `jwt_private_key` is set.
+    pub jwt_secret: Option<String>,
+    /// PEM file with an Ed25519 (EdDSA) or RSA (RS256) private key to sign with instead.
+    pub jwt_private_key: Option<String>,
+    pub jwt_key_id: Option<String>,
+    /// Retired secrets and public key files whose tokens are still accepted.
+    pub jwt_previous_secrets: Vec<String>,
+    pub jwt_previous_public_keys: Vec<String>,
+    pub require_auth: bool,
+    /// Argon2id cost for new password hashes; older hashes are replaced at the next login.
+    pub argon2_memory_kib: u32,
+    pub argon2_iterations: u32,
+    pub argon2_parallelism: u32,
+    pub password_min_length: usize,
+    /// Extra passwords to refuse, one per line, on top of the bundled breach list.
+    pub password_breach_list: Option<String>,
+    /// Master key for encrypting sensitive columns and attachments, or a file holding it.
+    pub encryption_key: Option<String>,
+    pub encryption_key_file: Option<String>,
+    /// Retired master keys, still accepted until `rotate-keys` has rewrapped the data keys.
+    pub encryption_previous_keys: Vec<String>,
     pub server_host: String,
     pub server_port: u16,
+    /// Where clients reach the server, for redirect URIs registered with identity providers.
+    pub public_url: String,
+    /// Identity providers from `OIDC_PROVIDERS`, each configured by `OIDC_<NAME>_*` variables.
+    pub oidc_providers: Vec<crate::services::OidcProvider>,
+    pub attachment_dir: String,
+    pub mail_transport: String,
+    pub smtp_url: Option<String>,
+    pub mail_from: String,
+    pub mail_dir: String,
+    pub require_email_verification: bool,
+    pub rate_limit_auth: Option<crate::services::RateLimit>,
+    pub rate_limit_uploads: Option<crate::services::RateLimit>,
+    pub rate_limit_api: Option<crate::services::RateLimit>,
+    /// Take the client address from `X-Forwarded-For` (only behind a reverse proxy that sets it).
+    pub trust_forwarded_for: bool,
+}
+
+fn rate_limit_var(name: &str, default: crate::services::RateLimit) -> Option<crate::services::RateLimit> {
+    match env::var(name) {
+        Ok(v) => crate::services::RateLimit::parse(&v).unwrap_or_else(|e| panic!("{}: {}", name, e)),
+        Err(_) => Some(default),
+    }
+}
+
+fn number_var<T: std::str::FromStr>(name: &str, default: T) -> T {
+    match env::var(name) {
+        Ok(v) => v.trim().parse().unwrap_or_else(|_| panic!("{} must be a number", name)),
+        Err(_) => default,
+    }
+}
+
+/// A comma-separated list; empty entries are dropped.
+fn list_var(name: &str) -> Vec<String> {
+    env::var(name)
+        .map(|v| v.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default()
+}
+
+/// One provider per name in `OIDC_PROVIDERS`. `OIDC_<NAME>_ISSUER` and `OIDC_<NAME>_CLIENT_ID`
+/// are required; the client secret, scopes and redirect URI are optional.
+fn oidc_providers_var(public_url: &str) -> Vec<crate::services::OidcProvider> {
+    list_var("OIDC_PROVIDERS")
+        .into_iter()
+        .map(|name| {
+            let prefix = format!("OIDC_{}_", name.to_uppercase().replace('-', "_"));
+            let var = |key: &str| env::var(format!("{}{}", prefix, key)).ok().filter(|v| !v.is_empty());
+            let required = |key: &str| var(key).unwrap_or_else(|| panic!("{}{} must be set for OIDC provider {}", prefix, key, name));
+            let scopes = var("SCOPES").unwrap_or_else(|| crate::services::DEFAULT_OIDC_SCOPES.to_string());
+            crate::services::OidcProvider {
+                issuer: required("ISSUER"),
+                client_id: required("CLIENT_ID"),
+                client_secret: var("CLIENT_SECRET"),
+                scopes: scopes.split([' ', ',']).filter(|s| !s.is_empty()).map(str::to_string).collect(),
+                redirect_uri: var("REDIRECT_URI").unwrap_or_else(|| format!("{}/api/auth/oidc/{}/callback", public_url.trim_end_matches('/'), name)),
+                name,
+            }
+        })
+        .collect()
 }
 
 impl Config {
     pub fn from_env() -> Result<Self, env::VarError> {
+        let server_host = env::var("SERVER_HOST")
+            .unwrap_or_else(|_| "127.0.0.1".to_string());
+        let server_port: u16 = env::var("SERVER_PORT")
+            .unwrap_or_else(|_| "9999".to_string())
+            .parse()
+            .expect("SERVER_PORT must be a valid number");
+        let public_url = env::var("PUBLIC_URL")
+            .unwrap_or_else(|_| format!("http://{}:{}", server_host, server_port));
         Ok(Config {
             database_url: env::var("DATABASE_URL")
                 .unwrap_or_else(|_| "sqlite:./wallet.db".to_string()),
-            jwt_secret: env::var("JWT_SECRET")
-                .unwrap_or_else(|_| "your-secret-key".to_string()),
-            server_host: env::var("SERVER_HOST")
-                .unwrap_or_else(|_| "127.0.0.1".to_string()),
-            server_port: env::var("SERVER_PORT")
-                .unwrap_or_else(|_| "9999".to_string())
-                .parse()
-                .expect("SERVER_PORT must be a valid number"),
+            jwt_secret: env::var("JWT_SECRET").ok(),
+            jwt_private_key: env::var("JWT_PRIVATE_KEY").ok().filter(|v| !v.is_empty()),
+            jwt_key_id: env::var("JWT_KEY_ID").ok().filter(|v| !v.is_empty()),
+            jwt_previous_secrets: list_var("JWT_PREVIOUS_SECRETS"),
+            jwt_previous_public_keys: list_var("JWT_PREVIOUS_PUBLIC_KEYS"),
+            require_auth: env::var("REQUIRE_AUTH")
+                .map(|v| v.eq_ignore_ascii_case("true") || v == "1")
+                .unwrap_or(false),
+            argon2_memory_kib: number_var("ARGON2_MEMORY_KIB", crate::services::DEFAULT_ARGON2_MEMORY_KIB),
+            argon2_iterations: number_var("ARGON2_ITERATIONS", crate::services::DEFAULT_ARGON2_ITERATIONS),
+            argon2_parallelism: number_var("ARGON2_PARALLELISM", crate::services::DEFAULT_ARGON2_PARALLELISM),
+            password_min_length: number_var("PASSWORD_MIN_LENGTH", crate::services::DEFAULT_PASSWORD_MIN_LENGTH),
+            password_breach_list: env::var("PASSWORD_BREACH_LIST").ok().filter(|v| !v.is_empty()),
+            encryption_key: env::var("ENCRYPTION_KEY").ok().filter(|v| !v.is_empty()),
+            encryption_key_file: env::var("ENCRYPTION_KEY_FILE").ok().filter(|v| !v.is_empty()),
+            encryption_previous_keys: list_var("ENCRYPTION_PREVIOUS_KEYS"),
+            oidc_providers: oidc_providers_var(&public_url),
+            server_host,
+            server_port,
+            public_url,
+            attachment_dir: env::var("ATTACHMENT_DIR")
+                .unwrap_or_else(|_| crate::services::DEFAULT_ATTACHMENT_DIR.to_string()),
+            mail_transport: env::var("MAIL_TRANSPORT")
+                .unwrap_or_else(|_| "log".to_string()),
+            smtp_url: env::var("SMTP_URL").ok(),
+            mail_from: env::var("MAIL_FROM")
+                .unwrap_or_else(|_| crate::services::DEFAULT_MAIL_FROM.to_string()),
+            mail_dir: env::var("MAIL_DIR")
+                .unwrap_or_else(|_| "./mail".to_string()),
+            require_email_verification: env::var("REQUIRE_EMAIL_VERIFICATION")
+                .map(|v| v.eq_ignore_ascii_case("true") || v == "1")
+                .unwrap_or(false),
+            rate_limit_auth: rate_limit_var("RATE_LIMIT_AUTH", crate::services::DEFAULT_RATE_LIMIT_AUTH),
+            rate_limit_uploads: rate_limit_var("RATE_LIMIT_UPLOADS", crate::services::DEFAULT_RATE_LIMIT_UPLOADS),
+            rate_limit_api: rate_limit_var("RATE_LIMIT_API", crate::services::DEFAULT_RATE_LIMIT_API),
+            trust_forwarded_for: env::var("TRUST_FORWARDED_FOR")
+                .map(|v| v.eq_ignore_ascii_case("true") || v == "1")
+                .unwrap_or(false),
         })
     }
 }
\ No newline at end of file
@@ -4,36 +4,124 @@ pub mod routes;
 pub mod services;
 
 pub use config::*;
-pub use models::*;
+// Model modules share names with service modules (`user`, `account`, …); reach those by path.
+pub use models::{Account, Asset, Transaction, User};
 pub use routes::*;
 pub use services::*;
 
-use axum::{routing::{get, post, patch, delete}, Router, middleware};
+use axum::{extract::DefaultBodyLimit, routing::{get, post, put, patch, delete}, Router, middleware};
 
 // Build the application router so tests can instantiate it.
 pub fn build_router(state: routes::AppState) -> Router {
+    // Each group has its own rate limit (see `RateLimits`).
+    let auth = Router::new()
+        .route("/auth/login", post(routes::auth_login))
+        .route("/auth/refresh", post(routes::auth_refresh))
+        .route("/auth/logout", post(routes::auth_logout))
+        .route("/auth/forgot", post(routes::auth_forgot))
+        .route("/auth/reset", post(routes::auth_reset))
+        .route("/auth/verify", post(routes::auth_verify))
+        .route("/auth/unlock", post(routes::auth_unlock))
+        .route("/auth/2fa", get(routes::get_two_factor))
+        .route("/auth/2fa/setup", post(routes::post_two_factor_setup))
+        .route("/auth/2fa/confirm", post(routes::post_two_factor_confirm))
+        .route("/auth/2fa/disable", post(routes::post_two_factor_disable))
+        .route("/auth/2fa/recovery-codes", post(routes::post_two_factor_recovery_codes))
+        .route("/auth/2fa/verify", post(routes::auth_mfa_verify))
+        .route("/auth/oidc", get(routes::list_oidc_providers))
+        .route("/auth/oidc/{provider}/authorize", get(routes::get_oidc_authorize))
+        .route("/auth/oidc/{provider}/callback", get(routes::get_oidc_callback))
+        .route("/auth/sessions", get(routes::list_auth_sessions).delete(routes::delete_auth_sessions))
+        .route("/auth/sessions/{id}", delete(routes::delete_auth_session))
+        .route_layer(middleware::from_fn_with_state(state.clone(), routes::rate_limit_auth));
+
+    let uploads = Router::new()
+        .route("/me/import", post(routes::post_me_import).layer(DefaultBodyLimit::max(routes::ARCHIVE_BODY_LIMIT)))
+        .route(
+            "/transactions/{id}/attachments",
+            post(routes::post_transaction_attachment)
+                .layer(DefaultBodyLimit::max(routes::ATTACHMENT_BODY_LIMIT))
+                .get(routes::list_transaction_attachments),
+        )
+        .route("/import/profiles", post(routes::post_import_profile).get(routes::list_import_profiles))
+        .route("/import/profiles/{id}", delete(routes::delete_import_profile_route))
+        .route("/import/batches", get(routes::list_import_batches))
+        .route("/import/batches/{id}", get(routes::get_import_batch))
+        .route("/import/batches/{id}/rollback", post(routes::rollback_import_batch_route))
+        .route("/import/{format}/preview", post(routes::preview_import))
+        .route("/import/{format}/commit", post(routes::commit_import_route))
+        .route("/import/beancount", post(routes::post_beancount_import))
+        .route_layer(middleware::from_fn_with_state(state.clone(), routes::rate_limit_uploads));
+
     let api = Router::new()
         // users
         .route("/users", post(routes::post_user))
         .route("/users/{id}", get(routes::get_user).patch(routes::patch_user).delete(routes::delete_user_route))
-        // auth
-        .route("/auth/login", post(routes::auth_login))
-        .route("/auth/refresh", post(routes::auth_refresh))
+        // me
+        .route("/me/export", get(routes::get_me_export))
+        // audit log
+        .route("/audit", get(routes::get_audit_log))
+        // personal access tokens
+        .route("/tokens", post(routes::post_token).get(routes::list_tokens))
+        .route("/tokens/{id}", delete(routes::delete_token_route))
         // accounts
         .route("/accounts", post(routes::post_account).get(routes::list_accounts))
         .route("/accounts/{id}", get(routes::get_account).patch(routes::patch_account).delete(routes::delete_account_route))
+        .route("/accounts/{id}/card", put(routes::put_account_card))
+        // reconciliations
+        .route("/accounts/{id}/reconciliations", post(routes::post_reconciliation).get(routes::list_reconciliations))
+        .route("/reconciliations/{id}", get(routes::get_reconciliation).delete(routes::delete_reconciliation_route))
+        .route("/reconciliations/{id}/complete", post(routes::complete_reconciliation_route))
         // transactions
         .route("/transactions", post(routes::post_transaction).get(routes::list_transactions))
+        .route("/transactions/suggest", post(routes::post_transaction_suggestion))
+        .route("/transactions/duplicates", get(routes::get_transaction_duplicates))
+        .route("/transactions/merge", post(routes::post_transaction_merge))
         .route("/transactions/{id}", get(routes::get_transaction).patch(routes::patch_transaction).delete(routes::delete_transaction_route))
+        .route("/transactions/{id}/unlock", post(routes::unlock_transaction_route))
+        .route("/transactions/{id}/tags", put(routes::put_transaction_tags))
+        // attachments (uploads are in the uploads group)
+        .route("/attachments/{id}", get(routes::get_attachment_file).delete(routes::delete_attachment_route))
+        .route("/attachments/{id}/thumbnail", get(routes::get_attachment_thumbnail))
+        // rules
+        .route("/rules", post(routes::post_rule).get(routes::list_rules))
+        .route("/rules/apply", post(routes::post_apply_rules))
+        .route("/rules/{id}", put(routes::put_rule).delete(routes::delete_rule_route))
+        // exports
+        .route("/export/beancount", get(routes::get_beancount_export))
+        .route("/export/ledger", get(routes::get_ledger_export))
+        // reports
+        .route("/reports/statement", get(routes::get_statement_report))
+        // dashboard
+        .route("/dashboard", get(routes::get_dashboard))
+        // stats
+        .route("/stats/cashflow", get(routes::get_cashflow_stats))
+        // schedules
+        .route("/recurring", post(routes::post_recurring).get(routes::list_recurring))
+        .route("/recurring/{id}", patch(routes::patch_recurring).delete(routes::delete_recurring_route))
+        .route("/investment-plans", post(routes::post_investment_plan).get(routes::list_investment_plans))
+        .route("/investment-plans/{id}", patch(routes::patch_investment_plan).delete(routes::delete_investment_plan_route))
+        // forecast
+        .route("/forecast", get(routes::get_forecast))
+        // insights
+        .route("/insights/anomalies", get(routes::get_anomalies))
+        .route("/insights/anomalies/dismissals", post(routes::post_anomaly_dismissal))
+        .route("/insights/anomalies/dismissals/{id}", delete(routes::delete_anomaly_dismissal_route))
         // assets
         .route("/assets", post(routes::post_asset).get(routes::list_assets))
         .route("/assets/{id}", get(routes::get_asset).patch(routes::patch_asset).delete(routes::delete_asset_route))
+        .route_layer(middleware::from_fn_with_state(state.clone(), routes::rate_limit_api))
+        .merge(auth)
+        .merge(uploads)
         .with_state(state.clone())
+        .layer(middleware::from_fn_with_state(state.clone(), routes::invalidate_caches))
         // Attach auth middleware (toggle via REQUIRE_AUTH=1), allowlist login and register
         .layer(middleware::from_fn_with_state(state.clone(), routes::require_auth));
 
     Router::new()
         .route("/health", get(routes::health_check))
+        .route("/.well-known/jwks.json", get(routes::get_jwks))
         .nest("/api", api)
         .with_state(state)
+        .layer(middleware::from_fn(routes::assign_request_id))
 }
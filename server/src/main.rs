@@ -1,7 +1,7 @@
-use axum::{routing::{get, post, patch, delete}, Router};
 use dotenv::dotenv;
 use std::net::SocketAddr;
-use server::{config::Config, routes::{health_check, AppState, post_user, get_user, patch_user, delete_user_route, post_account, get_account, list_accounts, patch_account, delete_account_route, post_transaction, get_transaction, list_transactions, patch_transaction, delete_transaction_route, post_asset, get_asset, list_assets, patch_asset, delete_asset_route}, services::establish_connection};
+use server::{config::Config, routes::AppState, services::{establish_connection, init_encryption, mailer_from_config, is_placeholder_jwt_secret, rotate_encryption_keys, JwtKeys, LocalStorage, MasterKeys, OidcProviders, PasswordHasher, RateLimits}};
+use std::sync::Arc;
 use migration::{Migrator, MigratorTrait};
 
 #[tokio::main]
@@ -9,13 +9,41 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     dotenv().ok();
 
     let config = Config::from_env()?;
+    // Refuses to start with a well-known secret when auth is required.
+    let jwt_keys = JwtKeys::from_config(&config)?;
+    if !config.require_auth && config.jwt_private_key.is_none() && config.jwt_secret.as_deref().is_none_or(is_placeholder_jwt_secret) {
+        eprintln!("warning: signing tokens with a well-known JWT_SECRET; set your own before enabling REQUIRE_AUTH");
+    }
 
     let db = establish_connection(&config.database_url).await?;
 
     // Run database migrations on startup
     Migrator::up(&db, None).await?;
-
-    let state = AppState { db: db.clone() };
+    // Fails fast when the database holds encrypted data and no key, or a key it cannot use.
+    init_encryption(&db, MasterKeys::from_config(&config)?).await?;
+
+    // `server rotate-keys`, with the server stopped: re-encrypt everything, then exit.
+    if std::env::args().nth(1).as_deref() == Some("rotate-keys") {
+        let report = rotate_encryption_keys(&db, &LocalStorage::new(&config.attachment_dir)).await?;
+        println!(
+            "Re-encrypted {} rows and {} attachment files; {} data keys are now wrapped by master key {}",
+            report.rows,
+            report.blobs,
+            report.data_keys,
+            report.master_key_id,
+        );
+        return Ok(());
+    }
+
+    let state = AppState::new(db.clone())
+        .with_attachment_storage(Arc::new(LocalStorage::new(&config.attachment_dir)))
+        .with_mailer(mailer_from_config(&config)?)
+        .with_email_verification_required(config.require_email_verification)
+        .with_rate_limits(RateLimits::new(config.rate_limit_auth, config.rate_limit_uploads, config.rate_limit_api))
+        .with_trusted_forwarded_for(config.trust_forwarded_for)
+        .with_jwt_keys(jwt_keys)
+        .with_password_hasher(PasswordHasher::from_config(&config)?)
+        .with_oidc_providers(OidcProviders::new(config.oidc_providers.clone()));
 
     let app = server::build_router(state.clone());
 
@@ -27,7 +55,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("Server running on http://{}", addr);
 
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    // Peer addresses feed the login throttle and the rate limits.
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
 
     Ok(())
 }
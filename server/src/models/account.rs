@@ -7,11 +7,17 @@ pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
     pub user_id: i32,
-    pub name: String,
+    pub name: super::sealed::SealedText,
     pub account_type: String,
     pub balance: Decimal,
     pub currency: String,
     pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Credit cards: day of month the statement closes.
+    pub statement_day: Option<i32>,
+    /// Credit cards: day of month the statement balance is due.
+    pub payment_due_day: Option<i32>,
+    /// Credit cards: account the bill is paid from.
+    pub payment_account_id: Option<i32>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -24,6 +30,8 @@ pub enum Relation {
     User,
     #[sea_orm(has_many = "super::transaction::Entity")]
     Transaction,
+    #[sea_orm(has_many = "super::reconciliation::Entity")]
+    Reconciliation,
 }
 
 impl Related<super::user::Entity> for Entity {
@@ -38,4 +46,23 @@ impl Related<super::transaction::Entity> for Entity {
     }
 }
 
-impl ActiveModelBehavior for ActiveModel {}
\ No newline at end of file
+impl Related<super::reconciliation::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Reconciliation.def()
+    }
+}
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    /// Seals `name` with the owner's data key when encryption is on.
+    async fn before_save<C: ConnectionTrait>(mut self, db: &C, insert: bool) -> Result<Self, DbErr> {
+        if let Some(plaintext) = crate::services::pending_seal(&self.name, insert) {
+            let owner = match self.user_id.try_as_ref() {
+                Some(user_id) => *user_id,
+                None => crate::services::account_owner(db, *self.id.as_ref()).await?,
+            };
+            self.name = sea_orm::Set(crate::services::seal_text(db, owner, &plaintext).await?.into());
+        }
+        Ok(self)
+    }
+}
\ No newline at end of file
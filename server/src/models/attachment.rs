@@ -0,0 +1,46 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A file attached to a transaction. A user's identical files share one stored blob, named by
+/// `sha256` and the owner (`services::blob_key`).
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "attachments")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub transaction_id: i32,
+    pub file_name: super::sealed::SealedText,
+    pub content_type: String,
+    pub size: i64, // bytes
+    pub sha256: String, // lowercase hex
+    pub has_thumbnail: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::transaction::Entity",
+        from = "Column::TransactionId",
+        to = "super::transaction::Column::Id"
+    )]
+    Transaction,
+}
+
+impl Related<super::transaction::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Transaction.def()
+    }
+}
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    /// Seals `file_name` with the owner's data key when encryption is on.
+    async fn before_save<C: ConnectionTrait>(mut self, db: &C, insert: bool) -> Result<Self, DbErr> {
+        if let Some(plaintext) = crate::services::pending_seal(&self.file_name, insert) {
+            let owner = crate::services::transaction_owner(db, None, self.transaction_id.try_as_ref().copied()).await?;
+            self.file_name = sea_orm::Set(crate::services::seal_text(db, owner, &plaintext).await?.into());
+        }
+        Ok(self)
+    }
+}
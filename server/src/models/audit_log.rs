@@ -0,0 +1,26 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// One create, update or delete. `user_id` owns the entity (whose history it is), `actor_user_id`
+/// made the change when known. For updates `before`/`after` hold only the changed fields.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "audit_log")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub user_id: i32,
+    pub actor_user_id: Option<i32>,
+    pub action: String, // create | update | delete
+    pub entity_type: String, // user | account | transaction | asset
+    pub entity_id: i32,
+    pub before: Option<Json>,
+    pub after: Option<Json>,
+    pub request_id: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,25 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A data key for sealed columns and attachment blobs, stored wrapped (encrypted) by the master
+/// key named in `master_key_id`. Users each have their own; keys with no `user_id` sealed
+/// attachment blobs back when identical files were shared across users. The newest key is used
+/// for writing; older ones stay for what they encrypted.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "data_keys")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    /// Named in each ciphertext; 32 hex characters.
+    pub key_id: String,
+    pub user_id: Option<i32>,
+    #[serde(skip)]
+    pub wrapped_key: String, // base64 of nonce || ciphertext
+    pub master_key_id: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,36 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// One buy or sell an import batch applied to an asset, kept so rolling the batch back can
+/// apply the opposite.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "import_holding_changes")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub batch_id: i32,
+    pub asset_id: i32,
+    /// Positive for buys, negative for sells.
+    pub units: Decimal,
+    pub unit_price: Decimal,
+    /// The asset did not exist before the import.
+    pub created_asset: bool,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::import_batch::Entity",
+        from = "Column::BatchId",
+        to = "super::import_batch::Column::Id"
+    )]
+    ImportBatch,
+}
+
+impl Related<super::import_batch::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ImportBatch.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -2,8 +2,29 @@ pub mod user;
 pub mod account;
 pub mod transaction;
 pub mod asset;
+pub mod reconciliation;
+pub mod import_profile;
+pub mod import_batch;
+pub mod import_holding_change;
+pub mod recurring_transaction;
+pub mod investment_plan;
+pub mod anomaly_dismissal;
+pub mod rule;
+pub mod attachment;
+pub mod session;
+pub mod email_token;
+pub mod recovery_code;
+pub mod mfa_challenge;
+pub mod api_token;
+pub mod audit_log;
+pub mod data_key;
+pub mod oidc_login;
+pub mod user_identity;
+pub mod sealed;
 
-pub use user::*;
-pub use account::*;
-pub use transaction::*;
-pub use asset::*;
\ No newline at end of file
+// Every entity module defines `Entity`, `Model`, `Column` and so on, so the core entities are
+// re-exported under their own names and everything else is reached through its module.
+pub use user::Entity as User;
+pub use account::Entity as Account;
+pub use transaction::Entity as Transaction;
+pub use asset::Entity as Asset;
@@ -0,0 +1,24 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A sign-in sent to an identity provider and not yet back. Looked up by the SHA-256 of the
+/// `state` parameter; the nonce and PKCE verifier are needed to finish it.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "oidc_logins")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub state_hash: String,
+    pub provider: String,
+    #[serde(skip)]
+    pub nonce: String,
+    #[serde(skip)]
+    pub code_verifier: String,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
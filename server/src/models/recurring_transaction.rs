@@ -0,0 +1,55 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "recurring_transactions")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub user_id: i32,
+    pub account_id: i32,
+    pub transaction_type: String, // "income", "expense", "transfer"
+    pub amount: Decimal,
+    pub description: super::sealed::SealedText,
+    pub category: Option<String>,
+    pub frequency: String, // "daily", "weekly", "monthly", "yearly"
+    pub interval: i32,     // every `interval` periods
+    pub start_date: chrono::NaiveDate,
+    pub end_date: Option<chrono::NaiveDate>,
+    pub active: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::account::Entity",
+        from = "Column::AccountId",
+        to = "super::account::Column::Id"
+    )]
+    Account,
+}
+
+impl Related<super::account::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Account.def()
+    }
+}
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    /// Seals `description` with the owner's data key when encryption is on.
+    async fn before_save<C: ConnectionTrait>(mut self, db: &C, insert: bool) -> Result<Self, DbErr> {
+        if let Some(plaintext) = crate::services::pending_seal(&self.description, insert) {
+            let owner = match self.user_id.try_as_ref() {
+                Some(user_id) => *user_id,
+                None => {
+                    let id = self.id.try_as_ref().copied().ok_or_else(|| DbErr::Custom("recurring transaction without an owner".into()))?;
+                    Entity::find_by_id(id).one(db).await?.ok_or_else(|| DbErr::RecordNotFound(format!("recurring transaction {}", id)))?.user_id
+                }
+            };
+            self.description = sea_orm::Set(crate::services::seal_text(db, owner, &plaintext).await?.into());
+        }
+        Ok(self)
+    }
+}
@@ -0,0 +1,45 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A categorization rule: every condition that is set must match, then every action that is set applies.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "rules")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub user_id: i32,
+    pub name: String,
+    pub priority: i32, // lower runs first
+    pub enabled: bool,
+    // conditions
+    pub description_contains: Option<String>,
+    pub description_regex: Option<String>,
+    pub counterparty_contains: Option<String>,
+    pub account_id: Option<i32>,
+    pub min_amount: Option<Decimal>,
+    pub max_amount: Option<Decimal>,
+    // actions
+    pub set_category: Option<String>,
+    pub add_tags: Option<Json>, // JSON array of strings
+    pub rename_description: Option<String>,
+    pub mark_transfer: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::UserId",
+        to = "super::user::Column::Id"
+    )]
+    User,
+}
+
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,123 @@
+use sea_orm::sea_query::{ArrayType, ColumnType, Nullable, ValueType, ValueTypeErr};
+use sea_orm::{ColIdx, DbErr, QueryResult, TryGetError, TryGetable, Value};
+use serde::{Deserialize, Serialize};
+use std::ops::Deref;
+
+/// A text column that is stored encrypted once a master key is configured (see
+/// `services::encryption`). It always holds plaintext in memory: values are decrypted as rows
+/// are read and sealed by the entity's `before_save`, so code can treat it like a `String`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SealedText(pub String);
+
+impl SealedText {
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+/// A sealed column, nullable or not, as `services::pending_seal` sees it.
+pub trait SealedColumn {
+    fn plaintext(&self) -> Option<&str>;
+}
+
+impl SealedColumn for SealedText {
+    fn plaintext(&self) -> Option<&str> {
+        Some(&self.0)
+    }
+}
+
+impl SealedColumn for Option<SealedText> {
+    fn plaintext(&self) -> Option<&str> {
+        self.as_ref().map(|s| s.0.as_str())
+    }
+}
+
+impl Deref for SealedText {
+    type Target = String;
+
+    fn deref(&self) -> &String {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for SealedText {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<String> for SealedText {
+    fn from(s: String) -> Self {
+        SealedText(s)
+    }
+}
+
+impl From<&str> for SealedText {
+    fn from(s: &str) -> Self {
+        SealedText(s.to_string())
+    }
+}
+
+impl From<SealedText> for String {
+    fn from(s: SealedText) -> Self {
+        s.0
+    }
+}
+
+impl PartialEq<str> for SealedText {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for SealedText {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<String> for SealedText {
+    fn eq(&self, other: &String) -> bool {
+        &self.0 == other
+    }
+}
+
+impl From<SealedText> for Value {
+    fn from(s: SealedText) -> Self {
+        Value::String(Some(Box::new(s.0)))
+    }
+}
+
+impl TryGetable for SealedText {
+    fn try_get_by<I: ColIdx>(res: &QueryResult, index: I) -> Result<Self, TryGetError> {
+        let stored = String::try_get_by(res, index)?;
+        crate::services::open_text(&stored)
+            .map(SealedText)
+            .map_err(|e| TryGetError::DbErr(DbErr::Custom(e.to_string())))
+    }
+}
+
+impl ValueType for SealedText {
+    fn try_from(v: Value) -> Result<Self, ValueTypeErr> {
+        <String as ValueType>::try_from(v).map(SealedText)
+    }
+
+    fn type_name() -> String {
+        "SealedText".to_string()
+    }
+
+    fn array_type() -> ArrayType {
+        ArrayType::String
+    }
+
+    fn column_type() -> ColumnType {
+        ColumnType::Text
+    }
+}
+
+impl Nullable for SealedText {
+    fn null() -> Value {
+        Value::String(None)
+    }
+}
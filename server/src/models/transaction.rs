@@ -9,8 +9,18 @@ pub struct Model {
     pub account_id: i32,
     pub transaction_type: String, // "income", "expense", "transfer"
     pub amount: Decimal,
-    pub description: String,
+    pub description: super::sealed::SealedText,
     pub category: Option<String>,
+    pub status: String, // "pending", "cleared", "reconciled"
+    pub reconciliation_id: Option<i32>,
+    pub import_batch_id: Option<i32>,
+    pub counterparty: Option<super::sealed::SealedText>,
+    /// Identifier assigned by the source (bank FITID, Alipay trade number, ...), used to link and dedupe imports.
+    pub external_id: Option<String>,
+    /// For refunds: the transaction being refunded.
+    pub refund_of_id: Option<i32>,
+    /// JSON array of strings.
+    pub tags: Option<Json>,
     pub created_at: chrono::DateTime<chrono::Utc>,
 }
 
@@ -22,6 +32,12 @@ pub enum Relation {
         to = "super::account::Column::Id"
     )]
     Account,
+    #[sea_orm(
+        belongs_to = "super::import_batch::Entity",
+        from = "Column::ImportBatchId",
+        to = "super::import_batch::Column::Id"
+    )]
+    ImportBatch,
 }
 
 impl Related<super::account::Entity> for Entity {
@@ -30,4 +46,28 @@ impl Related<super::account::Entity> for Entity {
     }
 }
 
-impl ActiveModelBehavior for ActiveModel {}
\ No newline at end of file
+impl Related<super::import_batch::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ImportBatch.def()
+    }
+}
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    /// Seals `description` and `counterparty` with the owner's data key when encryption is on.
+    async fn before_save<C: ConnectionTrait>(mut self, db: &C, insert: bool) -> Result<Self, DbErr> {
+        let description = crate::services::pending_seal(&self.description, insert);
+        let counterparty = crate::services::pending_seal(&self.counterparty, insert);
+        if description.is_none() && counterparty.is_none() {
+            return Ok(self);
+        }
+        let owner = crate::services::transaction_owner(db, self.account_id.try_as_ref().copied(), self.id.try_as_ref().copied()).await?;
+        if let Some(plaintext) = description {
+            self.description = sea_orm::Set(crate::services::seal_text(db, owner, &plaintext).await?.into());
+        }
+        if let Some(plaintext) = counterparty {
+            self.counterparty = sea_orm::Set(Some(crate::services::seal_text(db, owner, &plaintext).await?.into()));
+        }
+        Ok(self)
+    }
+}
\ No newline at end of file
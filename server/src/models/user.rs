@@ -13,6 +13,18 @@ pub struct Model {
     pub password_hash: String,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub email_verified_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Base32 TOTP secret; two-factor login is on only once `totp_enabled_at` is set.
+    #[serde(skip)]
+    pub totp_secret: Option<String>,
+    pub totp_enabled_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Last accepted TOTP time step, so a code works once.
+    #[serde(skip)]
+    pub totp_last_step: Option<i64>,
+    /// Wrong passwords since the last successful login.
+    pub failed_logins: i32,
+    /// Logins are refused until then (or until the emailed unlock token is used).
+    pub locked_until: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -2,9 +2,11 @@ use axum::{extract::{Path, State, Query}, http::StatusCode, Json};
 use serde::Deserialize;
 use crate::routes::AppState;
 use sea_orm::prelude::Decimal;
-use crate::services::{create_account, get_account_by_id, find_accounts_by_user, update_account, delete_account};
+use crate::services::{AuditContext, create_account, get_account_by_id, find_accounts_by_user, update_account, delete_account, set_card_schedule, attachment_hashes, release_attachment_blobs};
+use crate::models::transaction;
+use sea_orm::{ColumnTrait, Condition};
 use std::str::FromStr;
-use crate::routes::{ErrorResp, json_error, internal_json, bad_request_json};
+use crate::routes::{ErrorResp, json_error, internal_json, bad_request_json, service_json};
 
 #[derive(Deserialize)]
 pub struct CreateAccountReq {
@@ -26,9 +28,9 @@ pub struct UpdateAccountReq {
 #[derive(Deserialize)]
 pub struct AccountsQuery { pub user_id: i32 }
 
-pub async fn post_account(State(state): State<AppState>, Json(body): Json<CreateAccountReq>) -> Result<(StatusCode, Json<crate::models::account::Model>), (StatusCode, Json<ErrorResp>)> {
+pub async fn post_account(State(state): State<AppState>, audit: AuditContext, Json(body): Json<CreateAccountReq>) -> Result<(StatusCode, Json<crate::models::account::Model>), (StatusCode, Json<ErrorResp>)> {
     let bal = Decimal::from_str(&body.balance).map_err(bad_request_json)?;
-    let model = create_account(&state.db, body.user_id, body.name, body.account_type, bal, body.currency).await.map_err(internal_json)?;
+    let model = create_account(&state.db, &audit, body.user_id, body.name, body.account_type, bal, body.currency).await.map_err(internal_json)?;
     Ok((StatusCode::CREATED, Json(model)))
 }
 
@@ -44,19 +46,35 @@ pub async fn list_accounts(State(state): State<AppState>, Query(q): Query<Accoun
     Ok(Json(list))
 }
 
-pub async fn patch_account(State(state): State<AppState>, Path(id): Path<i32>, Json(body): Json<UpdateAccountReq>) -> Result<Json<crate::models::account::Model>, (StatusCode, Json<ErrorResp>)> {
+pub async fn patch_account(State(state): State<AppState>, Path(id): Path<i32>, audit: AuditContext, Json(body): Json<UpdateAccountReq>) -> Result<Json<crate::models::account::Model>, (StatusCode, Json<ErrorResp>)> {
     let balance = match body.balance {
         Some(s) => Some(Decimal::from_str(&s).map_err(bad_request_json)?),
         None => None,
     };
-    match update_account(&state.db, id, body.name, body.account_type, balance, body.currency).await.map_err(internal_json)? {
+    match update_account(&state.db, &audit, id, body.name, body.account_type, balance, body.currency).await.map_err(internal_json)? {
         Some(m) => Ok(Json(m)),
         None => Err(json_error(StatusCode::NOT_FOUND, "not_found", "account not found")),
     }
 }
 
-pub async fn delete_account_route(State(state): State<AppState>, Path(id): Path<i32>) -> Result<StatusCode, (StatusCode, Json<ErrorResp>)> {
-    let affected = delete_account(&state.db, id).await.map_err(internal_json)?;
+pub async fn delete_account_route(State(state): State<AppState>, Path(id): Path<i32>, audit: AuditContext) -> Result<StatusCode, (StatusCode, Json<ErrorResp>)> {
+    let hashes = attachment_hashes(&state.db, Condition::all().add(transaction::Column::AccountId.eq(id))).await.map_err(internal_json)?;
+    let affected = delete_account(&state.db, &audit, id).await.map_err(internal_json)?;
+    release_attachment_blobs(&state.db, state.attachments.as_ref(), hashes).await.map_err(internal_json)?;
     if affected == 0 { return Err(json_error(StatusCode::NOT_FOUND, "not_found", "account not found")); }
     Ok(StatusCode::NO_CONTENT)
 }
+
+#[derive(Deserialize)]
+pub struct CardScheduleReq {
+    pub statement_day: Option<i32>,
+    pub payment_due_day: Option<i32>,
+    pub payment_account_id: Option<i32>,
+}
+
+pub async fn put_account_card(State(state): State<AppState>, Path(id): Path<i32>, audit: AuditContext, Json(body): Json<CardScheduleReq>) -> Result<Json<crate::models::account::Model>, (StatusCode, Json<ErrorResp>)> {
+    match set_card_schedule(&state.db, &audit, id, body.statement_day, body.payment_due_day, body.payment_account_id).await.map_err(service_json)? {
+        Some(m) => Ok(Json(m)),
+        None => Err(json_error(StatusCode::NOT_FOUND, "not_found", "account not found")),
+    }
+}
@@ -2,7 +2,7 @@ use axum::{extract::{Path, State, Query}, http::StatusCode, Json};
 use serde::Deserialize;
 use crate::routes::AppState;
 use sea_orm::prelude::Decimal;
-use crate::services::{create_asset, get_asset_by_id, find_assets_by_user, update_asset, delete_asset};
+use crate::services::{AuditContext, create_asset, get_asset_by_id, find_assets_by_user, update_asset, delete_asset};
 use std::str::FromStr;
 use crate::routes::{ErrorResp, json_error, internal_json, bad_request_json};
 
@@ -27,10 +27,10 @@ pub struct UpdateAssetReq {
 #[derive(Deserialize)]
 pub struct AssetsQuery { pub user_id: i32 }
 
-pub async fn post_asset(State(state): State<AppState>, Json(body): Json<CreateAssetReq>) -> Result<(StatusCode, Json<crate::models::asset::Model>), (StatusCode, Json<ErrorResp>)> {
+pub async fn post_asset(State(state): State<AppState>, audit: AuditContext, Json(body): Json<CreateAssetReq>) -> Result<(StatusCode, Json<crate::models::asset::Model>), (StatusCode, Json<ErrorResp>)> {
     let qty = Decimal::from_str(&body.quantity).map_err(bad_request_json)?;
     let avg = Decimal::from_str(&body.avg_price).map_err(bad_request_json)?;
-    let model = create_asset(&state.db, body.user_id, body.symbol, body.name, qty, avg, body.asset_type).await.map_err(internal_json)?;
+    let model = create_asset(&state.db, &audit, body.user_id, body.symbol, body.name, qty, avg, body.asset_type).await.map_err(internal_json)?;
     Ok((StatusCode::CREATED, Json(model)))
 }
 
@@ -46,17 +46,17 @@ pub async fn list_assets(State(state): State<AppState>, Query(q): Query<AssetsQu
     Ok(Json(list))
 }
 
-pub async fn patch_asset(State(state): State<AppState>, Path(id): Path<i32>, Json(body): Json<UpdateAssetReq>) -> Result<Json<crate::models::asset::Model>, (StatusCode, Json<ErrorResp>)> {
+pub async fn patch_asset(State(state): State<AppState>, Path(id): Path<i32>, audit: AuditContext, Json(body): Json<UpdateAssetReq>) -> Result<Json<crate::models::asset::Model>, (StatusCode, Json<ErrorResp>)> {
     let quantity = match body.quantity { Some(s) => Some(Decimal::from_str(&s).map_err(bad_request_json)?), None => None };
     let avg_price = match body.avg_price { Some(s) => Some(Decimal::from_str(&s).map_err(bad_request_json)?), None => None };
-    match update_asset(&state.db, id, body.name, quantity, avg_price, body.asset_type).await.map_err(internal_json)? {
+    match update_asset(&state.db, &audit, id, body.name, quantity, avg_price, body.asset_type).await.map_err(internal_json)? {
         Some(m) => Ok(Json(m)),
         None => Err(json_error(StatusCode::NOT_FOUND, "not_found", "asset not found")),
     }
 }
 
-pub async fn delete_asset_route(State(state): State<AppState>, Path(id): Path<i32>) -> Result<StatusCode, (StatusCode, Json<ErrorResp>)> {
-    let affected = delete_asset(&state.db, id).await.map_err(internal_json)?;
+pub async fn delete_asset_route(State(state): State<AppState>, Path(id): Path<i32>, audit: AuditContext) -> Result<StatusCode, (StatusCode, Json<ErrorResp>)> {
+    let affected = delete_asset(&state.db, &audit, id).await.map_err(internal_json)?;
     if affected == 0 { return Err(json_error(StatusCode::NOT_FOUND, "not_found", "asset not found")); }
     Ok(StatusCode::NO_CONTENT)
 }
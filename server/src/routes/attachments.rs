@@ -0,0 +1,109 @@
+use axum::{extract::{Multipart, Path, State}, http::{header, StatusCode}, response::IntoResponse, Json};
+use crate::routes::AppState;
+use crate::models::attachment;
+use crate::services::{
+    add_attachment, attachment_owner, find_attachments_by_transaction, get_attachment_by_id, get_transaction_by_id, read_attachment, read_attachment_thumbnail,
+    delete_attachment, AttachmentError, MAX_ATTACHMENT_BYTES,
+};
+use crate::routes::{ErrorResp, json_error, internal_json, service_json};
+
+/// Room for the multipart framing around a maximum-size file.
+pub const ATTACHMENT_BODY_LIMIT: usize = MAX_ATTACHMENT_BYTES + 64 * 1024;
+
+fn attachment_json(e: AttachmentError) -> (StatusCode, Json<ErrorResp>) {
+    match e {
+        AttachmentError::Service(e) => service_json(e),
+        AttachmentError::TooLarge => json_error(StatusCode::PAYLOAD_TOO_LARGE, "too_large", e.to_string()),
+        AttachmentError::UnsupportedType(_) => json_error(StatusCode::UNSUPPORTED_MEDIA_TYPE, "unsupported_media_type", e.to_string()),
+        AttachmentError::Storage(_) => internal_json(e),
+    }
+}
+
+fn storage_json(e: std::io::Error) -> (StatusCode, Json<ErrorResp>) {
+    if e.kind() == std::io::ErrorKind::NotFound {
+        json_error(StatusCode::NOT_FOUND, "not_found", "attachment file is missing from storage")
+    } else {
+        internal_json(e)
+    }
+}
+
+/// `inline; filename*=UTF-8''…` (RFC 6266), so Chinese file names survive.
+fn disposition(file_name: &str) -> String {
+    let encoded: String = file_name
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect();
+    format!("inline; filename*=UTF-8''{}", encoded)
+}
+
+/// Multipart upload; the file goes in a part named `file`.
+pub async fn post_transaction_attachment(State(state): State<AppState>, Path(id): Path<i32>, mut multipart: Multipart) -> Result<(StatusCode, Json<attachment::Model>), (StatusCode, Json<ErrorResp>)> {
+    let multipart_json = |e: axum::extract::multipart::MultipartError| json_error(e.status(), "invalid_request", e.body_text());
+    loop {
+        let Some(field) = multipart.next_field().await.map_err(multipart_json)? else {
+            return Err(json_error(StatusCode::BAD_REQUEST, "invalid_request", "missing multipart part \"file\""));
+        };
+        if field.name() != Some("file") {
+            continue;
+        }
+        let file_name = field.file_name().map(str::to_string);
+        let declared = field.content_type().map(str::to_string);
+        let bytes = field.bytes().await.map_err(multipart_json)?;
+        let added = add_attachment(&state.db, state.attachments.as_ref(), id, file_name.as_deref(), declared.as_deref(), bytes.to_vec())
+            .await
+            .map_err(attachment_json)?;
+        return match added {
+            Some((model, true)) => Ok((StatusCode::CREATED, Json(model))),
+            Some((model, false)) => Ok((StatusCode::OK, Json(model))),
+            None => Err(json_error(StatusCode::NOT_FOUND, "not_found", "transaction not found")),
+        };
+    }
+}
+
+pub async fn list_transaction_attachments(State(state): State<AppState>, Path(id): Path<i32>) -> Result<Json<Vec<attachment::Model>>, (StatusCode, Json<ErrorResp>)> {
+    if get_transaction_by_id(&state.db, id).await.map_err(internal_json)?.is_none() {
+        return Err(json_error(StatusCode::NOT_FOUND, "not_found", "transaction not found"));
+    }
+    let list = find_attachments_by_transaction(&state.db, id).await.map_err(internal_json)?;
+    Ok(Json(list))
+}
+
+async fn find_attachment(state: &AppState, id: i32) -> Result<attachment::Model, (StatusCode, Json<ErrorResp>)> {
+    get_attachment_by_id(&state.db, id)
+        .await
+        .map_err(internal_json)?
+        .ok_or_else(|| json_error(StatusCode::NOT_FOUND, "not_found", "attachment not found"))
+}
+
+pub async fn get_attachment_file(State(state): State<AppState>, Path(id): Path<i32>) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResp>)> {
+    let model = find_attachment(&state, id).await?;
+    let owner = attachment_owner(&state.db, &model).await.map_err(internal_json)?;
+    let bytes = read_attachment(state.attachments.as_ref(), owner, &model).map_err(storage_json)?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, model.content_type.clone()),
+            (header::CONTENT_DISPOSITION, disposition(&model.file_name)),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        ],
+        bytes,
+    ))
+}
+
+pub async fn get_attachment_thumbnail(State(state): State<AppState>, Path(id): Path<i32>) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResp>)> {
+    let model = find_attachment(&state, id).await?;
+    if !model.has_thumbnail {
+        return Err(json_error(StatusCode::NOT_FOUND, "not_found", "attachment has no thumbnail"));
+    }
+    let owner = attachment_owner(&state.db, &model).await.map_err(internal_json)?;
+    let bytes = read_attachment_thumbnail(state.attachments.as_ref(), owner, &model).map_err(storage_json)?;
+    Ok(([(header::CONTENT_TYPE, "image/png")], bytes))
+}
+
+pub async fn delete_attachment_route(State(state): State<AppState>, Path(id): Path<i32>) -> Result<StatusCode, (StatusCode, Json<ErrorResp>)> {
+    let affected = delete_attachment(&state.db, state.attachments.as_ref(), id).await.map_err(internal_json)?;
+    if affected == 0 { return Err(json_error(StatusCode::NOT_FOUND, "not_found", "attachment not found")); }
+    Ok(StatusCode::NO_CONTENT)
+}
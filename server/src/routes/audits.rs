@@ -0,0 +1,67 @@
+use axum::{extract::{Query, Request, State}, http::{HeaderValue, StatusCode}, middleware::Next, response::Response, Json};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Deserialize;
+use crate::routes::{AppState, CurrentUser};
+use crate::models::audit_log;
+use crate::services::{list_audit_log, ServiceError, AUDIT_ENTITY_TYPES};
+use crate::routes::{ErrorResp, internal_json, service_json};
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+const DEFAULT_AUDIT_PAGE: u64 = 100;
+
+/// Identifies a request in responses and the audit log.
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+/// Keeps the client's (or proxy's) `X-Request-Id` when it is short and printable, otherwise
+/// makes one up, and echoes it on the response.
+pub async fn assign_request_id(mut req: Request, next: Next) -> Response {
+    let id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|v| !v.is_empty() && v.len() <= 128 && v.bytes().all(|b| b.is_ascii_graphic()))
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    req.extensions_mut().insert(RequestId(id.clone()));
+    let mut res = next.run(req).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        res.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    res
+}
+
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    /// `user`, `user_identity`, `account`, `transaction` or `asset`.
+    pub entity: Option<String>,
+    pub entity_id: Option<i32>,
+    /// RFC 3339 timestamp, or `YYYY-MM-DD` for the start of that day (UTC).
+    pub from: Option<String>,
+    pub limit: Option<u64>,
+}
+
+fn parse_from(raw: &str) -> Result<DateTime<Utc>, ServiceError> {
+    let raw = raw.trim();
+    if let Ok(t) = DateTime::parse_from_rfc3339(raw) {
+        return Ok(t.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+        .map(|d| d.and_hms_opt(0, 0, 0).expect("midnight").and_utc())
+        .map_err(|_| ServiceError::Invalid(format!("invalid from: {}", raw)))
+}
+
+/// The caller's history: changes to their data, and changes they made.
+pub async fn get_audit_log(State(state): State<AppState>, CurrentUser(uid): CurrentUser, Query(q): Query<AuditQuery>) -> Result<Json<Vec<audit_log::Model>>, (StatusCode, Json<ErrorResp>)> {
+    if let Some(entity) = q.entity.as_deref() {
+        if !AUDIT_ENTITY_TYPES.contains(&entity) {
+            return Err(service_json(ServiceError::Invalid(format!("unknown entity {:?}; use {}", entity, AUDIT_ENTITY_TYPES.join(", ")))));
+        }
+    }
+    let from = q.from.as_deref().map(parse_from).transpose().map_err(service_json)?;
+    let list = list_audit_log(&state.db, uid, q.entity.as_deref(), q.entity_id, from, q.limit.unwrap_or(DEFAULT_AUDIT_PAGE))
+        .await
+        .map_err(internal_json)?;
+    Ok(Json(list))
+}
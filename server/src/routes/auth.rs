@@ -1,11 +1,15 @@
-use axum::{extract::{State, Request}, http::StatusCode, middleware::Next, response::Response, Json};
+use axum::{body::Body, extract::{FromRequestParts, Path, Query, State, Request}, http::{header, request::Parts, Extensions, HeaderMap, StatusCode}, middleware::Next, response::{IntoResponse, Redirect, Response}, Json};
 use serde::{Deserialize, Serialize};
 use crate::routes::AppState;
-use crate::services::get_user_by_email;
-use bcrypt::verify;
-use jsonwebtoken::{encode, decode, EncodingKey, DecodingKey, Header, Validation};
+use crate::services::{get_user_by_email, get_user_by_id, start_session, rotate_refresh_token, end_session, list_sessions, revoke_session, revoke_all_sessions, session_is_active, SessionError, SessionInfo,
+    request_password_reset, reset_password, verify_email, resend_verification_email, totp_status, begin_totp_setup, confirm_totp_setup,
+    disable_totp, regenerate_recovery_codes, start_mfa_challenge, complete_mfa_challenge, MfaError, TotpSetup, TotpStatus, MFA_CHALLENGE_MINUTES,
+    record_failed_login, clear_failed_logins, unlock_account, upgrade_password_hash, authenticate_api_token, is_api_token, token_target_owner, ApiTokenAuth, AuditContext, JwtKeys, TokenTarget,
+    begin_oidc_login, complete_oidc_login, oidc_user, OidcError};
+use crate::models::user;
+use jsonwebtoken::jwk::JwkSet;
 use chrono::{Utc, Duration};
-use crate::routes::{ErrorResp, json_error, internal_json};
+use crate::routes::{ErrorResp, RequestId, UserOut, client_ip, json_error, internal_json, new_password_hash, password_json, retry_after_error, too_many_requests};
 
 #[derive(Deserialize)]
 pub struct LoginReq {
@@ -19,12 +23,31 @@ pub struct LoginResp {
     pub refresh_token: String,
 }
 
+/// Returned by login instead of tokens when the user has two-factor authentication on.
+#[derive(Serialize)]
+pub struct MfaChallengeResp {
+    pub mfa_required: bool,
+    pub mfa_token: String,
+    /// Seconds left to complete the login with `POST /api/auth/2fa/verify`.
+    pub expires_in: i64,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum LoginResult {
+    Tokens(LoginResp),
+    MfaRequired(MfaChallengeResp),
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Claims {
     sub: String,
     uid: i32,
     iat: i64,
     exp: i64,
+    /// Session (refresh token family) the token was issued for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sid: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -33,37 +56,116 @@ pub struct RefreshReq { pub refresh_token: String }
 #[derive(Serialize)]
 pub struct RefreshResp { pub token: String, pub refresh_token: String }
 
+#[derive(Deserialize)]
+pub struct LogoutReq { pub refresh_token: String }
+
+#[derive(Deserialize)]
+pub struct ForgotReq { pub email: String }
+
+#[derive(Deserialize)]
+pub struct ResetReq { pub token: String, pub password: String }
+
+#[derive(Deserialize)]
+pub struct VerifyReq { pub token: String }
+
+#[derive(Deserialize)]
+pub struct MfaVerifyReq { pub mfa_token: String, pub code: String }
+
+#[derive(Deserialize)]
+pub struct TotpCodeReq { pub code: String }
+
+/// Disabling 2FA or replacing recovery codes needs the password and a current code.
+#[derive(Deserialize)]
+pub struct TotpReauthReq { pub password: String, pub code: String }
+
+#[derive(Serialize)]
+pub struct RecoveryCodesResp { pub recovery_codes: Vec<String> }
+
 pub async fn auth_login(
     State(state): State<AppState>,
+    headers: HeaderMap,
+    extensions: Extensions,
     Json(body): Json<LoginReq>,
-) -> Result<Json<LoginResp>, (StatusCode, Json<ErrorResp>)> {
+) -> Result<Json<LoginResult>, Response> {
     // Basic input validation
     if body.email.trim().is_empty() || !is_valid_email(&body.email) {
-        return Err(json_error(StatusCode::BAD_REQUEST, "invalid_request", "invalid email"));
+        return Err(json_error(StatusCode::BAD_REQUEST, "invalid_request", "invalid email").into_response());
     }
     if body.password.len() < 6 {
-        return Err(json_error(StatusCode::BAD_REQUEST, "invalid_request", "password too short"));
+        return Err(json_error(StatusCode::BAD_REQUEST, "invalid_request", "password too short").into_response());
+    }
+
+    // Throttled guesses are turned away before any database or hashing work.
+    let ip = client_ip(&headers, &extensions, state.trust_forwarded_for);
+    if let Some(wait) = state.login_throttle.retry_after(&body.email, ip) {
+        return Err(too_many_requests(wait));
     }
+    let invalid_credentials = || json_error(StatusCode::UNAUTHORIZED, "invalid_credentials", "invalid credentials").into_response();
 
     // Find user by email
     let Some(user) = get_user_by_email(&state.db, &body.email)
         .await
-        .map_err(internal_json)?
+        .map_err(internal_response)?
     else {
-        return Err(json_error(StatusCode::UNAUTHORIZED, "invalid_credentials", "invalid credentials"));
+        // Hash anyway, so the response time does not tell which emails are registered.
+        state.passwords.verify_nothing(&body.password);
+        state.login_throttle.record_failure(&body.email, ip);
+        return Err(invalid_credentials());
     };
 
-    // Verify password
-    let ok = verify(&body.password, &user.password_hash).map_err(internal_json)?;
+    // Verify password. Until it checks out, a locked account answers like any other, so the
+    // lock does not reveal that the email is registered.
+    let locked_until = user.locked_until.filter(|until| *until > Utc::now());
+    let ok = state.passwords.verify(&body.password, &user.password_hash).map_err(internal_response)?;
     if !ok {
-        return Err(json_error(StatusCode::UNAUTHORIZED, "invalid_credentials", "invalid credentials"));
+        state.login_throttle.record_failure(&body.email, ip);
+        if locked_until.is_none() {
+            record_failed_login(&state.db, &state.mailer, &user).await.map_err(internal_response)?;
+        }
+        return Err(invalid_credentials());
+    }
+    if let Some(until) = locked_until {
+        return Err(account_locked(until));
+    }
+    state.login_throttle.record_success(&body.email);
+    clear_failed_logins(&state.db, &user).await.map_err(internal_response)?;
+    // Only now is the plaintext at hand to move a bcrypt or outdated Argon2 hash forward.
+    if state.passwords.needs_rehash(&user.password_hash) {
+        let password_hash = state.passwords.hash(&body.password).map_err(internal_response)?;
+        upgrade_password_hash(&state.db, &user, password_hash).await.map_err(internal_response)?;
     }
 
-    // Prepare tokens
-    let token = create_access_token(user.id, &user.email).map_err(internal_json)?;
-    let refresh = create_refresh_token(user.id, &user.email).map_err(internal_json)?;
+    if state.require_email_verification && user.email_verified_at.is_none() {
+        resend_verification_email(&state.db, &state.mailer, &user).await.map_err(internal_response)?;
+        return Err(json_error(StatusCode::FORBIDDEN, "email_not_verified", "email address is not verified; check your inbox").into_response());
+    }
+
+    Ok(Json(finish_login(&state, &user, &headers).await.map_err(IntoResponse::into_response)?))
+}
+
+/// Tokens for a user who has proven who they are, or an MFA challenge when two-factor is on.
+async fn finish_login(state: &AppState, user: &user::Model, headers: &HeaderMap) -> Result<LoginResult, (StatusCode, Json<ErrorResp>)> {
+    if user.totp_enabled_at.is_some() {
+        let mfa_token = start_mfa_challenge(&state.db, user.id).await.map_err(internal_json)?;
+        return Ok(LoginResult::MfaRequired(MfaChallengeResp { mfa_required: true, mfa_token, expires_in: MFA_CHALLENGE_MINUTES * 60 }));
+    }
+    Ok(LoginResult::Tokens(issue_login_tokens(state, user, headers).await?))
+}
+
+fn internal_response<E: std::fmt::Display>(e: E) -> Response {
+    internal_json(e).into_response()
+}
+
+fn account_locked(until: chrono::DateTime<Utc>) -> Response {
+    let wait = (until - Utc::now()).to_std().unwrap_or_default();
+    retry_after_error(StatusCode::LOCKED, "account_locked", "account is locked after too many failed logins; wait or use the unlock link sent by email", wait)
+}
 
-    Ok(Json(LoginResp { token, refresh_token: refresh }))
+async fn issue_login_tokens(state: &AppState, user: &user::Model, headers: &HeaderMap) -> Result<LoginResp, (StatusCode, Json<ErrorResp>)> {
+    let user_agent = headers.get(header::USER_AGENT).and_then(|v| v.to_str().ok()).map(|v| v.chars().take(255).collect());
+    let session = start_session(&state.db, user.id, user_agent).await.map_err(internal_json)?;
+    let token = create_access_token(&state.jwt_keys, user.id, &user.email, &session.family_id).map_err(internal_json)?;
+    Ok(LoginResp { token, refresh_token: session.refresh_token })
 }
 
 fn is_valid_email(s: &str) -> bool {
@@ -72,17 +174,36 @@ fn is_valid_email(s: &str) -> bool {
     s.contains('@') && s.contains('.') && !s.ends_with('.')
 }
 
-pub fn verify_token(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
-    let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "your-secret-key".to_string());
-    let data = decode::<Claims>(token, &DecodingKey::from_secret(secret.as_bytes()), &Validation::default())?;
-    Ok(data.claims)
+pub fn verify_token(keys: &JwtKeys, token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+    keys.verify(token)
 }
 
 pub async fn require_auth(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     mut req: Request,
     next: Next,
 ) -> Result<Response, (StatusCode, Json<ErrorResp>)> {
+    // The router is nested under /api, so paths arrive without the prefix.
+    let path = req.uri().path().to_string();
+    let method = req.method().clone();
+
+    // Personal access tokens are checked whenever one is presented, so their scopes hold even
+    // when auth is not enforced globally.
+    if let Some(token) = bearer_token(req.headers()).filter(|t| is_api_token(t)) {
+        let Some(auth) = authenticate_api_token(&state.db, token).await.map_err(internal_json)? else {
+            return Err(json_error(StatusCode::UNAUTHORIZED, "invalid_token", "invalid or expired token"));
+        };
+        let Some(scope) = required_scope(&method, &path) else {
+            return Err(json_error(StatusCode::FORBIDDEN, "insufficient_scope", "this endpoint is not available to API tokens"));
+        };
+        if !auth.allows(scope) {
+            return Err(json_error(StatusCode::FORBIDDEN, "insufficient_scope", format!("token lacks the {} scope", scope)));
+        }
+        let mut req = check_token_targets(&state, &auth, req).await?;
+        req.extensions_mut().insert(auth);
+        return Ok(next.run(req).await);
+    }
+
     // Allow skipping in development unless explicitly enabled
     let require = std::env::var("REQUIRE_AUTH").unwrap_or_else(|_| "false".into());
     let require = require.eq_ignore_ascii_case("true") || require == "1";
@@ -91,14 +212,130 @@ pub async fn require_auth(
     }
 
     // Allowlist public endpoints
-    let path = req.uri().path();
-    let method = req.method();
-    let is_public = path == "/api/auth/login" || path == "/api/auth/refresh" || (path == "/api/users" && method == axum::http::Method::POST);
+    // Logout and refresh present a refresh token instead of an access token; the email flows
+    // present a mailed token, the second login step an MFA challenge token.
+    let is_public = path == "/auth/login"
+        || path == "/auth/refresh"
+        || path == "/auth/logout"
+        || path == "/auth/forgot"
+        || path == "/auth/reset"
+        || path == "/auth/verify"
+        || path == "/auth/unlock"
+        || path == "/auth/2fa/verify"
+        || path.starts_with("/auth/oidc")
+        || (path == "/users" && method == axum::http::Method::POST);
     if is_public {
         return Ok(next.run(req).await);
     }
 
-    let Some(header_val) = req.headers().get(axum::http::header::AUTHORIZATION) else {
+    let claims = session_claims(&state, req.headers()).await?;
+
+    // Attach claims for downstream handlers if needed
+    req.extensions_mut().insert(claims);
+    Ok(next.run(req).await)
+}
+
+/// The scope a personal access token needs for a request (`path` relative to `/api`). `None`
+/// means tokens cannot use the endpoint at all: account settings, sessions, tokens themselves
+/// and whole-account export/import need a login.
+pub fn required_scope(method: &axum::http::Method, path: &str) -> Option<&'static str> {
+    let read = method == axum::http::Method::GET || method == axum::http::Method::HEAD;
+    if path == "/transactions/suggest" {
+        return Some("transactions:read");
+    }
+    let resource = path.trim_start_matches('/').split('/').next().unwrap_or_default();
+    match (resource, read) {
+        ("accounts" | "reconciliations", true) => Some("accounts:read"),
+        ("accounts" | "reconciliations", false) => Some("accounts:write"),
+        ("transactions" | "attachments" | "rules" | "recurring" | "investment-plans", true) => Some("transactions:read"),
+        ("transactions" | "attachments" | "rules" | "recurring" | "investment-plans" | "import", false) => Some("transactions:write"),
+        ("import", true) => Some("transactions:read"),
+        ("assets", true) => Some("assets:read"),
+        ("assets", false) => Some("assets:write"),
+        ("reports" | "dashboard" | "stats" | "forecast" | "insights" | "export", true) => Some("reports:read"),
+        _ => None,
+    }
+}
+
+/// Largest JSON body `check_token_targets` reads; axum's `Json` extractor refuses bigger ones anyway.
+const TOKEN_JSON_LIMIT: usize = 2 * 1024 * 1024;
+
+/// Handlers take the user and account to work on from the request, so a token request is
+/// refused when anything it names (the entity in the path, `user_id`, `account_id` and similar
+/// ids in the query or a JSON body) belongs to someone other than the token's user.
+async fn check_token_targets(state: &AppState, auth: &ApiTokenAuth, req: Request) -> Result<Request, (StatusCode, Json<ErrorResp>)> {
+    let (parts, body) = req.into_parts();
+    let mut targets: Vec<TokenTarget> = path_target(parts.uri.path()).into_iter().collect();
+    if let Some(query) = parts.uri.query() {
+        for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+            targets.extend(value.parse().ok().and_then(|id| id_target(&key, id)));
+        }
+    }
+    let is_json = parts.headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).is_some_and(|v| v.starts_with("application/json"));
+    let body = if is_json {
+        let bytes = axum::body::to_bytes(body, TOKEN_JSON_LIMIT)
+            .await
+            .map_err(|_| json_error(StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large", "request body is too large"))?;
+        if let Ok(serde_json::Value::Object(fields)) = serde_json::from_slice(&bytes) {
+            for (key, value) in fields {
+                targets.extend(value.as_i64().and_then(|id| i32::try_from(id).ok()).and_then(|id| id_target(&key, id)));
+            }
+        }
+        Body::from(bytes)
+    } else {
+        body
+    };
+    for target in targets {
+        if token_target_owner(&state.db, target).await.map_err(internal_json)?.is_some_and(|owner| owner != auth.user_id) {
+            return Err(json_error(StatusCode::FORBIDDEN, "forbidden", "the token cannot access another user's data"));
+        }
+    }
+    Ok(Request::from_parts(parts, body))
+}
+
+/// The entity a path (relative to `/api`) addresses by id, like `/transactions/7/tags`.
+fn path_target(path: &str) -> Option<TokenTarget> {
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    let id = |i: usize| segments.get(i).and_then(|s| s.parse().ok());
+    match segments.as_slice() {
+        ["import", "profiles", ..] => id(2).map(TokenTarget::ImportProfile),
+        ["import", "batches", ..] => id(2).map(TokenTarget::ImportBatch),
+        ["insights", "anomalies", "dismissals", ..] => id(3).map(TokenTarget::AnomalyDismissal),
+        [resource, ..] => {
+            let target = match *resource {
+                "accounts" => TokenTarget::Account,
+                "reconciliations" => TokenTarget::Reconciliation,
+                "transactions" => TokenTarget::Transaction,
+                "attachments" => TokenTarget::Attachment,
+                "rules" => TokenTarget::Rule,
+                "recurring" => TokenTarget::Recurring,
+                "investment-plans" => TokenTarget::InvestmentPlan,
+                "assets" => TokenTarget::Asset,
+                _ => return None,
+            };
+            id(1).map(target)
+        }
+        [] => None,
+    }
+}
+
+/// The entity a query parameter or JSON field names, by its key.
+fn id_target(key: &str, id: i32) -> Option<TokenTarget> {
+    match key {
+        "user_id" => Some(TokenTarget::User(id)),
+        "account_id" | "payment_account_id" => Some(TokenTarget::Account(id)),
+        "transaction_id" | "keep_id" | "remove_id" => Some(TokenTarget::Transaction(id)),
+        "profile_id" => Some(TokenTarget::ImportProfile(id)),
+        _ => None,
+    }
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers.get(header::AUTHORIZATION)?.to_str().ok()?.strip_prefix("Bearer ")
+}
+
+fn bearer_claims(keys: &JwtKeys, headers: &HeaderMap) -> Result<Claims, (StatusCode, Json<ErrorResp>)> {
+    let Some(header_val) = headers.get(axum::http::header::AUTHORIZATION) else {
         return Err(json_error(StatusCode::UNAUTHORIZED, "missing_authorization", "missing Authorization header"));
     };
     let auth = header_val.to_str().unwrap_or_default();
@@ -107,45 +344,279 @@ pub async fn require_auth(
         return Err(json_error(StatusCode::UNAUTHORIZED, "invalid_authorization", "expected Bearer token"));
     }
     let token = &auth[prefix.len()..];
-    let claims = verify_token(token).map_err(|_| json_error(StatusCode::UNAUTHORIZED, "invalid_token", "invalid or expired token"))?;
+    verify_token(keys, token).map_err(|_| json_error(StatusCode::UNAUTHORIZED, "invalid_token", "invalid or expired token"))
+}
 
-    // Attach claims for downstream handlers if needed
-    req.extensions_mut().insert(claims);
-    Ok(next.run(req).await)
+/// `bearer_claims`, plus a check that the token's session has not been revoked since it was
+/// issued. Tokens without a session id predate session tracking and expire on their own.
+async fn session_claims(state: &AppState, headers: &HeaderMap) -> Result<Claims, (StatusCode, Json<ErrorResp>)> {
+    let claims = bearer_claims(&state.jwt_keys, headers)?;
+    if let Some(sid) = &claims.sid {
+        if !session_is_active(&state.db, claims.uid, sid).await.map_err(internal_json)? {
+            return Err(json_error(StatusCode::UNAUTHORIZED, "invalid_token", "the session has been signed out"));
+        }
+    }
+    Ok(claims)
+}
+
+/// The caller's user id for `/api/me/*` routes. Uses the claims (or API token) `require_auth`
+/// attached, and checks the bearer token itself when auth is not enforced globally.
+pub struct CurrentUser(pub i32);
+
+async fn request_claims(parts: &Parts, state: &AppState) -> Result<Claims, (StatusCode, Json<ErrorResp>)> {
+    match parts.extensions.get::<Claims>() {
+        Some(claims) => Ok(claims.clone()),
+        None => session_claims(state, &parts.headers).await,
+    }
 }
 
-fn create_access_token(uid: i32, email: &str) -> Result<String, jsonwebtoken::errors::Error> {
+impl FromRequestParts<AppState> for CurrentUser {
+    type Rejection = (StatusCode, Json<ErrorResp>);
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        if let Some(auth) = parts.extensions.get::<ApiTokenAuth>() {
+            return Ok(CurrentUser(auth.user_id));
+        }
+        Ok(CurrentUser(request_claims(parts, state).await?.uid))
+    }
+}
+
+/// For the audit log. Never rejects: without valid credentials (possible while auth is not
+/// enforced) the actor is simply unknown.
+impl FromRequestParts<AppState> for AuditContext {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let actor_user_id = match parts.extensions.get::<ApiTokenAuth>() {
+            Some(auth) => Some(auth.user_id),
+            None => request_claims(parts, state).await.ok().map(|c| c.uid),
+        };
+        Ok(AuditContext {
+            actor_user_id,
+            request_id: parts.extensions.get::<RequestId>().map(|r| r.0.clone()),
+            ip: client_ip(&parts.headers, &parts.extensions, state.trust_forwarded_for).map(|ip| ip.to_string()),
+        })
+    }
+}
+
+/// Like `CurrentUser`, plus the session the access token belongs to (absent for tokens issued
+/// before sessions were tracked).
+pub struct CurrentSession {
+    pub user_id: i32,
+    pub session_id: Option<String>,
+}
+
+impl FromRequestParts<AppState> for CurrentSession {
+    type Rejection = (StatusCode, Json<ErrorResp>);
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let claims = request_claims(parts, state).await?;
+        Ok(CurrentSession { user_id: claims.uid, session_id: claims.sid })
+    }
+}
+
+fn create_access_token(keys: &JwtKeys, uid: i32, email: &str, sid: &str) -> Result<String, jsonwebtoken::errors::Error> {
     let now = Utc::now();
     // Access token validity (e.g., 1 hour)
     let exp = now + Duration::hours(1);
-    let claims = Claims { sub: email.to_string(), uid, iat: now.timestamp(), exp: exp.timestamp() };
-    let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "your-secret-key".to_string());
-    encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.as_bytes()))
+    let claims = Claims { sub: email.to_string(), uid, iat: now.timestamp(), exp: exp.timestamp(), sid: Some(sid.to_string()) };
+    keys.sign(&claims)
 }
 
-fn create_refresh_token(uid: i32, email: &str) -> Result<String, jsonwebtoken::errors::Error> {
-    let now = Utc::now();
-    // Refresh token validity (e.g., 30 days)
-    let exp = now + Duration::days(30);
-    let claims = Claims { sub: email.to_string(), uid, iat: now.timestamp(), exp: exp.timestamp() };
-    let secret = std::env::var("JWT_REFRESH_SECRET").unwrap_or_else(|_| std::env::var("JWT_SECRET").unwrap_or_else(|_| "your-secret-key".to_string()));
-    encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.as_bytes()))
+/// The public keys access tokens are signed with, for services that verify tokens themselves.
+/// Empty while tokens are signed with a shared secret.
+pub async fn get_jwks(State(state): State<AppState>) -> impl IntoResponse {
+    ([(header::CACHE_CONTROL, "public, max-age=300")], Json::<JwkSet>(state.jwt_keys.jwks()))
 }
 
-fn verify_refresh(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
-    let secret = std::env::var("JWT_REFRESH_SECRET").unwrap_or_else(|_| std::env::var("JWT_SECRET").unwrap_or_else(|_| "your-secret-key".to_string()));
-    let data = decode::<Claims>(token, &DecodingKey::from_secret(secret.as_bytes()), &Validation::default())?;
-    Ok(data.claims)
+fn session_json(e: SessionError) -> (StatusCode, Json<ErrorResp>) {
+    match e {
+        SessionError::Db(e) => internal_json(e),
+        SessionError::InvalidToken => json_error(StatusCode::UNAUTHORIZED, "invalid_token", e.to_string()),
+        SessionError::Reused => json_error(StatusCode::UNAUTHORIZED, "token_reused", e.to_string()),
+    }
 }
 
-pub async fn auth_refresh(Json(body): Json<RefreshReq>) -> Result<Json<RefreshResp>, (StatusCode, Json<ErrorResp>)> {
+pub async fn auth_refresh(State(state): State<AppState>, Json(body): Json<RefreshReq>) -> Result<Json<RefreshResp>, (StatusCode, Json<ErrorResp>)> {
     if body.refresh_token.trim().is_empty() {
         return Err(json_error(StatusCode::BAD_REQUEST, "invalid_request", "missing refresh_token"));
     }
-    let claims = verify_refresh(&body.refresh_token)
-        .map_err(|_| json_error(StatusCode::UNAUTHORIZED, "invalid_token", "invalid or expired refresh token"))?;
+    let session = rotate_refresh_token(&state.db, body.refresh_token.trim()).await.map_err(session_json)?;
+    let Some(user) = get_user_by_id(&state.db, session.user_id).await.map_err(internal_json)? else {
+        return Err(session_json(SessionError::InvalidToken));
+    };
+
+    let token = create_access_token(&state.jwt_keys, user.id, &user.email, &session.family_id).map_err(internal_json)?;
+    Ok(Json(RefreshResp { token, refresh_token: session.refresh_token }))
+}
+
+/// Revokes the session the refresh token belongs to. Unknown tokens are not an error, so
+/// logging out twice is harmless.
+pub async fn auth_logout(State(state): State<AppState>, Json(body): Json<LogoutReq>) -> Result<StatusCode, (StatusCode, Json<ErrorResp>)> {
+    end_session(&state.db, body.refresh_token.trim()).await.map_err(internal_json)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn list_auth_sessions(State(state): State<AppState>, current: CurrentSession) -> Result<Json<Vec<SessionInfo>>, (StatusCode, Json<ErrorResp>)> {
+    let list = list_sessions(&state.db, current.user_id, current.session_id.as_deref()).await.map_err(internal_json)?;
+    Ok(Json(list))
+}
+
+pub async fn delete_auth_session(State(state): State<AppState>, current: CurrentSession, Path(id): Path<String>) -> Result<StatusCode, (StatusCode, Json<ErrorResp>)> {
+    let revoked = revoke_session(&state.db, current.user_id, &id).await.map_err(internal_json)?;
+    if revoked == 0 { return Err(json_error(StatusCode::NOT_FOUND, "not_found", "session not found")); }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Signs out every device, including the caller's.
+pub async fn delete_auth_sessions(State(state): State<AppState>, current: CurrentSession) -> Result<StatusCode, (StatusCode, Json<ErrorResp>)> {
+    revoke_all_sessions(&state.db, current.user_id).await.map_err(internal_json)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Mails a reset token if the address is registered. Always 202, so it reveals nothing.
+pub async fn auth_forgot(State(state): State<AppState>, Json(body): Json<ForgotReq>) -> Result<StatusCode, (StatusCode, Json<ErrorResp>)> {
+    if !is_valid_email(&body.email) {
+        return Err(json_error(StatusCode::BAD_REQUEST, "invalid_request", "invalid email"));
+    }
+    request_password_reset(&state.db, &state.mailer, &body.email).await.map_err(internal_json)?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+pub async fn auth_reset(State(state): State<AppState>, audit: AuditContext, Json(body): Json<ResetReq>) -> Result<StatusCode, (StatusCode, Json<ErrorResp>)> {
+    let password_hash = new_password_hash(&state, &body.password)?;
+    if !reset_password(&state.db, &audit, body.token.trim(), password_hash).await.map_err(internal_json)? {
+        return Err(json_error(StatusCode::BAD_REQUEST, "invalid_token", "invalid or expired reset token"));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn auth_verify(State(state): State<AppState>, audit: AuditContext, Json(body): Json<VerifyReq>) -> Result<Json<UserOut>, (StatusCode, Json<ErrorResp>)> {
+    match verify_email(&state.db, &audit, body.token.trim()).await.map_err(internal_json)? {
+        Some(user) => Ok(Json(user.into())),
+        None => Err(json_error(StatusCode::BAD_REQUEST, "invalid_token", "invalid or expired verification token")),
+    }
+}
+
+pub async fn auth_unlock(State(state): State<AppState>, audit: AuditContext, Json(body): Json<VerifyReq>) -> Result<StatusCode, (StatusCode, Json<ErrorResp>)> {
+    if !unlock_account(&state.db, &audit, body.token.trim()).await.map_err(internal_json)? {
+        return Err(json_error(StatusCode::BAD_REQUEST, "invalid_token", "invalid or expired unlock token"));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Serialize)]
+pub struct OidcProviderOut {
+    pub name: String,
+    pub authorize_url: String,
+}
+
+/// What the identity provider appends to the redirect URI.
+#[derive(Deserialize)]
+pub struct OidcCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+fn oidc_json(e: OidcError) -> (StatusCode, Json<ErrorResp>) {
+    match e {
+        OidcError::Db(e) => internal_json(e),
+        OidcError::UnknownProvider => json_error(StatusCode::NOT_FOUND, "not_found", e.to_string()),
+        OidcError::InvalidState => json_error(StatusCode::BAD_REQUEST, "invalid_state", e.to_string()),
+        OidcError::Provider(_) => json_error(StatusCode::BAD_GATEWAY, "provider_error", e.to_string()),
+        OidcError::InvalidIdToken(_) => json_error(StatusCode::UNAUTHORIZED, "invalid_token", e.to_string()),
+        OidcError::EmailNotVerified => json_error(StatusCode::FORBIDDEN, "email_not_verified", e.to_string()),
+        OidcError::EmailInUse => json_error(StatusCode::CONFLICT, "conflict", e.to_string()),
+    }
+}
+
+/// The identity providers a login page can offer, in configuration order.
+pub async fn list_oidc_providers(State(state): State<AppState>) -> Json<Vec<OidcProviderOut>> {
+    let list = state.oidc.names().into_iter()
+        .map(|name| OidcProviderOut { authorize_url: format!("/api/auth/oidc/{}/authorize", name), name })
+        .collect();
+    Json(list)
+}
+
+/// Sends the browser to the provider's authorization endpoint.
+pub async fn get_oidc_authorize(State(state): State<AppState>, Path(provider): Path<String>) -> Result<Redirect, (StatusCode, Json<ErrorResp>)> {
+    let url = begin_oidc_login(&state.db, &state.oidc, &provider).await.map_err(oidc_json)?;
+    Ok(Redirect::to(url.as_str()))
+}
+
+/// Where the provider sends the browser back to. Answers like `POST /api/auth/login`.
+pub async fn get_oidc_callback(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    audit: AuditContext,
+    Path(provider): Path<String>,
+    Query(query): Query<OidcCallbackQuery>,
+) -> Result<Json<LoginResult>, (StatusCode, Json<ErrorResp>)> {
+    if let Some(error) = query.error {
+        let description = query.error_description.unwrap_or_default();
+        return Err(json_error(StatusCode::UNAUTHORIZED, "access_denied", format!("identity provider: {} {}", error, description).trim_end().to_string()));
+    }
+    let (Some(code), Some(oidc_state)) = (query.code, query.state) else {
+        return Err(json_error(StatusCode::BAD_REQUEST, "invalid_request", "missing code or state"));
+    };
+    let identity = complete_oidc_login(&state.db, &state.oidc, &provider, &code, &oidc_state).await.map_err(oidc_json)?;
+    let user = oidc_user(&state.db, &audit, &identity).await.map_err(oidc_json)?;
+    Ok(Json(finish_login(&state, &user, &headers).await?))
+}
+
+fn mfa_json(e: MfaError) -> (StatusCode, Json<ErrorResp>) {
+    match e {
+        MfaError::Db(e) => internal_json(e),
+        MfaError::AlreadyEnabled | MfaError::NotEnabled | MfaError::NoPendingSetup => json_error(StatusCode::CONFLICT, "conflict", e.to_string()),
+        MfaError::InvalidCode => json_error(StatusCode::UNAUTHORIZED, "invalid_code", e.to_string()),
+        MfaError::InvalidChallenge => json_error(StatusCode::UNAUTHORIZED, "invalid_token", e.to_string()),
+    }
+}
+
+/// Second step of a two-factor login: the challenge from `auth_login` plus a TOTP or recovery code.
+pub async fn auth_mfa_verify(State(state): State<AppState>, headers: HeaderMap, Json(body): Json<MfaVerifyReq>) -> Result<Json<LoginResp>, (StatusCode, Json<ErrorResp>)> {
+    let user = complete_mfa_challenge(&state.db, body.mfa_token.trim(), &body.code).await.map_err(mfa_json)?;
+    Ok(Json(issue_login_tokens(&state, &user, &headers).await?))
+}
+
+pub async fn get_two_factor(State(state): State<AppState>, CurrentUser(user_id): CurrentUser) -> Result<Json<TotpStatus>, (StatusCode, Json<ErrorResp>)> {
+    match totp_status(&state.db, user_id).await.map_err(internal_json)? {
+        Some(status) => Ok(Json(status)),
+        None => Err(json_error(StatusCode::NOT_FOUND, "not_found", "user not found")),
+    }
+}
+
+pub async fn post_two_factor_setup(State(state): State<AppState>, CurrentUser(user_id): CurrentUser) -> Result<Json<TotpSetup>, (StatusCode, Json<ErrorResp>)> {
+    let setup = begin_totp_setup(&state.db, user_id).await.map_err(mfa_json)?;
+    Ok(Json(setup))
+}
+
+pub async fn post_two_factor_confirm(State(state): State<AppState>, CurrentUser(user_id): CurrentUser, audit: AuditContext, Json(body): Json<TotpCodeReq>) -> Result<Json<RecoveryCodesResp>, (StatusCode, Json<ErrorResp>)> {
+    let recovery_codes = confirm_totp_setup(&state.db, &audit, user_id, &body.code).await.map_err(mfa_json)?;
+    Ok(Json(RecoveryCodesResp { recovery_codes }))
+}
+
+/// Re-authentication for sensitive 2FA changes: a bearer token alone is not enough.
+async fn check_password(state: &AppState, user_id: i32, password: &str) -> Result<(), (StatusCode, Json<ErrorResp>)> {
+    let Some(user) = get_user_by_id(&state.db, user_id).await.map_err(internal_json)? else {
+        return Err(json_error(StatusCode::NOT_FOUND, "not_found", "user not found"));
+    };
+    if !state.passwords.verify(password, &user.password_hash).map_err(password_json)? {
+        return Err(json_error(StatusCode::UNAUTHORIZED, "invalid_credentials", "invalid credentials"));
+    }
+    Ok(())
+}
+
+pub async fn post_two_factor_disable(State(state): State<AppState>, CurrentUser(user_id): CurrentUser, audit: AuditContext, Json(body): Json<TotpReauthReq>) -> Result<StatusCode, (StatusCode, Json<ErrorResp>)> {
+    check_password(&state, user_id, &body.password).await?;
+    disable_totp(&state.db, &audit, user_id, &body.code).await.map_err(mfa_json)?;
+    Ok(StatusCode::NO_CONTENT)
+}
 
-    let token = create_access_token(claims.uid, &claims.sub).map_err(internal_json)?;
-    let refresh_token = create_refresh_token(claims.uid, &claims.sub).map_err(internal_json)?;
-    Ok(Json(RefreshResp { token, refresh_token }))
+pub async fn post_two_factor_recovery_codes(State(state): State<AppState>, CurrentUser(user_id): CurrentUser, Json(body): Json<TotpReauthReq>) -> Result<Json<RecoveryCodesResp>, (StatusCode, Json<ErrorResp>)> {
+    check_password(&state, user_id, &body.password).await?;
+    let recovery_codes = regenerate_recovery_codes(&state.db, user_id, &body.code).await.map_err(mfa_json)?;
+    Ok(Json(RecoveryCodesResp { recovery_codes }))
 }
@@ -0,0 +1,32 @@
+use axum::{extract::{State, Query, Request}, http::{Method, StatusCode}, middleware::Next, response::Response, Json};
+use serde::Deserialize;
+use crate::routes::AppState;
+use crate::services::{build_dashboard, Dashboard};
+use crate::routes::{ErrorResp, service_json};
+
+#[derive(Deserialize)]
+pub struct DashboardQuery {
+    pub user_id: i32,
+    /// Where "this month" starts, e.g. `480` for China; defaults to UTC.
+    #[serde(default)]
+    pub utc_offset_minutes: i32,
+}
+
+pub async fn get_dashboard(State(state): State<AppState>, Query(q): Query<DashboardQuery>) -> Result<Json<Dashboard>, (StatusCode, Json<ErrorResp>)> {
+    if let Some(cached) = state.dashboard_cache.get(q.user_id, q.utc_offset_minutes) {
+        return Ok(Json(cached));
+    }
+    let dashboard = build_dashboard(&state.db, q.user_id, q.utc_offset_minutes).await.map_err(service_json)?;
+    state.dashboard_cache.put(q.user_id, q.utc_offset_minutes, dashboard.clone());
+    Ok(Json(dashboard))
+}
+
+/// Drops cached read models after any successful write.
+pub async fn invalidate_caches(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let is_write = !matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    let res = next.run(req).await;
+    if is_write && res.status().is_success() {
+        state.dashboard_cache.clear();
+    }
+    res
+}
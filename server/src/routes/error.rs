@@ -1,4 +1,4 @@
-use axum::{http::StatusCode, Json};
+use axum::{http::{header, StatusCode}, response::{IntoResponse, Response}, Json};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -11,6 +11,18 @@ pub fn json_error(status: StatusCode, code: &str, msg: impl Into<String>) -> (St
     (status, Json(ErrorResp { error: msg.into(), code: code.to_string() }))
 }
 
+/// An error the client can retry later, with `Retry-After` in whole seconds (at least 1).
+pub fn retry_after_error(status: StatusCode, code: &str, msg: impl Into<String>, retry_after: std::time::Duration) -> Response {
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    let mut res = json_error(status, code, msg).into_response();
+    res.headers_mut().insert(header::RETRY_AFTER, seconds.max(1).into());
+    res
+}
+
+pub fn too_many_requests(retry_after: std::time::Duration) -> Response {
+    retry_after_error(StatusCode::TOO_MANY_REQUESTS, "rate_limited", "too many requests; try again later", retry_after)
+}
+
 pub fn internal_json<E: std::fmt::Display>(e: E) -> (StatusCode, Json<ErrorResp>) {
     json_error(StatusCode::INTERNAL_SERVER_ERROR, "internal", e.to_string())
 }
@@ -19,3 +31,21 @@ pub fn bad_request_json<E: std::fmt::Display>(e: E) -> (StatusCode, Json<ErrorRe
     json_error(StatusCode::BAD_REQUEST, "bad_request", e.to_string())
 }
 
+/// Policy failures are the client's to fix; a hash that cannot be made or read is ours.
+pub fn password_json(e: crate::services::PasswordError) -> (StatusCode, Json<ErrorResp>) {
+    use crate::services::PasswordError;
+    match e {
+        PasswordError::Hash(_) => internal_json(e),
+        _ => json_error(StatusCode::BAD_REQUEST, "weak_password", e.to_string()),
+    }
+}
+
+pub fn service_json(e: crate::services::ServiceError) -> (StatusCode, Json<ErrorResp>) {
+    use crate::services::ServiceError;
+    match e {
+        ServiceError::Db(e) => internal_json(e),
+        ServiceError::Invalid(m) => json_error(StatusCode::BAD_REQUEST, "invalid_request", m),
+        ServiceError::Locked(m) => json_error(StatusCode::CONFLICT, "locked", m),
+        ServiceError::Conflict(m) => json_error(StatusCode::CONFLICT, "conflict", m),
+    }
+}
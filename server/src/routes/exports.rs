@@ -0,0 +1,27 @@
+use axum::{extract::{State, Query}, http::{header, StatusCode}, response::IntoResponse, Json};
+use serde::Deserialize;
+use crate::routes::AppState;
+use crate::services::{export_beancount, export_ledger, import_beancount, AuditContext, BeancountImportSummary};
+use crate::routes::{ErrorResp, internal_json, service_json};
+
+#[derive(Deserialize)]
+pub struct ExportQuery { pub user_id: i32 }
+
+fn plain_text(body: String) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], body)
+}
+
+pub async fn get_beancount_export(State(state): State<AppState>, Query(q): Query<ExportQuery>) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResp>)> {
+    let text = export_beancount(&state.db, q.user_id).await.map_err(internal_json)?;
+    Ok(plain_text(text))
+}
+
+pub async fn get_ledger_export(State(state): State<AppState>, Query(q): Query<ExportQuery>) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResp>)> {
+    let text = export_ledger(&state.db, q.user_id).await.map_err(internal_json)?;
+    Ok(plain_text(text))
+}
+
+pub async fn post_beancount_import(State(state): State<AppState>, audit: AuditContext, Query(q): Query<ExportQuery>, body: String) -> Result<(StatusCode, Json<BeancountImportSummary>), (StatusCode, Json<ErrorResp>)> {
+    let summary = import_beancount(&state.db, &audit, q.user_id, &body).await.map_err(service_json)?;
+    Ok((StatusCode::CREATED, Json(summary)))
+}
@@ -0,0 +1,30 @@
+use axum::{extract::{State, Query}, http::StatusCode, Json};
+use serde::Deserialize;
+use crate::routes::AppState;
+use sea_orm::prelude::Decimal;
+use crate::services::{build_forecast, Forecast, ForecastOptions};
+use std::str::FromStr;
+use crate::routes::{ErrorResp, bad_request_json, service_json};
+
+#[derive(Deserialize)]
+pub struct ForecastQuery {
+    pub user_id: i32,
+    pub days: Option<u32>,
+    #[serde(default)]
+    pub utc_offset_minutes: i32,
+    /// Warning threshold, defaults to `0`.
+    pub min_balance: Option<String>,
+    pub lookback_days: Option<u32>,
+}
+
+pub async fn get_forecast(State(state): State<AppState>, Query(q): Query<ForecastQuery>) -> Result<Json<Forecast>, (StatusCode, Json<ErrorResp>)> {
+    let defaults = ForecastOptions::default();
+    let opts = ForecastOptions {
+        days: q.days.unwrap_or(defaults.days),
+        utc_offset_minutes: q.utc_offset_minutes,
+        min_balance: q.min_balance.as_deref().map(Decimal::from_str).transpose().map_err(bad_request_json)?.unwrap_or(defaults.min_balance),
+        lookback_days: q.lookback_days.unwrap_or(defaults.lookback_days),
+    };
+    let forecast = build_forecast(&state.db, q.user_id, opts).await.map_err(service_json)?;
+    Ok(Json(forecast))
+}
@@ -0,0 +1,122 @@
+use axum::{body::Bytes, extract::{Path, State, Query}, http::StatusCode, Json};
+use serde::{Deserialize, Serialize};
+use crate::routes::AppState;
+use crate::services::{
+    create_import_profile, get_import_profile_by_id, find_import_profiles_by_user, delete_import_profile,
+    parse_import, drop_already_imported, commit_import, get_import_batch_by_id, find_import_batches_by_user, rollback_import_batch,
+    get_account_by_id, load_rules, apply_rules_to_rows, attachment_hashes, release_attachment_blobs, AuditContext, CsvMapping, ImportIssue, ImportPreview,
+};
+use crate::models::transaction;
+use sea_orm::{ColumnTrait, Condition};
+use crate::routes::{ErrorResp, json_error, internal_json, bad_request_json, service_json};
+
+#[derive(Deserialize)]
+pub struct CreateImportProfileReq {
+    pub user_id: i32,
+    pub name: String,
+    pub mapping: CsvMapping,
+}
+
+#[derive(Deserialize)]
+pub struct ImportProfilesQuery { pub user_id: i32 }
+
+#[derive(Deserialize)]
+pub struct ImportBatchesQuery { pub user_id: i32 }
+
+#[derive(Deserialize)]
+pub struct ImportQuery {
+    pub account_id: i32,
+    pub profile_id: Option<i32>,
+    /// Statement account number to import from a multi-account OFX file.
+    pub statement_account: Option<String>,
+    /// Commit the valid rows even if some lines failed to parse.
+    #[serde(default)]
+    pub skip_errors: bool,
+}
+
+#[derive(Serialize)]
+pub struct ImportCommitResp {
+    pub batch: crate::models::import_batch::Model,
+    pub skipped: Vec<ImportIssue>,
+}
+
+pub async fn post_import_profile(State(state): State<AppState>, Json(body): Json<CreateImportProfileReq>) -> Result<(StatusCode, Json<crate::models::import_profile::Model>), (StatusCode, Json<ErrorResp>)> {
+    let model = create_import_profile(&state.db, body.user_id, body.name, body.mapping).await.map_err(service_json)?;
+    Ok((StatusCode::CREATED, Json(model)))
+}
+
+pub async fn list_import_profiles(State(state): State<AppState>, Query(q): Query<ImportProfilesQuery>) -> Result<Json<Vec<crate::models::import_profile::Model>>, (StatusCode, Json<ErrorResp>)> {
+    let list = find_import_profiles_by_user(&state.db, q.user_id).await.map_err(internal_json)?;
+    Ok(Json(list))
+}
+
+pub async fn delete_import_profile_route(State(state): State<AppState>, Path(id): Path<i32>) -> Result<StatusCode, (StatusCode, Json<ErrorResp>)> {
+    let affected = delete_import_profile(&state.db, id).await.map_err(internal_json)?;
+    if affected == 0 { return Err(json_error(StatusCode::NOT_FOUND, "not_found", "import profile not found")); }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Resolves the target account and parses the upload; shared by preview and commit.
+async fn parse_upload(state: &AppState, format: &str, q: &ImportQuery, bytes: &[u8]) -> Result<(crate::models::account::Model, ImportPreview), (StatusCode, Json<ErrorResp>)> {
+    let Some(account) = get_account_by_id(&state.db, q.account_id).await.map_err(internal_json)? else {
+        return Err(json_error(StatusCode::NOT_FOUND, "not_found", "account not found"));
+    };
+    let mapping = match q.profile_id {
+        Some(id) => {
+            let Some(profile) = get_import_profile_by_id(&state.db, id).await.map_err(internal_json)? else {
+                return Err(json_error(StatusCode::NOT_FOUND, "not_found", "import profile not found"));
+            };
+            if profile.user_id != account.user_id {
+                return Err(json_error(StatusCode::BAD_REQUEST, "invalid_request", "profile belongs to another user"));
+            }
+            Some(serde_json::from_value::<CsvMapping>(profile.mapping).map_err(bad_request_json)?)
+        }
+        None => None,
+    };
+    let mut preview = parse_import(format, bytes, mapping.as_ref(), q.statement_account.as_deref()).map_err(service_json)?;
+    drop_already_imported(&state.db, account.id, &mut preview).await.map_err(internal_json)?;
+    let rules = load_rules(&state.db, account.user_id).await.map_err(internal_json)?;
+    apply_rules_to_rows(&rules, account.id, &mut preview.rows);
+    Ok((account, preview))
+}
+
+pub async fn preview_import(State(state): State<AppState>, Path(format): Path<String>, Query(q): Query<ImportQuery>, body: Bytes) -> Result<Json<ImportPreview>, (StatusCode, Json<ErrorResp>)> {
+    let (_, preview) = parse_upload(&state, &format, &q, &body).await?;
+    Ok(Json(preview))
+}
+
+pub async fn commit_import_route(State(state): State<AppState>, audit: AuditContext, Path(format): Path<String>, Query(q): Query<ImportQuery>, body: Bytes) -> Result<(StatusCode, Json<ImportCommitResp>), (StatusCode, Json<ErrorResp>)> {
+    let (account, preview) = parse_upload(&state, &format, &q, &body).await?;
+    if !preview.errors.is_empty() && !q.skip_errors {
+        let first = &preview.errors[0];
+        return Err(json_error(StatusCode::BAD_REQUEST, "invalid_rows", format!("{} rows failed to parse (line {}: {})", preview.errors.len(), first.line, first.message)));
+    }
+    if preview.rows.is_empty() && preview.holdings.is_empty() {
+        return Err(json_error(StatusCode::BAD_REQUEST, "invalid_request", "nothing to import"));
+    }
+    let batch = commit_import(&state.db, &audit, &account, &format, &preview.rows, &preview.holdings).await.map_err(internal_json)?;
+    Ok((StatusCode::CREATED, Json(ImportCommitResp { batch, skipped: preview.errors })))
+}
+
+pub async fn list_import_batches(State(state): State<AppState>, Query(q): Query<ImportBatchesQuery>) -> Result<Json<Vec<crate::models::import_batch::Model>>, (StatusCode, Json<ErrorResp>)> {
+    let list = find_import_batches_by_user(&state.db, q.user_id).await.map_err(internal_json)?;
+    Ok(Json(list))
+}
+
+pub async fn get_import_batch(State(state): State<AppState>, Path(id): Path<i32>) -> Result<Json<crate::models::import_batch::Model>, (StatusCode, Json<ErrorResp>)> {
+    match get_import_batch_by_id(&state.db, id).await.map_err(internal_json)? {
+        Some(m) => Ok(Json(m)),
+        None => Err(json_error(StatusCode::NOT_FOUND, "not_found", "import batch not found")),
+    }
+}
+
+pub async fn rollback_import_batch_route(State(state): State<AppState>, Path(id): Path<i32>, audit: AuditContext) -> Result<Json<crate::models::import_batch::Model>, (StatusCode, Json<ErrorResp>)> {
+    let hashes = attachment_hashes(&state.db, Condition::all().add(transaction::Column::ImportBatchId.eq(id))).await.map_err(internal_json)?;
+    match rollback_import_batch(&state.db, &audit, id).await.map_err(service_json)? {
+        Some(m) => {
+            release_attachment_blobs(&state.db, state.attachments.as_ref(), hashes).await.map_err(internal_json)?;
+            Ok(Json(m))
+        }
+        None => Err(json_error(StatusCode::NOT_FOUND, "not_found", "import batch not found")),
+    }
+}
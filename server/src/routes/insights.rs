@@ -0,0 +1,42 @@
+use axum::{extract::{Path, State, Query}, http::StatusCode, Json};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use crate::routes::AppState;
+use crate::models::anomaly_dismissal;
+use crate::services::{delete_anomaly_dismissal, detect_anomalies, dismiss_anomaly, AnomalyReport};
+use crate::routes::{ErrorResp, json_error, internal_json, bad_request_json, service_json};
+
+#[derive(Deserialize)]
+pub struct AnomaliesQuery {
+    pub user_id: i32,
+    pub days: Option<u32>,
+    /// Last local day of the window, `YYYY-MM-DD`; defaults to today.
+    pub to: Option<String>,
+    #[serde(default)]
+    pub utc_offset_minutes: i32,
+    #[serde(default)]
+    pub include_dismissed: bool,
+}
+
+#[derive(Deserialize)]
+pub struct DismissAnomalyReq {
+    pub user_id: i32,
+    pub key: String,
+}
+
+pub async fn get_anomalies(State(state): State<AppState>, Query(q): Query<AnomaliesQuery>) -> Result<Json<AnomalyReport>, (StatusCode, Json<ErrorResp>)> {
+    let to = q.to.as_deref().map(|d| NaiveDate::parse_from_str(d.trim(), "%Y-%m-%d")).transpose().map_err(bad_request_json)?;
+    let report = detect_anomalies(&state.db, q.user_id, q.days.unwrap_or(30), to, q.utc_offset_minutes, q.include_dismissed).await.map_err(service_json)?;
+    Ok(Json(report))
+}
+
+pub async fn post_anomaly_dismissal(State(state): State<AppState>, Json(body): Json<DismissAnomalyReq>) -> Result<(StatusCode, Json<anomaly_dismissal::Model>), (StatusCode, Json<ErrorResp>)> {
+    let model = dismiss_anomaly(&state.db, body.user_id, body.key).await.map_err(service_json)?;
+    Ok((StatusCode::CREATED, Json(model)))
+}
+
+pub async fn delete_anomaly_dismissal_route(State(state): State<AppState>, Path(id): Path<i32>) -> Result<StatusCode, (StatusCode, Json<ErrorResp>)> {
+    let affected = delete_anomaly_dismissal(&state.db, id).await.map_err(internal_json)?;
+    if affected == 0 { return Err(json_error(StatusCode::NOT_FOUND, "not_found", "dismissal not found")); }
+    Ok(StatusCode::NO_CONTENT)
+}
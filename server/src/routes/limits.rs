@@ -0,0 +1,42 @@
+use axum::{extract::{ConnectInfo, Request, State}, http::HeaderMap, middleware::Next, response::Response};
+use crate::routes::{too_many_requests, AppState};
+use crate::services::RateLimiter;
+use std::net::{IpAddr, SocketAddr};
+
+/// The client's address: the socket peer, or with `trust_forwarded_for` the last hop recorded
+/// in `X-Forwarded-For` (the one the proxy in front of us added). `None` for in-process calls.
+pub fn client_ip(headers: &HeaderMap, extensions: &axum::http::Extensions, trust_forwarded_for: bool) -> Option<IpAddr> {
+    if trust_forwarded_for {
+        let forwarded = headers
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit(',').next())
+            .and_then(|v| v.trim().parse().ok());
+        if forwarded.is_some() {
+            return forwarded;
+        }
+    }
+    extensions.get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| addr.ip())
+}
+
+async fn limit(limiter: &RateLimiter, state: &AppState, req: Request, next: Next) -> Response {
+    // Requests that did not come over a socket (tests, internal calls) are not limited.
+    if let Some(ip) = client_ip(req.headers(), req.extensions(), state.trust_forwarded_for) {
+        if let Err(wait) = limiter.check(ip) {
+            return too_many_requests(wait);
+        }
+    }
+    next.run(req).await
+}
+
+pub async fn rate_limit_auth(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    limit(&state.rate_limits.auth, &state, req, next).await
+}
+
+pub async fn rate_limit_uploads(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    limit(&state.rate_limits.uploads, &state, req, next).await
+}
+
+pub async fn rate_limit_api(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    limit(&state.rate_limits.api, &state, req, next).await
+}
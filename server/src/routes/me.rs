@@ -0,0 +1,23 @@
+use axum::{extract::State, http::StatusCode, Json};
+use crate::routes::{AppState, CurrentUser};
+use crate::services::{export_archive, import_archive, get_user_by_id, Archive, ArchiveImportSummary, AuditContext};
+use crate::routes::{ErrorResp, json_error, internal_json, service_json};
+
+/// Restores can be much larger than the default 2 MB JSON body limit.
+pub const ARCHIVE_BODY_LIMIT: usize = 64 * 1024 * 1024;
+
+pub async fn get_me_export(State(state): State<AppState>, CurrentUser(uid): CurrentUser) -> Result<Json<Archive>, (StatusCode, Json<ErrorResp>)> {
+    let Some(user) = get_user_by_id(&state.db, uid).await.map_err(internal_json)? else {
+        return Err(json_error(StatusCode::NOT_FOUND, "not_found", "user not found"));
+    };
+    let archive = export_archive(&state.db, &user).await.map_err(internal_json)?;
+    Ok(Json(archive))
+}
+
+pub async fn post_me_import(State(state): State<AppState>, CurrentUser(uid): CurrentUser, audit: AuditContext, Json(archive): Json<Archive>) -> Result<(StatusCode, Json<ArchiveImportSummary>), (StatusCode, Json<ErrorResp>)> {
+    if get_user_by_id(&state.db, uid).await.map_err(internal_json)?.is_none() {
+        return Err(json_error(StatusCode::NOT_FOUND, "not_found", "user not found"));
+    }
+    let summary = import_archive(&state.db, &audit, uid, archive).await.map_err(service_json)?;
+    Ok((StatusCode::CREATED, Json(summary)))
+}
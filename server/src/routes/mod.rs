@@ -4,13 +4,103 @@ pub mod accounts;
 pub mod transactions;
 pub mod assets;
 pub mod auth;
+pub mod reconciliations;
+pub mod imports;
+pub mod exports;
+pub mod me;
+pub mod reports;
+pub mod stats;
+pub mod dashboards;
+pub mod schedules;
+pub mod forecasts;
+pub mod insights;
+pub mod rules;
+pub mod attachments;
+pub mod limits;
+pub mod tokens;
+pub mod audits;
 pub mod error;
 
 use sea_orm::DatabaseConnection;
+use crate::services::{DashboardCache, JwtKeys, LocalStorage, LogMailer, LoginThrottle, OidcProviders, PasswordHasher, RateLimits, SharedMailer, SharedStorage, SuggestionModels, DEFAULT_ATTACHMENT_DIR, DEFAULT_JWT_SECRET};
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: DatabaseConnection,
+    pub dashboard_cache: DashboardCache,
+    pub suggestion_models: SuggestionModels,
+    pub attachments: SharedStorage,
+    pub mailer: SharedMailer,
+    /// Refuse logins until the user has followed the verification email.
+    pub require_email_verification: bool,
+    pub login_throttle: LoginThrottle,
+    pub rate_limits: RateLimits,
+    /// Whether `X-Forwarded-For` names the client (see `client_ip`).
+    pub trust_forwarded_for: bool,
+    pub jwt_keys: Arc<JwtKeys>,
+    pub passwords: PasswordHasher,
+    /// Identity providers offered alongside passwords; none by default.
+    pub oidc: OidcProviders,
+}
+
+impl AppState {
+    pub fn new(db: DatabaseConnection) -> Self {
+        AppState {
+            db,
+            dashboard_cache: DashboardCache::default(),
+            suggestion_models: SuggestionModels::default(),
+            attachments: Arc::new(LocalStorage::new(DEFAULT_ATTACHMENT_DIR)),
+            mailer: Arc::new(LogMailer),
+            require_email_verification: false,
+            login_throttle: LoginThrottle::default(),
+            rate_limits: RateLimits::default(),
+            trust_forwarded_for: false,
+            jwt_keys: Arc::new(JwtKeys::hs256(DEFAULT_JWT_SECRET)),
+            passwords: PasswordHasher::default(),
+            oidc: OidcProviders::default(),
+        }
+    }
+
+    pub fn with_attachment_storage(mut self, storage: SharedStorage) -> Self {
+        self.attachments = storage;
+        self
+    }
+
+    pub fn with_mailer(mut self, mailer: SharedMailer) -> Self {
+        self.mailer = mailer;
+        self
+    }
+
+    pub fn with_email_verification_required(mut self, required: bool) -> Self {
+        self.require_email_verification = required;
+        self
+    }
+
+    pub fn with_rate_limits(mut self, rate_limits: RateLimits) -> Self {
+        self.rate_limits = rate_limits;
+        self
+    }
+
+    pub fn with_trusted_forwarded_for(mut self, trust: bool) -> Self {
+        self.trust_forwarded_for = trust;
+        self
+    }
+
+    pub fn with_jwt_keys(mut self, keys: JwtKeys) -> Self {
+        self.jwt_keys = Arc::new(keys);
+        self
+    }
+
+    pub fn with_password_hasher(mut self, passwords: PasswordHasher) -> Self {
+        self.passwords = passwords;
+        self
+    }
+
+    pub fn with_oidc_providers(mut self, providers: OidcProviders) -> Self {
+        self.oidc = providers;
+        self
+    }
 }
 
 pub use health::*;
@@ -19,4 +109,19 @@ pub use accounts::*;
 pub use transactions::*;
 pub use assets::*;
 pub use auth::*;
+pub use reconciliations::*;
+pub use imports::*;
+pub use exports::*;
+pub use me::*;
+pub use reports::*;
+pub use stats::*;
+pub use dashboards::*;
+pub use schedules::*;
+pub use forecasts::*;
+pub use insights::*;
+pub use rules::*;
+pub use attachments::*;
+pub use limits::*;
+pub use tokens::*;
+pub use audits::*;
 pub use error::*;
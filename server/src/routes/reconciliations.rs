@@ -0,0 +1,46 @@
+use axum::{extract::{Path, State}, http::StatusCode, Json};
+use serde::Deserialize;
+use crate::routes::AppState;
+use sea_orm::prelude::Decimal;
+use crate::services::{create_reconciliation, get_reconciliation_by_id, find_reconciliations_by_account, reconciliation_summary, complete_reconciliation, delete_reconciliation, AuditContext, ReconciliationSummary};
+use std::str::FromStr;
+use crate::routes::{ErrorResp, json_error, internal_json, bad_request_json, service_json};
+
+#[derive(Deserialize)]
+pub struct CreateReconciliationReq {
+    pub statement_date: String,
+    pub ending_balance: String,
+}
+
+pub async fn post_reconciliation(State(state): State<AppState>, Path(account_id): Path<i32>, Json(body): Json<CreateReconciliationReq>) -> Result<(StatusCode, Json<ReconciliationSummary>), (StatusCode, Json<ErrorResp>)> {
+    let date = chrono::NaiveDate::parse_from_str(&body.statement_date, "%Y-%m-%d").map_err(bad_request_json)?;
+    let ending = Decimal::from_str(&body.ending_balance).map_err(bad_request_json)?;
+    let model = create_reconciliation(&state.db, account_id, date, ending).await.map_err(service_json)?;
+    let summary = reconciliation_summary(&state.db, model).await.map_err(internal_json)?;
+    Ok((StatusCode::CREATED, Json(summary)))
+}
+
+pub async fn list_reconciliations(State(state): State<AppState>, Path(account_id): Path<i32>) -> Result<Json<Vec<crate::models::reconciliation::Model>>, (StatusCode, Json<ErrorResp>)> {
+    let list = find_reconciliations_by_account(&state.db, account_id).await.map_err(internal_json)?;
+    Ok(Json(list))
+}
+
+pub async fn get_reconciliation(State(state): State<AppState>, Path(id): Path<i32>) -> Result<Json<ReconciliationSummary>, (StatusCode, Json<ErrorResp>)> {
+    match get_reconciliation_by_id(&state.db, id).await.map_err(internal_json)? {
+        Some(m) => Ok(Json(reconciliation_summary(&state.db, m).await.map_err(internal_json)?)),
+        None => Err(json_error(StatusCode::NOT_FOUND, "not_found", "reconciliation not found")),
+    }
+}
+
+pub async fn complete_reconciliation_route(State(state): State<AppState>, Path(id): Path<i32>, audit: AuditContext) -> Result<Json<ReconciliationSummary>, (StatusCode, Json<ErrorResp>)> {
+    match complete_reconciliation(&state.db, &audit, id).await.map_err(service_json)? {
+        Some(s) => Ok(Json(s)),
+        None => Err(json_error(StatusCode::NOT_FOUND, "not_found", "reconciliation not found")),
+    }
+}
+
+pub async fn delete_reconciliation_route(State(state): State<AppState>, Path(id): Path<i32>) -> Result<StatusCode, (StatusCode, Json<ErrorResp>)> {
+    let affected = delete_reconciliation(&state.db, id).await.map_err(service_json)?;
+    if affected == 0 { return Err(json_error(StatusCode::NOT_FOUND, "not_found", "reconciliation not found")); }
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,39 @@
+use axum::{extract::{State, Query}, http::{header, StatusCode}, response::IntoResponse, Json};
+use serde::Deserialize;
+use crate::routes::AppState;
+use crate::services::{account_statement, user_statements, get_account_by_id, render_statement_pdf, render_statement_xlsx, MonthPeriod};
+use crate::routes::{ErrorResp, json_error, internal_json, service_json};
+
+#[derive(Deserialize)]
+pub struct StatementQuery {
+    pub account_id: i32,
+    /// `YYYY-MM`
+    pub month: String,
+    /// `xlsx` or `pdf`
+    pub format: String,
+    /// Where the month starts, e.g. `480` for China; defaults to UTC.
+    #[serde(default)]
+    pub utc_offset_minutes: i32,
+}
+
+pub async fn get_statement_report(State(state): State<AppState>, Query(q): Query<StatementQuery>) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResp>)> {
+    let period = MonthPeriod::parse(&q.month, q.utc_offset_minutes).map_err(service_json)?;
+    let Some(account) = get_account_by_id(&state.db, q.account_id).await.map_err(internal_json)? else {
+        return Err(json_error(StatusCode::NOT_FOUND, "not_found", "account not found"));
+    };
+    let (bytes, content_type) = match q.format.as_str() {
+        "xlsx" => {
+            let all = user_statements(&state.db, account.user_id, &period).await.map_err(internal_json)?;
+            let statement = account_statement(&state.db, account, &period).await.map_err(internal_json)?;
+            let bytes = render_statement_xlsx(&statement, &all, &period, q.utc_offset_minutes).map_err(internal_json)?;
+            (bytes, "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet")
+        }
+        "pdf" => {
+            let statement = account_statement(&state.db, account, &period).await.map_err(internal_json)?;
+            (render_statement_pdf(&statement, &period, q.utc_offset_minutes), "application/pdf")
+        }
+        other => return Err(json_error(StatusCode::BAD_REQUEST, "invalid_request", format!("unsupported format: {}", other))),
+    };
+    let disposition = format!("attachment; filename=\"statement-{}-{}.{}\"", q.account_id, period.label(), q.format);
+    Ok(([(header::CONTENT_TYPE, content_type.to_string()), (header::CONTENT_DISPOSITION, disposition)], bytes))
+}
@@ -0,0 +1,60 @@
+use axum::{extract::{Path, State, Query}, http::StatusCode, Json};
+use serde::Deserialize;
+use crate::routes::AppState;
+use crate::models::rule;
+use crate::services::{apply_rules_retroactively, create_rule, delete_rule, find_rules_by_user, update_rule, AuditContext, RuleApplication, RuleSpec};
+use crate::routes::{ErrorResp, json_error, internal_json, service_json};
+
+#[derive(Deserialize)]
+pub struct CreateRuleReq {
+    pub user_id: i32,
+    #[serde(flatten)]
+    pub rule: RuleSpec,
+}
+
+#[derive(Deserialize)]
+pub struct RulesQuery { pub user_id: i32 }
+
+fn dry_run_by_default() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+pub struct ApplyRulesReq {
+    pub user_id: i32,
+    /// Only these rules; all enabled rules when omitted.
+    pub rule_ids: Option<Vec<i32>>,
+    /// Replace categories that are already set.
+    #[serde(default)]
+    pub overwrite_category: bool,
+    #[serde(default = "dry_run_by_default")]
+    pub dry_run: bool,
+}
+
+pub async fn post_rule(State(state): State<AppState>, Json(body): Json<CreateRuleReq>) -> Result<(StatusCode, Json<rule::Model>), (StatusCode, Json<ErrorResp>)> {
+    let model = create_rule(&state.db, body.user_id, body.rule).await.map_err(service_json)?;
+    Ok((StatusCode::CREATED, Json(model)))
+}
+
+pub async fn list_rules(State(state): State<AppState>, Query(q): Query<RulesQuery>) -> Result<Json<Vec<rule::Model>>, (StatusCode, Json<ErrorResp>)> {
+    let list = find_rules_by_user(&state.db, q.user_id).await.map_err(internal_json)?;
+    Ok(Json(list))
+}
+
+pub async fn put_rule(State(state): State<AppState>, Path(id): Path<i32>, Json(body): Json<RuleSpec>) -> Result<Json<rule::Model>, (StatusCode, Json<ErrorResp>)> {
+    match update_rule(&state.db, id, body).await.map_err(service_json)? {
+        Some(m) => Ok(Json(m)),
+        None => Err(json_error(StatusCode::NOT_FOUND, "not_found", "rule not found")),
+    }
+}
+
+pub async fn delete_rule_route(State(state): State<AppState>, Path(id): Path<i32>) -> Result<StatusCode, (StatusCode, Json<ErrorResp>)> {
+    let affected = delete_rule(&state.db, id).await.map_err(internal_json)?;
+    if affected == 0 { return Err(json_error(StatusCode::NOT_FOUND, "not_found", "rule not found")); }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn post_apply_rules(State(state): State<AppState>, audit: AuditContext, Json(body): Json<ApplyRulesReq>) -> Result<Json<RuleApplication>, (StatusCode, Json<ErrorResp>)> {
+    let result = apply_rules_retroactively(&state.db, &audit, body.user_id, body.rule_ids, body.overwrite_category, body.dry_run).await.map_err(service_json)?;
+    Ok(Json(result))
+}
@@ -0,0 +1,166 @@
+use axum::{extract::{Path, State, Query}, http::StatusCode, Json};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use crate::routes::AppState;
+use crate::models::{investment_plan, recurring_transaction};
+use sea_orm::prelude::Decimal;
+use crate::services::{
+    create_investment_plan, create_recurring_transaction, delete_investment_plan, delete_recurring_transaction,
+    find_investment_plans_by_user, find_recurring_transactions_by_user, update_investment_plan, update_recurring_transaction,
+    Frequency, InvestmentPlanChanges, NewInvestmentPlan, NewRecurringTransaction, RecurringTransactionChanges,
+};
+use std::str::FromStr;
+use crate::routes::{ErrorResp, json_error, internal_json, bad_request_json, service_json};
+
+type ApiError = (StatusCode, Json<ErrorResp>);
+
+fn parse_date(raw: &str) -> Result<NaiveDate, ApiError> {
+    NaiveDate::parse_from_str(raw.trim(), "%Y-%m-%d").map_err(bad_request_json)
+}
+
+fn parse_opt_date(raw: Option<String>) -> Result<Option<NaiveDate>, ApiError> {
+    raw.as_deref().map(parse_date).transpose()
+}
+
+fn parse_opt_amount(raw: Option<String>) -> Result<Option<Decimal>, ApiError> {
+    raw.as_deref().map(Decimal::from_str).transpose().map_err(bad_request_json)
+}
+
+fn parse_opt_frequency(raw: Option<String>) -> Result<Option<Frequency>, ApiError> {
+    raw.as_deref().map(Frequency::parse).transpose().map_err(service_json)
+}
+
+#[derive(Deserialize)]
+pub struct SchedulesQuery { pub user_id: i32 }
+
+#[derive(Deserialize)]
+pub struct CreateRecurringReq {
+    pub user_id: i32,
+    pub account_id: i32,
+    pub transaction_type: String,
+    pub amount: String,
+    pub description: String,
+    pub category: Option<String>,
+    pub frequency: String,
+    pub interval: Option<i32>,
+    pub start_date: String,
+    pub end_date: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateRecurringReq {
+    pub amount: Option<String>,
+    pub description: Option<String>,
+    pub category: Option<String>,
+    pub frequency: Option<String>,
+    pub interval: Option<i32>,
+    pub end_date: Option<String>,
+    pub active: Option<bool>,
+}
+
+pub async fn post_recurring(State(state): State<AppState>, Json(body): Json<CreateRecurringReq>) -> Result<(StatusCode, Json<recurring_transaction::Model>), ApiError> {
+    let new = NewRecurringTransaction {
+        account_id: body.account_id,
+        transaction_type: body.transaction_type,
+        amount: Decimal::from_str(&body.amount).map_err(bad_request_json)?,
+        description: body.description,
+        category: body.category,
+        frequency: Frequency::parse(&body.frequency).map_err(service_json)?,
+        interval: body.interval.unwrap_or(1),
+        start_date: parse_date(&body.start_date)?,
+        end_date: parse_opt_date(body.end_date)?,
+    };
+    let model = create_recurring_transaction(&state.db, body.user_id, new).await.map_err(service_json)?;
+    Ok((StatusCode::CREATED, Json(model)))
+}
+
+pub async fn list_recurring(State(state): State<AppState>, Query(q): Query<SchedulesQuery>) -> Result<Json<Vec<recurring_transaction::Model>>, ApiError> {
+    let list = find_recurring_transactions_by_user(&state.db, q.user_id).await.map_err(internal_json)?;
+    Ok(Json(list))
+}
+
+pub async fn patch_recurring(State(state): State<AppState>, Path(id): Path<i32>, Json(body): Json<UpdateRecurringReq>) -> Result<Json<recurring_transaction::Model>, ApiError> {
+    let changes = RecurringTransactionChanges {
+        amount: parse_opt_amount(body.amount)?,
+        description: body.description,
+        category: body.category,
+        frequency: parse_opt_frequency(body.frequency)?,
+        interval: body.interval,
+        end_date: parse_opt_date(body.end_date)?,
+        active: body.active,
+    };
+    match update_recurring_transaction(&state.db, id, changes).await.map_err(service_json)? {
+        Some(m) => Ok(Json(m)),
+        None => Err(json_error(StatusCode::NOT_FOUND, "not_found", "recurring transaction not found")),
+    }
+}
+
+pub async fn delete_recurring_route(State(state): State<AppState>, Path(id): Path<i32>) -> Result<StatusCode, ApiError> {
+    let affected = delete_recurring_transaction(&state.db, id).await.map_err(internal_json)?;
+    if affected == 0 { return Err(json_error(StatusCode::NOT_FOUND, "not_found", "recurring transaction not found")); }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct CreateInvestmentPlanReq {
+    pub user_id: i32,
+    pub account_id: i32,
+    pub name: String,
+    pub symbol: String,
+    pub amount: String,
+    pub frequency: String,
+    pub interval: Option<i32>,
+    pub start_date: String,
+    pub end_date: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateInvestmentPlanReq {
+    pub name: Option<String>,
+    pub amount: Option<String>,
+    pub frequency: Option<String>,
+    pub interval: Option<i32>,
+    pub end_date: Option<String>,
+    pub active: Option<bool>,
+}
+
+pub async fn post_investment_plan(State(state): State<AppState>, Json(body): Json<CreateInvestmentPlanReq>) -> Result<(StatusCode, Json<investment_plan::Model>), ApiError> {
+    let new = NewInvestmentPlan {
+        account_id: body.account_id,
+        name: body.name,
+        symbol: body.symbol,
+        amount: Decimal::from_str(&body.amount).map_err(bad_request_json)?,
+        frequency: Frequency::parse(&body.frequency).map_err(service_json)?,
+        interval: body.interval.unwrap_or(1),
+        start_date: parse_date(&body.start_date)?,
+        end_date: parse_opt_date(body.end_date)?,
+    };
+    let model = create_investment_plan(&state.db, body.user_id, new).await.map_err(service_json)?;
+    Ok((StatusCode::CREATED, Json(model)))
+}
+
+pub async fn list_investment_plans(State(state): State<AppState>, Query(q): Query<SchedulesQuery>) -> Result<Json<Vec<investment_plan::Model>>, ApiError> {
+    let list = find_investment_plans_by_user(&state.db, q.user_id).await.map_err(internal_json)?;
+    Ok(Json(list))
+}
+
+pub async fn patch_investment_plan(State(state): State<AppState>, Path(id): Path<i32>, Json(body): Json<UpdateInvestmentPlanReq>) -> Result<Json<investment_plan::Model>, ApiError> {
+    let changes = InvestmentPlanChanges {
+        name: body.name,
+        amount: parse_opt_amount(body.amount)?,
+        frequency: parse_opt_frequency(body.frequency)?,
+        interval: body.interval,
+        end_date: parse_opt_date(body.end_date)?,
+        active: body.active,
+    };
+    match update_investment_plan(&state.db, id, changes).await.map_err(service_json)? {
+        Some(m) => Ok(Json(m)),
+        None => Err(json_error(StatusCode::NOT_FOUND, "not_found", "investment plan not found")),
+    }
+}
+
+pub async fn delete_investment_plan_route(State(state): State<AppState>, Path(id): Path<i32>) -> Result<StatusCode, ApiError> {
+    let affected = delete_investment_plan(&state.db, id).await.map_err(internal_json)?;
+    if affected == 0 { return Err(json_error(StatusCode::NOT_FOUND, "not_found", "investment plan not found")); }
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,31 @@
+use axum::{extract::{State, Query}, http::StatusCode, Json};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use crate::routes::AppState;
+use crate::services::{cashflow_stats, Cashflow, GroupBy, Interval, ServiceError};
+use crate::routes::{ErrorResp, service_json};
+
+#[derive(Deserialize)]
+pub struct CashflowQuery {
+    pub user_id: i32,
+    /// Inclusive local dates, `YYYY-MM-DD`.
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub interval: Option<String>,
+    pub group_by: Option<String>,
+    #[serde(default)]
+    pub utc_offset_minutes: i32,
+}
+
+fn parse_date(raw: &str) -> Result<NaiveDate, ServiceError> {
+    NaiveDate::parse_from_str(raw.trim(), "%Y-%m-%d").map_err(|_| ServiceError::Invalid(format!("invalid date: {}", raw)))
+}
+
+pub async fn get_cashflow_stats(State(state): State<AppState>, Query(q): Query<CashflowQuery>) -> Result<Json<Cashflow>, (StatusCode, Json<ErrorResp>)> {
+    let from = q.from.as_deref().map(parse_date).transpose().map_err(service_json)?;
+    let to = q.to.as_deref().map(parse_date).transpose().map_err(service_json)?;
+    let interval = Interval::parse(q.interval.as_deref().unwrap_or("month")).map_err(service_json)?;
+    let group_by = q.group_by.as_deref().map(GroupBy::parse).transpose().map_err(service_json)?;
+    let stats = cashflow_stats(&state.db, q.user_id, from, to, interval, group_by, q.utc_offset_minutes).await.map_err(service_json)?;
+    Ok(Json(stats))
+}
@@ -0,0 +1,31 @@
+use axum::{extract::{Path, State}, http::StatusCode, Json};
+use serde::Deserialize;
+use crate::routes::{AppState, CurrentUser};
+use crate::models::api_token;
+use crate::services::{create_api_token, delete_api_token, list_api_tokens, CreatedApiToken};
+use crate::routes::{ErrorResp, json_error, internal_json, service_json};
+
+#[derive(Deserialize)]
+pub struct CreateTokenReq {
+    pub name: String,
+    pub scopes: Vec<String>,
+    /// Never expires when omitted.
+    pub expires_in_days: Option<i64>,
+}
+
+/// The response is the only time the token itself is shown.
+pub async fn post_token(State(state): State<AppState>, CurrentUser(uid): CurrentUser, Json(body): Json<CreateTokenReq>) -> Result<(StatusCode, Json<CreatedApiToken>), (StatusCode, Json<ErrorResp>)> {
+    let created = create_api_token(&state.db, uid, &body.name, body.scopes, body.expires_in_days).await.map_err(service_json)?;
+    Ok((StatusCode::CREATED, Json(created)))
+}
+
+pub async fn list_tokens(State(state): State<AppState>, CurrentUser(uid): CurrentUser) -> Result<Json<Vec<api_token::Model>>, (StatusCode, Json<ErrorResp>)> {
+    let list = list_api_tokens(&state.db, uid).await.map_err(internal_json)?;
+    Ok(Json(list))
+}
+
+pub async fn delete_token_route(State(state): State<AppState>, CurrentUser(uid): CurrentUser, Path(id): Path<i32>) -> Result<StatusCode, (StatusCode, Json<ErrorResp>)> {
+    let affected = delete_api_token(&state.db, uid, id).await.map_err(internal_json)?;
+    if affected == 0 { return Err(json_error(StatusCode::NOT_FOUND, "not_found", "token not found")); }
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -2,9 +2,11 @@ use axum::{extract::{Path, State, Query}, http::StatusCode, Json};
 use serde::Deserialize;
 use crate::routes::AppState;
 use sea_orm::prelude::Decimal;
-use crate::services::{create_transaction, get_transaction_by_id, find_transactions_by_account, update_transaction, delete_transaction};
+use crate::services::{AuditContext, create_transaction, get_transaction_by_id, find_transactions_by_account, update_transaction, unlock_transaction, delete_transaction, set_transaction_tags, Suggestions, SuggestionInput, DEFAULT_SUGGESTIONS, find_duplicates, merge_transactions, DuplicatePair, DEFAULT_DUPLICATE_DAYS, DEFAULT_DUPLICATE_SCORE, attachment_hashes, release_attachment_blobs};
+use crate::models::transaction;
+use sea_orm::{ColumnTrait, Condition};
 use std::str::FromStr;
-use crate::routes::{ErrorResp, json_error, internal_json, bad_request_json};
+use crate::routes::{ErrorResp, json_error, internal_json, bad_request_json, service_json};
 
 #[derive(Deserialize)]
 pub struct CreateTransactionReq {
@@ -13,6 +15,7 @@ pub struct CreateTransactionReq {
     pub amount: String,
     pub description: String,
     pub category: Option<String>,
+    pub status: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -21,14 +24,44 @@ pub struct UpdateTransactionReq {
     pub amount: Option<String>,
     pub description: Option<String>,
     pub category: Option<String>,
+    pub status: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TransactionTagsReq {
+    pub tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SuggestCategoryReq {
+    pub user_id: i32,
+    pub description: String,
+    pub amount: String,
+    pub counterparty: Option<String>,
+    pub transaction_type: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct DuplicatesQuery {
+    pub user_id: i32,
+    pub days: Option<i64>,
+    pub min_score: Option<f64>,
+}
+
+#[derive(Deserialize)]
+pub struct MergeTransactionsReq {
+    pub user_id: i32,
+    pub keep_id: i32,
+    pub remove_id: i32,
 }
 
 #[derive(Deserialize)]
 pub struct TransactionsQuery { pub account_id: i32 }
 
-pub async fn post_transaction(State(state): State<AppState>, Json(body): Json<CreateTransactionReq>) -> Result<(StatusCode, Json<crate::models::transaction::Model>), (StatusCode, Json<ErrorResp>)> {
+pub async fn post_transaction(State(state): State<AppState>, audit: AuditContext, Json(body): Json<CreateTransactionReq>) -> Result<(StatusCode, Json<crate::models::transaction::Model>), (StatusCode, Json<ErrorResp>)> {
     let amount = Decimal::from_str(&body.amount).map_err(bad_request_json)?;
-    let model = create_transaction(&state.db, body.account_id, body.transaction_type, amount, body.description, body.category).await.map_err(internal_json)?;
+    let model = create_transaction(&state.db, &audit, body.account_id, body.transaction_type, amount, body.description, body.category, body.status).await.map_err(service_json)?;
     Ok((StatusCode::CREATED, Json(model)))
 }
 
@@ -44,19 +77,62 @@ pub async fn list_transactions(State(state): State<AppState>, Query(q): Query<Tr
     Ok(Json(list))
 }
 
-pub async fn patch_transaction(State(state): State<AppState>, Path(id): Path<i32>, Json(body): Json<UpdateTransactionReq>) -> Result<Json<crate::models::transaction::Model>, (StatusCode, Json<ErrorResp>)> {
+pub async fn patch_transaction(State(state): State<AppState>, Path(id): Path<i32>, audit: AuditContext, Json(body): Json<UpdateTransactionReq>) -> Result<Json<crate::models::transaction::Model>, (StatusCode, Json<ErrorResp>)> {
     let amount = match body.amount {
         Some(s) => Some(Decimal::from_str(&s).map_err(bad_request_json)?),
         None => None,
     };
-    match update_transaction(&state.db, id, body.transaction_type, amount, body.description, body.category).await.map_err(internal_json)? {
+    match update_transaction(&state.db, &audit, id, body.transaction_type, amount, body.description, body.category, body.status).await.map_err(service_json)? {
+        Some(m) => Ok(Json(m)),
+        None => Err(json_error(StatusCode::NOT_FOUND, "not_found", "transaction not found")),
+    }
+}
+
+pub async fn unlock_transaction_route(State(state): State<AppState>, Path(id): Path<i32>, audit: AuditContext) -> Result<Json<crate::models::transaction::Model>, (StatusCode, Json<ErrorResp>)> {
+    match unlock_transaction(&state.db, &audit, id).await.map_err(internal_json)? {
         Some(m) => Ok(Json(m)),
         None => Err(json_error(StatusCode::NOT_FOUND, "not_found", "transaction not found")),
     }
 }
 
-pub async fn delete_transaction_route(State(state): State<AppState>, Path(id): Path<i32>) -> Result<StatusCode, (StatusCode, Json<ErrorResp>)> {
-    let affected = delete_transaction(&state.db, id).await.map_err(internal_json)?;
+pub async fn delete_transaction_route(State(state): State<AppState>, Path(id): Path<i32>, audit: AuditContext) -> Result<StatusCode, (StatusCode, Json<ErrorResp>)> {
+    let hashes = attachment_hashes(&state.db, Condition::all().add(transaction::Column::Id.eq(id))).await.map_err(internal_json)?;
+    let affected = delete_transaction(&state.db, &audit, id).await.map_err(service_json)?;
     if affected == 0 { return Err(json_error(StatusCode::NOT_FOUND, "not_found", "transaction not found")); }
+    release_attachment_blobs(&state.db, state.attachments.as_ref(), hashes).await.map_err(internal_json)?;
     Ok(StatusCode::NO_CONTENT)
 }
+
+pub async fn put_transaction_tags(State(state): State<AppState>, Path(id): Path<i32>, audit: AuditContext, Json(body): Json<TransactionTagsReq>) -> Result<Json<crate::models::transaction::Model>, (StatusCode, Json<ErrorResp>)> {
+    match set_transaction_tags(&state.db, &audit, id, body.tags).await.map_err(service_json)? {
+        Some(m) => Ok(Json(m)),
+        None => Err(json_error(StatusCode::NOT_FOUND, "not_found", "transaction not found")),
+    }
+}
+
+pub async fn post_transaction_suggestion(State(state): State<AppState>, Json(body): Json<SuggestCategoryReq>) -> Result<Json<Suggestions>, (StatusCode, Json<ErrorResp>)> {
+    let amount = Decimal::from_str(&body.amount).map_err(bad_request_json)?;
+    let input = SuggestionInput {
+        description: &body.description,
+        counterparty: body.counterparty.as_deref(),
+        amount,
+        transaction_type: body.transaction_type.as_deref().unwrap_or("expense"),
+    };
+    let limit = body.limit.unwrap_or(DEFAULT_SUGGESTIONS).clamp(1, 20);
+    let suggestions = state.suggestion_models.suggest(&state.db, body.user_id, input, limit).await.map_err(internal_json)?;
+    Ok(Json(suggestions))
+}
+
+pub async fn get_transaction_duplicates(State(state): State<AppState>, Query(q): Query<DuplicatesQuery>) -> Result<Json<Vec<DuplicatePair>>, (StatusCode, Json<ErrorResp>)> {
+    let min_score = q.min_score.unwrap_or(DEFAULT_DUPLICATE_SCORE);
+    if !(0.0..=1.0).contains(&min_score) {
+        return Err(json_error(StatusCode::BAD_REQUEST, "invalid_request", "min_score must be between 0 and 1"));
+    }
+    let pairs = find_duplicates(&state.db, q.user_id, q.days.unwrap_or(DEFAULT_DUPLICATE_DAYS), min_score).await.map_err(service_json)?;
+    Ok(Json(pairs))
+}
+
+pub async fn post_transaction_merge(State(state): State<AppState>, audit: AuditContext, Json(body): Json<MergeTransactionsReq>) -> Result<Json<crate::models::transaction::Model>, (StatusCode, Json<ErrorResp>)> {
+    let merged = merge_transactions(&state.db, &audit, body.user_id, body.keep_id, body.remove_id).await.map_err(service_json)?;
+    Ok(Json(merged))
+}
@@ -1,9 +1,10 @@
 use axum::{extract::{Path, State}, http::StatusCode, Json};
 use serde::{Deserialize, Serialize};
 use crate::routes::AppState;
-use crate::services::{create_user, get_user_by_id, get_user_by_username, get_user_by_email, update_user, update_user_password, delete_user};
-use bcrypt::{hash, DEFAULT_COST};
-use crate::routes::{ErrorResp, json_error, internal_json};
+use crate::services::{AuditContext, create_user, get_user_by_id, get_user_by_username, get_user_by_email, update_user, update_user_password, delete_user, find_accounts_by_user, attachment_hashes, release_attachment_blobs, revoke_all_sessions, send_verification_email};
+use crate::models::transaction;
+use sea_orm::{ColumnTrait, Condition};
+use crate::routes::{ErrorResp, json_error, internal_json, password_json};
 
 #[derive(Deserialize)]
 pub struct CreateUserReq {
@@ -26,6 +27,7 @@ pub struct UserOut {
     pub email: String,
     pub created_at: String,
     pub updated_at: String,
+    pub email_verified_at: Option<String>,
 }
 
 impl From<crate::models::user::Model> for UserOut {
@@ -36,11 +38,18 @@ impl From<crate::models::user::Model> for UserOut {
             email: m.email,
             created_at: m.created_at.to_rfc3339(),
             updated_at: m.updated_at.to_rfc3339(),
+            email_verified_at: m.email_verified_at.map(|t| t.to_rfc3339()),
         }
     }
 }
 
-pub async fn post_user(State(state): State<AppState>, Json(body): Json<CreateUserReq>) -> Result<(StatusCode, Json<UserOut>), (StatusCode, Json<ErrorResp>)> {
+/// Checks a newly chosen password against the policy and hashes it.
+pub fn new_password_hash(state: &AppState, password: &str) -> Result<String, (StatusCode, Json<ErrorResp>)> {
+    state.passwords.check_policy(password).map_err(password_json)?;
+    state.passwords.hash(password).map_err(password_json)
+}
+
+pub async fn post_user(State(state): State<AppState>, audit: AuditContext, Json(body): Json<CreateUserReq>) -> Result<(StatusCode, Json<UserOut>), (StatusCode, Json<ErrorResp>)> {
     // basic uniqueness check for user-friendly message
     if let Ok(Some(_)) = get_user_by_username(&state.db, &body.username).await {
         return Err(json_error(StatusCode::CONFLICT, "conflict", "username already exists"));
@@ -48,8 +57,9 @@ pub async fn post_user(State(state): State<AppState>, Json(body): Json<CreateUse
     if let Ok(Some(_)) = get_user_by_email(&state.db, &body.email).await {
         return Err(json_error(StatusCode::CONFLICT, "conflict", "email already exists"));
     }
-    let password_hash = hash(&body.password, DEFAULT_COST).map_err(internal_json)?;
-    let model = create_user(&state.db, body.username, body.email, password_hash).await.map_err(internal_json)?;
+    let password_hash = new_password_hash(&state, &body.password)?;
+    let model = create_user(&state.db, &audit, body.username, body.email, password_hash).await.map_err(internal_json)?;
+    send_verification_email(&state.db, &state.mailer, &model).await.map_err(internal_json)?;
     Ok((StatusCode::CREATED, Json(model.into())))
 }
 
@@ -60,7 +70,7 @@ pub async fn get_user(State(state): State<AppState>, Path(id): Path<i32>) -> Res
     }
 }
 
-pub async fn patch_user(State(state): State<AppState>, Path(id): Path<i32>, Json(body): Json<UpdateUserReq>) -> Result<Json<UserOut>, (StatusCode, Json<ErrorResp>)> {
+pub async fn patch_user(State(state): State<AppState>, Path(id): Path<i32>, audit: AuditContext, Json(body): Json<UpdateUserReq>) -> Result<Json<UserOut>, (StatusCode, Json<ErrorResp>)> {
     if let Some(ref email) = body.email {
         if let Ok(Some(existing)) = get_user_by_email(&state.db, email).await {
             if existing.id != id {
@@ -69,17 +79,29 @@ pub async fn patch_user(State(state): State<AppState>, Path(id): Path<i32>, Json
         }
     }
     if let Some(pw) = body.password.clone() {
-        let hashed = hash(&pw, DEFAULT_COST).map_err(internal_json)?;
-        let _ = update_user_password(&state.db, id, hashed).await.map_err(internal_json)?;
+        let hashed = new_password_hash(&state, &pw)?;
+        let _ = update_user_password(&state.db, &audit, id, hashed).await.map_err(internal_json)?;
+        // A new password signs out every device.
+        revoke_all_sessions(&state.db, id).await.map_err(internal_json)?;
     }
-    match update_user(&state.db, id, body.username, body.email).await.map_err(internal_json)? {
-        Some(m) => Ok(Json(m.into())),
+    let email_given = body.email.is_some();
+    match update_user(&state.db, &audit, id, body.username, body.email).await.map_err(internal_json)? {
+        Some(m) => {
+            // Changing the address clears verification; confirm the new one.
+            if email_given && m.email_verified_at.is_none() {
+                send_verification_email(&state.db, &state.mailer, &m).await.map_err(internal_json)?;
+            }
+            Ok(Json(m.into()))
+        }
         None => Err(json_error(StatusCode::NOT_FOUND, "not_found", "user not found")),
     }
 }
 
-pub async fn delete_user_route(State(state): State<AppState>, Path(id): Path<i32>) -> Result<StatusCode, (StatusCode, Json<ErrorResp>)> {
-    let affected = delete_user(&state.db, id).await.map_err(internal_json)?;
+pub async fn delete_user_route(State(state): State<AppState>, Path(id): Path<i32>, audit: AuditContext) -> Result<StatusCode, (StatusCode, Json<ErrorResp>)> {
+    let account_ids: Vec<i32> = find_accounts_by_user(&state.db, id).await.map_err(internal_json)?.into_iter().map(|a| a.id).collect();
+    let hashes = attachment_hashes(&state.db, Condition::all().add(transaction::Column::AccountId.is_in(account_ids))).await.map_err(internal_json)?;
+    let affected = delete_user(&state.db, &audit, id).await.map_err(internal_json)?;
+    release_attachment_blobs(&state.db, state.attachments.as_ref(), hashes).await.map_err(internal_json)?;
     if affected == 0 { return Err(json_error(StatusCode::NOT_FOUND, "not_found", "user not found")); }
     Ok(StatusCode::NO_CONTENT)
 }
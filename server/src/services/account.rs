@@ -1,9 +1,11 @@
-use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set, TransactionTrait};
 use crate::models::account;
+use crate::services::{audit_create, audit_delete, audit_update, AuditContext, ServiceError};
 use sea_orm::prelude::Decimal;
 
 pub async fn create_account(
     db: &DatabaseConnection,
+    audit: &AuditContext,
     user_id: i32,
     name: String,
     account_type: String,
@@ -12,13 +14,17 @@ pub async fn create_account(
 ) -> Result<account::Model, sea_orm::DbErr> {
     let active = account::ActiveModel {
         user_id: Set(user_id),
-        name: Set(name),
+        name: Set(name.into()),
         account_type: Set(account_type),
         balance: Set(balance),
         currency: Set(currency),
         ..Default::default()
     };
-    active.insert(db).await
+    let txn = db.begin().await?;
+    let model = active.insert(&txn).await?;
+    audit_create(&txn, audit, model.user_id, "account", model.id, &model).await?;
+    txn.commit().await?;
+    Ok(model)
 }
 
 pub async fn get_account_by_id(
@@ -40,19 +46,23 @@ pub async fn find_accounts_by_user(
 
 pub async fn update_account(
     db: &DatabaseConnection,
+    audit: &AuditContext,
     id: i32,
     name: Option<String>,
     account_type: Option<String>,
     balance: Option<Decimal>,
     currency: Option<String>,
 ) -> Result<Option<account::Model>, sea_orm::DbErr> {
-    if let Some(model) = account::Entity::find_by_id(id).one(db).await? {
-        let mut active: account::ActiveModel = model.into();
-        if let Some(v) = name { active.name = Set(v); }
+    let txn = db.begin().await?;
+    if let Some(model) = account::Entity::find_by_id(id).one(&txn).await? {
+        let mut active: account::ActiveModel = model.clone().into();
+        if let Some(v) = name { active.name = Set(v.into()); }
         if let Some(v) = account_type { active.account_type = Set(v); }
         if let Some(v) = balance { active.balance = Set(v); }
         if let Some(v) = currency { active.currency = Set(v); }
-        let updated = active.update(db).await?;
+        let updated = active.update(&txn).await?;
+        audit_update(&txn, audit, updated.user_id, "account", id, &model, &updated).await?;
+        txn.commit().await?;
         Ok(Some(updated))
     } else {
         Ok(None)
@@ -61,8 +71,48 @@ pub async fn update_account(
 
 pub async fn delete_account(
     db: &DatabaseConnection,
+    audit: &AuditContext,
     id: i32,
 ) -> Result<u64, sea_orm::DbErr> {
-    let res = account::Entity::delete_by_id(id).exec(db).await?;
+    let txn = db.begin().await?;
+    let Some(model) = account::Entity::find_by_id(id).one(&txn).await? else { return Ok(0) };
+    let res = account::Entity::delete_by_id(id).exec(&txn).await?;
+    // Its transactions go with it (cascade); the account's entry stands for them.
+    audit_delete(&txn, audit, model.user_id, "account", id, &model).await?;
+    txn.commit().await?;
     Ok(res.rows_affected)
 }
+
+/// Sets (or, with all `None`, clears) a credit card's billing cycle.
+pub async fn set_card_schedule(
+    db: &DatabaseConnection,
+    audit: &AuditContext,
+    id: i32,
+    statement_day: Option<i32>,
+    payment_due_day: Option<i32>,
+    payment_account_id: Option<i32>,
+) -> Result<Option<account::Model>, ServiceError> {
+    for day in [statement_day, payment_due_day].into_iter().flatten() {
+        if !(1..=31).contains(&day) {
+            return Err(ServiceError::Invalid(format!("day of month out of range: {}", day)));
+        }
+    }
+    let Some(model) = account::Entity::find_by_id(id).one(db).await? else {
+        return Ok(None);
+    };
+    if let Some(payer) = payment_account_id {
+        let owned = account::Entity::find_by_id(payer).one(db).await?.is_some_and(|p| p.user_id == model.user_id);
+        if payer == id || !owned {
+            return Err(ServiceError::Invalid(format!("invalid payment account {}", payer)));
+        }
+    }
+    let mut active: account::ActiveModel = model.clone().into();
+    active.statement_day = Set(statement_day);
+    active.payment_due_day = Set(payment_due_day);
+    active.payment_account_id = Set(payment_account_id);
+    let txn = db.begin().await?;
+    let updated = active.update(&txn).await?;
+    audit_update(&txn, audit, updated.user_id, "account", id, &model, &updated).await?;
+    txn.commit().await?;
+    Ok(Some(updated))
+}
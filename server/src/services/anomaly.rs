@@ -0,0 +1,265 @@
+use chrono::{Datelike, Duration, Months, NaiveDate, Utc};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, Order, QueryFilter, QueryOrder, Set};
+use serde::Serialize;
+use crate::models::{anomaly_dismissal, transaction};
+use crate::services::{created_at_expr, created_before, find_accounts_by_user, ServiceError};
+use sea_orm::prelude::Decimal;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+const MAX_ANOMALY_DAYS: u32 = 366;
+/// Earlier expenses a category needs before its outliers are judged.
+const MIN_CATEGORY_HISTORY: usize = 5;
+/// Modified z-score (Iglewicz and Hoaglin) above which an amount is an outlier.
+const OUTLIER_SCORE: f64 = 3.5;
+/// Same amount at the same merchant on the same account within this window looks like a double charge.
+const DUPLICATE_WINDOW: Duration = Duration::hours(48);
+/// Earlier expenses needed before a first-time merchant can be called unusual.
+const MIN_MERCHANT_HISTORY: usize = 10;
+/// A first-time merchant is flagged when the charge is this many times the user's median expense.
+const NEW_MERCHANT_FACTOR: f64 = 3.0;
+/// Full months averaged for the category spike baseline.
+const SPIKE_TRAILING_MONTHS: u32 = 3;
+const SPIKE_FACTOR: f64 = 1.5;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Anomaly {
+    /// Stable identifier, used to dismiss it: `amount_outlier:<id>`, `duplicate:<id>:<earlier id>`,
+    /// `new_merchant:<id>` or `category_spike:<category>:<YYYY-MM>`.
+    pub key: String,
+    /// `amount_outlier`, `duplicate`, `new_merchant` or `category_spike`.
+    pub kind: &'static str,
+    pub date: NaiveDate,
+    pub transaction_id: Option<i32>,
+    /// For duplicates: the earlier, matching charge.
+    pub related_transaction_id: Option<i32>,
+    pub account_id: Option<i32>,
+    pub category: Option<String>,
+    pub amount: Decimal,
+    /// What would have been typical: the category median, the user's median expense, or the trailing monthly average.
+    pub expected: Option<Decimal>,
+    pub message: String,
+    /// Set when the user has dismissed this anomaly.
+    pub dismissal_id: Option<i32>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AnomalyReport {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub anomalies: Vec<Anomaly>,
+}
+
+fn median(sorted: &[f64]) -> f64 {
+    let n = sorted.len();
+    if n % 2 == 1 { sorted[n / 2] } else { (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0 }
+}
+
+fn sorted(values: &[f64]) -> Vec<f64> {
+    let mut v = values.to_vec();
+    v.sort_by(f64::total_cmp);
+    v
+}
+
+fn money(v: f64) -> Decimal {
+    Decimal::try_from(v).unwrap_or_default().round_dp(2).normalize()
+}
+
+fn merchant(t: &transaction::Model) -> String {
+    match &t.counterparty {
+        Some(p) if !p.trim().is_empty() => p.trim().to_lowercase(),
+        _ => t.description.trim().to_lowercase(),
+    }
+}
+
+/// Whether `amount` is far above the category's `history`; returns the median it was judged against.
+fn outlier(history: &[f64], amount: f64) -> Option<f64> {
+    if history.len() < MIN_CATEGORY_HISTORY {
+        return None;
+    }
+    let values = sorted(history);
+    let med = median(&values);
+    let deviations = sorted(&values.iter().map(|v| (v - med).abs()).collect::<Vec<_>>());
+    let mad = median(&deviations);
+    let unusual = if mad > 0.0 { 0.6745 * (amount - med) / mad > OUTLIER_SCORE } else { amount > med * NEW_MERCHANT_FACTOR };
+    unusual.then_some(med)
+}
+
+/// Flags unusual expenses dated in the `days` local days ending on `to` (default today), using
+/// the user's whole history as the baseline. Dismissed anomalies are left out unless asked for.
+pub async fn detect_anomalies(
+    db: &DatabaseConnection,
+    user_id: i32,
+    days: u32,
+    to: Option<NaiveDate>,
+    utc_offset_minutes: i32,
+    include_dismissed: bool,
+) -> Result<AnomalyReport, ServiceError> {
+    if days == 0 || days > MAX_ANOMALY_DAYS {
+        return Err(ServiceError::Invalid(format!("days must be between 1 and {}", MAX_ANOMALY_DAYS)));
+    }
+    let offset = Duration::minutes(utc_offset_minutes as i64);
+    let to = to.unwrap_or_else(|| (Utc::now() + offset).date_naive());
+    let from = to - Duration::days(days as i64 - 1);
+    let local = |t: &transaction::Model| (t.created_at + offset).date_naive();
+
+    let account_ids: Vec<i32> = find_accounts_by_user(db, user_id).await?.into_iter().map(|a| a.id).collect();
+    let end = (to + Duration::days(1)).and_hms_opt(0, 0, 0).expect("midnight").and_utc() - offset;
+    let expenses: Vec<transaction::Model> = transaction::Entity::find()
+        .filter(transaction::Column::AccountId.is_in(account_ids))
+        .filter(transaction::Column::TransactionType.eq("expense"))
+        .filter(created_before(end))
+        .order_by(created_at_expr(), Order::Asc)
+        .order_by_asc(transaction::Column::Id)
+        .all(db)
+        .await?;
+
+    let mut anomalies = Vec::new();
+    let mut by_category: HashMap<String, Vec<f64>> = HashMap::new();
+    let mut all: Vec<f64> = Vec::new();
+    let mut merchants: HashSet<String> = HashSet::new();
+    for (i, t) in expenses.iter().enumerate() {
+        let date = local(t);
+        let amount = f64::try_from(t.amount).unwrap_or_default();
+        let name = merchant(t);
+        if date >= from {
+            if let Some(category) = &t.category {
+                if let Some(med) = by_category.get(category).and_then(|h| outlier(h, amount)) {
+                    anomalies.push(Anomaly {
+                        key: format!("amount_outlier:{}", t.id),
+                        kind: "amount_outlier",
+                        date,
+                        transaction_id: Some(t.id),
+                        related_transaction_id: None,
+                        account_id: Some(t.account_id),
+                        category: Some(category.clone()),
+                        amount: t.amount,
+                        expected: Some(money(med)),
+                        message: format!("{} is unusually large for {} (typically {})", t.amount, category, money(med)),
+                        dismissal_id: None,
+                    });
+                }
+            }
+
+            let earlier = expenses[..i]
+                .iter()
+                .rev()
+                .take_while(|e| t.created_at - e.created_at <= DUPLICATE_WINDOW)
+                .find(|e| e.account_id == t.account_id && e.amount == t.amount && merchant(e) == name);
+            if let Some(e) = earlier {
+                anomalies.push(Anomaly {
+                    key: format!("duplicate:{}:{}", t.id, e.id),
+                    kind: "duplicate",
+                    date,
+                    transaction_id: Some(t.id),
+                    related_transaction_id: Some(e.id),
+                    account_id: Some(t.account_id),
+                    category: t.category.clone(),
+                    amount: t.amount,
+                    expected: None,
+                    message: format!("possible duplicate of transaction {} ({} at {})", e.id, t.amount, name),
+                    dismissal_id: None,
+                });
+            }
+
+            if !merchants.contains(&name) && all.len() >= MIN_MERCHANT_HISTORY {
+                let med = median(&sorted(&all));
+                if amount >= med * NEW_MERCHANT_FACTOR {
+                    anomalies.push(Anomaly {
+                        key: format!("new_merchant:{}", t.id),
+                        kind: "new_merchant",
+                        date,
+                        transaction_id: Some(t.id),
+                        related_transaction_id: None,
+                        account_id: Some(t.account_id),
+                        category: t.category.clone(),
+                        amount: t.amount,
+                        expected: Some(money(med)),
+                        message: format!("first payment to {} is {}, well above the usual {}", name, t.amount, money(med)),
+                        dismissal_id: None,
+                    });
+                }
+            }
+        }
+        if let Some(category) = &t.category {
+            by_category.entry(category.clone()).or_default().push(amount);
+        }
+        all.push(amount);
+        merchants.insert(name);
+    }
+
+    // Month-to-date spending per category against the average of the preceding full months.
+    let month_start = to.with_day(1).expect("first of month");
+    let trailing_start = month_start - Months::new(SPIKE_TRAILING_MONTHS);
+    let mut current: BTreeMap<&str, Decimal> = BTreeMap::new();
+    let mut trailing: BTreeMap<&str, BTreeMap<(i32, u32), Decimal>> = BTreeMap::new();
+    for t in &expenses {
+        let (Some(category), date) = (t.category.as_deref(), local(t)) else { continue };
+        if date >= month_start {
+            *current.entry(category).or_default() += t.amount;
+        } else if date >= trailing_start {
+            *trailing.entry(category).or_default().entry((date.year(), date.month())).or_default() += t.amount;
+        }
+    }
+    for (category, spent) in current {
+        // At least two earlier months of spending, so one-off categories do not spike.
+        let Some(months) = trailing.get(category).filter(|m| m.len() >= 2) else { continue };
+        let average = months.values().copied().sum::<Decimal>() / Decimal::from(SPIKE_TRAILING_MONTHS);
+        if f64::try_from(spent).unwrap_or_default() > f64::try_from(average).unwrap_or_default() * SPIKE_FACTOR {
+            let average = average.round_dp(2).normalize();
+            anomalies.push(Anomaly {
+                key: format!("category_spike:{}:{}", category, month_start.format("%Y-%m")),
+                kind: "category_spike",
+                date: to,
+                transaction_id: None,
+                related_transaction_id: None,
+                account_id: None,
+                category: Some(category.to_string()),
+                amount: spent.normalize(),
+                expected: Some(average),
+                message: format!("{} spending this month is {}, against a monthly average of {}", category, spent.normalize(), average),
+                dismissal_id: None,
+            });
+        }
+    }
+
+    let dismissed: HashMap<String, i32> = anomaly_dismissal::Entity::find()
+        .filter(anomaly_dismissal::Column::UserId.eq(user_id))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|d| (d.anomaly_key, d.id))
+        .collect();
+    for a in anomalies.iter_mut() {
+        a.dismissal_id = dismissed.get(&a.key).copied();
+    }
+    anomalies.retain(|a| include_dismissed || a.dismissal_id.is_none());
+    anomalies.sort_by(|a, b| b.date.cmp(&a.date).then_with(|| a.key.cmp(&b.key)));
+    Ok(AnomalyReport { from, to, anomalies })
+}
+
+/// Hides an anomaly from future reports. Dismissing twice returns the existing dismissal.
+pub async fn dismiss_anomaly(db: &DatabaseConnection, user_id: i32, key: String) -> Result<anomaly_dismissal::Model, ServiceError> {
+    let key = key.trim().to_string();
+    if key.is_empty() {
+        return Err(ServiceError::Invalid("key is required".into()));
+    }
+    let existing = anomaly_dismissal::Entity::find()
+        .filter(anomaly_dismissal::Column::UserId.eq(user_id))
+        .filter(anomaly_dismissal::Column::AnomalyKey.eq(key.clone()))
+        .one(db)
+        .await?;
+    if let Some(d) = existing {
+        return Ok(d);
+    }
+    let active = anomaly_dismissal::ActiveModel {
+        user_id: Set(user_id),
+        anomaly_key: Set(key),
+        ..Default::default()
+    };
+    Ok(active.insert(db).await?)
+}
+
+pub async fn delete_anomaly_dismissal(db: &DatabaseConnection, id: i32) -> Result<u64, sea_orm::DbErr> {
+    let res = anomaly_dismissal::Entity::delete_by_id(id).exec(db).await?;
+    Ok(res.rows_affected)
+}
@@ -0,0 +1,177 @@
+use chrono::{Duration, Utc};
+use sea_orm::{ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, EntityTrait, PrimaryKeyTrait, QueryFilter, QueryOrder, QuerySelect, Set};
+use sea_orm::sea_query::Expr;
+use serde::Serialize;
+use crate::models::{account, anomaly_dismissal, api_token, asset, attachment, import_batch, import_profile, investment_plan, reconciliation, recurring_transaction, rule, transaction};
+use crate::services::{new_secret_token, secret_token_hash, ServiceError};
+
+/// Marks a bearer token as a personal access token rather than a JWT.
+pub const API_TOKEN_PREFIX: &str = "wlt_";
+/// A `:write` scope includes the matching `:read`.
+pub const API_SCOPES: &[&str] = &[
+    "accounts:read",
+    "accounts:write",
+    "transactions:read",
+    "transactions:write",
+    "assets:read",
+    "assets:write",
+    "reports:read",
+];
+pub const MAX_API_TOKEN_DAYS: i64 = 3650;
+/// `last_used_at` is written at most this often per token.
+const LAST_USED_RESOLUTION_SECONDS: i64 = 60;
+
+/// Returned once, at creation; afterwards only the prefix is known.
+#[derive(Serialize, Debug, Clone)]
+pub struct CreatedApiToken {
+    #[serde(flatten)]
+    pub info: api_token::Model,
+    pub token: String,
+}
+
+/// The caller of a request authenticated with a personal access token.
+#[derive(Debug, Clone)]
+pub struct ApiTokenAuth {
+    pub user_id: i32,
+    pub token_id: i32,
+    pub scopes: Vec<String>,
+}
+
+impl ApiTokenAuth {
+    pub fn allows(&self, scope: &str) -> bool {
+        let implied = scope.strip_suffix(":read").map(|resource| format!("{}:write", resource));
+        self.scopes.iter().any(|s| s == scope || Some(s) == implied.as_ref())
+    }
+}
+
+pub fn is_api_token(token: &str) -> bool {
+    token.starts_with(API_TOKEN_PREFIX)
+}
+
+pub async fn create_api_token(db: &DatabaseConnection, user_id: i32, name: &str, scopes: Vec<String>, expires_in_days: Option<i64>) -> Result<CreatedApiToken, ServiceError> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > 100 {
+        return Err(ServiceError::Invalid("name must be 1 to 100 characters".into()));
+    }
+    let mut scopes: Vec<String> = scopes.into_iter().map(|s| s.trim().to_string()).collect();
+    scopes.sort();
+    scopes.dedup();
+    if scopes.is_empty() {
+        return Err(ServiceError::Invalid("at least one scope is required".into()));
+    }
+    if let Some(unknown) = scopes.iter().find(|s| !API_SCOPES.contains(&s.as_str())) {
+        return Err(ServiceError::Invalid(format!("unknown scope {:?}; use {}", unknown, API_SCOPES.join(", "))));
+    }
+    if let Some(days) = expires_in_days {
+        if !(1..=MAX_API_TOKEN_DAYS).contains(&days) {
+            return Err(ServiceError::Invalid(format!("expires_in_days must be between 1 and {}", MAX_API_TOKEN_DAYS)));
+        }
+    }
+    let token = format!("{}{}", API_TOKEN_PREFIX, new_secret_token());
+    let now = Utc::now();
+    let active = api_token::ActiveModel {
+        user_id: Set(user_id),
+        name: Set(name.to_string()),
+        token_prefix: Set(token[..API_TOKEN_PREFIX.len() + 6].to_string()),
+        token_hash: Set(secret_token_hash(&token)),
+        scopes: Set(serde_json::json!(scopes)),
+        expires_at: Set(expires_in_days.map(|days| now + Duration::days(days))),
+        created_at: Set(now),
+        ..Default::default()
+    };
+    let info = active.insert(db).await?;
+    Ok(CreatedApiToken { info, token })
+}
+
+pub async fn list_api_tokens(db: &DatabaseConnection, user_id: i32) -> Result<Vec<api_token::Model>, sea_orm::DbErr> {
+    api_token::Entity::find()
+        .filter(api_token::Column::UserId.eq(user_id))
+        .order_by_asc(api_token::Column::Id)
+        .all(db)
+        .await
+}
+
+pub async fn delete_api_token(db: &DatabaseConnection, user_id: i32, id: i32) -> Result<u64, sea_orm::DbErr> {
+    let res = api_token::Entity::delete_many()
+        .filter(api_token::Column::Id.eq(id))
+        .filter(api_token::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+    Ok(res.rows_affected)
+}
+
+/// Looks up a presented token; `None` when it is unknown or expired. Records the use.
+pub async fn authenticate_api_token(db: &DatabaseConnection, token: &str) -> Result<Option<ApiTokenAuth>, sea_orm::DbErr> {
+    let row = api_token::Entity::find()
+        .filter(api_token::Column::TokenHash.eq(secret_token_hash(token)))
+        .one(db)
+        .await?;
+    let Some(row) = row else { return Ok(None) };
+    let now = Utc::now();
+    if row.expires_at.is_some_and(|at| at <= now) {
+        return Ok(None);
+    }
+    // Scripts may call many times a second; a write per request is not worth it.
+    api_token::Entity::update_many()
+        .col_expr(api_token::Column::LastUsedAt, Expr::value(now))
+        .filter(api_token::Column::Id.eq(row.id))
+        .filter(
+            Condition::any()
+                .add(api_token::Column::LastUsedAt.is_null())
+                .add(api_token::Column::LastUsedAt.lt(now - Duration::seconds(LAST_USED_RESOLUTION_SECONDS))),
+        )
+        .exec(db)
+        .await?;
+    let scopes = serde_json::from_value(row.scopes).unwrap_or_default();
+    Ok(Some(ApiTokenAuth { user_id: row.user_id, token_id: row.id, scopes }))
+}
+
+/// Something a token request names by id; it must belong to the token's user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenTarget {
+    User(i32),
+    Account(i32),
+    Reconciliation(i32),
+    Transaction(i32),
+    Attachment(i32),
+    Rule(i32),
+    Recurring(i32),
+    InvestmentPlan(i32),
+    Asset(i32),
+    ImportProfile(i32),
+    ImportBatch(i32),
+    AnomalyDismissal(i32),
+}
+
+async fn column_of<E>(db: &DatabaseConnection, id: i32, column: E::Column) -> Result<Option<i32>, sea_orm::DbErr>
+where
+    E: EntityTrait,
+    i32: Into<<E::PrimaryKey as PrimaryKeyTrait>::ValueType>,
+{
+    E::find_by_id(id).select_only().column(column).into_tuple().one(db).await
+}
+
+/// The user `target` belongs to, or `None` if it does not exist (the handler reports that).
+pub async fn token_target_owner(db: &DatabaseConnection, target: TokenTarget) -> Result<Option<i32>, sea_orm::DbErr> {
+    let account_id = match target {
+        TokenTarget::User(id) => return Ok(Some(id)),
+        TokenTarget::Account(id) => Some(id),
+        TokenTarget::Reconciliation(id) => column_of::<reconciliation::Entity>(db, id, reconciliation::Column::AccountId).await?,
+        TokenTarget::Transaction(id) => column_of::<transaction::Entity>(db, id, transaction::Column::AccountId).await?,
+        TokenTarget::Attachment(id) => match column_of::<attachment::Entity>(db, id, attachment::Column::TransactionId).await? {
+            Some(transaction_id) => column_of::<transaction::Entity>(db, transaction_id, transaction::Column::AccountId).await?,
+            None => None,
+        },
+        TokenTarget::Rule(id) => return column_of::<rule::Entity>(db, id, rule::Column::UserId).await,
+        TokenTarget::Recurring(id) => return column_of::<recurring_transaction::Entity>(db, id, recurring_transaction::Column::UserId).await,
+        TokenTarget::InvestmentPlan(id) => return column_of::<investment_plan::Entity>(db, id, investment_plan::Column::UserId).await,
+        TokenTarget::Asset(id) => return column_of::<asset::Entity>(db, id, asset::Column::UserId).await,
+        TokenTarget::ImportProfile(id) => return column_of::<import_profile::Entity>(db, id, import_profile::Column::UserId).await,
+        TokenTarget::ImportBatch(id) => return column_of::<import_batch::Entity>(db, id, import_batch::Column::UserId).await,
+        TokenTarget::AnomalyDismissal(id) => return column_of::<anomaly_dismissal::Entity>(db, id, anomaly_dismissal::Column::UserId).await,
+    };
+    match account_id {
+        Some(account_id) => column_of::<account::Entity>(db, account_id, account::Column::UserId).await,
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,335 @@
+use chrono::{DateTime, Utc};
+use sea_orm::{ActiveModelTrait, ActiveValue::NotSet, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, PaginatorTrait, QueryFilter, QueryOrder, Set, TransactionTrait};
+use serde::{Deserialize, Serialize};
+use crate::models::{account, anomaly_dismissal, asset, attachment, import_batch, import_holding_change, import_profile, investment_plan, reconciliation, recurring_transaction, rule, transaction, user};
+use crate::models::asset::asset_price;
+use crate::services::{audit_create, audit_update, find_asset_prices_by_symbols, find_investment_plans_by_user, find_recurring_transactions_by_user, find_rules_by_user, upsert_asset_price, AuditContext, ServiceError};
+use std::collections::HashMap;
+
+pub const ARCHIVE_FORMAT: &str = "your-wallet-archive";
+/// Bump when the archive layout changes; `import_archive` accepts this version and older.
+pub const ARCHIVE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ArchiveUser {
+    pub username: String,
+    pub email: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Everything a user owns. New user-owned tables must be added here (with `#[serde(default)]`
+/// so older archives still load) and to both `export_archive` and `import_archive`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Archive {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub user: ArchiveUser,
+    #[serde(default)]
+    pub accounts: Vec<account::Model>,
+    #[serde(default)]
+    pub reconciliations: Vec<reconciliation::Model>,
+    #[serde(default)]
+    pub import_profiles: Vec<import_profile::Model>,
+    #[serde(default)]
+    pub import_batches: Vec<import_batch::Model>,
+    #[serde(default)]
+    pub import_holding_changes: Vec<import_holding_change::Model>,
+    #[serde(default)]
+    pub transactions: Vec<transaction::Model>,
+    #[serde(default)]
+    pub assets: Vec<asset::Model>,
+    #[serde(default)]
+    pub asset_prices: Vec<asset_price::Model>,
+    #[serde(default)]
+    pub recurring_transactions: Vec<recurring_transaction::Model>,
+    #[serde(default)]
+    pub investment_plans: Vec<investment_plan::Model>,
+    #[serde(default)]
+    pub anomaly_dismissals: Vec<anomaly_dismissal::Model>,
+    #[serde(default)]
+    pub rules: Vec<rule::Model>,
+    /// Metadata only: the files stay in this server's attachment storage.
+    #[serde(default)]
+    pub attachments: Vec<attachment::Model>,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct ArchiveImportSummary {
+    pub accounts: usize,
+    pub reconciliations: usize,
+    pub import_profiles: usize,
+    pub import_batches: usize,
+    pub import_holding_changes: usize,
+    pub transactions: usize,
+    pub assets: usize,
+    pub asset_prices: usize,
+    pub recurring_transactions: usize,
+    pub investment_plans: usize,
+    pub anomaly_dismissals: usize,
+    pub rules: usize,
+    pub attachments: usize,
+}
+
+pub async fn export_archive(db: &DatabaseConnection, user: &user::Model) -> Result<Archive, sea_orm::DbErr> {
+    let accounts = account::Entity::find()
+        .filter(account::Column::UserId.eq(user.id))
+        .order_by_asc(account::Column::Id)
+        .all(db)
+        .await?;
+    let account_ids: Vec<i32> = accounts.iter().map(|a| a.id).collect();
+    let reconciliations = reconciliation::Entity::find()
+        .filter(reconciliation::Column::AccountId.is_in(account_ids.clone()))
+        .order_by_asc(reconciliation::Column::Id)
+        .all(db)
+        .await?;
+    let import_profiles = import_profile::Entity::find()
+        .filter(import_profile::Column::UserId.eq(user.id))
+        .order_by_asc(import_profile::Column::Id)
+        .all(db)
+        .await?;
+    let import_batches = import_batch::Entity::find()
+        .filter(import_batch::Column::UserId.eq(user.id))
+        .order_by_asc(import_batch::Column::Id)
+        .all(db)
+        .await?;
+    let import_holding_changes = import_holding_change::Entity::find()
+        .filter(import_holding_change::Column::BatchId.is_in(import_batches.iter().map(|b| b.id).collect::<Vec<_>>()))
+        .order_by_asc(import_holding_change::Column::Id)
+        .all(db)
+        .await?;
+    let transactions = transaction::Entity::find()
+        .filter(transaction::Column::AccountId.is_in(account_ids))
+        .order_by_asc(transaction::Column::Id)
+        .all(db)
+        .await?;
+    let attachments = attachment::Entity::find()
+        .filter(attachment::Column::TransactionId.is_in(transactions.iter().map(|t| t.id).collect::<Vec<_>>()))
+        .order_by_asc(attachment::Column::Id)
+        .all(db)
+        .await?;
+    let assets = asset::Entity::find()
+        .filter(asset::Column::UserId.eq(user.id))
+        .order_by_asc(asset::Column::Id)
+        .all(db)
+        .await?;
+    let asset_prices = find_asset_prices_by_symbols(db, assets.iter().map(|a| a.symbol.clone()).collect()).await?;
+    let recurring_transactions = find_recurring_transactions_by_user(db, user.id).await?;
+    let investment_plans = find_investment_plans_by_user(db, user.id).await?;
+    let anomaly_dismissals = anomaly_dismissal::Entity::find()
+        .filter(anomaly_dismissal::Column::UserId.eq(user.id))
+        .order_by_asc(anomaly_dismissal::Column::Id)
+        .all(db)
+        .await?;
+    let rules = find_rules_by_user(db, user.id).await?;
+    Ok(Archive {
+        format: ARCHIVE_FORMAT.to_string(),
+        version: ARCHIVE_VERSION,
+        exported_at: Utc::now(),
+        user: ArchiveUser { username: user.username.clone(), email: user.email.clone(), created_at: user.created_at },
+        accounts,
+        reconciliations,
+        import_profiles,
+        import_batches,
+        import_holding_changes,
+        transactions,
+        assets,
+        asset_prices,
+        recurring_transactions,
+        investment_plans,
+        anomaly_dismissals,
+        rules,
+        attachments,
+    })
+}
+
+fn remap(map: &HashMap<i32, i32>, old: i32, what: &str) -> Result<i32, ServiceError> {
+    map.get(&old).copied().ok_or_else(|| ServiceError::Invalid(format!("archive references unknown {} {}", what, old)))
+}
+
+fn remap_opt(map: &HashMap<i32, i32>, old: Option<i32>, what: &str) -> Result<Option<i32>, ServiceError> {
+    old.map(|id| remap(map, id, what)).transpose()
+}
+
+/// Restores an archive into `user_id`, which must not own any data yet. Every row gets a new
+/// id and references are rewritten; nothing is written unless the whole archive applies.
+/// The login identity (username, email, password) of the target user is left unchanged.
+pub async fn import_archive(db: &DatabaseConnection, audit: &AuditContext, user_id: i32, archive: Archive) -> Result<ArchiveImportSummary, ServiceError> {
+    if archive.format != ARCHIVE_FORMAT {
+        return Err(ServiceError::Invalid(format!("not a wallet archive: {:?}", archive.format)));
+    }
+    if archive.version == 0 || archive.version > ARCHIVE_VERSION {
+        return Err(ServiceError::Invalid(format!(
+            "unsupported archive version {} (this server reads up to {})",
+            archive.version, ARCHIVE_VERSION
+        )));
+    }
+
+    let txn = db.begin().await?;
+    let owned = account::Entity::find().filter(account::Column::UserId.eq(user_id)).count(&txn).await?
+        + asset::Entity::find().filter(asset::Column::UserId.eq(user_id)).count(&txn).await?
+        + import_profile::Entity::find().filter(import_profile::Column::UserId.eq(user_id)).count(&txn).await?;
+    if owned > 0 {
+        return Err(ServiceError::Conflict("archives can only be restored into an empty account".into()));
+    }
+
+    let mut summary = ArchiveImportSummary::default();
+    // Card payment accounts may come later in the archive, so they are linked after all inserts.
+    let mut accounts = HashMap::new();
+    let mut payers = Vec::new();
+    for a in archive.accounts {
+        let (old, payer) = (a.id, a.payment_account_id);
+        let mut active = a.into_active_model().reset_all();
+        active.id = NotSet;
+        active.user_id = Set(user_id);
+        active.payment_account_id = Set(None);
+        let new = active.insert(&txn).await?;
+        audit_create(&txn, audit, user_id, "account", new.id, &new).await?;
+        accounts.insert(old, new.id);
+        if let Some(payer) = payer {
+            payers.push((new, payer));
+        }
+        summary.accounts += 1;
+    }
+    for (model, payer) in payers {
+        let payer = remap(&accounts, payer, "account")?;
+        let mut active: account::ActiveModel = model.clone().into();
+        active.payment_account_id = Set(Some(payer));
+        let updated = active.update(&txn).await?;
+        audit_update(&txn, audit, user_id, "account", updated.id, &model, &updated).await?;
+    }
+
+    let mut reconciliations = HashMap::new();
+    for r in archive.reconciliations {
+        let (old, account_id) = (r.id, remap(&accounts, r.account_id, "account")?);
+        let mut active = r.into_active_model().reset_all();
+        active.id = NotSet;
+        active.account_id = Set(account_id);
+        reconciliations.insert(old, active.insert(&txn).await?.id);
+        summary.reconciliations += 1;
+    }
+
+    for p in archive.import_profiles {
+        let mut active = p.into_active_model().reset_all();
+        active.id = NotSet;
+        active.user_id = Set(user_id);
+        active.insert(&txn).await?;
+        summary.import_profiles += 1;
+    }
+
+    let mut batches = HashMap::new();
+    for b in archive.import_batches {
+        let (old, account_id) = (b.id, remap(&accounts, b.account_id, "account")?);
+        let mut active = b.into_active_model().reset_all();
+        active.id = NotSet;
+        active.user_id = Set(user_id);
+        active.account_id = Set(account_id);
+        batches.insert(old, active.insert(&txn).await?.id);
+        summary.import_batches += 1;
+    }
+
+    // Refunds may point at rows later in the archive, so links are patched after all inserts.
+    let mut transactions = HashMap::new();
+    let mut refunds = Vec::new();
+    for t in archive.transactions {
+        let old = t.id;
+        let account_id = remap(&accounts, t.account_id, "account")?;
+        let reconciliation_id = remap_opt(&reconciliations, t.reconciliation_id, "reconciliation")?;
+        let import_batch_id = remap_opt(&batches, t.import_batch_id, "import batch")?;
+        let refund_of = t.refund_of_id;
+        let mut active = t.into_active_model().reset_all();
+        active.id = NotSet;
+        active.account_id = Set(account_id);
+        active.reconciliation_id = Set(reconciliation_id);
+        active.import_batch_id = Set(import_batch_id);
+        active.refund_of_id = Set(None);
+        let new = active.insert(&txn).await?;
+        audit_create(&txn, audit, user_id, "transaction", new.id, &new).await?;
+        transactions.insert(old, new.id);
+        if let Some(original) = refund_of {
+            refunds.push((new, original));
+        }
+        summary.transactions += 1;
+    }
+    for (model, original) in refunds {
+        let original = remap(&transactions, original, "transaction")?;
+        let mut active: transaction::ActiveModel = model.clone().into();
+        active.refund_of_id = Set(Some(original));
+        let updated = active.update(&txn).await?;
+        audit_update(&txn, audit, user_id, "transaction", updated.id, &model, &updated).await?;
+    }
+
+    let mut assets = HashMap::new();
+    for a in archive.assets {
+        let old = a.id;
+        let mut active = a.into_active_model().reset_all();
+        active.id = NotSet;
+        active.user_id = Set(user_id);
+        let new = active.insert(&txn).await?;
+        audit_create(&txn, audit, user_id, "asset", new.id, &new).await?;
+        assets.insert(old, new.id);
+        summary.assets += 1;
+    }
+    for c in archive.import_holding_changes {
+        let batch_id = remap(&batches, c.batch_id, "import batch")?;
+        // The asset may have been deleted since; then there is nothing to undo.
+        let Some(asset_id) = assets.get(&c.asset_id).copied() else { continue };
+        let mut active = c.into_active_model().reset_all();
+        active.id = NotSet;
+        active.batch_id = Set(batch_id);
+        active.asset_id = Set(asset_id);
+        active.insert(&txn).await?;
+        summary.import_holding_changes += 1;
+    }
+    for p in archive.asset_prices {
+        upsert_asset_price(&txn, p.symbol, p.price, p.currency, p.updated_at).await?;
+        summary.asset_prices += 1;
+    }
+    for r in archive.recurring_transactions {
+        let account_id = remap(&accounts, r.account_id, "account")?;
+        let mut active = r.into_active_model().reset_all();
+        active.id = NotSet;
+        active.user_id = Set(user_id);
+        active.account_id = Set(account_id);
+        active.insert(&txn).await?;
+        summary.recurring_transactions += 1;
+    }
+    for p in archive.investment_plans {
+        let account_id = remap(&accounts, p.account_id, "account")?;
+        let mut active = p.into_active_model().reset_all();
+        active.id = NotSet;
+        active.user_id = Set(user_id);
+        active.account_id = Set(account_id);
+        active.insert(&txn).await?;
+        summary.investment_plans += 1;
+    }
+    // Keys name transactions by id, so dismissals of per-transaction anomalies reappear once;
+    // category spikes keep theirs.
+    for d in archive.anomaly_dismissals {
+        let mut active = d.into_active_model().reset_all();
+        active.id = NotSet;
+        active.user_id = Set(user_id);
+        active.insert(&txn).await?;
+        summary.anomaly_dismissals += 1;
+    }
+    for r in archive.rules {
+        let account_id = remap_opt(&accounts, r.account_id, "account")?;
+        let mut active = r.into_active_model().reset_all();
+        active.id = NotSet;
+        active.user_id = Set(user_id);
+        active.account_id = Set(account_id);
+        active.insert(&txn).await?;
+        summary.rules += 1;
+    }
+    for a in archive.attachments {
+        let transaction_id = remap(&transactions, a.transaction_id, "transaction")?;
+        let mut active = a.into_active_model().reset_all();
+        active.id = NotSet;
+        active.transaction_id = Set(transaction_id);
+        active.insert(&txn).await?;
+        summary.attachments += 1;
+    }
+
+    txn.commit().await?;
+    Ok(summary)
+}
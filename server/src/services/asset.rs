@@ -1,9 +1,13 @@
-use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
+use sea_orm::{ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, QueryFilter, Set, TransactionTrait};
 use crate::models::asset;
+use crate::services::{audit_create, audit_delete, audit_update, AuditContext};
+use crate::models::asset::asset_price;
 use sea_orm::prelude::Decimal;
 
+#[allow(clippy::too_many_arguments)]
 pub async fn create_asset(
     db: &DatabaseConnection,
+    audit: &AuditContext,
     user_id: i32,
     symbol: String,
     name: String,
@@ -20,7 +24,11 @@ pub async fn create_asset(
         asset_type: Set(asset_type),
         ..Default::default()
     };
-    active.insert(db).await
+    let txn = db.begin().await?;
+    let model = active.insert(&txn).await?;
+    audit_create(&txn, audit, model.user_id, "asset", model.id, &model).await?;
+    txn.commit().await?;
+    Ok(model)
 }
 
 pub async fn get_asset_by_id(
@@ -42,19 +50,23 @@ pub async fn find_assets_by_user(
 
 pub async fn update_asset(
     db: &DatabaseConnection,
+    audit: &AuditContext,
     id: i32,
     name: Option<String>,
     quantity: Option<Decimal>,
     avg_price: Option<Decimal>,
     asset_type: Option<String>,
 ) -> Result<Option<asset::Model>, sea_orm::DbErr> {
-    if let Some(model) = asset::Entity::find_by_id(id).one(db).await? {
-        let mut active: asset::ActiveModel = model.into();
+    let txn = db.begin().await?;
+    if let Some(model) = asset::Entity::find_by_id(id).one(&txn).await? {
+        let mut active: asset::ActiveModel = model.clone().into();
         if let Some(v) = name { active.name = Set(v); }
         if let Some(v) = quantity { active.quantity = Set(v); }
         if let Some(v) = avg_price { active.avg_price = Set(v); }
         if let Some(v) = asset_type { active.asset_type = Set(v); }
-        let updated = active.update(db).await?;
+        let updated = active.update(&txn).await?;
+        audit_update(&txn, audit, updated.user_id, "asset", id, &model, &updated).await?;
+        txn.commit().await?;
         Ok(Some(updated))
     } else {
         Ok(None)
@@ -63,8 +75,58 @@ pub async fn update_asset(
 
 pub async fn delete_asset(
     db: &DatabaseConnection,
+    audit: &AuditContext,
     id: i32,
 ) -> Result<u64, sea_orm::DbErr> {
-    let res = asset::Entity::delete_by_id(id).exec(db).await?;
+    let txn = db.begin().await?;
+    let Some(model) = asset::Entity::find_by_id(id).one(&txn).await? else { return Ok(0) };
+    let res = asset::Entity::delete_by_id(id).exec(&txn).await?;
+    audit_delete(&txn, audit, model.user_id, "asset", id, &model).await?;
+    txn.commit().await?;
     Ok(res.rows_affected)
 }
+
+pub async fn find_asset_prices_by_symbols(
+    db: &DatabaseConnection,
+    symbols: Vec<String>,
+) -> Result<Vec<asset_price::Model>, sea_orm::DbErr> {
+    asset_price::Entity::find()
+        .filter(asset_price::Column::Symbol.is_in(symbols))
+        .all(db)
+        .await
+}
+
+/// `asset_prices` keeps only the latest quote per symbol; an older quote never replaces a newer one.
+pub async fn upsert_asset_price<C: ConnectionTrait>(
+    db: &C,
+    symbol: String,
+    price: Decimal,
+    currency: String,
+    updated_at: chrono::DateTime<chrono::Utc>,
+) -> Result<asset_price::Model, sea_orm::DbErr> {
+    match asset_price::Entity::find()
+        .filter(asset_price::Column::Symbol.eq(symbol.as_str()))
+        .one(db)
+        .await?
+    {
+        Some(model) if model.updated_at > updated_at => Ok(model),
+        Some(model) => {
+            let mut active: asset_price::ActiveModel = model.into();
+            active.price = Set(price);
+            active.currency = Set(currency);
+            active.updated_at = Set(updated_at);
+            active.update(db).await
+        }
+        None => {
+            asset_price::ActiveModel {
+                symbol: Set(symbol),
+                price: Set(price),
+                currency: Set(currency),
+                updated_at: Set(updated_at),
+                ..Default::default()
+            }
+            .insert(db)
+            .await
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use sea_orm::{ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, EntityTrait, JoinType, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, RelationTrait, Set};
+use sha2::{Digest, Sha256};
+use crate::models::{account, attachment, transaction};
+use crate::services::{account_owner, open_blob, seal_blob, AttachmentStorage, ServiceError};
+use std::collections::BTreeSet;
+use std::io::Cursor;
+
+pub const MAX_ATTACHMENT_BYTES: usize = 10 * 1024 * 1024;
+/// Thumbnails fit in a square of this many pixels and are always PNG.
+pub const THUMBNAIL_SIZE: u32 = 320;
+/// Larger images are stored but not decoded for a thumbnail.
+const MAX_IMAGE_DIMENSION: u32 = 12_000;
+
+#[derive(Debug)]
+pub enum AttachmentError {
+    Service(ServiceError),
+    /// Over `MAX_ATTACHMENT_BYTES`.
+    TooLarge,
+    /// Not one of the accepted formats; carries what the client claimed it was.
+    UnsupportedType(String),
+    Storage(std::io::Error),
+}
+
+impl std::fmt::Display for AttachmentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttachmentError::Service(e) => write!(f, "{}", e),
+            AttachmentError::TooLarge => write!(f, "attachments are limited to {} bytes", MAX_ATTACHMENT_BYTES),
+            AttachmentError::UnsupportedType(t) => write!(f, "unsupported file type {}; use JPEG, PNG, WebP or PDF", t),
+            AttachmentError::Storage(e) => write!(f, "attachment storage: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AttachmentError {}
+
+impl From<ServiceError> for AttachmentError {
+    fn from(e: ServiceError) -> Self {
+        AttachmentError::Service(e)
+    }
+}
+
+impl From<sea_orm::DbErr> for AttachmentError {
+    fn from(e: sea_orm::DbErr) -> Self {
+        AttachmentError::Service(ServiceError::Db(e))
+    }
+}
+
+impl From<std::io::Error> for AttachmentError {
+    fn from(e: std::io::Error) -> Self {
+        AttachmentError::Storage(e)
+    }
+}
+
+/// Content type from the file's leading bytes; the name and the client's claim are not trusted.
+pub fn sniff_content_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else if bytes.starts_with(b"%PDF-") {
+        Some("application/pdf")
+    } else {
+        None
+    }
+}
+
+/// Where a user's copy of a file is stored. Identical files are shared within a user only, so
+/// every blob can be sealed with its owner's data key.
+pub fn blob_key(user_id: i32, sha256: &str) -> String {
+    format!("{}.u{}", sha256, user_id)
+}
+
+pub fn thumbnail_key(blob_key: &str) -> String {
+    format!("{}.thumb", blob_key)
+}
+
+/// Reads `key`, falling back to `legacy`: blobs stored before they were kept per user sit under
+/// the bare hash until `rotate_encryption_keys` moves them.
+pub fn get_blob(storage: &dyn AttachmentStorage, key: &str, legacy: &str) -> std::io::Result<Vec<u8>> {
+    match storage.get(key) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => storage.get(legacy),
+        found => found,
+    }
+}
+
+fn make_thumbnail(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut reader = image::ImageReader::new(Cursor::new(bytes)).with_guessed_format().ok()?;
+    let mut limits = image::Limits::default();
+    limits.max_image_width = Some(MAX_IMAGE_DIMENSION);
+    limits.max_image_height = Some(MAX_IMAGE_DIMENSION);
+    reader.limits(limits);
+    let thumbnail = reader.decode().ok()?.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+    let mut out = Cursor::new(Vec::new());
+    thumbnail.write_to(&mut out, image::ImageFormat::Png).ok()?;
+    Some(out.into_inner())
+}
+
+/// Last path component, without control characters, at most 255 characters.
+fn clean_file_name(name: Option<&str>) -> String {
+    let base = name.unwrap_or("").rsplit(['/', '\\']).next().unwrap_or("");
+    let cleaned: String = base.chars().filter(|c| !c.is_control()).take(255).collect();
+    match cleaned.trim() {
+        "" => "attachment".to_string(),
+        s => s.to_string(),
+    }
+}
+
+/// Stores `bytes` against a transaction. Returns `None` when the transaction does not exist,
+/// otherwise the attachment and whether it is new: uploading the same file to the same
+/// transaction again returns the existing attachment. A user's identical files share one blob,
+/// sealed with the user's data key when encryption is on.
+pub async fn add_attachment(
+    db: &DatabaseConnection,
+    storage: &dyn AttachmentStorage,
+    transaction_id: i32,
+    file_name: Option<&str>,
+    declared_type: Option<&str>,
+    bytes: Vec<u8>,
+) -> Result<Option<(attachment::Model, bool)>, AttachmentError> {
+    let Some(owner_transaction) = transaction::Entity::find_by_id(transaction_id).one(db).await? else {
+        return Ok(None);
+    };
+    let user_id = account_owner(db, owner_transaction.account_id).await?;
+    if bytes.len() > MAX_ATTACHMENT_BYTES {
+        return Err(AttachmentError::TooLarge);
+    }
+    if bytes.is_empty() {
+        return Err(ServiceError::Invalid("file is empty".into()).into());
+    }
+    let Some(content_type) = sniff_content_type(&bytes) else {
+        return Err(AttachmentError::UnsupportedType(declared_type.unwrap_or("unknown").to_string()));
+    };
+    let size = bytes.len() as i64;
+    // Hashing and decoding a large photo would stall the runtime, so both run off it.
+    let (bytes, sha256, thumbnail) = tokio::task::spawn_blocking(move || {
+        let sha256: String = Sha256::digest(&bytes).iter().map(|b| format!("{:02x}", b)).collect();
+        let thumbnail = if content_type.starts_with("image/") { make_thumbnail(&bytes) } else { None };
+        (bytes, sha256, thumbnail)
+    })
+    .await
+    .map_err(|e| AttachmentError::Storage(std::io::Error::other(e)))?;
+
+    let existing = attachment::Entity::find()
+        .filter(attachment::Column::TransactionId.eq(transaction_id))
+        .filter(attachment::Column::Sha256.eq(sha256.clone()))
+        .one(db)
+        .await?;
+    if let Some(existing) = existing {
+        return Ok(Some((existing, false)));
+    }
+    let key = blob_key(user_id, &sha256);
+    if !storage.exists(&key)? {
+        storage.put(&key, &seal_blob(db, user_id, &bytes).await?)?;
+    }
+    let has_thumbnail = match thumbnail {
+        Some(png) => {
+            let key = thumbnail_key(&key);
+            if !storage.exists(&key)? {
+                storage.put(&key, &seal_blob(db, user_id, &png).await?)?;
+            }
+            true
+        }
+        None => false,
+    };
+    let active = attachment::ActiveModel {
+        transaction_id: Set(transaction_id),
+        file_name: Set(clean_file_name(file_name).into()),
+        content_type: Set(content_type.to_string()),
+        size: Set(size),
+        sha256: Set(sha256),
+        has_thumbnail: Set(has_thumbnail),
+        ..Default::default()
+    };
+    Ok(Some((active.insert(db).await?, true)))
+}
+
+pub async fn find_attachments_by_transaction(db: &DatabaseConnection, transaction_id: i32) -> Result<Vec<attachment::Model>, sea_orm::DbErr> {
+    attachment::Entity::find()
+        .filter(attachment::Column::TransactionId.eq(transaction_id))
+        .order_by_asc(attachment::Column::Id)
+        .all(db)
+        .await
+}
+
+pub async fn get_attachment_by_id(db: &DatabaseConnection, id: i32) -> Result<Option<attachment::Model>, sea_orm::DbErr> {
+    attachment::Entity::find_by_id(id).one(db).await
+}
+
+/// The owner of the attachment's transaction, whose copy of the blob `read_attachment` reads.
+pub async fn attachment_owner(db: &DatabaseConnection, model: &attachment::Model) -> Result<i32, sea_orm::DbErr> {
+    crate::services::transaction_owner(db, None, Some(model.transaction_id)).await
+}
+
+pub fn read_attachment(storage: &dyn AttachmentStorage, user_id: i32, model: &attachment::Model) -> std::io::Result<Vec<u8>> {
+    open_blob(get_blob(storage, &blob_key(user_id, &model.sha256), &model.sha256)?).map_err(std::io::Error::other)
+}
+
+pub fn read_attachment_thumbnail(storage: &dyn AttachmentStorage, user_id: i32, model: &attachment::Model) -> std::io::Result<Vec<u8>> {
+    let key = thumbnail_key(&blob_key(user_id, &model.sha256));
+    open_blob(get_blob(storage, &key, &thumbnail_key(&model.sha256))?).map_err(std::io::Error::other)
+}
+
+pub async fn delete_attachment(db: &DatabaseConnection, storage: &dyn AttachmentStorage, id: i32) -> Result<u64, sea_orm::DbErr> {
+    let Some(model) = get_attachment_by_id(db, id).await? else { return Ok(0) };
+    let user_id = attachment_owner(db, &model).await?;
+    let res = attachment::Entity::delete_by_id(id).exec(db).await?;
+    release_attachment_blobs(db, storage, vec![(user_id, model.sha256)]).await?;
+    Ok(res.rows_affected)
+}
+
+fn attachments_with_owner() -> sea_orm::Select<attachment::Entity> {
+    attachment::Entity::find().inner_join(transaction::Entity).join(JoinType::InnerJoin, transaction::Relation::Account.def())
+}
+
+/// Owner and blob hash of the attachments on transactions matching `condition` (a condition
+/// on transaction columns). Collect them before deleting transactions, then pass them to
+/// `release_attachment_blobs` once the rows are gone.
+pub async fn attachment_hashes(db: &DatabaseConnection, condition: Condition) -> Result<Vec<(i32, String)>, sea_orm::DbErr> {
+    attachments_with_owner()
+        .select_only()
+        .column(account::Column::UserId)
+        .column(attachment::Column::Sha256)
+        .distinct()
+        .filter(condition)
+        .into_tuple()
+        .all(db)
+        .await
+}
+
+/// Removes the blobs (and thumbnails) among `hashes` that their owner no longer refers to, and
+/// any shared blob from before blobs were kept per user once nobody does. A blob that cannot
+/// be removed is only reported: the rows are already gone and a stray file does no harm.
+pub async fn release_attachment_blobs(db: &DatabaseConnection, storage: &dyn AttachmentStorage, hashes: Vec<(i32, String)>) -> Result<(), sea_orm::DbErr> {
+    for (user_id, sha256) in hashes.into_iter().collect::<BTreeSet<_>>() {
+        let owned = attachments_with_owner()
+            .filter(attachment::Column::Sha256.eq(sha256.clone()))
+            .filter(account::Column::UserId.eq(user_id))
+            .count(db)
+            .await?;
+        if owned > 0 {
+            continue;
+        }
+        let mut keys = vec![blob_key(user_id, &sha256)];
+        if attachment::Entity::find().filter(attachment::Column::Sha256.eq(sha256.clone())).count(db).await? == 0 {
+            keys.push(sha256);
+        }
+        for key in keys.iter().flat_map(|k| [thumbnail_key(k), k.clone()]) {
+            if let Err(e) = storage.delete(&key) {
+                eprintln!("could not remove attachment blob {}: {}", key, e);
+            }
+        }
+    }
+    Ok(())
+}
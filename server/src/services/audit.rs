@@ -0,0 +1,154 @@
+use chrono::{DateTime, Utc};
+use sea_orm::{ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, QuerySelect, Set};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use crate::models::{account, audit_log};
+use crate::services::{open_text, seal_text, EncryptionError};
+
+pub const AUDIT_CREATE: &str = "create";
+pub const AUDIT_UPDATE: &str = "update";
+pub const AUDIT_DELETE: &str = "delete";
+pub const AUDIT_ENTITY_TYPES: &[&str] = &["user", "user_identity", "account", "transaction", "asset"];
+pub const MAX_AUDIT_PAGE: u64 = 500;
+/// Logged as changed, never with their values.
+const REDACTED_FIELDS: &[&str] = &["password_hash"];
+const REDACTED: &str = "[redacted]";
+/// Sealed columns, sealed here too so the log does not keep a plaintext copy.
+const SEALED_FIELDS: &[(&str, &str)] = &[("account", "name"), ("transaction", "description"), ("transaction", "counterparty")];
+
+/// Who is making a change, for the audit log. Routes build it from the request; everything is
+/// optional since auth may be off and in-process callers have no request.
+#[derive(Debug, Clone, Default)]
+pub struct AuditContext {
+    pub actor_user_id: Option<i32>,
+    pub request_id: Option<String>,
+    pub ip: Option<String>,
+}
+
+fn fields<T: Serialize>(model: &T) -> Map<String, Value> {
+    match serde_json::to_value(model) {
+        Ok(Value::Object(map)) => map,
+        _ => Map::new(),
+    }
+}
+
+fn redact(mut map: Map<String, Value>) -> Value {
+    for (key, value) in map.iter_mut() {
+        if REDACTED_FIELDS.contains(&key.as_str()) {
+            *value = Value::String(REDACTED.into());
+        }
+    }
+    Value::Object(map)
+}
+
+fn sealed_fields(entity_type: &str) -> impl Iterator<Item = &'static str> + '_ {
+    SEALED_FIELDS.iter().filter(move |(entity, _)| *entity == entity_type).map(|(_, field)| *field)
+}
+
+async fn seal_fields<C: ConnectionTrait>(db: &C, user_id: i32, entity_type: &str, mut value: Option<Value>) -> Result<Option<Value>, sea_orm::DbErr> {
+    if let Some(Value::Object(map)) = &mut value {
+        for field in sealed_fields(entity_type) {
+            if let Some(Value::String(text)) = map.get_mut(field) {
+                *text = seal_text(db, user_id, text).await?;
+            }
+        }
+    }
+    Ok(value)
+}
+
+fn open_fields(entity_type: &str, value: &mut Option<Value>) -> Result<(), EncryptionError> {
+    if let Some(Value::Object(map)) = value {
+        for field in sealed_fields(entity_type) {
+            if let Some(Value::String(text)) = map.get_mut(field) {
+                *text = open_text(text)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn append<C: ConnectionTrait>(db: &C, ctx: &AuditContext, user_id: i32, action: &str, entity_type: &str, entity_id: i32, before: Option<Value>, after: Option<Value>) -> Result<(), sea_orm::DbErr> {
+    audit_log::ActiveModel {
+        user_id: Set(user_id),
+        actor_user_id: Set(ctx.actor_user_id),
+        action: Set(action.to_string()),
+        entity_type: Set(entity_type.to_string()),
+        entity_id: Set(entity_id),
+        before: Set(seal_fields(db, user_id, entity_type, before).await?),
+        after: Set(seal_fields(db, user_id, entity_type, after).await?),
+        request_id: Set(ctx.request_id.clone()),
+        ip: Set(ctx.ip.clone()),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+    Ok(())
+}
+
+/// Records a new entity owned by `user_id`, with all its fields as `after`.
+pub async fn audit_create<C: ConnectionTrait, T: Serialize>(db: &C, ctx: &AuditContext, user_id: i32, entity_type: &str, entity_id: i32, after: &T) -> Result<(), sea_orm::DbErr> {
+    append(db, ctx, user_id, AUDIT_CREATE, entity_type, entity_id, None, Some(redact(fields(after)))).await
+}
+
+/// Records the fields that differ between `before` and `after`; nothing when none do.
+pub async fn audit_update<C: ConnectionTrait, T: Serialize>(db: &C, ctx: &AuditContext, user_id: i32, entity_type: &str, entity_id: i32, before: &T, after: &T) -> Result<(), sea_orm::DbErr> {
+    let (mut old, mut new) = (fields(before), fields(after));
+    let changed: Vec<String> = new.keys().filter(|k| old.get(*k) != new.get(*k)).cloned().collect();
+    if changed.is_empty() {
+        return Ok(());
+    }
+    old.retain(|k, _| changed.contains(k));
+    new.retain(|k, _| changed.contains(k));
+    append(db, ctx, user_id, AUDIT_UPDATE, entity_type, entity_id, Some(redact(old)), Some(redact(new))).await
+}
+
+/// Records a removed entity, with its last state as `before`.
+pub async fn audit_delete<C: ConnectionTrait, T: Serialize>(db: &C, ctx: &AuditContext, user_id: i32, entity_type: &str, entity_id: i32, before: &T) -> Result<(), sea_orm::DbErr> {
+    append(db, ctx, user_id, AUDIT_DELETE, entity_type, entity_id, Some(redact(fields(before))), None).await
+}
+
+/// The owner of an account's transactions.
+pub async fn account_owner<C: ConnectionTrait>(db: &C, account_id: i32) -> Result<i32, sea_orm::DbErr> {
+    account::Entity::find_by_id(account_id)
+        .one(db)
+        .await?
+        .map(|a| a.user_id)
+        .ok_or_else(|| sea_orm::DbErr::RecordNotFound(format!("account {}", account_id)))
+}
+
+/// Changes to the user's entities and changes the user made, newest first.
+pub async fn list_audit_log(
+    db: &DatabaseConnection,
+    user_id: i32,
+    entity_type: Option<&str>,
+    entity_id: Option<i32>,
+    from: Option<DateTime<Utc>>,
+    limit: u64,
+) -> Result<Vec<audit_log::Model>, sea_orm::DbErr> {
+    let mut query = audit_log::Entity::find().filter(
+        Condition::any()
+            .add(audit_log::Column::UserId.eq(user_id))
+            .add(audit_log::Column::ActorUserId.eq(user_id)),
+    );
+    if let Some(entity_type) = entity_type {
+        query = query.filter(audit_log::Column::EntityType.eq(entity_type));
+    }
+    if let Some(entity_id) = entity_id {
+        query = query.filter(audit_log::Column::EntityId.eq(entity_id));
+    }
+    if let Some(from) = from {
+        query = query.filter(audit_log::Column::CreatedAt.gte(from));
+    }
+    let mut rows = query
+        .order_by_desc(audit_log::Column::Id)
+        .limit(limit.min(MAX_AUDIT_PAGE))
+        .all(db)
+        .await?;
+    for row in &mut rows {
+        open_fields(&row.entity_type, &mut row.before)?;
+        open_fields(&row.entity_type, &mut row.after)?;
+    }
+    Ok(rows)
+}
@@ -0,0 +1,534 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set, TransactionTrait};
+use serde::Serialize;
+use crate::models::{account, asset, transaction};
+use crate::models::asset::asset_price;
+use crate::services::{audit_create, find_accounts_by_user, find_asset_prices_by_symbols, find_assets_by_user, tags_from_json, tags_to_json, upsert_asset_price, AuditContext, ImportIssue, ServiceError, STATUS_CLEARED, STATUS_PENDING, STATUS_RECONCILED};
+use sea_orm::prelude::Decimal;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::str::FromStr;
+
+const TRANSFER_ACCOUNT: &str = "Equity:Transfers";
+const UNCATEGORIZED: &str = "Uncategorized";
+
+/// Everything a plain-text export needs, with account paths already assigned.
+struct Book {
+    accounts: Vec<(account::Model, String)>,
+    transactions: Vec<transaction::Model>,
+    assets: Vec<asset::Model>,
+    prices: Vec<asset_price::Model>,
+}
+
+impl Book {
+    fn path_of(&self, account_id: i32) -> &str {
+        self.accounts.iter().find(|(a, _)| a.id == account_id).map(|(_, p)| p.as_str()).unwrap_or(TRANSFER_ACCOUNT)
+    }
+
+    /// The balancing account for a wallet transaction.
+    fn counter_account(t: &transaction::Model) -> String {
+        let category = t.category.as_deref().and_then(component).unwrap_or_else(|| UNCATEGORIZED.to_string());
+        match t.transaction_type.as_str() {
+            "expense" => format!("Expenses:{}", category),
+            "income" => format!("Income:{}", category),
+            _ => TRANSFER_ACCOUNT.to_string(),
+        }
+    }
+
+    fn currency_of(&self, account_id: i32) -> String {
+        self.accounts
+            .iter()
+            .find(|(a, _)| a.id == account_id)
+            .map(|(a, _)| commodity(&a.currency))
+            .unwrap_or_else(|| "CNY".to_string())
+    }
+
+    /// Imported transactions can predate the account row, so an account opens at whichever comes first.
+    fn open_date(&self, a: &account::Model) -> NaiveDate {
+        self.transactions
+            .iter()
+            .filter(|t| t.account_id == a.id)
+            .map(|t| t.created_at)
+            .chain(std::iter::once(a.created_at))
+            .min()
+            .unwrap_or(a.created_at)
+            .date_naive()
+    }
+
+    fn start_date(&self) -> NaiveDate {
+        self.accounts
+            .iter()
+            .map(|(a, _)| a.created_at)
+            .chain(self.transactions.iter().map(|t| t.created_at))
+            .chain(self.assets.iter().map(|a| a.created_at))
+            .min()
+            .unwrap_or_else(Utc::now)
+            .date_naive()
+    }
+}
+
+fn account_root(account_type: &str) -> &'static str {
+    match account_type {
+        "credit" | "credit_card" | "loan" | "liability" => "Liabilities",
+        _ => "Assets",
+    }
+}
+
+/// Turns free text into an account component: letters (any script), digits and dashes, capitalised.
+fn component(raw: &str) -> Option<String> {
+    let mut out = String::new();
+    for c in raw.trim().chars() {
+        if c.is_alphanumeric() {
+            out.push(c);
+        } else if !out.ends_with('-') && !out.is_empty() {
+            out.push('-');
+        }
+    }
+    let out = out.trim_end_matches('-');
+    let mut chars = out.chars();
+    let first = chars.next()?;
+    Some(first.to_uppercase().chain(chars).collect())
+}
+
+/// Beancount tags allow letters, digits and `-_/.`; anything else becomes a dash.
+fn tag(raw: &str) -> String {
+    raw.chars().map(|c| if c.is_alphanumeric() || "-_/.".contains(c) { c } else { '-' }).collect()
+}
+
+/// Beancount commodities are upper-case and must start with a letter.
+fn commodity(raw: &str) -> String {
+    let mut out: String = raw
+        .trim()
+        .to_uppercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "'._-".contains(c) { c } else { '-' })
+        .collect();
+    if !out.starts_with(|c: char| c.is_ascii_uppercase()) {
+        out.insert(0, 'X');
+    }
+    out.trim_end_matches(|c: char| !c.is_ascii_alphanumeric()).to_string()
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+async fn load_book(db: &DatabaseConnection, user_id: i32) -> Result<Book, sea_orm::DbErr> {
+    let accounts = find_accounts_by_user(db, user_id).await?;
+    let mut used = HashSet::new();
+    let accounts: Vec<(account::Model, String)> = accounts
+        .into_iter()
+        .map(|a| {
+            let kind = component(&a.account_type).unwrap_or_else(|| "Other".to_string());
+            let name = component(&a.name).unwrap_or_else(|| format!("Account{}", a.id));
+            let mut path = format!("{}:{}:{}", account_root(&a.account_type), kind, name);
+            if !used.insert(path.clone()) {
+                path = format!("{}-{}", path, a.id);
+                used.insert(path.clone());
+            }
+            (a, path)
+        })
+        .collect();
+    let ids: Vec<i32> = accounts.iter().map(|(a, _)| a.id).collect();
+    let transactions = transaction::Entity::find()
+        .filter(transaction::Column::AccountId.is_in(ids))
+        .order_by_asc(transaction::Column::CreatedAt)
+        .order_by_asc(transaction::Column::Id)
+        .all(db)
+        .await?;
+    let assets = find_assets_by_user(db, user_id).await?;
+    let mut prices = find_asset_prices_by_symbols(db, assets.iter().map(|a| a.symbol.clone()).collect()).await?;
+    prices.sort_by(|a, b| (a.updated_at, &a.symbol).cmp(&(b.updated_at, &b.symbol)));
+    Ok(Book { accounts, transactions, assets, prices })
+}
+
+/// Exports the user's book as Beancount. Wallet-only details ride along as metadata so
+/// `import_beancount` can restore them.
+pub async fn export_beancount(db: &DatabaseConnection, user_id: i32) -> Result<String, sea_orm::DbErr> {
+    let book = load_book(db, user_id).await?;
+    let start = book.start_date();
+    let mut out = String::new();
+    let _ = writeln!(out, "; Exported from Your Wallet\noption \"title\" \"Your Wallet\"\n");
+
+    for (a, path) in &book.accounts {
+        let _ = writeln!(out, "{} open {} {}", book.open_date(a), path, commodity(&a.currency));
+        let _ = writeln!(out, "  wallet_name: {}", quote(&a.name));
+        let _ = writeln!(out, "  wallet_type: {}", quote(&a.account_type));
+        let _ = writeln!(out, "  wallet_balance: {}\n", quote(&a.balance.normalize().to_string()));
+    }
+    let mut counters: Vec<String> = book.transactions.iter().map(Book::counter_account).collect();
+    counters.sort();
+    counters.dedup();
+    for c in counters {
+        let _ = writeln!(out, "{} open {}", start, c);
+    }
+    out.push('\n');
+
+    for a in &book.assets {
+        let _ = writeln!(out, "{} commodity {}", a.created_at.date_naive(), commodity(&a.symbol));
+        let _ = writeln!(out, "  symbol: {}", quote(&a.symbol));
+        let _ = writeln!(out, "  name: {}", quote(&a.name));
+        let _ = writeln!(out, "  asset_type: {}", quote(&a.asset_type));
+        let _ = writeln!(out, "  quantity: {}", quote(&a.quantity.normalize().to_string()));
+        let _ = writeln!(out, "  avg_price: {}\n", quote(&a.avg_price.normalize().to_string()));
+    }
+
+    for t in &book.transactions {
+        let flag = if t.status == STATUS_PENDING { '!' } else { '*' };
+        let payee = t.counterparty.as_deref().map(|p| format!("{} ", quote(p))).unwrap_or_default();
+        let tags = tags_from_json(t.tags.as_ref());
+        let links: String = tags.iter().map(|t| format!(" #{}", tag(t))).collect();
+        let _ = writeln!(out, "{} {} {}{}{}", t.created_at.date_naive(), flag, payee, quote(&t.description), links);
+        let _ = writeln!(out, "  wallet_status: {}", quote(&t.status));
+        // Tags that had to be rewritten for Beancount keep their original spelling here.
+        if tags.iter().any(|t| tag(t) != *t) {
+            let _ = writeln!(out, "  wallet_tags: {}", quote(&serde_json::json!(tags).to_string()));
+        }
+        let _ = writeln!(out, "  wallet_created_at: {}", quote(&t.created_at.to_rfc3339()));
+        if let Some(c) = &t.category {
+            let _ = writeln!(out, "  category: {}", quote(c));
+        }
+        if let Some(e) = &t.external_id {
+            let _ = writeln!(out, "  external_id: {}", quote(e));
+        }
+        let signed = crate::services::signed_amount(t).normalize();
+        let currency = book.currency_of(t.account_id);
+        let _ = writeln!(out, "  {}  {} {}", book.path_of(t.account_id), signed, currency);
+        let _ = writeln!(out, "  {}  {} {}\n", Book::counter_account(t), -signed, currency);
+    }
+
+    for p in &book.prices {
+        let _ = writeln!(out, "{} price {} {} {}", p.updated_at.date_naive(), commodity(&p.symbol), p.price.normalize(), commodity(&p.currency));
+    }
+    Ok(out)
+}
+
+/// Exports the user's book in ledger-cli syntax; metadata goes into `; key: value` comments.
+pub async fn export_ledger(db: &DatabaseConnection, user_id: i32) -> Result<String, sea_orm::DbErr> {
+    let book = load_book(db, user_id).await?;
+    let mut out = String::new();
+    let _ = writeln!(out, "; Exported from Your Wallet\n");
+
+    for (a, path) in &book.accounts {
+        let _ = writeln!(out, "account {}", path);
+        let _ = writeln!(out, "    ; wallet_name: {}", a.name);
+        let _ = writeln!(out, "    ; wallet_type: {}", a.account_type);
+        let _ = writeln!(out, "    ; wallet_balance: {}", a.balance.normalize());
+    }
+    out.push('\n');
+    for a in &book.assets {
+        let _ = writeln!(out, "commodity {}", commodity(&a.symbol));
+        let _ = writeln!(out, "    note {}", a.name);
+        let _ = writeln!(out, "    ; quantity: {}", a.quantity.normalize());
+        let _ = writeln!(out, "    ; avg_price: {}", a.avg_price.normalize());
+    }
+    if !book.assets.is_empty() {
+        out.push('\n');
+    }
+
+    for t in &book.transactions {
+        let flag = if t.status == STATUS_PENDING { '!' } else { '*' };
+        let _ = writeln!(out, "{} {} {}", t.created_at.format("%Y/%m/%d"), flag, t.description);
+        if let Some(p) = &t.counterparty {
+            let _ = writeln!(out, "    ; counterparty: {}", p);
+        }
+        if let Some(c) = &t.category {
+            let _ = writeln!(out, "    ; category: {}", c);
+        }
+        let signed = crate::services::signed_amount(t).normalize();
+        let currency = book.currency_of(t.account_id);
+        let _ = writeln!(out, "    {}  {} {}", book.path_of(t.account_id), signed, currency);
+        let _ = writeln!(out, "    {}  {} {}\n", Book::counter_account(t), -signed, currency);
+    }
+
+    for p in &book.prices {
+        let _ = writeln!(out, "P {} {} {} {}", p.updated_at.format("%Y/%m/%d %H:%M:%S"), commodity(&p.symbol), p.price.normalize(), commodity(&p.currency));
+    }
+    Ok(out)
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct BeancountImportSummary {
+    pub accounts: usize,
+    pub transactions: usize,
+    pub assets: usize,
+    pub prices: usize,
+    /// Directives outside the supported subset.
+    pub ignored: Vec<ImportIssue>,
+}
+
+struct Posting {
+    account: String,
+    amount: Option<(Decimal, String)>,
+}
+
+enum Directive {
+    Open { date: NaiveDate, account: String, currencies: Vec<String> },
+    Commodity { date: NaiveDate, symbol: String },
+    Price { date: NaiveDate, symbol: String, price: Decimal, currency: String },
+    Txn { date: NaiveDate, flag: char, payee: Option<String>, narration: String, tags: Vec<String>, postings: Vec<Posting> },
+}
+
+struct Entry {
+    line: usize,
+    directive: Directive,
+    meta: HashMap<String, String>,
+}
+
+/// Splits a line into tokens, keeping quoted strings whole and dropping `;` comments.
+fn tokenize(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == ';' {
+            break;
+        } else if c == '"' {
+            chars.next();
+            let mut s = String::new();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => s.extend(chars.next()),
+                    '"' => break,
+                    _ => s.push(c),
+                }
+            }
+            tokens.push(s);
+        } else {
+            let mut s = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                s.push(c);
+                chars.next();
+            }
+            tokens.push(s);
+        }
+    }
+    tokens
+}
+
+fn invalid(line: usize, msg: impl std::fmt::Display) -> ServiceError {
+    ServiceError::Invalid(format!("line {}: {}", line, msg))
+}
+
+fn parse_decimal(line: usize, raw: &str) -> Result<Decimal, ServiceError> {
+    Decimal::from_str(&raw.replace(',', "")).map_err(|_| invalid(line, format!("invalid number {:?}", raw)))
+}
+
+fn parse_entries(text: &str, ignored: &mut Vec<ImportIssue>) -> Result<Vec<Entry>, ServiceError> {
+    let mut entries: Vec<Entry> = Vec::new();
+    for (i, raw) in text.lines().enumerate() {
+        let line = i + 1;
+        let tokens = tokenize(raw);
+        if tokens.is_empty() {
+            continue;
+        }
+        let indented = raw.starts_with(' ') || raw.starts_with('\t');
+        if indented {
+            let Some(entry) = entries.last_mut() else { continue };
+            let first = tokens[0].as_str();
+            if first.ends_with(':') && first.starts_with(|c: char| c.is_ascii_lowercase()) {
+                let key = first.trim_end_matches(':').to_string();
+                entry.meta.insert(key, tokens[1..].join(" "));
+            } else if let Directive::Txn { postings, .. } = &mut entry.directive {
+                // Optional posting flag, then account, then `number currency` (cost/price annotations ignored).
+                let rest: Vec<&String> = tokens.iter().skip_while(|t| *t == "!" || *t == "*").collect();
+                let Some(account) = rest.first() else { continue };
+                let amount = match (rest.get(1), rest.get(2)) {
+                    (Some(n), Some(c)) => Some((parse_decimal(line, n)?, c.to_string())),
+                    (Some(n), None) => return Err(invalid(line, format!("amount {:?} has no currency", n))),
+                    _ => None,
+                };
+                postings.push(Posting { account: account.to_string(), amount });
+            }
+            continue;
+        }
+
+        let Ok(date) = NaiveDate::parse_from_str(&tokens[0], "%Y-%m-%d") else {
+            if !matches!(tokens[0].as_str(), "option" | "plugin") {
+                ignored.push(ImportIssue { line, message: format!("unsupported: {}", tokens[0]) });
+            }
+            continue;
+        };
+        let kind = tokens.get(1).map(String::as_str).unwrap_or("");
+        let arg = |n: usize| tokens.get(n).cloned().ok_or_else(|| invalid(line, format!("{} is missing an argument", kind)));
+        let directive = match kind {
+            "open" => Directive::Open {
+                date,
+                account: arg(2)?,
+                currencies: tokens.get(3).map(|c| c.split(',').map(str::to_string).collect()).unwrap_or_default(),
+            },
+            "commodity" => Directive::Commodity { date, symbol: arg(2)? },
+            "price" => Directive::Price { date, symbol: arg(2)?, price: parse_decimal(line, &arg(3)?)?, currency: arg(4)? },
+            "*" | "!" | "txn" => {
+                let strings: Vec<String> = tokens[2..].iter().take_while(|t| !t.starts_with('#') && !t.starts_with('^')).cloned().collect();
+                let tags: Vec<String> = tokens[2 + strings.len()..].iter().filter_map(|t| t.strip_prefix('#')).filter(|t| !t.is_empty()).map(str::to_string).collect();
+                let (payee, narration) = match strings.len() {
+                    0 => (None, String::new()),
+                    1 => (None, strings[0].clone()),
+                    _ => (Some(strings[0].clone()).filter(|p| !p.is_empty()), strings[1].clone()),
+                };
+                Directive::Txn { date, flag: if kind == "!" { '!' } else { '*' }, payee, narration, tags, postings: Vec::new() }
+            }
+            other => {
+                ignored.push(ImportIssue { line, message: format!("unsupported directive: {}", other) });
+                continue;
+            }
+        };
+        entries.push(Entry { line, directive, meta: HashMap::new() });
+    }
+    Ok(entries)
+}
+
+fn last_component(path: &str) -> &str {
+    path.rsplit(':').next().unwrap_or(path)
+}
+
+/// Imports a Beancount subset (`open`, `commodity`, `price` and transactions) into the user's
+/// book in one database transaction. Asset and liability accounts become wallet accounts;
+/// expense and income accounts become categories.
+pub async fn import_beancount(db: &DatabaseConnection, audit: &AuditContext, user_id: i32, text: &str) -> Result<BeancountImportSummary, ServiceError> {
+    let mut summary = BeancountImportSummary::default();
+    let entries = parse_entries(text, &mut summary.ignored)?;
+    let is_wallet = |path: &str| path.starts_with("Assets:") || path.starts_with("Liabilities:");
+
+    let txn = db.begin().await?;
+    let mut wallet: HashMap<String, i32> = HashMap::new();
+    let mut symbols: HashMap<String, String> = HashMap::new();
+    for e in &entries {
+        match &e.directive {
+            Directive::Open { date, account, currencies } if is_wallet(account) => {
+                let parts: Vec<&str> = account.split(':').collect();
+                let default_type = if parts.len() > 2 { parts[1].to_lowercase() } else if parts[0] == "Liabilities" { "credit".into() } else { "bank".into() };
+                let balance = match e.meta.get("wallet_balance") {
+                    Some(b) => parse_decimal(e.line, b)?,
+                    None => Decimal::ZERO,
+                };
+                let model = account::ActiveModel {
+                    user_id: Set(user_id),
+                    name: Set(e.meta.get("wallet_name").cloned().unwrap_or_else(|| last_component(account).to_string()).into()),
+                    account_type: Set(e.meta.get("wallet_type").cloned().unwrap_or(default_type)),
+                    balance: Set(balance),
+                    currency: Set(currencies.first().cloned().unwrap_or_else(|| "CNY".to_string())),
+                    created_at: Set(date.and_hms_opt(0, 0, 0).expect("midnight").and_utc()),
+                    ..Default::default()
+                }
+                .insert(&txn)
+                .await?;
+                audit_create(&txn, audit, user_id, "account", model.id, &model).await?;
+                wallet.insert(account.clone(), model.id);
+                summary.accounts += 1;
+            }
+            Directive::Commodity { date, symbol } => {
+                let original = e.meta.get("symbol").cloned().unwrap_or_else(|| symbol.clone());
+                symbols.insert(symbol.clone(), original.clone());
+                // Plain currency declarations carry no holding.
+                let Some(quantity) = e.meta.get("quantity") else { continue };
+                let avg_price = match e.meta.get("avg_price") {
+                    Some(p) => parse_decimal(e.line, p)?,
+                    None => Decimal::ZERO,
+                };
+                let model = asset::ActiveModel {
+                    user_id: Set(user_id),
+                    symbol: Set(original.clone()),
+                    name: Set(e.meta.get("name").cloned().unwrap_or(original)),
+                    quantity: Set(parse_decimal(e.line, quantity)?),
+                    avg_price: Set(avg_price),
+                    asset_type: Set(e.meta.get("asset_type").cloned().unwrap_or_else(|| "stock".to_string())),
+                    created_at: Set(date.and_hms_opt(0, 0, 0).expect("midnight").and_utc()),
+                    ..Default::default()
+                }
+                .insert(&txn)
+                .await?;
+                audit_create(&txn, audit, user_id, "asset", model.id, &model).await?;
+                summary.assets += 1;
+            }
+            Directive::Price { date, symbol, price, currency } => {
+                let symbol = symbols.get(symbol).cloned().unwrap_or_else(|| symbol.clone());
+                let at: DateTime<Utc> = date.and_hms_opt(0, 0, 0).expect("midnight").and_utc();
+                upsert_asset_price(&txn, symbol, *price, currency.clone(), at).await?;
+                summary.prices += 1;
+            }
+            Directive::Txn { .. } => {
+                let rows = transaction_rows(e, &wallet)?;
+                if rows.is_empty() {
+                    summary.ignored.push(ImportIssue { line: e.line, message: "no posting to a wallet account".into() });
+                }
+                for row in rows {
+                    let model = row.insert(&txn).await?;
+                    audit_create(&txn, audit, user_id, "transaction", model.id, &model).await?;
+                    summary.transactions += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    txn.commit().await?;
+    Ok(summary)
+}
+
+/// One wallet transaction per posting to an opened asset/liability account.
+fn transaction_rows(e: &Entry, wallet: &HashMap<String, i32>) -> Result<Vec<transaction::ActiveModel>, ServiceError> {
+    let Directive::Txn { date, flag, payee, narration, tags, postings } = &e.directive else {
+        return Ok(Vec::new());
+    };
+    // Fill in the one posting allowed to omit its amount.
+    let elided = postings.iter().filter(|p| p.amount.is_none()).count();
+    if elided > 1 {
+        return Err(invalid(e.line, "more than one posting without amount"));
+    }
+    let total: Decimal = postings.iter().filter_map(|p| p.amount.as_ref().map(|(a, _)| *a)).sum();
+    let amounts: Vec<Decimal> = postings.iter().map(|p| p.amount.as_ref().map(|(a, _)| *a).unwrap_or(-total)).collect();
+
+    let counter = postings.iter().find(|p| !wallet.contains_key(&p.account));
+    let status = match e.meta.get("wallet_status").map(String::as_str) {
+        // A reconciled row needs a reconciliation session, which the file cannot carry.
+        Some(STATUS_RECONCILED) | Some(STATUS_CLEARED) => STATUS_CLEARED,
+        Some(STATUS_PENDING) => STATUS_PENDING,
+        _ if *flag == '!' => STATUS_PENDING,
+        _ => STATUS_CLEARED,
+    };
+    let created_at = e
+        .meta
+        .get("wallet_created_at")
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        .map(|d| d.with_timezone(&Utc))
+        .unwrap_or_else(|| date.and_hms_opt(0, 0, 0).expect("midnight").and_utc());
+    let mut tags = e.meta.get("wallet_tags").and_then(|s| serde_json::from_str::<Vec<String>>(s).ok()).unwrap_or_else(|| tags.clone());
+    let mut seen = HashSet::new();
+    tags.retain(|t| seen.insert(t.clone()));
+
+    let mut rows = Vec::new();
+    for (p, amount) in postings.iter().zip(amounts) {
+        let Some(account_id) = wallet.get(&p.account) else { continue };
+        let (transaction_type, amount) = match counter {
+            Some(c) if c.account.starts_with("Expenses:") || c.account.starts_with("Income:") => {
+                (if amount.is_sign_negative() { "expense" } else { "income" }, amount.abs())
+            }
+            _ => ("transfer", amount),
+        };
+        let category = e.meta.get("category").cloned().or_else(|| {
+            counter
+                .filter(|c| c.account.starts_with("Expenses:") || c.account.starts_with("Income:"))
+                .map(|c| last_component(&c.account).to_string())
+                .filter(|c| c != UNCATEGORIZED)
+        });
+        rows.push(transaction::ActiveModel {
+            account_id: Set(*account_id),
+            transaction_type: Set(transaction_type.to_string()),
+            amount: Set(amount),
+            description: Set(narration.as_str().into()),
+            category: Set(category),
+            status: Set(status.to_string()),
+            counterparty: Set(payee.clone().or_else(|| e.meta.get("counterparty").cloned()).map(Into::into)),
+            external_id: Set(e.meta.get("external_id").cloned()),
+            tags: Set(tags_to_json(&tags)),
+            created_at: Set(created_at),
+            ..Default::default()
+        });
+    }
+    Ok(rows)
+}
@@ -0,0 +1,274 @@
+use chrono::{DateTime, Utc};
+use crate::services::{decode_text, parse_amount, parse_local_datetime, ImportIssue, ImportPreview, ImportRow, ServiceError};
+use sea_orm::prelude::Decimal;
+
+/// Both apps export local China time.
+const CHINA_UTC_OFFSET_MINUTES: i32 = 480;
+const DATE_FORMATS: [&str; 3] = ["%Y-%m-%d %H:%M:%S", "%Y/%m/%d %H:%M:%S", "%Y/%m/%d %H:%M"];
+
+/// One data line of a payment-app bill, before format-specific classification.
+struct BillLine {
+    line: usize,
+    date: DateTime<Utc>,
+    kind: String,
+    direction: String,
+    amount: Decimal,
+    status: String,
+    counterparty: String,
+    description: String,
+    trade_no: String,
+    merchant_no: String,
+}
+
+#[derive(PartialEq)]
+enum Direction {
+    Expense,
+    Income,
+    /// 不计收支 / `/`: money moved between the user's own balances.
+    Neutral,
+}
+
+impl BillLine {
+    fn direction(&self) -> Direction {
+        match self.direction.as_str() {
+            "支出" => Direction::Expense,
+            "收入" => Direction::Income,
+            _ => Direction::Neutral,
+        }
+    }
+
+    fn into_row(self, transaction_type: &str, category: Option<String>, refund_of: Option<String>) -> ImportRow {
+        let description = if self.description.is_empty() { self.kind } else { self.description };
+        ImportRow {
+            line: self.line,
+            date: self.date,
+            transaction_type: transaction_type.to_string(),
+            amount: self.amount,
+            description,
+            category,
+            counterparty: Some(self.counterparty).filter(|c| !c.is_empty()),
+            external_id: Some(self.trade_no).filter(|t| !t.is_empty()),
+            refund_of,
+            tags: Vec::new(),
+        }
+    }
+}
+
+/// Column aliases across export versions.
+struct BillColumns {
+    date: &'static [&'static str],
+    kind: &'static [&'static str],
+    direction: &'static [&'static str],
+    amount: &'static [&'static str],
+    status: &'static [&'static str],
+    counterparty: &'static [&'static str],
+    description: &'static [&'static str],
+    trade_no: &'static [&'static str],
+    merchant_no: &'static [&'static str],
+}
+
+const ALIPAY_COLUMNS: BillColumns = BillColumns {
+    date: &["交易时间", "交易创建时间"],
+    kind: &["交易分类", "类型"],
+    direction: &["收/支"],
+    amount: &["金额", "金额（元）", "金额(元)"],
+    status: &["交易状态"],
+    counterparty: &["交易对方"],
+    description: &["商品说明", "商品名称"],
+    trade_no: &["交易订单号", "交易号"],
+    merchant_no: &["商家订单号"],
+};
+
+const WECHAT_COLUMNS: BillColumns = BillColumns {
+    date: &["交易时间"],
+    kind: &["交易类型"],
+    direction: &["收/支"],
+    amount: &["金额(元)", "金额（元）", "金额"],
+    status: &["当前状态"],
+    counterparty: &["交易对方"],
+    description: &["商品"],
+    trade_no: &["交易单号"],
+    merchant_no: &["商户单号"],
+};
+
+/// Alipay exports GBK, WeChat Pay exports UTF-8 with a BOM.
+fn decode_bill(bytes: &[u8]) -> Result<String, ServiceError> {
+    decode_text(bytes, "utf-8").or_else(|_| decode_text(bytes, "gb18030"))
+}
+
+/// Skips the preamble up to the header row and stops at the footer separator.
+fn read_bill(bytes: &[u8], columns: &BillColumns, preview: &mut ImportPreview) -> Result<Vec<BillLine>, ServiceError> {
+    let text = decode_bill(bytes)?;
+    let lines: Vec<&str> = text.lines().collect();
+    let header_at = lines
+        .iter()
+        .position(|l| columns.date.iter().any(|d| l.trim_start().starts_with(d)) || l.trim_start().starts_with("交易号"))
+        .ok_or_else(|| ServiceError::Invalid("bill header row not found".into()))?;
+    let body: String = lines[header_at..]
+        .iter()
+        .take_while(|l| !l.trim_start().starts_with("---"))
+        .map(|l| format!("{}\n", l))
+        .collect();
+
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(body.as_bytes());
+    let headers = reader.headers().map_err(|e| ServiceError::Invalid(e.to_string()))?.clone();
+    let find = |aliases: &[&str]| headers.iter().position(|h| aliases.contains(&h.trim()));
+    let required = |aliases: &[&str]| find(aliases).ok_or_else(|| ServiceError::Invalid(format!("bill column not found: {}", aliases[0])));
+    let date_col = required(columns.date)?;
+    let direction_col = required(columns.direction)?;
+    let amount_col = required(columns.amount)?;
+    let status_col = required(columns.status)?;
+    let optional = [columns.kind, columns.counterparty, columns.description, columns.trade_no, columns.merchant_no].map(find);
+
+    let mut out = Vec::new();
+    for record in reader.records() {
+        let record = match record {
+            Ok(r) => r,
+            Err(e) => {
+                let line = e.position().map(|p| p.line() as usize).unwrap_or_default() + header_at;
+                preview.errors.push(ImportIssue { line, message: e.to_string() });
+                continue;
+            }
+        };
+        let line = record.position().map(|p| p.line() as usize).unwrap_or_default() + header_at;
+        if record.iter().all(|f| f.is_empty()) {
+            continue;
+        }
+        // `/` is how both apps print an empty cell.
+        let get = |i: Option<usize>| -> String {
+            let v = i.and_then(|i| record.get(i)).unwrap_or("").trim();
+            if v == "/" { String::new() } else { v.to_string() }
+        };
+        let raw_date = get(Some(date_col));
+        let Some(date) = DATE_FORMATS.iter().find_map(|f| parse_local_datetime(&raw_date, f, CHINA_UTC_OFFSET_MINUTES)) else {
+            preview.errors.push(ImportIssue { line, message: format!("invalid date: {:?}", raw_date) });
+            continue;
+        };
+        let raw_amount = get(Some(amount_col));
+        let Some(amount) = parse_amount(&raw_amount) else {
+            preview.errors.push(ImportIssue { line, message: format!("invalid amount: {:?}", raw_amount) });
+            continue;
+        };
+        out.push(BillLine {
+            line,
+            date,
+            kind: get(optional[0]),
+            direction: get(Some(direction_col)),
+            amount: amount.abs(),
+            status: get(Some(status_col)),
+            counterparty: get(optional[1]),
+            description: get(optional[2]),
+            trade_no: get(optional[3]),
+            merchant_no: get(optional[4]),
+        });
+    }
+    Ok(out)
+}
+
+/// Finds the payment a refund belongs to within the same file.
+fn find_original<'a>(lines: &'a [BillLine], refund: &BillLine, base_trade_no: &str) -> Option<&'a BillLine> {
+    let originals = || lines.iter().filter(|l| l.direction() == Direction::Expense);
+    originals()
+        .find(|l| !base_trade_no.is_empty() && l.trade_no == base_trade_no)
+        .or_else(|| originals().find(|l| !refund.merchant_no.is_empty() && l.merchant_no == refund.merchant_no))
+        .or_else(|| {
+            originals()
+                .filter(|l| l.counterparty == refund.counterparty && l.amount >= refund.amount && l.date <= refund.date)
+                .max_by_key(|l| l.date)
+        })
+}
+
+/// `(transaction_type, category, refund_of)` for kept lines, `None` for ignored ones.
+type Decision = Option<(&'static str, Option<String>, Option<String>)>;
+
+fn push_rows(preview: &mut ImportPreview, lines: Vec<BillLine>, decisions: Vec<Decision>) {
+    for (l, decision) in lines.into_iter().zip(decisions) {
+        if let Some((ty, category, refund_of)) = decision {
+            preview.rows.push(l.into_row(ty, category, refund_of));
+        }
+    }
+}
+
+/// Parses an Alipay 交易明细 CSV export.
+pub fn parse_alipay(bytes: &[u8]) -> Result<ImportPreview, ServiceError> {
+    let mut preview = ImportPreview::default();
+    let lines = read_bill(bytes, &ALIPAY_COLUMNS, &mut preview)?;
+
+    // Refund trade numbers extend the original's, e.g. `2025...123*refund1`.
+    let refund_base = |l: &BillLine| l.trade_no.split(['*', '_']).next().unwrap_or("").to_string();
+    let is_refund = |l: &BillLine| l.status == "退款成功" || l.description.starts_with("退款");
+    let refunded: Vec<String> = lines
+        .iter()
+        .filter(|l| is_refund(l))
+        .filter_map(|r| find_original(&lines, r, &refund_base(r)).map(|o| o.trade_no.clone()))
+        .collect();
+
+    let mut decisions: Vec<Decision> = Vec::with_capacity(lines.len());
+    for l in &lines {
+        if is_refund(l) {
+            let base = refund_base(l);
+            let original = find_original(&lines, l, &base);
+            let category = original.map(|o| o.kind.clone()).filter(|k| !k.is_empty());
+            let refund_of = original.map(|o| o.trade_no.clone()).or(Some(base)).filter(|t| !t.is_empty());
+            decisions.push(Some(("income", category, refund_of)));
+            continue;
+        }
+        let closed_and_refunded = l.status == "交易关闭" && refunded.contains(&l.trade_no);
+        let skip = match l.direction() {
+            Direction::Neutral => Some("internal transfer"),
+            _ if l.status.contains("失败") || l.status.starts_with("等待") => Some("trade not completed"),
+            _ if l.status == "交易关闭" && !closed_and_refunded => Some("trade closed"),
+            _ => None,
+        };
+        if let Some(reason) = skip {
+            preview.ignored.push(ImportIssue { line: l.line, message: format!("{}: {} {}", reason, l.status, l.description) });
+            decisions.push(None);
+            continue;
+        }
+        let ty = if l.direction() == Direction::Expense { "expense" } else { "income" };
+        decisions.push(Some((ty, Some(l.kind.clone()).filter(|k| !k.is_empty()), None)));
+    }
+
+    push_rows(&mut preview, lines, decisions);
+    Ok(preview)
+}
+
+/// Parses a WeChat Pay 账单明细 CSV export.
+pub fn parse_wechat(bytes: &[u8]) -> Result<ImportPreview, ServiceError> {
+    let mut preview = ImportPreview::default();
+    let lines = read_bill(bytes, &WECHAT_COLUMNS, &mut preview)?;
+
+    let is_refund = |l: &BillLine| l.kind.ends_with("退款") && l.direction() == Direction::Income;
+    let mut decisions: Vec<Decision> = Vec::with_capacity(lines.len());
+    for l in &lines {
+        if is_refund(l) {
+            let original = find_original(&lines, l, "");
+            decisions.push(Some(("income", None, original.map(|o| o.trade_no.clone()).filter(|t| !t.is_empty()))));
+            continue;
+        }
+        let skip = match l.direction() {
+            Direction::Neutral => Some("internal transfer"),
+            _ if l.status.contains("失败") => Some("payment failed"),
+            _ if l.status.contains("退还") => Some("transfer returned"),
+            _ => None,
+        };
+        if let Some(reason) = skip {
+            preview.ignored.push(ImportIssue { line: l.line, message: format!("{}: {} {}", reason, l.kind, l.status) });
+            decisions.push(None);
+            continue;
+        }
+        let ty = if l.direction() == Direction::Expense { "expense" } else { "income" };
+        // Only person-to-person kinds say anything about the category.
+        let category = match l.kind.as_str() {
+            "转账" | "微信红包" | "群收款" => Some(l.kind.clone()),
+            _ => None,
+        };
+        decisions.push(Some((ty, category, None)));
+    }
+
+    push_rows(&mut preview, lines, decisions);
+    Ok(preview)
+}
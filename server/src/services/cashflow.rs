@@ -0,0 +1,245 @@
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Months, NaiveDate, TimeZone, Utc};
+use sea_orm::{DatabaseConnection, DbBackend, FromQueryResult, Statement};
+use serde::Serialize;
+use crate::models::sealed::SealedText;
+use crate::services::{scaled_amount, scaled_sum_sql, ServiceError};
+use sea_orm::prelude::Decimal;
+use std::collections::BTreeMap;
+
+/// Upper bound on the buckets one request may produce (about 3 years of days).
+pub const MAX_CASHFLOW_BUCKETS: usize = 1100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Interval {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+impl Interval {
+    pub fn parse(raw: &str) -> Result<Self, ServiceError> {
+        match raw {
+            "day" => Ok(Interval::Day),
+            "week" => Ok(Interval::Week),
+            "month" => Ok(Interval::Month),
+            "year" => Ok(Interval::Year),
+            other => Err(ServiceError::Invalid(format!("invalid interval: {}", other))),
+        }
+    }
+
+    /// SQLite expression for the first local day of the bucket, as `YYYY-MM-DD`.
+    /// Weeks start on Monday.
+    fn sql(self, local: &str) -> String {
+        match self {
+            Interval::Day => format!("date({})", local),
+            Interval::Week => format!("date({}, 'weekday 0', '-6 days')", local),
+            Interval::Month => format!("date({}, 'start of month')", local),
+            Interval::Year => format!("date({}, 'start of year')", local),
+        }
+    }
+
+    fn floor(self, d: NaiveDate) -> NaiveDate {
+        match self {
+            Interval::Day => d,
+            Interval::Week => d - Duration::days(d.weekday().num_days_from_monday() as i64),
+            Interval::Month => d.with_day(1).expect("first of month"),
+            Interval::Year => NaiveDate::from_ymd_opt(d.year(), 1, 1).expect("first of year"),
+        }
+    }
+
+    fn next(self, d: NaiveDate) -> NaiveDate {
+        match self {
+            Interval::Day => d + Duration::days(1),
+            Interval::Week => d + Duration::days(7),
+            Interval::Month => d + Months::new(1),
+            Interval::Year => d + Months::new(12),
+        }
+    }
+
+    fn label(self, d: NaiveDate) -> String {
+        match self {
+            Interval::Day | Interval::Week => d.format("%Y-%m-%d").to_string(),
+            Interval::Month => d.format("%Y-%m").to_string(),
+            Interval::Year => d.format("%Y").to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    Category,
+    Account,
+}
+
+impl GroupBy {
+    pub fn parse(raw: &str) -> Result<Self, ServiceError> {
+        match raw {
+            "category" => Ok(GroupBy::Category),
+            "account" => Ok(GroupBy::Account),
+            other => Err(ServiceError::Invalid(format!("invalid group_by: {}", other))),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct CashflowGroup {
+    /// Category name (null when uncategorised) or account name.
+    pub key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<i32>,
+    pub income: Decimal,
+    pub expense: Decimal,
+    pub net: Decimal,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CashflowBucket {
+    /// `YYYY-MM-DD` (day, week starting Monday), `YYYY-MM` or `YYYY`.
+    pub period: String,
+    /// Bucket start as an instant, i.e. local midnight in UTC.
+    pub start: DateTime<Utc>,
+    pub income: Decimal,
+    pub expense: Decimal,
+    pub net: Decimal,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub groups: Option<Vec<CashflowGroup>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Cashflow {
+    pub interval: Interval,
+    pub utc_offset_minutes: i32,
+    pub buckets: Vec<CashflowBucket>,
+    pub income: Decimal,
+    pub expense: Decimal,
+    pub net: Decimal,
+}
+
+/// (account id, category or account name)
+type GroupKey = (Option<i32>, Option<String>);
+
+#[derive(FromQueryResult)]
+struct CashflowRow {
+    bucket: String,
+    kind: String,
+    amount: i64,
+    category: Option<String>,
+    account_id: Option<i32>,
+    account_name: Option<SealedText>,
+}
+
+/// Income and expense per time bucket for all of the user's accounts, aggregated in SQL.
+/// Transfers are left out. `from`/`to` are inclusive local dates; buckets follow
+/// `utc_offset_minutes` (China is 480). Empty buckets in the range are returned as zeros.
+pub async fn cashflow_stats(
+    db: &DatabaseConnection,
+    user_id: i32,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    interval: Interval,
+    group_by: Option<GroupBy>,
+    utc_offset_minutes: i32,
+) -> Result<Cashflow, ServiceError> {
+    let tz = FixedOffset::east_opt(utc_offset_minutes * 60)
+        .ok_or_else(|| ServiceError::Invalid(format!("invalid utc offset {}", utc_offset_minutes)))?;
+    if let (Some(f), Some(t)) = (from, to) {
+        if f > t {
+            return Err(ServiceError::Invalid("from is after to".into()));
+        }
+    }
+    let utc_midnight = |d: NaiveDate| tz.from_local_datetime(&d.and_hms_opt(0, 0, 0).expect("midnight")).unwrap().with_timezone(&Utc);
+    let sql_time = |d: NaiveDate| utc_midnight(d).format("%Y-%m-%d %H:%M:%S").to_string();
+
+    // `datetime()` normalises both stored formats (SQLite's default and RFC 3339) to UTC text.
+    let local = format!("datetime(t.created_at, '{:+} minutes')", utc_offset_minutes);
+    let (group_cols, group_key) = match group_by {
+        None => ("NULL AS category, NULL AS account_id, NULL AS account_name", ""),
+        Some(GroupBy::Category) => ("t.category AS category, NULL AS account_id, NULL AS account_name", ", t.category"),
+        Some(GroupBy::Account) => ("NULL AS category, a.id AS account_id, a.name AS account_name", ", a.id"),
+    };
+    let mut sql = format!(
+        "SELECT {bucket} AS bucket, t.transaction_type AS kind, {sum} AS amount, {group_cols} \
+         FROM transactions t JOIN accounts a ON a.id = t.account_id \
+         WHERE a.user_id = ? AND t.transaction_type IN ('income', 'expense')",
+        bucket = interval.sql(&local),
+        sum = scaled_sum_sql("t.amount"),
+    );
+    let mut values: Vec<sea_orm::Value> = vec![user_id.into()];
+    if let Some(f) = from {
+        sql.push_str(" AND datetime(t.created_at) >= ?");
+        values.push(sql_time(f).into());
+    }
+    if let Some(t) = to {
+        sql.push_str(" AND datetime(t.created_at) < ?");
+        values.push(sql_time(t + Duration::days(1)).into());
+    }
+    sql.push_str(&format!(" GROUP BY bucket, kind{} ORDER BY bucket", group_key));
+    let rows = CashflowRow::find_by_statement(Statement::from_sql_and_values(DbBackend::Sqlite, sql, values))
+        .all(db)
+        .await?;
+
+    let mut buckets: BTreeMap<NaiveDate, BTreeMap<GroupKey, CashflowGroup>> = BTreeMap::new();
+    for row in rows {
+        let start = NaiveDate::parse_from_str(&row.bucket, "%Y-%m-%d")
+            .map_err(|_| ServiceError::Invalid(format!("unexpected bucket {:?}", row.bucket)))?;
+        let key = match group_by {
+            Some(GroupBy::Account) => (row.account_id, row.account_name.map(String::from)),
+            _ => (None, row.category.filter(|c| !c.is_empty())),
+        };
+        let group = buckets.entry(start).or_default().entry(key.clone()).or_insert_with(|| CashflowGroup {
+            key: key.1,
+            account_id: key.0,
+            ..Default::default()
+        });
+        // Uncategorised rows stored as NULL and as "" land in the same group.
+        if row.kind == "income" {
+            group.income += scaled_amount(row.amount);
+        } else {
+            group.expense += scaled_amount(row.amount);
+        }
+        group.net = group.income - group.expense;
+    }
+
+    // Fill gaps so charts get a continuous series.
+    let first = from.map(|d| interval.floor(d)).or_else(|| buckets.keys().next().copied());
+    let last = to.map(|d| interval.floor(d)).or_else(|| buckets.keys().next_back().copied());
+    if let (Some(first), Some(last)) = (first, last) {
+        let mut d = first;
+        let mut count = 0;
+        while d <= last {
+            count += 1;
+            if count > MAX_CASHFLOW_BUCKETS {
+                return Err(ServiceError::Invalid(format!("range spans more than {} buckets; use a longer interval", MAX_CASHFLOW_BUCKETS)));
+            }
+            buckets.entry(d).or_default();
+            d = interval.next(d);
+        }
+    }
+
+    let mut out = Cashflow { interval, utc_offset_minutes, buckets: Vec::new(), income: Decimal::ZERO, expense: Decimal::ZERO, net: Decimal::ZERO };
+    for (start, groups) in buckets {
+        let income: Decimal = groups.values().map(|g| g.income).sum();
+        let expense: Decimal = groups.values().map(|g| g.expense).sum();
+        out.income += income;
+        out.expense += expense;
+        out.buckets.push(CashflowBucket {
+            period: interval.label(start),
+            start: utc_midnight(start),
+            income: income.normalize(),
+            expense: expense.normalize(),
+            net: (income - expense).normalize(),
+            groups: group_by.map(|_| {
+                groups
+                    .into_values()
+                    .map(|g| CashflowGroup { income: g.income.normalize(), expense: g.expense.normalize(), net: g.net.normalize(), ..g })
+                    .collect()
+            }),
+        });
+    }
+    out.income = out.income.normalize();
+    out.expense = out.expense.normalize();
+    out.net = (out.income - out.expense).normalize();
+    Ok(out)
+}
@@ -0,0 +1,224 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use crate::services::{ImportIssue, ImportPreview, ImportRow, ServiceError};
+use sea_orm::prelude::Decimal;
+use std::str::FromStr;
+
+/// How a single signed amount column maps to income/expense.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AmountSign {
+    /// `-12.00` is an expense, `12.00` is income (most bank exports).
+    #[default]
+    NegativeIsExpense,
+    /// `12.00` is an expense, `-12.00` is income (most credit card exports).
+    PositiveIsExpense,
+}
+
+/// Column mapping for a CSV export. Columns are header names, or 0-based indexes.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CsvMapping {
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+    #[serde(default = "default_true")]
+    pub has_header: bool,
+    /// Preamble records before the header (bank name, export period, ...). Counted as CSV
+    /// records, so a quoted field spanning lines is one, and blank lines are not counted.
+    #[serde(default)]
+    pub skip_rows: usize,
+    /// Any WHATWG label, e.g. `utf-8`, `gbk`, `gb18030`.
+    #[serde(default = "default_encoding")]
+    pub encoding: String,
+    pub date_column: String,
+    /// chrono format, e.g. `%Y-%m-%d` or `%Y/%m/%d %H:%M:%S`.
+    pub date_format: String,
+    /// Offset of the exported local times from UTC, in minutes (480 for China).
+    #[serde(default)]
+    pub utc_offset_minutes: i32,
+    pub amount_column: Option<String>,
+    #[serde(default)]
+    pub amount_sign: AmountSign,
+    /// Money out; used together with `credit_column` instead of `amount_column`.
+    pub debit_column: Option<String>,
+    /// Money in.
+    pub credit_column: Option<String>,
+    pub description_column: Option<String>,
+    pub category_column: Option<String>,
+}
+
+fn default_delimiter() -> char { ',' }
+fn default_true() -> bool { true }
+fn default_encoding() -> String { "utf-8".to_string() }
+
+impl CsvMapping {
+    pub fn validate(&self) -> Result<(), ServiceError> {
+        if self.amount_column.is_none() && self.debit_column.is_none() && self.credit_column.is_none() {
+            return Err(ServiceError::Invalid("mapping needs amount_column or debit_column/credit_column".into()));
+        }
+        if !self.delimiter.is_ascii() {
+            return Err(ServiceError::Invalid("delimiter must be a single ASCII character".into()));
+        }
+        if encoding_rs::Encoding::for_label(self.encoding.as_bytes()).is_none() {
+            return Err(ServiceError::Invalid(format!("unknown encoding: {}", self.encoding)));
+        }
+        if FixedOffset::east_opt(self.utc_offset_minutes * 60).is_none() {
+            return Err(ServiceError::Invalid("utc_offset_minutes out of range".into()));
+        }
+        Ok(())
+    }
+}
+
+/// Decodes raw upload bytes with the given encoding label, dropping any BOM.
+pub fn decode_text(bytes: &[u8], encoding: &str) -> Result<String, ServiceError> {
+    let enc = encoding_rs::Encoding::for_label(encoding.as_bytes())
+        .ok_or_else(|| ServiceError::Invalid(format!("unknown encoding: {}", encoding)))?;
+    let (text, _, had_errors) = enc.decode(bytes);
+    if had_errors {
+        return Err(ServiceError::Invalid(format!("file is not valid {}", enc.name())));
+    }
+    Ok(text.into_owned())
+}
+
+/// Parses amounts as banks print them: `¥1,234.50`, `-12.00`, `(12.00)`, `+3`.
+pub fn parse_amount(raw: &str) -> Option<Decimal> {
+    let s = raw.trim();
+    let (negative, s) = match s.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+        Some(inner) => (true, inner),
+        None => (false, s),
+    };
+    let cleaned: String = s
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == '.' || *c == '-' || *c == '+')
+        .collect();
+    if cleaned.is_empty() {
+        return None;
+    }
+    let value = Decimal::from_str(cleaned.trim_start_matches('+')).ok()?;
+    Some(if negative { -value } else { value })
+}
+
+/// Parses a local date or date-time with `format` and converts it to UTC.
+pub fn parse_local_datetime(raw: &str, format: &str, utc_offset_minutes: i32) -> Option<DateTime<Utc>> {
+    let raw = raw.trim();
+    let naive = NaiveDateTime::parse_from_str(raw, format)
+        .ok()
+        .or_else(|| NaiveDate::parse_from_str(raw, format).ok().and_then(|d| d.and_hms_opt(0, 0, 0)))?;
+    let offset = FixedOffset::east_opt(utc_offset_minutes * 60)?;
+    offset.from_local_datetime(&naive).single().map(|d| d.with_timezone(&Utc))
+}
+
+fn resolve_column(headers: Option<&csv::StringRecord>, name: &str) -> Option<usize> {
+    if let Some(h) = headers {
+        if let Some(i) = h.iter().position(|c| c.trim() == name.trim()) {
+            return Some(i);
+        }
+    }
+    name.trim().parse().ok()
+}
+
+struct Columns {
+    date: usize,
+    amount: Option<usize>,
+    debit: Option<usize>,
+    credit: Option<usize>,
+    description: Option<usize>,
+    category: Option<usize>,
+}
+
+pub fn parse_csv(bytes: &[u8], mapping: &CsvMapping) -> Result<ImportPreview, ServiceError> {
+    mapping.validate()?;
+    let text = decode_text(bytes, &mapping.encoding)?;
+    // The reader sees the whole text, so record positions are line numbers in the file.
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(mapping.delimiter as u8)
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(text.as_bytes());
+    let mut records = reader.records();
+    records.by_ref().take(mapping.skip_rows).for_each(drop);
+    let headers = if mapping.has_header {
+        let header = records.next().transpose().map_err(|e| ServiceError::Invalid(e.to_string()))?;
+        Some(header.unwrap_or_default())
+    } else {
+        None
+    };
+
+    let find = |name: &Option<String>, what: &str| -> Result<Option<usize>, ServiceError> {
+        match name {
+            Some(n) => resolve_column(headers.as_ref(), n)
+                .map(Some)
+                .ok_or_else(|| ServiceError::Invalid(format!("{} column not found: {}", what, n))),
+            None => Ok(None),
+        }
+    };
+    let cols = Columns {
+        date: resolve_column(headers.as_ref(), &mapping.date_column)
+            .ok_or_else(|| ServiceError::Invalid(format!("date column not found: {}", mapping.date_column)))?,
+        amount: find(&mapping.amount_column, "amount")?,
+        debit: find(&mapping.debit_column, "debit")?,
+        credit: find(&mapping.credit_column, "credit")?,
+        description: find(&mapping.description_column, "description")?,
+        category: find(&mapping.category_column, "category")?,
+    };
+
+    let mut preview = ImportPreview::default();
+    for record in records {
+        let record = match record {
+            Ok(r) => r,
+            Err(e) => {
+                let line = e.position().map(|p| p.line() as usize).unwrap_or_default();
+                preview.errors.push(ImportIssue { line, message: e.to_string() });
+                continue;
+            }
+        };
+        let line = record.position().map(|p| p.line() as usize).unwrap_or_default();
+        if record.iter().all(|f| f.is_empty()) {
+            continue;
+        }
+        match parse_record(&record, &cols, mapping, line) {
+            Ok(row) => preview.rows.push(row),
+            Err(message) => preview.errors.push(ImportIssue { line, message }),
+        }
+    }
+    Ok(preview)
+}
+
+fn parse_record(record: &csv::StringRecord, cols: &Columns, mapping: &CsvMapping, line: usize) -> Result<ImportRow, String> {
+    let field = |i: Option<usize>| i.and_then(|i| record.get(i)).unwrap_or("").trim();
+
+    let raw_date = field(Some(cols.date));
+    let date = parse_local_datetime(raw_date, &mapping.date_format, mapping.utc_offset_minutes)
+        .ok_or_else(|| format!("invalid date: {:?}", raw_date))?;
+
+    // Signed amount where negative means money out.
+    let signed = if let Some(i) = cols.amount {
+        let raw = field(Some(i));
+        let v = parse_amount(raw).ok_or_else(|| format!("invalid amount: {:?}", raw))?;
+        match mapping.amount_sign {
+            AmountSign::NegativeIsExpense => v,
+            AmountSign::PositiveIsExpense => -v,
+        }
+    } else {
+        let debit = parse_amount(field(cols.debit)).unwrap_or_default();
+        let credit = parse_amount(field(cols.credit)).unwrap_or_default();
+        credit.abs() - debit.abs()
+    };
+    if signed.is_zero() {
+        return Err("amount is zero or missing".to_string());
+    }
+
+    let category = Some(field(cols.category)).filter(|c| !c.is_empty()).map(str::to_string);
+    Ok(ImportRow {
+        line,
+        date,
+        transaction_type: if signed.is_sign_negative() { "expense" } else { "income" }.to_string(),
+        amount: signed.abs(),
+        description: field(cols.description).to_string(),
+        category,
+        counterparty: None,
+        external_id: None,
+        refund_of: None,
+        tags: Vec::new(),
+    })
+}
@@ -0,0 +1,174 @@
+use chrono::{Datelike, Duration, Utc};
+use sea_orm::{ColumnTrait, DatabaseConnection, DbBackend, EntityTrait, FromQueryResult, Order, QueryFilter, QueryOrder, QuerySelect, Statement};
+use serde::Serialize;
+use crate::models::{account, transaction};
+use crate::services::{cashflow_stats, created_at_expr, find_accounts_by_user, find_asset_prices_by_symbols, find_assets_by_user, scaled_amount, scaled_sum_sql, GroupBy, Interval, ServiceError};
+use sea_orm::prelude::Decimal;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration as StdDuration, Instant};
+
+const TOP_CATEGORIES: usize = 5;
+const RECENT_TRANSACTIONS: u64 = 10;
+
+/// How long a dashboard may be served from memory when nothing has been written.
+pub const DASHBOARD_TTL: StdDuration = StdDuration::from_secs(30);
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AccountBalance {
+    pub account_id: i32,
+    pub name: String,
+    pub account_type: String,
+    pub currency: String,
+    /// Opening `balance` plus every transaction.
+    pub balance: Decimal,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CategorySpend {
+    pub category: Option<String>,
+    pub expense: Decimal,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Dashboard {
+    /// Positive account balances plus holdings at market value.
+    pub total_assets: Decimal,
+    /// Negative account balances (e.g. credit cards), as a positive number.
+    pub total_liabilities: Decimal,
+    pub net_worth: Decimal,
+    pub holdings_value: Decimal,
+    pub month_income: Decimal,
+    pub month_expense: Decimal,
+    pub top_categories: Vec<CategorySpend>,
+    pub accounts: Vec<AccountBalance>,
+    pub recent_transactions: Vec<transaction::Model>,
+    pub generated_at: chrono::DateTime<Utc>,
+}
+
+#[derive(FromQueryResult)]
+struct AccountMovement {
+    account_id: i32,
+    kind: String,
+    amount: i64,
+}
+
+/// Current balance per account, with the transactions summed per account and type in SQL.
+async fn account_balances(db: &DatabaseConnection, accounts: Vec<account::Model>) -> Result<Vec<AccountBalance>, sea_orm::DbErr> {
+    let rows = AccountMovement::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Sqlite,
+        format!(
+            "SELECT t.account_id AS account_id, t.transaction_type AS kind, {} AS amount \
+             FROM transactions t JOIN accounts a ON a.id = t.account_id \
+             WHERE a.user_id = ? GROUP BY t.account_id, t.transaction_type",
+            scaled_sum_sql("t.amount")
+        ),
+        [accounts.first().map(|a| a.user_id).unwrap_or_default().into()],
+    ))
+    .all(db)
+    .await?;
+    let mut totals: HashMap<i32, Decimal> = HashMap::new();
+    for r in rows {
+        let amount = scaled_amount(r.amount);
+        *totals.entry(r.account_id).or_default() += if r.kind == "expense" { -amount } else { amount };
+    }
+    Ok(accounts
+        .into_iter()
+        .map(|a| {
+            let moved = totals.get(&a.id).copied().unwrap_or_default();
+            AccountBalance {
+                account_id: a.id,
+                balance: (a.balance + moved).normalize(),
+                name: a.name.into(),
+                account_type: a.account_type,
+                currency: a.currency,
+            }
+        })
+        .collect())
+}
+
+/// Everything the home screen shows, in one call. Amounts are summed across currencies as-is.
+pub async fn build_dashboard(db: &DatabaseConnection, user_id: i32, utc_offset_minutes: i32) -> Result<Dashboard, ServiceError> {
+    let accounts = find_accounts_by_user(db, user_id).await?;
+    let account_ids: Vec<i32> = accounts.iter().map(|a| a.id).collect();
+    let balances = account_balances(db, accounts).await?;
+
+    let assets = find_assets_by_user(db, user_id).await?;
+    let prices: HashMap<String, Decimal> = find_asset_prices_by_symbols(db, assets.iter().map(|a| a.symbol.clone()).collect())
+        .await?
+        .into_iter()
+        .map(|p| (p.symbol, p.price))
+        .collect();
+    // Without a quote a holding is valued at cost.
+    let holdings_value: Decimal = assets
+        .iter()
+        .map(|a| a.quantity * prices.get(&a.symbol).copied().unwrap_or(a.avg_price))
+        .sum();
+
+    let total_assets = balances.iter().map(|b| b.balance).filter(|b| b.is_sign_positive()).sum::<Decimal>() + holdings_value;
+    let total_liabilities = -balances.iter().map(|b| b.balance).filter(|b| b.is_sign_negative()).sum::<Decimal>();
+
+    let today = (Utc::now() + Duration::minutes(utc_offset_minutes as i64)).date_naive();
+    let month_start = today.with_day(1).expect("first of month");
+    let month = cashflow_stats(db, user_id, Some(month_start), Some(today), Interval::Month, Some(GroupBy::Category), utc_offset_minutes).await?;
+    let mut top_categories: Vec<CategorySpend> = month
+        .buckets
+        .into_iter()
+        .flat_map(|b| b.groups.unwrap_or_default())
+        .filter(|g| !g.expense.is_zero())
+        .map(|g| CategorySpend { category: g.key, expense: g.expense })
+        .collect();
+    top_categories.sort_by_key(|c| std::cmp::Reverse(c.expense));
+    top_categories.truncate(TOP_CATEGORIES);
+
+    let recent_transactions = transaction::Entity::find()
+        .filter(transaction::Column::AccountId.is_in(account_ids))
+        .order_by(created_at_expr(), Order::Desc)
+        .order_by_desc(transaction::Column::Id)
+        .limit(RECENT_TRANSACTIONS)
+        .all(db)
+        .await?;
+
+    Ok(Dashboard {
+        total_assets: total_assets.normalize(),
+        total_liabilities: total_liabilities.normalize(),
+        net_worth: (total_assets - total_liabilities).normalize(),
+        holdings_value: holdings_value.normalize(),
+        month_income: month.income,
+        month_expense: month.expense,
+        top_categories,
+        accounts: balances,
+        recent_transactions,
+        generated_at: Utc::now(),
+    })
+}
+
+/// Short-lived per-user dashboards. Any successful write through the API clears it
+/// (see `routes::invalidate_caches`), so the TTL only bounds staleness from outside writers.
+#[derive(Clone, Default)]
+pub struct DashboardCache {
+    /// Keyed by (user id, utc offset).
+    entries: Arc<Mutex<HashMap<(i32, i32), CachedDashboard>>>,
+}
+
+type CachedDashboard = (Instant, Dashboard);
+
+impl DashboardCache {
+    pub fn get(&self, user_id: i32, utc_offset_minutes: i32) -> Option<Dashboard> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries
+            .get(&(user_id, utc_offset_minutes))
+            .filter(|(at, _)| at.elapsed() < DASHBOARD_TTL)
+            .map(|(_, d)| d.clone())
+    }
+
+    pub fn put(&self, user_id: i32, utc_offset_minutes: i32, dashboard: Dashboard) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.retain(|_, (at, _)| at.elapsed() < DASHBOARD_TTL);
+        entries.insert((user_id, utc_offset_minutes), (Instant::now(), dashboard));
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+}
@@ -0,0 +1,187 @@
+use chrono::Duration;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, Order, QueryFilter, QueryOrder, Set, TransactionTrait};
+use sea_orm::sea_query::Expr;
+use serde::Serialize;
+use sea_orm::prelude::Decimal;
+use crate::models::{account, attachment, transaction};
+use crate::services::{audit_delete, audit_update, created_at_expr, find_accounts_by_user, signed_amount, tags_from_json, tags_to_json, AuditContext, ServiceError, STATUS_RECONCILED};
+
+pub const DEFAULT_DUPLICATE_SCORE: f64 = 0.7;
+pub const DEFAULT_DUPLICATE_DAYS: i64 = 3;
+/// Weights of the score components; they add up to 1.
+const ACCOUNT_WEIGHT: f64 = 0.15;
+const AMOUNT_WEIGHT: f64 = 0.25;
+const DATE_WEIGHT: f64 = 0.2;
+const DESCRIPTION_WEIGHT: f64 = 0.4;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DuplicatePair {
+    /// 0 to 1; see `find_duplicates`.
+    pub score: f64,
+    pub first: transaction::Model,
+    pub second: transaction::Model,
+}
+
+/// Letters and digits only, lowercased, so "MEITUAN*外卖" and "meituan 外卖" compare equal.
+fn normalized(t: &transaction::Model) -> Vec<char> {
+    let text = format!("{} {}", t.counterparty.as_deref().map_or("", String::as_str), t.description);
+    text.to_lowercase().chars().filter(|c| c.is_alphanumeric()).collect()
+}
+
+/// Dice coefficient over character bigrams.
+fn similarity(a: &[char], b: &[char]) -> f64 {
+    if a == b {
+        return 1.0;
+    }
+    if a.len() < 2 || b.len() < 2 {
+        return 0.0;
+    }
+    let left: Vec<(char, char)> = a.windows(2).map(|w| (w[0], w[1])).collect();
+    let mut right: Vec<(char, char)> = b.windows(2).map(|w| (w[0], w[1])).collect();
+    let total = left.len() + right.len();
+    let mut shared = 0;
+    for pair in left {
+        if let Some(i) = right.iter().position(|p| *p == pair) {
+            right.swap_remove(i);
+            shared += 1;
+        }
+    }
+    2.0 * shared as f64 / total as f64
+}
+
+/// Candidate pairs among the user's transactions no more than `days` apart, scored by account
+/// (same account), amount (equal, or within 1%), date proximity and description similarity.
+/// Rows carrying different source ids, and refunds with their originals, are never paired.
+pub async fn find_duplicates(db: &DatabaseConnection, user_id: i32, days: i64, min_score: f64) -> Result<Vec<DuplicatePair>, ServiceError> {
+    if !(0..=31).contains(&days) {
+        return Err(ServiceError::Invalid("days must be between 0 and 31".into()));
+    }
+    let window = Duration::days(days.max(0)) + Duration::hours(12);
+    let account_ids: Vec<i32> = find_accounts_by_user(db, user_id).await?.into_iter().map(|a| a.id).collect();
+    let rows = transaction::Entity::find()
+        .filter(transaction::Column::AccountId.is_in(account_ids))
+        .order_by(created_at_expr(), Order::Asc)
+        .order_by_asc(transaction::Column::Id)
+        .all(db)
+        .await?;
+    let texts: Vec<Vec<char>> = rows.iter().map(normalized).collect();
+
+    let mut pairs = Vec::new();
+    for (i, a) in rows.iter().enumerate() {
+        for (j, b) in rows.iter().enumerate().skip(i + 1) {
+            let apart = b.created_at - a.created_at;
+            if apart > window {
+                break;
+            }
+            if matches!((&a.external_id, &b.external_id), (Some(x), Some(y)) if x != y)
+                || a.refund_of_id == Some(b.id)
+                || b.refund_of_id == Some(a.id)
+            {
+                continue;
+            }
+            let (x, y) = (signed_amount(a), signed_amount(b));
+            let amount = if x == y {
+                AMOUNT_WEIGHT
+            } else if x.is_sign_negative() == y.is_sign_negative() && (x - y).abs() * Decimal::from(100) <= x.abs().max(y.abs()) {
+                AMOUNT_WEIGHT / 2.0
+            } else {
+                continue;
+            };
+            let account = if a.account_id == b.account_id { ACCOUNT_WEIGHT } else { 0.0 };
+            let date = DATE_WEIGHT * (1.0 - apart.num_minutes() as f64 / window.num_minutes() as f64);
+            let description = DESCRIPTION_WEIGHT * similarity(&texts[i], &texts[j]);
+            let score = ((account + amount + date + description) * 1000.0).round() / 1000.0;
+            if score >= min_score {
+                pairs.push(DuplicatePair { score, first: a.clone(), second: b.clone() });
+            }
+        }
+    }
+    pairs.sort_by(|p, q| q.score.total_cmp(&p.score).then_with(|| p.first.id.cmp(&q.first.id)));
+    Ok(pairs)
+}
+
+fn has_category(c: &Option<String>) -> bool {
+    c.as_deref().is_some_and(|c| !c.trim().is_empty() && !c.eq_ignore_ascii_case("uncategorized"))
+}
+
+/// Folds `remove_id` into `keep_id` in one database transaction: tags are combined, the kept
+/// row's category is used unless it has none, missing counterparty and source id are taken
+/// over, refunds pointing at the removed row are relinked and its attachments moved (files the
+/// kept row already has are dropped), then the removed row is deleted.
+pub async fn merge_transactions(db: &DatabaseConnection, audit: &AuditContext, user_id: i32, keep_id: i32, remove_id: i32) -> Result<transaction::Model, ServiceError> {
+    if keep_id == remove_id {
+        return Err(ServiceError::Invalid("cannot merge a transaction with itself".into()));
+    }
+    let txn = db.begin().await?;
+    let mut found = Vec::new();
+    for id in [keep_id, remove_id] {
+        let t = transaction::Entity::find_by_id(id).one(&txn).await?;
+        let owner = match &t {
+            Some(t) => account::Entity::find_by_id(t.account_id).one(&txn).await?.map(|a| a.user_id),
+            None => None,
+        };
+        match t {
+            Some(t) if owner == Some(user_id) => found.push(t),
+            _ => return Err(ServiceError::Invalid(format!("transaction {} not found", id))),
+        }
+    }
+    let (remove, keep) = (found.pop().expect("two rows"), found.pop().expect("two rows"));
+    if keep.status == STATUS_RECONCILED || remove.status == STATUS_RECONCILED {
+        return Err(ServiceError::Locked("transaction is reconciled; unlock it first".into()));
+    }
+
+    let mut tags = tags_from_json(keep.tags.as_ref());
+    for tag in tags_from_json(remove.tags.as_ref()) {
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    let category = if has_category(&keep.category) || !has_category(&remove.category) { keep.category.clone() } else { remove.category.clone() };
+    let counterparty = keep.counterparty.clone().or(remove.counterparty.clone());
+    // Keeping the source id means re-importing the statement still recognises the row.
+    let external_id = keep.external_id.clone().or(remove.external_id.clone());
+    // Either row may be the other's refund; that link disappears with the merge.
+    let refund_of_id = [keep.refund_of_id, remove.refund_of_id].into_iter().flatten().find(|id| *id != keep.id && *id != remove.id);
+
+    let refunds = transaction::Entity::find()
+        .filter(transaction::Column::RefundOfId.eq(remove.id))
+        .filter(transaction::Column::Id.ne(keep.id))
+        .all(&txn)
+        .await?;
+    for refund in refunds {
+        let mut active: transaction::ActiveModel = refund.clone().into();
+        active.refund_of_id = Set(Some(keep.id));
+        let updated = active.update(&txn).await?;
+        audit_update(&txn, audit, user_id, "transaction", updated.id, &refund, &updated).await?;
+    }
+    let kept_files: Vec<String> = attachment::Entity::find()
+        .filter(attachment::Column::TransactionId.eq(keep.id))
+        .all(&txn)
+        .await?
+        .into_iter()
+        .map(|a| a.sha256)
+        .collect();
+    attachment::Entity::delete_many()
+        .filter(attachment::Column::TransactionId.eq(remove.id))
+        .filter(attachment::Column::Sha256.is_in(kept_files))
+        .exec(&txn)
+        .await?;
+    attachment::Entity::update_many()
+        .col_expr(attachment::Column::TransactionId, Expr::value(keep.id))
+        .filter(attachment::Column::TransactionId.eq(remove.id))
+        .exec(&txn)
+        .await?;
+    transaction::Entity::delete_by_id(remove.id).exec(&txn).await?;
+    audit_delete(&txn, audit, user_id, "transaction", remove.id, &remove).await?;
+    let mut active: transaction::ActiveModel = keep.clone().into();
+    active.tags = Set(tags_to_json(&tags));
+    active.category = Set(category);
+    active.counterparty = Set(counterparty);
+    active.external_id = Set(external_id);
+    active.refund_of_id = Set(refund_of_id);
+    let merged = active.update(&txn).await?;
+    audit_update(&txn, audit, user_id, "transaction", merged.id, &keep, &merged).await?;
+    txn.commit().await?;
+    Ok(merged)
+}
+
@@ -0,0 +1,168 @@
+use chrono::{Duration, Utc};
+use sea_orm::{ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set, TransactionTrait};
+use sea_orm::sea_query::Expr;
+use crate::models::{email_token, user};
+use crate::services::{audit_update, get_user_by_email, new_secret_token, revoke_all_sessions, secret_token_hash, send_in_background, AuditContext, Email, SharedMailer};
+
+pub const VERIFY_EMAIL: &str = "verify_email";
+pub const RESET_PASSWORD: &str = "reset_password";
+pub const UNLOCK_ACCOUNT: &str = "unlock_account";
+pub const VERIFY_EMAIL_HOURS: i64 = 48;
+pub const RESET_PASSWORD_MINUTES: i64 = 60;
+pub const UNLOCK_ACCOUNT_HOURS: i64 = 24;
+/// A login blocked for lack of verification re-sends the email at most this often.
+const VERIFY_RESEND_MINUTES: i64 = 10;
+
+/// Issues a token for `purpose`, replacing any the user was sent before for the same purpose,
+/// so only the latest email works.
+async fn issue_email_token(db: &DatabaseConnection, user_id: i32, purpose: &str, ttl: Duration) -> Result<String, sea_orm::DbErr> {
+    email_token::Entity::delete_many()
+        .filter(email_token::Column::UserId.eq(user_id))
+        .filter(email_token::Column::Purpose.eq(purpose))
+        .exec(db)
+        .await?;
+    let token = new_secret_token();
+    let now = Utc::now();
+    let active = email_token::ActiveModel {
+        user_id: Set(user_id),
+        purpose: Set(purpose.to_string()),
+        token_hash: Set(secret_token_hash(&token)),
+        expires_at: Set(now + ttl),
+        created_at: Set(now),
+        ..Default::default()
+    };
+    active.insert(db).await?;
+    Ok(token)
+}
+
+/// Marks the token used and returns its user, or `None` when it is unknown, expired, already
+/// used or meant for something else.
+async fn consume_email_token<C: ConnectionTrait>(db: &C, token: &str, purpose: &str) -> Result<Option<i32>, sea_orm::DbErr> {
+    let row = email_token::Entity::find()
+        .filter(email_token::Column::TokenHash.eq(secret_token_hash(token)))
+        .filter(email_token::Column::Purpose.eq(purpose))
+        .one(db)
+        .await?;
+    let Some(row) = row else { return Ok(None) };
+    if row.used_at.is_some() || row.expires_at <= Utc::now() {
+        return Ok(None);
+    }
+    // Conditional, so the same token cannot be spent twice concurrently.
+    let claimed = email_token::Entity::update_many()
+        .col_expr(email_token::Column::UsedAt, Expr::value(Utc::now()))
+        .filter(email_token::Column::Id.eq(row.id))
+        .filter(email_token::Column::UsedAt.is_null())
+        .exec(db)
+        .await?
+        .rows_affected
+        == 1;
+    Ok(claimed.then_some(row.user_id))
+}
+
+/// Mails the user a token for `POST /api/auth/verify`.
+pub async fn send_verification_email(db: &DatabaseConnection, mailer: &SharedMailer, user: &user::Model) -> Result<(), sea_orm::DbErr> {
+    let token = issue_email_token(db, user.id, VERIFY_EMAIL, Duration::hours(VERIFY_EMAIL_HOURS)).await?;
+    let body = format!(
+        "Hi {},\n\nPlease confirm your email address with this code (valid for {} hours):\n\n{}\n\nIf you did not create an account, ignore this email.\n",
+        user.username, VERIFY_EMAIL_HOURS, token
+    );
+    send_in_background(mailer.clone(), Email { to: user.email.clone(), subject: "Confirm your email address".into(), body });
+    Ok(())
+}
+
+/// Like `send_verification_email`, unless one was sent in the last few minutes. Returns whether
+/// an email went out.
+pub async fn resend_verification_email(db: &DatabaseConnection, mailer: &SharedMailer, user: &user::Model) -> Result<bool, sea_orm::DbErr> {
+    let last = email_token::Entity::find()
+        .filter(email_token::Column::UserId.eq(user.id))
+        .filter(email_token::Column::Purpose.eq(VERIFY_EMAIL))
+        .order_by_desc(email_token::Column::CreatedAt)
+        .one(db)
+        .await?;
+    if last.is_some_and(|t| t.created_at > Utc::now() - Duration::minutes(VERIFY_RESEND_MINUTES)) {
+        return Ok(false);
+    }
+    send_verification_email(db, mailer, user).await?;
+    Ok(true)
+}
+
+/// Mails a reset token when `email` belongs to a user. Unknown addresses are silently ignored,
+/// so the endpoint cannot be used to find out who is registered.
+pub async fn request_password_reset(db: &DatabaseConnection, mailer: &SharedMailer, email: &str) -> Result<(), sea_orm::DbErr> {
+    let Some(user) = get_user_by_email(db, email.trim()).await? else { return Ok(()) };
+    let token = issue_email_token(db, user.id, RESET_PASSWORD, Duration::minutes(RESET_PASSWORD_MINUTES)).await?;
+    let body = format!(
+        "Hi {},\n\nUse this code to choose a new password (valid for {} minutes):\n\n{}\n\nIf you did not ask for a reset, ignore this email; your password is unchanged.\n",
+        user.username, RESET_PASSWORD_MINUTES, token
+    );
+    send_in_background(mailer.clone(), Email { to: user.email, subject: "Reset your password".into(), body });
+    Ok(())
+}
+
+/// Tells the owner their account was locked after repeated wrong passwords, with a token for
+/// `POST /api/auth/unlock`.
+pub async fn send_unlock_email(db: &DatabaseConnection, mailer: &SharedMailer, user: &user::Model) -> Result<(), sea_orm::DbErr> {
+    let token = issue_email_token(db, user.id, UNLOCK_ACCOUNT, Duration::hours(UNLOCK_ACCOUNT_HOURS)).await?;
+    let body = format!(
+        "Hi {},\n\nYour account was locked after too many wrong passwords. It unlocks by itself after a while, or right away with this code (valid for {} hours):\n\n{}\n\nIf this was not you, consider resetting your password.\n",
+        user.username, UNLOCK_ACCOUNT_HOURS, token
+    );
+    send_in_background(mailer.clone(), Email { to: user.email.clone(), subject: "Your account was locked".into(), body });
+    Ok(())
+}
+
+/// Whoever holds a mailed token acts as its user.
+fn token_holder(audit: &AuditContext, user_id: i32) -> AuditContext {
+    AuditContext { actor_user_id: audit.actor_user_id.or(Some(user_id)), ..audit.clone() }
+}
+
+/// Lifts a lockout early. Returns false when the token is not valid.
+pub async fn unlock_account(db: &DatabaseConnection, audit: &AuditContext, token: &str) -> Result<bool, sea_orm::DbErr> {
+    let txn = db.begin().await?;
+    let Some(user_id) = consume_email_token(&txn, token, UNLOCK_ACCOUNT).await? else { return Ok(false) };
+    let Some(model) = user::Entity::find_by_id(user_id).one(&txn).await? else { return Ok(false) };
+    let mut active: user::ActiveModel = model.clone().into();
+    active.failed_logins = Set(0);
+    active.locked_until = Set(None);
+    let updated = active.update(&txn).await?;
+    audit_update(&txn, &token_holder(audit, user_id), user_id, "user", user_id, &model, &updated).await?;
+    txn.commit().await?;
+    Ok(true)
+}
+
+/// Marks the user's email as verified. `None` when the token is not valid.
+pub async fn verify_email(db: &DatabaseConnection, audit: &AuditContext, token: &str) -> Result<Option<user::Model>, sea_orm::DbErr> {
+    let txn = db.begin().await?;
+    let Some(user_id) = consume_email_token(&txn, token, VERIFY_EMAIL).await? else { return Ok(None) };
+    let Some(model) = user::Entity::find_by_id(user_id).one(&txn).await? else { return Ok(None) };
+    if model.email_verified_at.is_some() {
+        txn.commit().await?;
+        return Ok(Some(model));
+    }
+    let mut active: user::ActiveModel = model.clone().into();
+    active.email_verified_at = Set(Some(Utc::now()));
+    let updated = active.update(&txn).await?;
+    audit_update(&txn, &token_holder(audit, user_id), user_id, "user", user_id, &model, &updated).await?;
+    txn.commit().await?;
+    Ok(Some(updated))
+}
+
+/// Sets a new password from a reset token and signs the user out everywhere. Receiving the
+/// email proves the address, so it also counts as verification, and it lifts a lockout.
+/// Returns false when the token is not valid.
+pub async fn reset_password(db: &DatabaseConnection, audit: &AuditContext, token: &str, password_hash: String) -> Result<bool, sea_orm::DbErr> {
+    let txn = db.begin().await?;
+    let Some(user_id) = consume_email_token(&txn, token, RESET_PASSWORD).await? else { return Ok(false) };
+    let Some(model) = user::Entity::find_by_id(user_id).one(&txn).await? else { return Ok(false) };
+    let verified = model.email_verified_at.unwrap_or_else(Utc::now);
+    let mut active: user::ActiveModel = model.clone().into();
+    active.password_hash = Set(password_hash);
+    active.email_verified_at = Set(Some(verified));
+    active.failed_logins = Set(0);
+    active.locked_until = Set(None);
+    let updated = active.update(&txn).await?;
+    audit_update(&txn, &token_holder(audit, user_id), user_id, "user", user_id, &model, &updated).await?;
+    txn.commit().await?;
+    revoke_all_sessions(db, user_id).await?;
+    Ok(true)
+}
@@ -0,0 +1,442 @@
+use data_encoding::{BASE64, HEXLOWER_PERMISSIVE};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use sea_orm::{ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait, IntoActiveModel, JoinType, QueryFilter, QueryOrder, QuerySelect, RelationTrait, Set, TransactionTrait};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, LazyLock, RwLock};
+use crate::models::{account, attachment, data_key, recurring_transaction, sealed::SealedColumn, transaction};
+use crate::services::{account_owner, blob_key, get_blob, thumbnail_key, AttachmentStorage};
+
+/// Starts a sealed column value: `enc1:<key id>:<base64 of nonce || ciphertext>`.
+const TEXT_PREFIX: &str = "enc1:";
+/// Starts plaintext stored while encryption is off that would otherwise read as sealed (or as
+/// escaped itself), e.g. a description typed as `enc1:…`.
+const PLAIN_PREFIX: &str = "enc0:";
+/// Starts a sealed attachment blob, followed by the key id, the nonce and the ciphertext.
+const BLOB_MAGIC: &[u8] = b"WENC1";
+const KEY_ID_LEN: usize = 32;
+const KEY_LEN: usize = 32;
+const TAG_LEN: usize = 16;
+
+#[derive(Debug)]
+pub struct EncryptionError(pub String);
+
+impl std::fmt::Display for EncryptionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "encryption: {}", self.0)
+    }
+}
+
+impl std::error::Error for EncryptionError {}
+
+impl From<DbErr> for EncryptionError {
+    fn from(e: DbErr) -> Self {
+        EncryptionError(e.to_string())
+    }
+}
+
+impl From<EncryptionError> for DbErr {
+    fn from(e: EncryptionError) -> Self {
+        DbErr::Custom(e.to_string())
+    }
+}
+
+fn cipher(key: &[u8]) -> Result<LessSafeKey, EncryptionError> {
+    UnboundKey::new(&AES_256_GCM, key)
+        .map(LessSafeKey::new)
+        .map_err(|_| EncryptionError(format!("keys must be {} bytes", KEY_LEN)))
+}
+
+/// AES-256-GCM with a random nonce: `nonce || ciphertext || tag`.
+fn seal(key: &LessSafeKey, aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let mut nonce = [0u8; NONCE_LEN];
+    getrandom::fill(&mut nonce).expect("operating system random source");
+    let mut out = Vec::with_capacity(NONCE_LEN + plaintext.len() + TAG_LEN);
+    out.extend_from_slice(&nonce);
+    let mut in_out = plaintext.to_vec();
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(aad), &mut in_out)
+        .expect("plaintext within AES-GCM limits");
+    out.extend_from_slice(&in_out);
+    out
+}
+
+fn open(key: &LessSafeKey, aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+    if sealed.len() < NONCE_LEN + TAG_LEN {
+        return Err(EncryptionError("ciphertext is truncated".into()));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).expect("nonce length");
+    let mut in_out = ciphertext.to_vec();
+    let plaintext = key
+        .open_in_place(nonce, Aad::from(aad), &mut in_out)
+        .map_err(|_| EncryptionError("ciphertext does not match its key".into()))?;
+    Ok(plaintext.to_vec())
+}
+
+/// 32 bytes, as 64 hex characters or base64.
+fn decode_key(text: &str) -> Result<Vec<u8>, EncryptionError> {
+    let text = text.trim();
+    let bytes = if text.len() == KEY_LEN * 2 && text.chars().all(|c| c.is_ascii_hexdigit()) {
+        HEXLOWER_PERMISSIVE.decode(text.as_bytes())
+    } else {
+        BASE64.decode(text.as_bytes())
+    }
+    .map_err(|_| EncryptionError("a key must be 64 hex characters or base64".into()))?;
+    if bytes.len() != KEY_LEN {
+        return Err(EncryptionError(format!("a key must be {} bytes, got {}", KEY_LEN, bytes.len())));
+    }
+    Ok(bytes)
+}
+
+/// An unwrapped data key; the bytes are kept for rewrapping.
+struct DataKey {
+    bytes: [u8; KEY_LEN],
+    cipher: LessSafeKey,
+}
+
+impl DataKey {
+    fn new(bytes: [u8; KEY_LEN]) -> Result<Self, EncryptionError> {
+        Ok(DataKey { bytes, cipher: cipher(&bytes)? })
+    }
+}
+
+struct MasterKey {
+    id: String,
+    key: LessSafeKey,
+}
+
+impl MasterKey {
+    fn new(key: &[u8]) -> Result<Self, EncryptionError> {
+        let id: String = Sha256::digest(key).iter().take(8).map(|b| format!("{:02x}", b)).collect();
+        Ok(MasterKey { id, key: cipher(key)? })
+    }
+}
+
+/// Wraps the data keys. The current key wraps new ones; previous keys are only needed to
+/// unwrap data keys until `rotate_encryption_keys` has rewrapped them.
+pub struct MasterKeys {
+    current: MasterKey,
+    previous: Vec<MasterKey>,
+}
+
+impl MasterKeys {
+    pub fn new(key: &[u8]) -> Result<Self, EncryptionError> {
+        Ok(MasterKeys { current: MasterKey::new(key)?, previous: Vec::new() })
+    }
+
+    /// A key written as 64 hex characters or base64 (e.g. `openssl rand -base64 32`).
+    pub fn parse(text: &str) -> Result<Self, EncryptionError> {
+        Self::new(&decode_key(text)?)
+    }
+
+    pub fn with_previous_key(mut self, text: &str) -> Result<Self, EncryptionError> {
+        self.previous.push(MasterKey::new(&decode_key(text)?)?);
+        Ok(self)
+    }
+
+    /// `None` when neither `ENCRYPTION_KEY` nor `ENCRYPTION_KEY_FILE` is set. A key file holds
+    /// the key as text, or as 32 raw bytes.
+    pub fn from_config(config: &crate::config::Config) -> Result<Option<Self>, EncryptionError> {
+        let keys = match (&config.encryption_key, &config.encryption_key_file) {
+            (Some(key), _) => Self::parse(key)?,
+            (None, Some(path)) => {
+                let bytes = std::fs::read(path).map_err(|e| EncryptionError(format!("{}: {}", path, e)))?;
+                if bytes.len() == KEY_LEN {
+                    Self::new(&bytes)?
+                } else {
+                    let text = String::from_utf8(bytes).map_err(|_| EncryptionError(format!("{}: not a key", path)))?;
+                    Self::parse(&text).map_err(|e| EncryptionError(format!("{}: {}", path, e.0)))?
+                }
+            }
+            (None, None) => {
+                if !config.encryption_previous_keys.is_empty() {
+                    return Err(EncryptionError("ENCRYPTION_PREVIOUS_KEYS is set without ENCRYPTION_KEY".into()));
+                }
+                return Ok(None);
+            }
+        };
+        config.encryption_previous_keys.iter().try_fold(keys, |keys, key| keys.with_previous_key(key)).map(Some)
+    }
+
+    /// Identifies the current key in `data_keys.master_key_id`: a prefix of its SHA-256.
+    pub fn key_id(&self) -> &str {
+        &self.current.id
+    }
+
+    fn wrap(&self, key_id: &str, data_key: &[u8]) -> String {
+        BASE64.encode(&seal(&self.current.key, key_id.as_bytes(), data_key))
+    }
+
+    fn unwrap(&self, row: &data_key::Model) -> Result<DataKey, EncryptionError> {
+        let master = std::iter::once(&self.current)
+            .chain(&self.previous)
+            .find(|m| m.id == row.master_key_id)
+            .ok_or_else(|| EncryptionError(format!("data key {} is wrapped by master key {}, which is not configured", row.key_id, row.master_key_id)))?;
+        let wrapped = BASE64.decode(row.wrapped_key.as_bytes()).map_err(|_| EncryptionError(format!("data key {} is corrupt", row.key_id)))?;
+        let bytes = open(&master.key, row.key_id.as_bytes(), &wrapped)?;
+        DataKey::new(bytes.try_into().map_err(|_| EncryptionError(format!("data key {} is corrupt", row.key_id)))?)
+    }
+}
+
+/// The installed master keys and every unwrapped data key, by key id. Rows are decrypted as
+/// they are read, where there is no database access, so all data keys are loaded up front.
+#[derive(Default)]
+struct Keyring {
+    master: Option<Arc<MasterKeys>>,
+    data_keys: HashMap<String, Arc<DataKey>>,
+}
+
+static KEYRING: LazyLock<RwLock<Keyring>> = LazyLock::new(Default::default);
+
+fn master_keys() -> Option<Arc<MasterKeys>> {
+    KEYRING.read().expect("keyring poisoned").master.clone()
+}
+
+fn cached_data_key(key_id: &str) -> Option<Arc<DataKey>> {
+    KEYRING.read().expect("keyring poisoned").data_keys.get(key_id).cloned()
+}
+
+fn cache_data_key(key_id: &str, key: DataKey) -> Arc<DataKey> {
+    let key = Arc::new(key);
+    KEYRING.write().expect("keyring poisoned").data_keys.insert(key_id.to_string(), key.clone());
+    key
+}
+
+/// Installs the master keys and unwraps the database's data keys. Without master keys nothing
+/// is encrypted, which is refused once the database holds data keys.
+pub async fn init_encryption(db: &DatabaseConnection, master: Option<MasterKeys>) -> Result<(), EncryptionError> {
+    let rows = data_key::Entity::find().all(db).await?;
+    let Some(master) = master else {
+        if !rows.is_empty() {
+            return Err(EncryptionError("the database holds encrypted data; set ENCRYPTION_KEY or ENCRYPTION_KEY_FILE".into()));
+        }
+        return Ok(());
+    };
+    let mut keys = HashMap::new();
+    for row in &rows {
+        keys.insert(row.key_id.clone(), Arc::new(master.unwrap(row)?));
+    }
+    let mut keyring = KEYRING.write().expect("keyring poisoned");
+    keyring.master = Some(Arc::new(master));
+    keyring.data_keys.extend(keys);
+    Ok(())
+}
+
+pub fn encryption_enabled() -> bool {
+    master_keys().is_some()
+}
+
+async fn create_data_key<C: ConnectionTrait>(db: &C, master: &MasterKeys, user_id: i32) -> Result<(String, Arc<DataKey>), DbErr> {
+    let mut bytes = [0u8; KEY_LEN];
+    getrandom::fill(&mut bytes).expect("operating system random source");
+    let key_id = uuid::Uuid::new_v4().simple().to_string();
+    data_key::ActiveModel {
+        key_id: Set(key_id.clone()),
+        user_id: Set(Some(user_id)),
+        wrapped_key: Set(master.wrap(&key_id, &bytes)),
+        master_key_id: Set(master.key_id().to_string()),
+        created_at: Set(chrono::Utc::now()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+    let key = DataKey::new(bytes)?;
+    Ok((key_id.clone(), cache_data_key(&key_id, key)))
+}
+
+/// The newest data key of `user_id`, created on first use. `None` when encryption is off.
+async fn current_data_key<C: ConnectionTrait>(db: &C, user_id: i32) -> Result<Option<(String, Arc<DataKey>)>, DbErr> {
+    let Some(master) = master_keys() else { return Ok(None) };
+    let newest = data_key::Entity::find().filter(data_key::Column::UserId.eq(user_id)).order_by_desc(data_key::Column::Id).one(db).await?;
+    let key = match newest {
+        Some(row) => match cached_data_key(&row.key_id) {
+            Some(key) => (row.key_id, key),
+            // Created by another process since startup.
+            None => {
+                let key = master.unwrap(&row)?;
+                (row.key_id.clone(), cache_data_key(&row.key_id, key))
+            }
+        },
+        None => create_data_key(db, &master, user_id).await?,
+    };
+    Ok(Some(key))
+}
+
+/// Splits a sealed column value into its key id and sealed bytes; `None` for plaintext.
+fn parse_sealed_text(stored: &str) -> Option<(&str, Vec<u8>)> {
+    let (key_id, body) = stored.strip_prefix(TEXT_PREFIX)?.split_once(':')?;
+    if key_id.len() != KEY_ID_LEN || !key_id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some((key_id, BASE64.decode(body.as_bytes()).ok()?))
+}
+
+/// Whether `open_text` would not return `text` as it is.
+fn needs_escape(text: &str) -> bool {
+    text.starts_with(PLAIN_PREFIX) || parse_sealed_text(text).is_some()
+}
+
+/// Encrypts a column value with the owner's data key. When encryption is off it is stored as
+/// is, unless it would read as sealed.
+pub async fn seal_text<C: ConnectionTrait>(db: &C, user_id: i32, plaintext: &str) -> Result<String, DbErr> {
+    match current_data_key(db, user_id).await? {
+        Some((key_id, key)) => Ok(format!("{}{}:{}", TEXT_PREFIX, key_id, BASE64.encode(&seal(&key.cipher, key_id.as_bytes(), plaintext.as_bytes())))),
+        None if needs_escape(plaintext) => Ok(format!("{}{}", PLAIN_PREFIX, plaintext)),
+        None => Ok(plaintext.to_string()),
+    }
+}
+
+/// Decrypts a value written by `seal_text`. Plaintext (written before encryption was turned
+/// on) passes through.
+pub fn open_text(stored: &str) -> Result<String, EncryptionError> {
+    if let Some(plaintext) = stored.strip_prefix(PLAIN_PREFIX) {
+        return Ok(plaintext.to_string());
+    }
+    let Some((key_id, sealed)) = parse_sealed_text(stored) else { return Ok(stored.to_string()) };
+    let key = cached_data_key(key_id).ok_or_else(|| EncryptionError(format!("unknown data key {}", key_id)))?;
+    String::from_utf8(open(&key.cipher, key_id.as_bytes(), &sealed)?).map_err(|_| EncryptionError("sealed text is not UTF-8".into()))
+}
+
+/// Encrypts an attachment blob or thumbnail with the owner's data key; unchanged when
+/// encryption is off.
+pub async fn seal_blob<C: ConnectionTrait>(db: &C, user_id: i32, bytes: &[u8]) -> Result<Vec<u8>, DbErr> {
+    match current_data_key(db, user_id).await? {
+        Some((key_id, key)) => Ok([BLOB_MAGIC, key_id.as_bytes(), &seal(&key.cipher, key_id.as_bytes(), bytes)].concat()),
+        None => Ok(bytes.to_vec()),
+    }
+}
+
+/// Decrypts a blob written by `seal_blob`; plaintext blobs pass through.
+pub fn open_blob(bytes: Vec<u8>) -> Result<Vec<u8>, EncryptionError> {
+    let Some(rest) = bytes.strip_prefix(BLOB_MAGIC) else { return Ok(bytes) };
+    let (key_id, sealed) = rest.split_at_checked(KEY_ID_LEN).ok_or_else(|| EncryptionError("sealed blob is truncated".into()))?;
+    let key_id = std::str::from_utf8(key_id).map_err(|_| EncryptionError("sealed blob is corrupt".into()))?;
+    let key = cached_data_key(key_id).ok_or_else(|| EncryptionError(format!("unknown data key {}", key_id)))?;
+    open(&key.cipher, key_id.as_bytes(), sealed)
+}
+
+/// For an entity's `before_save`: the plaintext to pass through `seal_text`, when the value is
+/// about to be written (set, or any value on insert) and encryption is on or the value needs
+/// escaping. Values are always plaintext in memory, so one that looks sealed is user input.
+pub fn pending_seal<T: SealedColumn + Into<sea_orm::Value>>(value: &ActiveValue<T>, insert: bool) -> Option<String> {
+    let text = match value {
+        ActiveValue::Set(v) => v.plaintext()?,
+        ActiveValue::Unchanged(v) if insert => v.plaintext()?,
+        _ => return None,
+    };
+    (encryption_enabled() || needs_escape(text)).then(|| text.to_string())
+}
+
+/// The owner of a transaction being saved, from its account or, failing that, its stored row.
+pub async fn transaction_owner<C: ConnectionTrait>(db: &C, account_id: Option<i32>, id: Option<i32>) -> Result<i32, DbErr> {
+    let account_id = match (account_id, id) {
+        (Some(account_id), _) => account_id,
+        (None, Some(id)) => transaction::Entity::find_by_id(id)
+            .select_only()
+            .column(transaction::Column::AccountId)
+            .into_tuple()
+            .one(db)
+            .await?
+            .ok_or_else(|| DbErr::RecordNotFound(format!("transaction {}", id)))?,
+        (None, None) => return Err(DbErr::Custom("transaction without an account".into())),
+    };
+    account_owner(db, account_id).await
+}
+
+/// What `rotate_encryption_keys` did.
+#[derive(Debug, Default)]
+pub struct KeyRotation {
+    pub master_key_id: String,
+    pub data_keys: usize,
+    pub rows: usize,
+    pub blobs: usize,
+}
+
+/// Issues new data keys, re-encrypts every sealed column and attachment blob with them
+/// (encrypting any plaintext left from before encryption was turned on), and rewraps all data
+/// keys with the current master key, after which previous master keys can be dropped. Old data
+/// keys are kept: the audit log is append-only and its entries stay sealed with them.
+///
+/// Meant to run with the server stopped (`server rotate-keys`), after `init_encryption`.
+pub async fn rotate_encryption_keys(db: &DatabaseConnection, storage: &dyn AttachmentStorage) -> Result<KeyRotation, EncryptionError> {
+    let master = master_keys().ok_or_else(|| EncryptionError("set ENCRYPTION_KEY or ENCRYPTION_KEY_FILE to rotate keys".into()))?;
+    let mut report = KeyRotation { master_key_id: master.key_id().to_string(), ..Default::default() };
+    let txn = db.begin().await?;
+
+    let owners: Vec<i32> = account::Entity::find().select_only().column(account::Column::UserId).distinct().into_tuple().all(&txn).await?;
+    for user_id in owners {
+        create_data_key(&txn, &master, user_id).await?;
+    }
+    // Saving a value re-seals it with the owner's newest key.
+    for row in account::Entity::find().all(&txn).await? {
+        let name = row.name.clone();
+        let mut active = row.into_active_model();
+        active.name = Set(name);
+        active.update(&txn).await?;
+        report.rows += 1;
+    }
+    for row in transaction::Entity::find().all(&txn).await? {
+        let (description, counterparty) = (row.description.clone(), row.counterparty.clone());
+        let mut active = row.into_active_model();
+        active.description = Set(description);
+        active.counterparty = Set(counterparty);
+        active.update(&txn).await?;
+        report.rows += 1;
+    }
+    for row in recurring_transaction::Entity::find().all(&txn).await? {
+        let description = row.description.clone();
+        let mut active = row.into_active_model();
+        active.description = Set(description);
+        active.update(&txn).await?;
+        report.rows += 1;
+    }
+    for row in attachment::Entity::find().all(&txn).await? {
+        let file_name = row.file_name.clone();
+        let mut active = row.into_active_model();
+        active.file_name = Set(file_name);
+        active.update(&txn).await?;
+        report.rows += 1;
+    }
+    for row in data_key::Entity::find().all(&txn).await? {
+        let key = cached_data_key(&row.key_id).map_or_else(|| master.unwrap(&row).map(Arc::new), Ok)?;
+        let mut active = row.clone().into_active_model();
+        active.wrapped_key = Set(master.wrap(&row.key_id, &key.bytes));
+        active.master_key_id = Set(master.key_id().to_string());
+        active.update(&txn).await?;
+        report.data_keys += 1;
+    }
+    txn.commit().await?;
+
+    // Blobs go after the commit: their new key must exist before anything is sealed with it.
+    // Each owner's copy is re-sealed with their key; blobs still shared from before blobs were
+    // kept per user are copied to every owner, then removed.
+    let blobs: Vec<(i32, String, bool)> = attachment::Entity::find()
+        .select_only()
+        .column(account::Column::UserId)
+        .columns([attachment::Column::Sha256, attachment::Column::HasThumbnail])
+        .distinct()
+        .inner_join(transaction::Entity)
+        .join(JoinType::InnerJoin, transaction::Relation::Account.def())
+        .into_tuple()
+        .all(db)
+        .await?;
+    let mut shared = BTreeSet::new();
+    for (user_id, sha256, has_thumbnail) in blobs {
+        let key = blob_key(user_id, &sha256);
+        let mut keys = vec![(key.clone(), sha256.clone())];
+        if has_thumbnail {
+            keys.push((thumbnail_key(&key), thumbnail_key(&sha256)));
+        }
+        for (key, legacy) in keys {
+            let io = |e: std::io::Error| EncryptionError(format!("attachment {}: {}", key, e));
+            let bytes = open_blob(get_blob(storage, &key, &legacy).map_err(io)?)?;
+            storage.put(&key, &seal_blob(db, user_id, &bytes).await?).map_err(io)?;
+            report.blobs += 1;
+            shared.insert(legacy);
+        }
+    }
+    for legacy in shared {
+        storage.delete(&legacy).map_err(|e| EncryptionError(format!("attachment {}: {}", legacy, e)))?;
+    }
+    Ok(report)
+}
@@ -0,0 +1,284 @@
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use serde::Serialize;
+use crate::models::{account, transaction};
+use crate::services::{created_before, day_of_month, find_accounts_by_user, find_investment_plans_by_user, find_recurring_transactions_by_user, occurrences, signed_amount, Frequency, ServiceError};
+use sea_orm::prelude::Decimal;
+use std::collections::{HashMap, HashSet};
+
+pub const MAX_FORECAST_DAYS: u32 = 730;
+const MAX_LOOKBACK_DAYS: u32 = 3650;
+/// Account types whose balance is expected to be negative; they never raise low-balance warnings.
+const LIABILITY_TYPES: [&str; 3] = ["credit", "credit_card", "loan"];
+
+pub struct ForecastOptions {
+    pub days: u32,
+    pub utc_offset_minutes: i32,
+    /// Warn when a non-liability account is projected below this.
+    pub min_balance: Decimal,
+    /// History used for the discretionary spending baseline.
+    pub lookback_days: u32,
+}
+
+impl Default for ForecastOptions {
+    fn default() -> Self {
+        ForecastOptions { days: 90, utc_offset_minutes: 0, min_balance: Decimal::ZERO, lookback_days: 90 }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ForecastEvent {
+    /// `recurring`, `investment` or `card_payment`.
+    pub kind: &'static str,
+    pub description: String,
+    pub amount: Decimal,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ForecastPoint {
+    pub date: NaiveDate,
+    /// End-of-day balance, after the baseline and any events.
+    pub balance: Decimal,
+    pub events: Vec<ForecastEvent>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AccountForecast {
+    pub account_id: i32,
+    pub name: String,
+    pub account_type: String,
+    pub currency: String,
+    pub starting_balance: Decimal,
+    pub ending_balance: Decimal,
+    pub lowest_balance: Decimal,
+    pub lowest_date: NaiveDate,
+    /// Average daily discretionary spending, deducted every day.
+    pub daily_baseline: Decimal,
+    pub points: Vec<ForecastPoint>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct LowBalanceWarning {
+    pub account_id: i32,
+    pub name: String,
+    /// First day the balance drops below `min_balance`.
+    pub date: NaiveDate,
+    pub balance: Decimal,
+    pub lowest_balance: Decimal,
+    pub lowest_date: NaiveDate,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Forecast {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub days: u32,
+    pub min_balance: Decimal,
+    pub accounts: Vec<AccountForecast>,
+    pub warnings: Vec<LowBalanceWarning>,
+}
+
+/// A credit card whose statement is paid from another account on its due day.
+struct Card {
+    account_id: i32,
+    payer_id: i32,
+    statement_day: u32,
+    due_day: u32,
+    /// Statement balance still to be paid, as a positive amount.
+    owed: Decimal,
+}
+
+fn is_day(date: NaiveDate, day: u32) -> bool {
+    day_of_month(date.year(), date.month(), day) == Some(date)
+}
+
+/// Most recent statement closing date on or before `today`.
+fn last_statement(today: NaiveDate, day: u32) -> Option<NaiveDate> {
+    let this_month = day_of_month(today.year(), today.month(), day)?;
+    if this_month <= today {
+        return Some(this_month);
+    }
+    let prev = today.with_day(1)?.pred_opt()?;
+    day_of_month(prev.year(), prev.month(), day)
+}
+
+/// Projects every account's balance day by day from tomorrow on. Each day applies the average
+/// discretionary spending of the lookback window, then scheduled recurring transactions and
+/// investment plans, then credit card payments on due days; statements close at end of day.
+pub async fn build_forecast(db: &DatabaseConnection, user_id: i32, opts: ForecastOptions) -> Result<Forecast, ServiceError> {
+    if opts.days == 0 || opts.days > MAX_FORECAST_DAYS {
+        return Err(ServiceError::Invalid(format!("days must be between 1 and {}", MAX_FORECAST_DAYS)));
+    }
+    if opts.lookback_days == 0 || opts.lookback_days > MAX_LOOKBACK_DAYS {
+        return Err(ServiceError::Invalid(format!("lookback_days must be between 1 and {}", MAX_LOOKBACK_DAYS)));
+    }
+    let offset = Duration::minutes(opts.utc_offset_minutes as i64);
+    let local = |t: &transaction::Model| (t.created_at + offset).date_naive();
+    let today = (Utc::now() + offset).date_naive();
+    let from = today + Duration::days(1);
+    let to = today + Duration::days(opts.days as i64);
+    let window_start = today - Duration::days(opts.lookback_days as i64);
+
+    let accounts: Vec<account::Model> = find_accounts_by_user(db, user_id).await?;
+    let ids: HashSet<i32> = accounts.iter().map(|a| a.id).collect();
+    let end_of_today = (today + Duration::days(1)).and_hms_opt(0, 0, 0).expect("midnight").and_utc() - offset;
+    let transactions = transaction::Entity::find()
+        .filter(transaction::Column::AccountId.is_in(ids.iter().copied()))
+        .filter(created_before(end_of_today))
+        .all(db)
+        .await?;
+    let recurring: Vec<_> = find_recurring_transactions_by_user(db, user_id).await?.into_iter().filter(|r| r.active).collect();
+    let plans: Vec<_> = find_investment_plans_by_user(db, user_id).await?.into_iter().filter(|p| p.active).collect();
+
+    let mut balances: HashMap<i32, Decimal> = accounts.iter().map(|a| (a.id, a.balance)).collect();
+    for t in &transactions {
+        *balances.entry(t.account_id).or_default() += signed_amount(t);
+    }
+    let starting = balances.clone();
+
+    // Spending already covered by a recurring expense stays out of the baseline.
+    let scheduled: HashSet<(i32, String)> = recurring
+        .iter()
+        .filter(|r| r.transaction_type == "expense")
+        .map(|r| (r.account_id, r.description.trim().to_lowercase()))
+        .collect();
+    let mut spent: HashMap<i32, Decimal> = HashMap::new();
+    for t in &transactions {
+        let date = local(t);
+        if t.transaction_type == "expense"
+            && date > window_start
+            && !scheduled.contains(&(t.account_id, t.description.trim().to_lowercase()))
+        {
+            *spent.entry(t.account_id).or_default() += t.amount;
+        }
+    }
+    let baselines: HashMap<i32, Decimal> = spent
+        .into_iter()
+        .map(|(id, total)| (id, (total / Decimal::from(opts.lookback_days)).round_dp(2)))
+        .collect();
+
+    let mut events: HashMap<(i32, NaiveDate), Vec<ForecastEvent>> = HashMap::new();
+    for r in &recurring {
+        let amount = if r.transaction_type == "expense" { -r.amount } else { r.amount };
+        for date in occurrences(r.start_date, Frequency::parse(&r.frequency)?, r.interval, r.end_date, from, to) {
+            events.entry((r.account_id, date)).or_default().push(ForecastEvent {
+                kind: "recurring",
+                description: r.description.clone().into(),
+                amount,
+            });
+        }
+    }
+    for p in &plans {
+        for date in occurrences(p.start_date, Frequency::parse(&p.frequency)?, p.interval, p.end_date, from, to) {
+            events.entry((p.account_id, date)).or_default().push(ForecastEvent {
+                kind: "investment",
+                description: format!("{} ({})", p.name, p.symbol),
+                amount: -p.amount,
+            });
+        }
+    }
+
+    let names: HashMap<i32, &str> = accounts.iter().map(|a| (a.id, a.name.as_str())).collect();
+    let mut cards = Vec::new();
+    for a in &accounts {
+        let (Some(statement_day), Some(due_day), Some(payer_id)) = (a.statement_day, a.payment_due_day, a.payment_account_id) else {
+            continue;
+        };
+        if !ids.contains(&payer_id) {
+            continue;
+        }
+        let (statement_day, due_day) = (statement_day as u32, due_day as u32);
+        // What the last statement left owing, less anything paid onto the card since.
+        let owed = match last_statement(today, statement_day) {
+            Some(closed) => {
+                let mut at_close = a.balance;
+                let mut paid = Decimal::ZERO;
+                for t in transactions.iter().filter(|t| t.account_id == a.id) {
+                    let (date, signed) = (local(t), signed_amount(t));
+                    if date <= closed {
+                        at_close += signed;
+                    } else if date <= today && signed > Decimal::ZERO {
+                        paid += signed;
+                    }
+                }
+                (-at_close - paid).max(Decimal::ZERO)
+            }
+            None => Decimal::ZERO,
+        };
+        cards.push(Card { account_id: a.id, payer_id, statement_day, due_day, owed });
+    }
+
+    let mut points: HashMap<i32, Vec<ForecastPoint>> = HashMap::new();
+    let mut date = from;
+    while date <= to {
+        let mut today_events: HashMap<i32, Vec<ForecastEvent>> = HashMap::new();
+        for a in &accounts {
+            let balance = balances.entry(a.id).or_default();
+            *balance -= baselines.get(&a.id).copied().unwrap_or_default();
+            for e in events.remove(&(a.id, date)).unwrap_or_default() {
+                *balance += e.amount;
+                today_events.entry(a.id).or_default().push(e);
+            }
+        }
+        for card in cards.iter_mut().filter(|c| is_day(date, c.due_day) && c.owed > Decimal::ZERO) {
+            let owed = std::mem::take(&mut card.owed);
+            *balances.entry(card.account_id).or_default() += owed;
+            *balances.entry(card.payer_id).or_default() -= owed;
+            today_events.entry(card.account_id).or_default().push(ForecastEvent {
+                kind: "card_payment",
+                description: format!("Payment from {}", names[&card.payer_id]),
+                amount: owed,
+            });
+            today_events.entry(card.payer_id).or_default().push(ForecastEvent {
+                kind: "card_payment",
+                description: format!("Payment to {}", names[&card.account_id]),
+                amount: -owed,
+            });
+        }
+        for card in cards.iter_mut().filter(|c| is_day(date, c.statement_day)) {
+            card.owed = (-balances[&card.account_id]).max(Decimal::ZERO);
+        }
+        for a in &accounts {
+            points.entry(a.id).or_default().push(ForecastPoint {
+                date,
+                balance: balances[&a.id].normalize(),
+                events: today_events.remove(&a.id).unwrap_or_default(),
+            });
+        }
+        date += Duration::days(1);
+    }
+
+    let mut out = Vec::new();
+    let mut warnings = Vec::new();
+    for a in accounts {
+        let points = points.remove(&a.id).unwrap_or_default();
+        let lowest = points.iter().min_by_key(|p| p.balance);
+        let (lowest_balance, lowest_date) = lowest.map_or((starting[&a.id], from), |p| (p.balance, p.date));
+        if !LIABILITY_TYPES.contains(&a.account_type.as_str()) {
+            if let Some(p) = points.iter().find(|p| p.balance < opts.min_balance) {
+                warnings.push(LowBalanceWarning {
+                    account_id: a.id,
+                    name: a.name.to_string(),
+                    date: p.date,
+                    balance: p.balance,
+                    lowest_balance,
+                    lowest_date,
+                });
+            }
+        }
+        out.push(AccountForecast {
+            account_id: a.id,
+            starting_balance: starting[&a.id].normalize(),
+            ending_balance: points.last().map_or(starting[&a.id], |p| p.balance).normalize(),
+            lowest_balance,
+            lowest_date,
+            daily_baseline: baselines.get(&a.id).copied().unwrap_or_default().normalize(),
+            points,
+            name: a.name.into(),
+            account_type: a.account_type,
+            currency: a.currency,
+        });
+    }
+    warnings.sort_by_key(|w| (w.date, w.account_id));
+    Ok(Forecast { from, to, days: opts.days, min_balance: opts.min_balance, accounts: out, warnings })
+}
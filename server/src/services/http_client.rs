@@ -0,0 +1,182 @@
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::{self, pki_types::ServerName, ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+use url::Url;
+
+/// Whole request, from connecting to the last byte of the body.
+pub const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+/// Discovery documents, key sets and token responses are small; anything bigger is refused.
+const MAX_RESPONSE_BYTES: usize = 1024 * 1024;
+
+/// Server certificates are checked against the Mozilla roots bundled with `webpki-roots`.
+static TLS_CONFIG: LazyLock<Arc<ClientConfig>> = LazyLock::new(|| {
+    let roots = RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let config = ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .expect("ring supports the default TLS versions")
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Arc::new(config)
+});
+
+#[derive(Debug)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+#[derive(Debug)]
+pub struct HttpError(pub String);
+
+impl std::fmt::Display for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "http: {}", self.0)
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+impl From<std::io::Error> for HttpError {
+    fn from(e: std::io::Error) -> Self {
+        HttpError(e.to_string())
+    }
+}
+
+/// `GET url`, for discovery documents and key sets.
+pub async fn http_get(url: &Url) -> Result<HttpResponse, HttpError> {
+    http_request("GET", url, &[("Accept", "application/json")], None).await
+}
+
+/// `POST url` with a form body, for token endpoints.
+pub async fn http_post_form(url: &Url, headers: &[(&str, &str)], form: &[(&str, &str)]) -> Result<HttpResponse, HttpError> {
+    let body = url::form_urlencoded::Serializer::new(String::new()).extend_pairs(form).finish();
+    let mut all = vec![("Accept", "application/json"), ("Content-Type", "application/x-www-form-urlencoded")];
+    all.extend_from_slice(headers);
+    http_request("POST", url, &all, Some(body.into_bytes())).await
+}
+
+/// A one-shot HTTP/1.1 request over plain TCP or TLS. Just enough client for talking to
+/// identity providers: one connection per request, no redirects, no compression.
+pub async fn http_request(method: &str, url: &Url, headers: &[(&str, &str)], body: Option<Vec<u8>>) -> Result<HttpResponse, HttpError> {
+    tokio::time::timeout(HTTP_TIMEOUT, send(method, url, headers, body))
+        .await
+        .map_err(|_| HttpError(format!("{} timed out", url)))?
+}
+
+async fn send(method: &str, url: &Url, headers: &[(&str, &str)], body: Option<Vec<u8>>) -> Result<HttpResponse, HttpError> {
+    let host = url.host_str().ok_or_else(|| HttpError(format!("{} has no host", url)))?;
+    let port = url.port_or_known_default().ok_or_else(|| HttpError(format!("{} has no port", url)))?;
+    let mut target = url.path().to_string();
+    if let Some(query) = url.query() {
+        target.push('?');
+        target.push_str(query);
+    }
+    let host_header = match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    };
+
+    let mut request = format!("{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nUser-Agent: your-wallet\r\n", method, target, host_header);
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    let body = body.unwrap_or_default();
+    if !body.is_empty() || method == "POST" {
+        request.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+    request.push_str("\r\n");
+    let mut request = request.into_bytes();
+    request.extend_from_slice(&body);
+
+    // IPv6 literals come bracketed from `Url`; sockets and SNI want them bare.
+    let bare_host = host.trim_start_matches('[').trim_end_matches(']');
+    let tcp = TcpStream::connect((bare_host, port)).await?;
+    let raw = match url.scheme() {
+        "http" => exchange(tcp, &request).await?,
+        "https" => {
+            let name = ServerName::try_from(bare_host.to_string()).map_err(|e| HttpError(e.to_string()))?;
+            let tls = TlsConnector::from(TLS_CONFIG.clone()).connect(name, tcp).await?;
+            exchange(tls, &request).await?
+        }
+        other => return Err(HttpError(format!("unsupported scheme {}", other))),
+    };
+    parse_response(&raw)
+}
+
+async fn exchange<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, request: &[u8]) -> Result<Vec<u8>, HttpError> {
+    stream.write_all(request).await?;
+    stream.flush().await?;
+    let mut raw = Vec::new();
+    let mut chunk = [0u8; 8192];
+    loop {
+        let n = match stream.read(&mut chunk).await {
+            Ok(n) => n,
+            // Servers often close TLS connections without close_notify; the body length is
+            // checked in `parse_response` anyway.
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => 0,
+            Err(e) => return Err(e.into()),
+        };
+        if n == 0 {
+            break;
+        }
+        raw.extend_from_slice(&chunk[..n]);
+        if raw.len() > MAX_RESPONSE_BYTES {
+            return Err(HttpError("response too large".into()));
+        }
+    }
+    Ok(raw)
+}
+
+fn parse_response(raw: &[u8]) -> Result<HttpResponse, HttpError> {
+    let mut headers = [httparse::EMPTY_HEADER; 64];
+    let mut response = httparse::Response::new(&mut headers);
+    let head_len = match response.parse(raw).map_err(|e| HttpError(e.to_string()))? {
+        httparse::Status::Complete(len) => len,
+        httparse::Status::Partial => return Err(HttpError("incomplete response".into())),
+    };
+    let status = response.code.unwrap_or_default();
+    let header = |name: &str| {
+        response.headers.iter().find(|h| h.name.eq_ignore_ascii_case(name)).and_then(|h| std::str::from_utf8(h.value).ok()).map(str::trim)
+    };
+    let rest = &raw[head_len..];
+    let body = if header("transfer-encoding").is_some_and(|v| v.eq_ignore_ascii_case("chunked")) {
+        dechunk(rest)?
+    } else if let Some(length) = header("content-length") {
+        let length: usize = length.parse().map_err(|_| HttpError("bad Content-Length".into()))?;
+        if rest.len() < length {
+            return Err(HttpError("truncated response".into()));
+        }
+        rest[..length].to_vec()
+    } else {
+        rest.to_vec()
+    };
+    Ok(HttpResponse { status, body })
+}
+
+fn dechunk(mut rest: &[u8]) -> Result<Vec<u8>, HttpError> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = rest.windows(2).position(|w| w == b"\r\n").ok_or_else(|| HttpError("truncated chunk".into()))?;
+        let size_field = std::str::from_utf8(&rest[..line_end]).map_err(|_| HttpError("bad chunk size".into()))?;
+        let size_hex = size_field.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size_hex, 16).map_err(|_| HttpError("bad chunk size".into()))?;
+        rest = &rest[line_end + 2..];
+        if size == 0 {
+            return Ok(body);
+        }
+        if rest.len() < size + 2 {
+            return Err(HttpError("truncated chunk".into()));
+        }
+        body.extend_from_slice(&rest[..size]);
+        rest = &rest[size + 2..];
+    }
+}
@@ -0,0 +1,375 @@
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set, TransactionTrait};
+use serde::Serialize;
+use crate::models::{account, asset, import_batch, import_holding_change, import_profile, transaction};
+use crate::services::{audit_create, audit_delete, audit_update, parse_alipay, parse_csv, parse_ofx, parse_qif, parse_wechat, AuditContext, CsvMapping, ServiceError, tags_to_json, STATUS_PENDING, STATUS_RECONCILED};
+use sea_orm::prelude::Decimal;
+use std::collections::{HashMap, HashSet};
+
+pub const BATCH_COMMITTED: &str = "committed";
+pub const BATCH_ROLLED_BACK: &str = "rolled_back";
+
+/// A parsed statement line, ready to become a transaction.
+#[derive(Serialize, Clone, Debug)]
+pub struct ImportRow {
+    /// 1-based line in the source file, for error reporting.
+    pub line: usize,
+    pub date: chrono::DateTime<chrono::Utc>,
+    pub transaction_type: String,
+    pub amount: Decimal,
+    pub description: String,
+    pub category: Option<String>,
+    pub counterparty: Option<String>,
+    /// Source-side identifier (trade number, FITID).
+    pub external_id: Option<String>,
+    /// For refunds: `external_id` of the original payment.
+    pub refund_of: Option<String>,
+    /// Added by the user's rules.
+    pub tags: Vec<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ImportIssue {
+    pub line: usize,
+    pub message: String,
+}
+
+/// A change to an investment holding found in a brokerage statement.
+#[derive(Serialize, Clone, Debug)]
+pub struct HoldingChange {
+    pub line: usize,
+    pub date: chrono::DateTime<chrono::Utc>,
+    pub symbol: String,
+    pub name: String,
+    pub asset_type: String,
+    /// Positive for buys, negative for sells.
+    pub units: Decimal,
+    pub unit_price: Decimal,
+    /// Same id as the cash row of the trade, so both dedupe together.
+    pub external_id: Option<String>,
+}
+
+/// Dry-run result: what would be inserted and what could not be parsed.
+#[derive(Serialize, Debug, Default)]
+pub struct ImportPreview {
+    pub rows: Vec<ImportRow>,
+    pub errors: Vec<ImportIssue>,
+    /// Lines intentionally left out (internal transfers, closed trades, already imported, ...).
+    pub ignored: Vec<ImportIssue>,
+    pub holdings: Vec<HoldingChange>,
+    /// Statement accounts in the file that were not imported.
+    pub unmatched_accounts: Vec<String>,
+}
+
+/// Parses an upload in the given format. `csv` requires a mapping profile; for multi-account
+/// OFX files `statement_account` picks the statement to import.
+pub fn parse_import(format: &str, bytes: &[u8], mapping: Option<&CsvMapping>, statement_account: Option<&str>) -> Result<ImportPreview, ServiceError> {
+    match format {
+        "csv" => {
+            let mapping = mapping.ok_or_else(|| ServiceError::Invalid("csv import requires profile_id".into()))?;
+            parse_csv(bytes, mapping)
+        }
+        "alipay" => parse_alipay(bytes),
+        "wechat" => parse_wechat(bytes),
+        "ofx" | "qfx" => parse_ofx(bytes, statement_account),
+        "qif" => parse_qif(bytes),
+        other => Err(ServiceError::Invalid(format!("unsupported import format: {}", other))),
+    }
+}
+
+pub async fn create_import_profile(
+    db: &DatabaseConnection,
+    user_id: i32,
+    name: String,
+    mapping: CsvMapping,
+) -> Result<import_profile::Model, ServiceError> {
+    mapping.validate()?;
+    let mapping = serde_json::to_value(&mapping).map_err(|e| ServiceError::Invalid(e.to_string()))?;
+    let active = import_profile::ActiveModel {
+        user_id: Set(user_id),
+        name: Set(name),
+        mapping: Set(mapping),
+        ..Default::default()
+    };
+    Ok(active.insert(db).await?)
+}
+
+pub async fn get_import_profile_by_id(
+    db: &DatabaseConnection,
+    id: i32,
+) -> Result<Option<import_profile::Model>, sea_orm::DbErr> {
+    import_profile::Entity::find_by_id(id).one(db).await
+}
+
+pub async fn find_import_profiles_by_user(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<Vec<import_profile::Model>, sea_orm::DbErr> {
+    import_profile::Entity::find()
+        .filter(import_profile::Column::UserId.eq(user_id))
+        .all(db)
+        .await
+}
+
+pub async fn delete_import_profile(
+    db: &DatabaseConnection,
+    id: i32,
+) -> Result<u64, sea_orm::DbErr> {
+    let res = import_profile::Entity::delete_by_id(id).exec(db).await?;
+    Ok(res.rows_affected)
+}
+
+/// Moves rows whose `external_id` was already imported into the account (or repeats within
+/// the file) to `ignored`, so re-importing the same statement is a no-op.
+pub async fn drop_already_imported(
+    db: &DatabaseConnection,
+    account_id: i32,
+    preview: &mut ImportPreview,
+) -> Result<(), sea_orm::DbErr> {
+    let ids: Vec<String> = preview.rows.iter().filter_map(|r| r.external_id.clone()).collect();
+    if ids.is_empty() {
+        return Ok(());
+    }
+    let mut seen: HashSet<String> = HashSet::new();
+    for chunk in ids.chunks(500) {
+        let existing = transaction::Entity::find()
+            .filter(transaction::Column::AccountId.eq(account_id))
+            .filter(transaction::Column::ExternalId.is_in(chunk.iter().cloned()))
+            .all(db)
+            .await?;
+        seen.extend(existing.into_iter().filter_map(|t| t.external_id));
+    }
+    let mut duplicates: HashSet<String> = HashSet::new();
+    let rows = std::mem::take(&mut preview.rows);
+    for row in rows {
+        match &row.external_id {
+            Some(ext) if !seen.insert(ext.clone()) => {
+                preview.ignored.push(ImportIssue { line: row.line, message: format!("already imported: {}", ext) });
+                duplicates.insert(ext.clone());
+            }
+            _ => preview.rows.push(row),
+        }
+    }
+    preview.holdings.retain(|h| h.external_id.as_ref().is_none_or(|e| !duplicates.contains(e)));
+    Ok(())
+}
+
+/// Inserts all rows into `account` inside one database transaction and records the batch.
+/// Refunds are linked to their originals, whether those come from this batch or an earlier one.
+pub async fn commit_import(
+    db: &DatabaseConnection,
+    audit: &AuditContext,
+    account: &account::Model,
+    source: &str,
+    rows: &[ImportRow],
+    holdings: &[HoldingChange],
+) -> Result<import_batch::Model, sea_orm::DbErr> {
+    let txn = db.begin().await?;
+    let batch = import_batch::ActiveModel {
+        user_id: Set(account.user_id),
+        account_id: Set(account.id),
+        source: Set(source.to_string()),
+        row_count: Set(rows.len() as i32),
+        status: Set(BATCH_COMMITTED.to_string()),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await?;
+
+    // Originals first so refunds listed before them (newest-first exports) can still link.
+    let (refunds, originals): (Vec<&ImportRow>, Vec<&ImportRow>) = rows.iter().partition(|r| r.refund_of.is_some());
+    let mut inserted: HashMap<String, i32> = HashMap::new();
+    for row in originals.into_iter().chain(refunds) {
+        let refund_of_id = match &row.refund_of {
+            Some(ext) => match inserted.get(ext) {
+                Some(id) => Some(*id),
+                None => transaction::Entity::find()
+                    .filter(transaction::Column::AccountId.eq(account.id))
+                    .filter(transaction::Column::ExternalId.eq(ext.as_str()))
+                    .one(&txn)
+                    .await?
+                    .map(|t| t.id),
+            },
+            None => None,
+        };
+        let model = transaction::ActiveModel {
+            account_id: Set(account.id),
+            transaction_type: Set(row.transaction_type.clone()),
+            amount: Set(row.amount),
+            description: Set(row.description.clone().into()),
+            category: Set(row.category.clone()),
+            status: Set(STATUS_PENDING.to_string()),
+            import_batch_id: Set(Some(batch.id)),
+            counterparty: Set(row.counterparty.clone().map(Into::into)),
+            external_id: Set(row.external_id.clone()),
+            refund_of_id: Set(refund_of_id),
+            tags: Set(tags_to_json(&row.tags)),
+            created_at: Set(row.date),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+        audit_create(&txn, audit, account.user_id, "transaction", model.id, &model).await?;
+        if let Some(ext) = &row.external_id {
+            inserted.insert(ext.clone(), model.id);
+        }
+    }
+    for change in holdings {
+        let (asset_id, created_asset) = apply_holding_change(&txn, audit, account.user_id, change).await?;
+        import_holding_change::ActiveModel {
+            batch_id: Set(batch.id),
+            asset_id: Set(asset_id),
+            units: Set(change.units),
+            unit_price: Set(change.unit_price),
+            created_asset: Set(created_asset),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+    }
+    txn.commit().await?;
+    Ok(batch)
+}
+
+/// Buys raise quantity and re-average cost; sells lower quantity at unchanged cost. Returns the
+/// asset and whether it was created for this change.
+async fn apply_holding_change<C: sea_orm::ConnectionTrait>(
+    db: &C,
+    audit: &AuditContext,
+    user_id: i32,
+    change: &HoldingChange,
+) -> Result<(i32, bool), sea_orm::DbErr> {
+    let existing = asset::Entity::find()
+        .filter(asset::Column::UserId.eq(user_id))
+        .filter(asset::Column::Symbol.eq(change.symbol.as_str()))
+        .one(db)
+        .await?;
+    match existing {
+        Some(model) => {
+            let quantity = model.quantity + change.units;
+            let avg_price = if change.units.is_sign_positive() && !quantity.is_zero() {
+                (model.quantity * model.avg_price + change.units * change.unit_price) / quantity
+            } else {
+                model.avg_price
+            };
+            let mut active: asset::ActiveModel = model.clone().into();
+            active.quantity = Set(quantity);
+            active.avg_price = Set(avg_price);
+            active.updated_at = Set(chrono::Utc::now());
+            let updated = active.update(db).await?;
+            audit_update(db, audit, user_id, "asset", updated.id, &model, &updated).await?;
+            Ok((updated.id, false))
+        }
+        None => {
+            let model = asset::ActiveModel {
+                user_id: Set(user_id),
+                symbol: Set(change.symbol.clone()),
+                name: Set(change.name.clone()),
+                quantity: Set(change.units),
+                avg_price: Set(change.unit_price),
+                asset_type: Set(change.asset_type.clone()),
+                ..Default::default()
+            }
+            .insert(db)
+            .await?;
+            audit_create(db, audit, user_id, "asset", model.id, &model).await?;
+            Ok((model.id, true))
+        }
+    }
+}
+
+/// The opposite of `apply_holding_change`, applied to the asset as it is now, so later trades
+/// and edits are kept. An asset the import created goes away once it holds nothing again.
+async fn revert_holding_change<C: sea_orm::ConnectionTrait>(
+    db: &C,
+    audit: &AuditContext,
+    change: &import_holding_change::Model,
+) -> Result<(), sea_orm::DbErr> {
+    let Some(model) = asset::Entity::find_by_id(change.asset_id).one(db).await? else { return Ok(()) };
+    let quantity = model.quantity - change.units;
+    if change.created_asset && quantity.is_zero() {
+        asset::Entity::delete_by_id(model.id).exec(db).await?;
+        audit_delete(db, audit, model.user_id, "asset", model.id, &model).await?;
+        return Ok(());
+    }
+    let avg_price = if change.units.is_sign_positive() && !quantity.is_zero() {
+        (model.quantity * model.avg_price - change.units * change.unit_price) / quantity
+    } else {
+        model.avg_price
+    };
+    let mut active: asset::ActiveModel = model.clone().into();
+    active.quantity = Set(quantity);
+    active.avg_price = Set(avg_price);
+    active.updated_at = Set(chrono::Utc::now());
+    let updated = active.update(db).await?;
+    audit_update(db, audit, updated.user_id, "asset", updated.id, &model, &updated).await?;
+    Ok(())
+}
+
+pub async fn get_import_batch_by_id(
+    db: &DatabaseConnection,
+    id: i32,
+) -> Result<Option<import_batch::Model>, sea_orm::DbErr> {
+    import_batch::Entity::find_by_id(id).one(db).await
+}
+
+pub async fn find_import_batches_by_user(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<Vec<import_batch::Model>, sea_orm::DbErr> {
+    import_batch::Entity::find()
+        .filter(import_batch::Column::UserId.eq(user_id))
+        .order_by_desc(import_batch::Column::Id)
+        .all(db)
+        .await
+}
+
+/// Deletes every transaction created by the batch and undoes its holding changes. Refuses if any
+/// of the transactions has been reconciled.
+pub async fn rollback_import_batch(
+    db: &DatabaseConnection,
+    audit: &AuditContext,
+    id: i32,
+) -> Result<Option<import_batch::Model>, ServiceError> {
+    let Some(batch) = get_import_batch_by_id(db, id).await? else { return Ok(None) };
+    if batch.status != BATCH_COMMITTED {
+        return Err(ServiceError::Invalid("batch already rolled back".into()));
+    }
+    let locked = transaction::Entity::find()
+        .filter(transaction::Column::ImportBatchId.eq(id))
+        .filter(transaction::Column::Status.eq(STATUS_RECONCILED))
+        .one(db)
+        .await?;
+    if locked.is_some() {
+        return Err(ServiceError::Locked("batch contains reconciled transactions".into()));
+    }
+
+    let txn = db.begin().await?;
+    let removed = transaction::Entity::find()
+        .filter(transaction::Column::ImportBatchId.eq(id))
+        .all(&txn)
+        .await?;
+    transaction::Entity::delete_many()
+        .filter(transaction::Column::ImportBatchId.eq(id))
+        .exec(&txn)
+        .await?;
+    for t in &removed {
+        audit_delete(&txn, audit, batch.user_id, "transaction", t.id, t).await?;
+    }
+    let changes = import_holding_change::Entity::find()
+        .filter(import_holding_change::Column::BatchId.eq(id))
+        .order_by_desc(import_holding_change::Column::Id)
+        .all(&txn)
+        .await?;
+    for change in &changes {
+        revert_holding_change(&txn, audit, change).await?;
+    }
+    import_holding_change::Entity::delete_many()
+        .filter(import_holding_change::Column::BatchId.eq(id))
+        .exec(&txn)
+        .await?;
+    let mut active: import_batch::ActiveModel = batch.into();
+    active.status = Set(BATCH_ROLLED_BACK.to_string());
+    let batch = active.update(&txn).await?;
+    txn.commit().await?;
+    Ok(Some(batch))
+}
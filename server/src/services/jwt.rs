@@ -0,0 +1,279 @@
+use data_encoding::{BASE64, BASE64URL_NOPAD};
+use jsonwebtoken::jwk::{AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm, OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType};
+use jsonwebtoken::{decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use ring::signature::KeyPair;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// Used when `JWT_SECRET` is unset. Fine for trying the server out, refused when auth is required.
+pub const DEFAULT_JWT_SECRET: &str = "your-secret-key";
+/// Well-known secrets (the default and the one in the example `.env`), refused like the default.
+const PLACEHOLDER_JWT_SECRETS: &[&str] = &[DEFAULT_JWT_SECRET, "your-very-secret-jwt-key-change-this-in-production"];
+
+const ED25519_OID: &[u8] = &[0x2b, 0x65, 0x70];
+const RSA_ENCRYPTION_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+
+#[derive(Debug)]
+pub struct JwtKeyError(pub String);
+
+impl std::fmt::Display for JwtKeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "jwt keys: {}", self.0)
+    }
+}
+
+impl std::error::Error for JwtKeyError {}
+
+pub fn is_placeholder_jwt_secret(secret: &str) -> bool {
+    secret.trim().is_empty() || PLACEHOLDER_JWT_SECRETS.contains(&secret)
+}
+
+#[derive(Clone)]
+struct VerificationKey {
+    kid: String,
+    algorithm: Algorithm,
+    key: DecodingKey,
+    /// Published in the JWKS; `None` for shared secrets.
+    jwk: Option<Jwk>,
+}
+
+/// Signs access tokens with one key and accepts tokens from any of several, so the signing key
+/// can change without logging everyone out: the previous key stays for verification until the
+/// tokens it signed have expired. Tokens carry the key's id in the `kid` header.
+///
+/// HS256 uses a shared secret. EdDSA (Ed25519) and RS256 use a private key, and their public
+/// keys are served as a JWKS so other services can verify tokens themselves.
+#[derive(Clone)]
+pub struct JwtKeys {
+    kid: String,
+    algorithm: Algorithm,
+    signing: EncodingKey,
+    verification: Vec<VerificationKey>,
+}
+
+impl JwtKeys {
+    pub fn hs256(secret: &str) -> Self {
+        let current = secret_verification_key(secret);
+        JwtKeys { kid: current.kid.clone(), algorithm: Algorithm::HS256, signing: EncodingKey::from_secret(secret.as_bytes()), verification: vec![current] }
+    }
+
+    /// A PKCS#8 Ed25519 key (EdDSA), or a PKCS#8 or PKCS#1 RSA key (RS256), in PEM form.
+    pub fn from_private_pem(pem: &str) -> Result<Self, JwtKeyError> {
+        let (label, der) = decode_pem(pem)?;
+        let (signing, public) = match label.as_str() {
+            "PRIVATE KEY" => {
+                if let Ok(pair) = ring::signature::Ed25519KeyPair::from_pkcs8_maybe_unchecked(&der) {
+                    let signing = EncodingKey::from_ed_der(&der);
+                    (signing, PublicKey::Ed25519(pair.public_key().as_ref().to_vec()))
+                } else {
+                    let pair = ring::signature::RsaKeyPair::from_pkcs8(&der).map_err(|e| JwtKeyError(format!("unsupported private key: {}", e)))?;
+                    let signing = EncodingKey::from_rsa_pem(pem.as_bytes()).map_err(|e| JwtKeyError(e.to_string()))?;
+                    (signing, rsa_public_key(pair.public_key().as_ref())?)
+                }
+            }
+            "RSA PRIVATE KEY" => {
+                let pair = ring::signature::RsaKeyPair::from_der(&der).map_err(|e| JwtKeyError(format!("invalid RSA key: {}", e)))?;
+                (EncodingKey::from_rsa_der(&der), rsa_public_key(pair.public_key().as_ref())?)
+            }
+            other => return Err(JwtKeyError(format!("expected a PRIVATE KEY or RSA PRIVATE KEY, found {}", other))),
+        };
+        let current = public.verification_key();
+        Ok(JwtKeys { kid: current.kid.clone(), algorithm: current.algorithm, signing, verification: vec![current] })
+    }
+
+    /// Names the signing key, instead of its RFC 7638 thumbprint.
+    pub fn with_key_id(mut self, kid: &str) -> Self {
+        if let Some(current) = self.verification.iter_mut().find(|k| k.kid == self.kid) {
+            current.kid = kid.to_string();
+            if let Some(jwk) = current.jwk.as_mut() {
+                jwk.common.key_id = Some(kid.to_string());
+            }
+        }
+        self.kid = kid.to_string();
+        self
+    }
+
+    /// Keeps accepting tokens signed with a retired HS256 secret.
+    pub fn with_previous_secret(mut self, secret: &str) -> Self {
+        self.verification.push(secret_verification_key(secret));
+        self
+    }
+
+    /// Keeps accepting tokens signed by a retired key pair, given its public key (SPKI PEM).
+    pub fn with_previous_public_key(mut self, pem: &str) -> Result<Self, JwtKeyError> {
+        let (label, der) = decode_pem(pem)?;
+        if label != "PUBLIC KEY" {
+            return Err(JwtKeyError(format!("expected a PUBLIC KEY, found {}", label)));
+        }
+        self.verification.push(parse_spki(&der)?.verification_key());
+        Ok(self)
+    }
+
+    pub fn from_config(config: &crate::config::Config) -> Result<Self, JwtKeyError> {
+        let secret = config.jwt_secret.as_deref().filter(|s| !is_placeholder_jwt_secret(s));
+        let mut keys = match (&config.jwt_private_key, secret) {
+            (Some(path), _) => {
+                let pem = std::fs::read_to_string(path).map_err(|e| JwtKeyError(format!("{}: {}", path, e)))?;
+                let keys = JwtKeys::from_private_pem(&pem)?;
+                // Switching from a secret to a key pair keeps the secret's tokens valid.
+                match secret {
+                    Some(secret) => keys.with_previous_secret(secret),
+                    None => keys,
+                }
+            }
+            (None, Some(secret)) => JwtKeys::hs256(secret),
+            (None, None) if config.require_auth => {
+                return Err(JwtKeyError("REQUIRE_AUTH is on but JWT_SECRET is unset or a well-known example value; set a long random JWT_SECRET or a JWT_PRIVATE_KEY".into()));
+            }
+            (None, None) => JwtKeys::hs256(config.jwt_secret.as_deref().unwrap_or(DEFAULT_JWT_SECRET)),
+        };
+        if let Some(kid) = &config.jwt_key_id {
+            keys = keys.with_key_id(kid);
+        }
+        for secret in &config.jwt_previous_secrets {
+            keys = keys.with_previous_secret(secret);
+        }
+        for path in &config.jwt_previous_public_keys {
+            let pem = std::fs::read_to_string(path).map_err(|e| JwtKeyError(format!("{}: {}", path, e)))?;
+            keys = keys.with_previous_public_key(&pem)?;
+        }
+        Ok(keys)
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub fn key_id(&self) -> &str {
+        &self.kid
+    }
+
+    pub fn sign<T: Serialize>(&self, claims: &T) -> Result<String, jsonwebtoken::errors::Error> {
+        let mut header = Header::new(self.algorithm);
+        header.kid = Some(self.kid.clone());
+        encode(&header, claims, &self.signing)
+    }
+
+    /// Checks the signature and expiry. The key is picked by `kid`; tokens without one (issued
+    /// before keys had ids) or with an unknown one are tried against every key of their algorithm.
+    pub fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<T, jsonwebtoken::errors::Error> {
+        let header = decode_header(token)?;
+        let by_kid: Vec<&VerificationKey> = self.verification.iter().filter(|k| header.kid.as_deref() == Some(k.kid.as_str())).collect();
+        let candidates = if by_kid.is_empty() { self.verification.iter().collect() } else { by_kid };
+        let mut last = jsonwebtoken::errors::ErrorKind::InvalidSignature.into();
+        for key in candidates.into_iter().filter(|k| k.algorithm == header.alg) {
+            match decode::<T>(token, &key.key, &Validation::new(key.algorithm)) {
+                Ok(data) => return Ok(data.claims),
+                Err(e) => last = e,
+            }
+        }
+        Err(last)
+    }
+
+    /// The public keys, current first. Shared secrets are never included.
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet { keys: self.verification.iter().filter_map(|k| k.jwk.clone()).collect() }
+    }
+}
+
+enum PublicKey {
+    Ed25519(Vec<u8>),
+    Rsa { n: Vec<u8>, e: Vec<u8> },
+}
+
+impl PublicKey {
+    fn verification_key(&self) -> VerificationKey {
+        let (algorithm, key_algorithm, params, key) = match self {
+            PublicKey::Ed25519(x) => (
+                Algorithm::EdDSA,
+                KeyAlgorithm::EdDSA,
+                AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters { key_type: OctetKeyPairType::OctetKeyPair, curve: EllipticCurve::Ed25519, x: BASE64URL_NOPAD.encode(x) }),
+                DecodingKey::from_ed_der(x),
+            ),
+            PublicKey::Rsa { n, e } => {
+                let (n, e) = (BASE64URL_NOPAD.encode(n), BASE64URL_NOPAD.encode(e));
+                let key = DecodingKey::from_rsa_components(&n, &e).expect("base64url components");
+                (Algorithm::RS256, KeyAlgorithm::RS256, AlgorithmParameters::RSA(RSAKeyParameters { key_type: RSAKeyType::RSA, n, e }), key)
+            }
+        };
+        let kid = thumbprint(&params);
+        let common = CommonParameters { public_key_use: Some(PublicKeyUse::Signature), key_algorithm: Some(key_algorithm), key_id: Some(kid.clone()), ..Default::default() };
+        VerificationKey { kid, algorithm, key, jwk: Some(Jwk { common, algorithm: params }) }
+    }
+}
+
+fn secret_verification_key(secret: &str) -> VerificationKey {
+    let kid = thumbprint_of(&format!(r#"{{"k":"{}","kty":"oct"}}"#, BASE64URL_NOPAD.encode(secret.as_bytes())));
+    VerificationKey { kid, algorithm: Algorithm::HS256, key: DecodingKey::from_secret(secret.as_bytes()), jwk: None }
+}
+
+/// RFC 7638: the SHA-256 of the key's required members, in lexicographic order.
+fn thumbprint(params: &AlgorithmParameters) -> String {
+    let canonical = match params {
+        AlgorithmParameters::OctetKeyPair(p) => format!(r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#, p.x),
+        AlgorithmParameters::RSA(p) => format!(r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#, p.e, p.n),
+        _ => unreachable!("only Ed25519 and RSA keys are loaded"),
+    };
+    thumbprint_of(&canonical)
+}
+
+fn thumbprint_of(canonical: &str) -> String {
+    BASE64URL_NOPAD.encode(&Sha256::digest(canonical.as_bytes()))
+}
+
+fn decode_pem(pem: &str) -> Result<(String, Vec<u8>), JwtKeyError> {
+    let pem = pem.trim();
+    let label = pem
+        .lines()
+        .next()
+        .and_then(|l| l.strip_prefix("-----BEGIN "))
+        .and_then(|l| l.strip_suffix("-----"))
+        .ok_or_else(|| JwtKeyError("not a PEM file".into()))?;
+    let body: String = pem.lines().filter(|l| !l.starts_with("-----")).map(str::trim).collect();
+    let der = BASE64.decode(body.as_bytes()).map_err(|e| JwtKeyError(format!("invalid PEM: {}", e)))?;
+    Ok((label.to_string(), der))
+}
+
+/// Splits one DER element off `input`: (tag, contents, rest).
+fn der_element(input: &[u8]) -> Result<(u8, &[u8], &[u8]), JwtKeyError> {
+    let invalid = || JwtKeyError("invalid DER".into());
+    let (&tag, rest) = input.split_first().ok_or_else(invalid)?;
+    let (&first, rest) = rest.split_first().ok_or_else(invalid)?;
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let octets = (first & 0x7f) as usize;
+        if octets == 0 || octets > 4 || rest.len() < octets {
+            return Err(invalid());
+        }
+        (rest[..octets].iter().fold(0usize, |acc, &b| (acc << 8) | b as usize), &rest[octets..])
+    };
+    if rest.len() < len {
+        return Err(invalid());
+    }
+    Ok((tag, &rest[..len], &rest[len..]))
+}
+
+/// `SubjectPublicKeyInfo ::= SEQUENCE { SEQUENCE { algorithm OID, ... }, BIT STRING }`.
+fn parse_spki(der: &[u8]) -> Result<PublicKey, JwtKeyError> {
+    let (_, spki, _) = der_element(der)?;
+    let (_, algorithm, rest) = der_element(spki)?;
+    let (_, oid, _) = der_element(algorithm)?;
+    let (_, bits, _) = der_element(rest)?;
+    let key = bits.get(1..).ok_or_else(|| JwtKeyError("invalid DER".into()))?;
+    match oid {
+        ED25519_OID if key.len() == 32 => Ok(PublicKey::Ed25519(key.to_vec())),
+        RSA_ENCRYPTION_OID => rsa_public_key(key),
+        _ => Err(JwtKeyError("unsupported public key; use Ed25519 or RSA".into())),
+    }
+}
+
+/// `RSAPublicKey ::= SEQUENCE { modulus INTEGER, publicExponent INTEGER }`.
+fn rsa_public_key(der: &[u8]) -> Result<PublicKey, JwtKeyError> {
+    let (_, seq, _) = der_element(der)?;
+    let (_, n, rest) = der_element(seq)?;
+    let (_, e, _) = der_element(rest)?;
+    let unsigned = |v: &[u8]| v.iter().skip_while(|&&b| b == 0).copied().collect::<Vec<u8>>();
+    Ok(PublicKey::Rsa { n: unsigned(n), e: unsigned(e) })
+}
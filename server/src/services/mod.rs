@@ -3,9 +3,104 @@ pub mod user;
 pub mod account;
 pub mod transaction;
 pub mod asset;
+pub mod reconciliation;
+pub mod import;
+pub mod csv_import;
+pub mod bill_import;
+pub mod ofx_import;
+pub mod qif_import;
+pub mod beancount;
+pub mod archive;
+pub mod report;
+pub mod report_xlsx;
+pub mod report_pdf;
+pub mod cashflow;
+pub mod dashboard;
+pub mod schedule;
+pub mod forecast;
+pub mod anomaly;
+pub mod rule;
+pub mod suggest;
+pub mod duplicate;
+pub mod storage;
+pub mod attachment;
+pub mod session;
+pub mod mailer;
+pub mod email_token;
+pub mod totp;
+pub mod throttle;
+pub mod api_token;
+pub mod jwt;
+pub mod audit;
+pub mod encryption;
+pub mod password;
+pub mod http_client;
+pub mod oidc;
 
 pub use database::*;
 pub use user::*;
 pub use account::*;
 pub use transaction::*;
 pub use asset::*;
+pub use reconciliation::*;
+pub use import::*;
+pub use csv_import::*;
+pub use bill_import::*;
+pub use ofx_import::*;
+pub use qif_import::*;
+pub use beancount::*;
+pub use archive::*;
+pub use report::*;
+pub use report_xlsx::*;
+pub use report_pdf::*;
+pub use cashflow::*;
+pub use dashboard::*;
+pub use schedule::*;
+pub use forecast::*;
+pub use anomaly::*;
+pub use rule::*;
+pub use suggest::*;
+pub use duplicate::*;
+pub use storage::*;
+pub use attachment::*;
+pub use session::*;
+pub use mailer::*;
+pub use email_token::*;
+pub use totp::*;
+pub use throttle::*;
+pub use api_token::*;
+pub use jwt::*;
+pub use audit::*;
+pub use encryption::*;
+pub use password::*;
+pub use http_client::*;
+pub use oidc::*;
+
+/// Errors raised by services that need more than a plain database failure.
+#[derive(Debug)]
+pub enum ServiceError {
+    Db(sea_orm::DbErr),
+    /// The request is well-formed but violates a business rule.
+    Invalid(String),
+    /// The row is locked (e.g. reconciled) and must be unlocked first.
+    Locked(String),
+    /// The request clashes with existing data.
+    Conflict(String),
+}
+
+impl std::fmt::Display for ServiceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServiceError::Db(e) => write!(f, "{}", e),
+            ServiceError::Invalid(m) | ServiceError::Locked(m) | ServiceError::Conflict(m) => write!(f, "{}", m),
+        }
+    }
+}
+
+impl std::error::Error for ServiceError {}
+
+impl From<sea_orm::DbErr> for ServiceError {
+    fn from(e: sea_orm::DbErr) -> Self {
+        ServiceError::Db(e)
+    }
+}
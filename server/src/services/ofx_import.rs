@@ -0,0 +1,318 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, TimeZone, Utc};
+use crate::services::{decode_text, parse_amount, HoldingChange, ImportIssue, ImportPreview, ImportRow, ServiceError};
+
+/// An OFX element. SGML (1.x) leaves have no closing tag, XML (2.x) ones do; both parse the same.
+#[derive(Debug)]
+enum Node {
+    Aggregate { name: String, line: usize, children: Vec<Node> },
+    Leaf { name: String, value: String },
+}
+
+impl Node {
+    fn name(&self) -> &str {
+        match self {
+            Node::Aggregate { name, .. } | Node::Leaf { name, .. } => name,
+        }
+    }
+
+    fn line(&self) -> usize {
+        match self {
+            Node::Aggregate { line, .. } => *line,
+            Node::Leaf { .. } => 0,
+        }
+    }
+
+    fn children(&self) -> &[Node] {
+        match self {
+            Node::Aggregate { children, .. } => children,
+            Node::Leaf { .. } => &[],
+        }
+    }
+
+    /// Direct child value.
+    fn get(&self, name: &str) -> Option<&str> {
+        self.children().iter().find_map(|c| match c {
+            Node::Leaf { name: n, value } if n == name => Some(value.as_str()),
+            _ => None,
+        })
+    }
+
+    /// First descendant aggregate with this name.
+    fn find(&self, name: &str) -> Option<&Node> {
+        self.children().iter().find_map(|c| {
+            if c.name() == name && matches!(c, Node::Aggregate { .. }) { Some(c) } else { c.find(name) }
+        })
+    }
+
+    /// Every descendant aggregate with one of these names, in document order.
+    fn find_all<'a>(&'a self, names: &[&str], out: &mut Vec<&'a Node>) {
+        for c in self.children() {
+            if names.contains(&c.name()) && matches!(c, Node::Aggregate { .. }) {
+                out.push(c);
+            } else {
+                c.find_all(names, out);
+            }
+        }
+    }
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'").replace("&amp;", "&")
+}
+
+/// Builds the element tree, closing SGML aggregates implicitly when an outer end tag arrives.
+fn parse_tree(text: &str) -> Result<Node, ServiceError> {
+    let start = text.find("<OFX>").ok_or_else(|| ServiceError::Invalid("not an OFX file".into()))?;
+    let mut line = text[..start].matches('\n').count() + 1;
+    let mut stack = vec![Node::Aggregate { name: String::new(), line, children: Vec::new() }];
+    let mut rest = &text[start..];
+    while let Some(open) = rest.find('<') {
+        line += rest[..open].matches('\n').count();
+        rest = &rest[open + 1..];
+        let close = rest.find('>').ok_or_else(|| ServiceError::Invalid(format!("unterminated tag at line {}", line)))?;
+        let tag = rest[..close].trim();
+        rest = &rest[close + 1..];
+        if tag.starts_with('?') || tag.starts_with('!') {
+            continue;
+        }
+        if let Some(name) = tag.strip_prefix('/') {
+            // Pop to the matching aggregate; a stray end tag of an XML leaf matches nothing.
+            if let Some(pos) = stack.iter().rposition(|n| n.name() == name) {
+                while stack.len() > pos {
+                    let node = stack.pop().expect("non-empty stack");
+                    if let Some(Node::Aggregate { children, .. }) = stack.last_mut() {
+                        children.push(node);
+                    }
+                }
+            }
+            continue;
+        }
+        let value_end = rest.find('<').unwrap_or(rest.len());
+        let value = rest[..value_end].trim();
+        if value.is_empty() {
+            stack.push(Node::Aggregate { name: tag.to_string(), line, children: Vec::new() });
+        } else if let Some(Node::Aggregate { children, .. }) = stack.last_mut() {
+            children.push(Node::Leaf { name: tag.to_string(), value: unescape(value) });
+        }
+    }
+    while stack.len() > 1 {
+        let node = stack.pop().expect("non-empty stack");
+        if let Some(Node::Aggregate { children, .. }) = stack.last_mut() {
+            children.push(node);
+        }
+    }
+    stack.pop().ok_or_else(|| ServiceError::Invalid("empty OFX document".into()))
+}
+
+/// Parses `YYYYMMDD[HHMMSS[.XXX]][[+-]H[.M][:TZ]]`; without an offset the time is UTC.
+fn parse_ofx_date(raw: &str) -> Option<DateTime<Utc>> {
+    let (stamp, tz) = match raw.find('[') {
+        Some(i) => (&raw[..i], Some(raw[i + 1..].trim_end_matches(']'))),
+        None => (raw, None),
+    };
+    let digits: String = stamp.chars().take_while(|c| c.is_ascii_digit()).collect();
+    if digits.len() < 8 {
+        return None;
+    }
+    let date = NaiveDate::parse_from_str(&digits[..8], "%Y%m%d").ok()?;
+    let num = |r: std::ops::Range<usize>| digits.get(r).and_then(|s| s.parse::<u32>().ok()).unwrap_or(0);
+    let naive = date.and_hms_opt(num(8..10), num(10..12), num(12..14))?;
+    let offset_hours: f64 = tz
+        .and_then(|t| t.split(':').next())
+        .and_then(|h| h.parse().ok())
+        .unwrap_or(0.0);
+    let offset = FixedOffset::east_opt((offset_hours * 3600.0) as i32)?;
+    offset.from_local_datetime(&naive).single().map(|d| d.with_timezone(&Utc))
+}
+
+/// Account number of a statement (`BANKACCTFROM`, `CCACCTFROM` or `INVACCTFROM`).
+fn statement_account(stmt: &Node) -> String {
+    ["BANKACCTFROM", "CCACCTFROM", "INVACCTFROM"]
+        .iter()
+        .find_map(|n| stmt.find(n).and_then(|a| a.get("ACCTID")))
+        .unwrap_or("")
+        .to_string()
+}
+
+fn bank_row(trn: &Node, preview: &mut ImportPreview) -> Option<ImportRow> {
+    let line = trn.line();
+    let Some(date) = trn.get("DTPOSTED").and_then(parse_ofx_date) else {
+        preview.errors.push(ImportIssue { line, message: "missing or invalid DTPOSTED".into() });
+        return None;
+    };
+    let Some(amount) = trn.get("TRNAMT").and_then(parse_amount) else {
+        preview.errors.push(ImportIssue { line, message: "missing or invalid TRNAMT".into() });
+        return None;
+    };
+    let trntype = trn.get("TRNTYPE").unwrap_or("OTHER");
+    let transaction_type = match trntype {
+        // Transfers keep their sign, so they stay signed rather than becoming income/expense.
+        "XFER" => "transfer",
+        _ if amount.is_sign_negative() => "expense",
+        _ => "income",
+    };
+    let category = match trntype {
+        "INT" => Some("interest"),
+        "DIV" => Some("dividend"),
+        "FEE" | "SRVCHG" => Some("fees"),
+        _ => None,
+    };
+    let name = trn.get("NAME").or_else(|| trn.find("PAYEE").and_then(|p| p.get("NAME")));
+    let memo = trn.get("MEMO");
+    Some(ImportRow {
+        line,
+        date,
+        transaction_type: transaction_type.to_string(),
+        amount: if transaction_type == "transfer" { amount } else { amount.abs() },
+        description: memo.or(name).unwrap_or(trntype).to_string(),
+        category: category.map(str::to_string),
+        counterparty: name.map(str::to_string),
+        external_id: trn.get("FITID").map(str::to_string),
+        refund_of: None,
+        tags: Vec::new(),
+    })
+}
+
+/// Brokerage trade (`BUYSTOCK`, `SELLMF`, ...): a holdings change plus its cash movement.
+fn investment_trade(trade: &Node, securities: &[(String, String, String)], preview: &mut ImportPreview) {
+    let line = trade.line();
+    let Some(inner) = trade.find("INVBUY").or_else(|| trade.find("INVSELL")) else { return };
+    let invtran = inner.find("INVTRAN");
+    let fitid = invtran.and_then(|t| t.get("FITID")).map(str::to_string);
+    let Some(date) = invtran.and_then(|t| t.get("DTTRADE")).and_then(parse_ofx_date) else {
+        preview.errors.push(ImportIssue { line, message: "missing or invalid DTTRADE".into() });
+        return;
+    };
+    let unique_id = inner.find("SECID").and_then(|s| s.get("UNIQUEID")).unwrap_or("");
+    let (Some(units), Some(unit_price), Some(total)) = (
+        inner.get("UNITS").and_then(parse_amount),
+        inner.get("UNITPRICE").and_then(parse_amount),
+        inner.get("TOTAL").and_then(parse_amount),
+    ) else {
+        preview.errors.push(ImportIssue { line, message: "missing UNITS, UNITPRICE or TOTAL".into() });
+        return;
+    };
+    let (symbol, name) = securities
+        .iter()
+        .find(|(id, _, _)| id == unique_id)
+        .map(|(_, ticker, name)| (ticker.clone(), name.clone()))
+        .unwrap_or_else(|| (unique_id.to_string(), unique_id.to_string()));
+    let asset_type = match trade.name() {
+        "BUYMF" | "SELLMF" => "fund",
+        "BUYDEBT" | "SELLDEBT" => "bond",
+        _ => "stock",
+    };
+    let is_sell = trade.name().starts_with("SELL");
+    let units = if is_sell { -units.abs() } else { units.abs() };
+    let memo = invtran.and_then(|t| t.get("MEMO"));
+    preview.rows.push(ImportRow {
+        line,
+        date,
+        transaction_type: "transfer".to_string(),
+        amount: total,
+        description: memo.map(str::to_string).unwrap_or_else(|| format!("{} {} {}", if is_sell { "Sell" } else { "Buy" }, units.abs(), symbol)),
+        category: Some("investment".to_string()),
+        counterparty: None,
+        external_id: fitid.clone(),
+        refund_of: None,
+        tags: Vec::new(),
+    });
+    preview.holdings.push(HoldingChange {
+        line,
+        date,
+        symbol,
+        name,
+        asset_type: asset_type.to_string(),
+        units,
+        unit_price: unit_price.abs(),
+        external_id: fitid,
+    });
+}
+
+/// Parses OFX/QFX 1.x (SGML) and 2.x (XML) bank, credit card and investment statements.
+pub fn parse_ofx(bytes: &[u8], wanted_account: Option<&str>) -> Result<ImportPreview, ServiceError> {
+    // 1.x headers declare CHARSET:1252 and friends; anything non-UTF-8 is read as Windows-1252.
+    let text = decode_text(bytes, "utf-8").or_else(|_| decode_text(bytes, "windows-1252"))?;
+    let root = parse_tree(&text)?;
+    let mut statements = Vec::new();
+    root.find_all(&["STMTRS", "CCSTMTRS", "INVSTMTRS"], &mut statements);
+    if statements.is_empty() {
+        return Err(ServiceError::Invalid("no statements found in OFX file".into()));
+    }
+
+    let accounts: Vec<String> = statements.iter().map(|s| statement_account(s)).collect();
+    let chosen = match wanted_account {
+        Some(w) => accounts
+            .iter()
+            .position(|a| a == w)
+            .ok_or_else(|| ServiceError::Invalid(format!("statement account {} not in file", w)))?,
+        None if statements.len() == 1 => 0,
+        None => {
+            return Err(ServiceError::Invalid(format!(
+                "file has {} statements ({}); pass statement_account",
+                statements.len(),
+                accounts.join(", ")
+            )))
+        }
+    };
+
+    let mut preview = ImportPreview {
+        unmatched_accounts: accounts.iter().enumerate().filter(|(i, _)| *i != chosen).map(|(_, a)| a.clone()).collect(),
+        ..Default::default()
+    };
+    let stmt = statements[chosen];
+
+    // SECLIST sits beside the statement; map UNIQUEID -> (ticker, name).
+    let mut infos = Vec::new();
+    root.find_all(&["SECINFO"], &mut infos);
+    let securities: Vec<(String, String, String)> = infos
+        .iter()
+        .filter_map(|i| {
+            let id = i.find("SECID")?.get("UNIQUEID")?.to_string();
+            let name = i.get("SECNAME").unwrap_or(&id).to_string();
+            let ticker = i.get("TICKER").unwrap_or(&id).to_string();
+            Some((id, ticker, name))
+        })
+        .collect();
+
+    let mut items = Vec::new();
+    stmt.find_all(
+        &["STMTTRN", "BUYSTOCK", "SELLSTOCK", "BUYMF", "SELLMF", "BUYDEBT", "SELLDEBT", "BUYOTHER", "SELLOTHER", "INCOME"],
+        &mut items,
+    );
+    for item in items {
+        match item.name() {
+            "STMTTRN" => {
+                if let Some(row) = bank_row(item, &mut preview) {
+                    preview.rows.push(row);
+                }
+            }
+            // Dividends and interest paid into the brokerage account
+            "INCOME" => {
+                let invtran = item.find("INVTRAN");
+                let date = invtran.and_then(|t| t.get("DTTRADE")).and_then(parse_ofx_date);
+                let total = item.get("TOTAL").and_then(parse_amount);
+                let (Some(date), Some(total)) = (date, total) else {
+                    preview.errors.push(ImportIssue { line: item.line(), message: "missing DTTRADE or TOTAL".into() });
+                    continue;
+                };
+                let category = if item.get("INCOMETYPE") == Some("DIV") { "dividend" } else { "interest" };
+                preview.rows.push(ImportRow {
+                    line: item.line(),
+                    date,
+                    transaction_type: "income".to_string(),
+                    amount: total.abs(),
+                    description: invtran.and_then(|t| t.get("MEMO")).unwrap_or(category).to_string(),
+                    category: Some(category.to_string()),
+                    counterparty: None,
+                    external_id: invtran.and_then(|t| t.get("FITID")).map(str::to_string),
+                    refund_of: None,
+                    tags: Vec::new(),
+                });
+            }
+            _ => investment_trade(item, &securities, &mut preview),
+        }
+    }
+    Ok(preview)
+}
+
@@ -0,0 +1,412 @@
+use chrono::{Duration, Utc};
+use data_encoding::{BASE64, BASE64URL_NOPAD};
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set, TransactionTrait};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use url::Url;
+use crate::models::{oidc_login, user, user_identity};
+use crate::services::{audit_create, get_user_by_email, get_user_by_username, http_get, http_post_form, new_secret_token, secret_token_hash, AuditContext, HttpError, UNUSABLE_PASSWORD_HASH};
+
+/// Scopes asked for when a provider's config names none.
+pub const DEFAULT_OIDC_SCOPES: &str = "openid email profile";
+/// How long the user may take at the identity provider.
+pub const OIDC_LOGIN_MINUTES: i64 = 10;
+/// Discovery documents and key sets are fetched again after this long, and whenever an ID
+/// token is signed with a key not in the cached set.
+const METADATA_TTL: std::time::Duration = std::time::Duration::from_secs(3600);
+/// Clock skew tolerated on `exp` and `iat`, in seconds.
+const ID_TOKEN_LEEWAY: u64 = 60;
+
+/// One identity provider users can sign in with, as configured.
+#[derive(Clone, Debug)]
+pub struct OidcProvider {
+    /// Appears in the login URLs and in linked identities; changing it unlinks everyone.
+    pub name: String,
+    /// Exactly as the provider's `iss` claim spells it.
+    pub issuer: String,
+    pub client_id: String,
+    /// `None` for a public client, which relies on PKCE alone.
+    pub client_secret: Option<String>,
+    pub scopes: Vec<String>,
+    /// Registered with the provider: the callback endpoint, or a client page that passes
+    /// `code` and `state` on to it.
+    pub redirect_uri: String,
+}
+
+#[derive(Debug)]
+pub enum OidcError {
+    Db(sea_orm::DbErr),
+    UnknownProvider,
+    /// Unknown, expired or already used `state`.
+    InvalidState,
+    /// The provider could not be reached or answered with an error.
+    Provider(String),
+    InvalidIdToken(String),
+    /// No email, or one the provider has not verified, so there is nobody to link or create.
+    EmailNotVerified,
+    /// A local account has the email but has not verified it; linking could hand the account to
+    /// whoever registered it.
+    EmailInUse,
+}
+
+impl std::fmt::Display for OidcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OidcError::Db(e) => write!(f, "{}", e),
+            OidcError::UnknownProvider => write!(f, "unknown identity provider"),
+            OidcError::InvalidState => write!(f, "invalid or expired login state; start the login again"),
+            OidcError::Provider(m) => write!(f, "identity provider: {}", m),
+            OidcError::InvalidIdToken(m) => write!(f, "invalid ID token: {}", m),
+            OidcError::EmailNotVerified => write!(f, "the identity provider did not confirm a verified email address"),
+            OidcError::EmailInUse => write!(f, "an account with this email exists but its address is not verified; sign in with the password and verify it first"),
+        }
+    }
+}
+
+impl std::error::Error for OidcError {}
+
+impl From<sea_orm::DbErr> for OidcError {
+    fn from(e: sea_orm::DbErr) -> Self {
+        OidcError::Db(e)
+    }
+}
+
+impl From<HttpError> for OidcError {
+    fn from(e: HttpError) -> Self {
+        OidcError::Provider(e.to_string())
+    }
+}
+
+/// The parts of `/.well-known/openid-configuration` the login needs, plus the key set.
+struct ProviderMetadata {
+    authorization_endpoint: Url,
+    token_endpoint: Url,
+    /// Whether to send the client secret in the body instead of an `Authorization` header.
+    secret_in_body: bool,
+    jwks: JwkSet,
+    fetched_at: Instant,
+}
+
+#[derive(Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+    #[serde(default)]
+    token_endpoint_auth_methods_supported: Vec<String>,
+}
+
+/// The configured providers, with their discovery documents and key sets cached.
+#[derive(Clone, Default)]
+pub struct OidcProviders {
+    providers: Arc<Vec<OidcProvider>>,
+    metadata: Arc<Mutex<HashMap<String, Arc<ProviderMetadata>>>>,
+}
+
+impl OidcProviders {
+    pub fn new(providers: Vec<OidcProvider>) -> Self {
+        OidcProviders { providers: Arc::new(providers), metadata: Arc::default() }
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.providers.iter().map(|p| p.name.clone()).collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&OidcProvider> {
+        self.providers.iter().find(|p| p.name == name)
+    }
+
+    /// Cached unless stale or `refresh` is set. The lock is not held while fetching; two
+    /// concurrent refreshes just both fetch.
+    async fn metadata(&self, provider: &OidcProvider, refresh: bool) -> Result<Arc<ProviderMetadata>, OidcError> {
+        if !refresh {
+            let cached = self.metadata.lock().expect("metadata lock").get(&provider.name).cloned();
+            if let Some(cached) = cached.filter(|m| m.fetched_at.elapsed() < METADATA_TTL) {
+                return Ok(cached);
+            }
+        }
+        let fetched = Arc::new(fetch_metadata(provider).await?);
+        self.metadata.lock().expect("metadata lock").insert(provider.name.clone(), fetched.clone());
+        Ok(fetched)
+    }
+}
+
+fn parse_url(s: &str) -> Result<Url, OidcError> {
+    Url::parse(s).map_err(|e| OidcError::Provider(format!("{}: {}", s, e)))
+}
+
+async fn get_json<T: serde::de::DeserializeOwned>(url: &Url) -> Result<T, OidcError> {
+    let res = http_get(url).await?;
+    if !res.is_success() {
+        return Err(OidcError::Provider(format!("{} returned {}", url, res.status)));
+    }
+    serde_json::from_slice(&res.body).map_err(|e| OidcError::Provider(format!("{}: {}", url, e)))
+}
+
+async fn fetch_metadata(provider: &OidcProvider) -> Result<ProviderMetadata, OidcError> {
+    let url = parse_url(&format!("{}/.well-known/openid-configuration", provider.issuer.trim_end_matches('/')))?;
+    let discovery: Discovery = get_json(&url).await?;
+    // OpenID Connect Discovery 1.0 §4.3: the document must be for the issuer it was fetched from.
+    if discovery.issuer != provider.issuer {
+        return Err(OidcError::Provider(format!("discovery document is for issuer {}", discovery.issuer)));
+    }
+    let jwks: JwkSet = get_json(&parse_url(&discovery.jwks_uri)?).await?;
+    let methods = &discovery.token_endpoint_auth_methods_supported;
+    Ok(ProviderMetadata {
+        authorization_endpoint: parse_url(&discovery.authorization_endpoint)?,
+        token_endpoint: parse_url(&discovery.token_endpoint)?,
+        secret_in_body: !methods.is_empty() && !methods.iter().any(|m| m == "client_secret_basic") && methods.iter().any(|m| m == "client_secret_post"),
+        jwks,
+        fetched_at: Instant::now(),
+    })
+}
+
+fn pkce_challenge(verifier: &str) -> String {
+    BASE64URL_NOPAD.encode(&Sha256::digest(verifier.as_bytes()))
+}
+
+/// Starts a login with `provider`: remembers a fresh state, nonce and PKCE verifier, and returns
+/// the authorization URL to send the browser to. Expired logins are pruned.
+pub async fn begin_oidc_login(db: &DatabaseConnection, providers: &OidcProviders, provider: &str) -> Result<Url, OidcError> {
+    let provider = providers.get(provider).ok_or(OidcError::UnknownProvider)?;
+    let metadata = providers.metadata(provider, false).await?;
+    oidc_login::Entity::delete_many().filter(oidc_login::Column::ExpiresAt.lt(Utc::now())).exec(db).await?;
+
+    let (state, nonce, code_verifier) = (new_secret_token(), new_secret_token(), new_secret_token());
+    let now = Utc::now();
+    let active = oidc_login::ActiveModel {
+        state_hash: Set(secret_token_hash(&state)),
+        provider: Set(provider.name.clone()),
+        nonce: Set(nonce.clone()),
+        code_verifier: Set(code_verifier.clone()),
+        expires_at: Set(now + Duration::minutes(OIDC_LOGIN_MINUTES)),
+        created_at: Set(now),
+        ..Default::default()
+    };
+    active.insert(db).await?;
+
+    let mut url = metadata.authorization_endpoint.clone();
+    url.query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", &provider.client_id)
+        .append_pair("redirect_uri", &provider.redirect_uri)
+        .append_pair("scope", &provider.scopes.join(" "))
+        .append_pair("state", &state)
+        .append_pair("nonce", &nonce)
+        .append_pair("code_challenge", &pkce_challenge(&code_verifier))
+        .append_pair("code_challenge_method", "S256");
+    Ok(url)
+}
+
+/// Who the provider says signed in, from a validated ID token.
+#[derive(Clone, Debug)]
+pub struct OidcIdentity {
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub preferred_username: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct IdTokenClaims {
+    sub: String,
+    nonce: Option<String>,
+    azp: Option<String>,
+    email: Option<String>,
+    /// A boolean, though some providers send the string `"true"`.
+    email_verified: Option<Value>,
+    preferred_username: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+/// Finishes a login: consumes the state, redeems the code (with the PKCE verifier) at the token
+/// endpoint and validates the ID token it returns.
+pub async fn complete_oidc_login(db: &DatabaseConnection, providers: &OidcProviders, provider: &str, code: &str, state: &str) -> Result<OidcIdentity, OidcError> {
+    let provider = providers.get(provider).ok_or(OidcError::UnknownProvider)?;
+    let login = oidc_login::Entity::find()
+        .filter(oidc_login::Column::StateHash.eq(secret_token_hash(state)))
+        .filter(oidc_login::Column::Provider.eq(provider.name.clone()))
+        .one(db)
+        .await?;
+    let Some(login) = login else { return Err(OidcError::InvalidState) };
+    // Whoever deletes the row wins, so a state cannot be used twice.
+    let res = oidc_login::Entity::delete_by_id(login.id).exec(db).await?;
+    if res.rows_affected == 0 || login.expires_at <= Utc::now() {
+        return Err(OidcError::InvalidState);
+    }
+
+    let metadata = providers.metadata(provider, false).await?;
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", provider.redirect_uri.as_str()),
+        ("code_verifier", login.code_verifier.as_str()),
+    ];
+    let mut headers = Vec::new();
+    let basic;
+    match &provider.client_secret {
+        Some(secret) if metadata.secret_in_body => {
+            form.push(("client_id", &provider.client_id));
+            form.push(("client_secret", secret));
+        }
+        Some(secret) => {
+            // RFC 6749 §2.3.1: both parts are form-encoded before going into the header.
+            let encode = |s: &str| url::form_urlencoded::byte_serialize(s.as_bytes()).collect::<String>();
+            basic = format!("Basic {}", BASE64.encode(format!("{}:{}", encode(&provider.client_id), encode(secret)).as_bytes()));
+            headers.push(("Authorization", basic.as_str()));
+        }
+        None => form.push(("client_id", &provider.client_id)),
+    }
+    let res = http_post_form(&metadata.token_endpoint, &headers, &form).await?;
+    let body: TokenResponse = serde_json::from_slice(&res.body)
+        .map_err(|_| OidcError::Provider(format!("token endpoint returned {}", res.status)))?;
+    if let Some(error) = body.error {
+        return Err(OidcError::Provider(format!("{}: {}", error, body.error_description.unwrap_or_default())));
+    }
+    let Some(id_token) = body.id_token.filter(|_| res.is_success()) else {
+        return Err(OidcError::Provider(format!("token endpoint returned {} without an ID token", res.status)));
+    };
+
+    let claims = validate_id_token(providers, provider, &metadata, &id_token).await?;
+    if claims.nonce.as_deref() != Some(login.nonce.as_str()) {
+        return Err(OidcError::InvalidIdToken("nonce mismatch".into()));
+    }
+    if claims.azp.as_ref().is_some_and(|azp| *azp != provider.client_id) {
+        return Err(OidcError::InvalidIdToken("issued to another client".into()));
+    }
+    let email_verified = matches!(&claims.email_verified, Some(Value::Bool(true))) || matches!(&claims.email_verified, Some(Value::String(s)) if s == "true");
+    Ok(OidcIdentity {
+        provider: provider.name.clone(),
+        subject: claims.sub,
+        email: claims.email,
+        email_verified,
+        preferred_username: claims.preferred_username,
+    })
+}
+
+fn find_key<'a>(jwks: &'a JwkSet, kid: Option<&str>) -> Option<&'a Jwk> {
+    match kid {
+        Some(kid) => jwks.find(kid),
+        // Without a `kid` only an unambiguous key set will do.
+        None if jwks.keys.len() == 1 => jwks.keys.first(),
+        None => None,
+    }
+}
+
+/// Signature against the provider's key set, `iss`, `aud`, `exp` and `iat`. Keys the cached set
+/// lacks trigger one refetch, for providers that have rotated their keys.
+async fn validate_id_token(providers: &OidcProviders, provider: &OidcProvider, metadata: &ProviderMetadata, id_token: &str) -> Result<IdTokenClaims, OidcError> {
+    let invalid = |e: jsonwebtoken::errors::Error| OidcError::InvalidIdToken(e.to_string());
+    let header = decode_header(id_token).map_err(invalid)?;
+    // Shared-secret algorithms would let anyone holding the client secret mint tokens.
+    if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+        return Err(OidcError::InvalidIdToken(format!("{:?} is not accepted", header.alg)));
+    }
+    let refreshed;
+    let jwk = match find_key(&metadata.jwks, header.kid.as_deref()) {
+        Some(jwk) => jwk,
+        None => {
+            refreshed = providers.metadata(provider, true).await?;
+            find_key(&refreshed.jwks, header.kid.as_deref()).ok_or_else(|| OidcError::InvalidIdToken("signed with an unknown key".into()))?
+        }
+    };
+    let key = DecodingKey::from_jwk(jwk).map_err(invalid)?;
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&[&provider.issuer]);
+    validation.set_audience(&[&provider.client_id]);
+    validation.set_required_spec_claims(&["exp", "iat", "iss", "aud", "sub"]);
+    validation.leeway = ID_TOKEN_LEEWAY;
+    Ok(decode::<IdTokenClaims>(id_token, &key, &validation).map_err(invalid)?.claims)
+}
+
+/// A free username from the provider's preferred one or the email's local part.
+async fn available_username(db: &DatabaseConnection, identity: &OidcIdentity, email: &str) -> Result<String, sea_orm::DbErr> {
+    let wanted = identity.preferred_username.as_deref().unwrap_or_else(|| email.split('@').next().unwrap_or_default());
+    let base: String = wanted.chars().filter(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.')).take(40).collect();
+    let base = if base.is_empty() { "user".to_string() } else { base };
+    let mut candidate = base.clone();
+    let mut n = 1;
+    while get_user_by_username(db, &candidate).await?.is_some() {
+        n += 1;
+        candidate = format!("{}{}", base, n);
+    }
+    Ok(candidate)
+}
+
+/// The user an identity signs in as. A linked identity wins; otherwise the identity is linked
+/// to the account with the same verified email, or a new account without a password is made
+/// for it. Emails only count when the provider has verified them.
+pub async fn oidc_user(db: &DatabaseConnection, audit: &AuditContext, identity: &OidcIdentity) -> Result<user::Model, OidcError> {
+    let now = Utc::now();
+    let linked = user_identity::Entity::find()
+        .filter(user_identity::Column::Provider.eq(identity.provider.clone()))
+        .filter(user_identity::Column::Subject.eq(identity.subject.clone()))
+        .one(db)
+        .await?;
+    if let Some(linked) = linked {
+        let user_id = linked.user_id;
+        let mut active: user_identity::ActiveModel = linked.into();
+        active.email = Set(identity.email.clone());
+        active.last_login_at = Set(now);
+        active.update(db).await?;
+        return user::Entity::find_by_id(user_id).one(db).await?.ok_or(OidcError::InvalidState);
+    }
+
+    let Some(email) = identity.email.clone().filter(|_| identity.email_verified) else {
+        return Err(OidcError::EmailNotVerified);
+    };
+    let existing = get_user_by_email(db, &email).await?;
+    if existing.as_ref().is_some_and(|u| u.email_verified_at.is_none()) {
+        return Err(OidcError::EmailInUse);
+    }
+    let username = match existing {
+        Some(_) => None,
+        None => Some(available_username(db, identity, &email).await?),
+    };
+
+    let txn = db.begin().await?;
+    let user = match (existing, username) {
+        (Some(user), _) => user,
+        (None, username) => {
+            let active = user::ActiveModel {
+                username: Set(username.unwrap_or_default()),
+                email: Set(email.clone()),
+                password_hash: Set(UNUSABLE_PASSWORD_HASH.to_string()),
+                email_verified_at: Set(Some(now)),
+                ..Default::default()
+            };
+            let model = active.insert(&txn).await?;
+            let audit = AuditContext { actor_user_id: audit.actor_user_id.or(Some(model.id)), ..audit.clone() };
+            audit_create(&txn, &audit, model.id, "user", model.id, &model).await?;
+            model
+        }
+    };
+    let active = user_identity::ActiveModel {
+        user_id: Set(user.id),
+        provider: Set(identity.provider.clone()),
+        subject: Set(identity.subject.clone()),
+        email: Set(Some(email)),
+        created_at: Set(now),
+        last_login_at: Set(now),
+        ..Default::default()
+    };
+    let link = active.insert(&txn).await?;
+    let audit = AuditContext { actor_user_id: audit.actor_user_id.or(Some(user.id)), ..audit.clone() };
+    audit_create(&txn, &audit, user.id, "user_identity", link.id, &link).await?;
+    txn.commit().await?;
+    Ok(user)
+}
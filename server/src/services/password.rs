@@ -0,0 +1,162 @@
+use argon2::password_hash::{PasswordHash, PasswordHasher as _, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// OWASP's baseline for Argon2id: 19 MiB of memory, two passes, one lane.
+pub const DEFAULT_ARGON2_MEMORY_KIB: u32 = 19_456;
+pub const DEFAULT_ARGON2_ITERATIONS: u32 = 2;
+pub const DEFAULT_ARGON2_PARALLELISM: u32 = 1;
+pub const DEFAULT_PASSWORD_MIN_LENGTH: usize = 8;
+/// Longer passwords are refused so that hashing one stays cheap to ask for.
+pub const MAX_PASSWORD_LENGTH: usize = 256;
+/// Well-known passwords from public breaches, refused regardless of case.
+const BUNDLED_BREACH_LIST: &str = include_str!("breached_passwords.txt");
+/// Stored for users who signed up through an identity provider: no password matches it until
+/// one is set with a reset.
+pub const UNUSABLE_PASSWORD_HASH: &str = "!";
+
+#[derive(Debug)]
+pub enum PasswordError {
+    /// Fewer characters than the policy's minimum, which it carries.
+    TooShort(usize),
+    TooLong,
+    /// On the breach list.
+    Breached,
+    /// Bad parameters, or a stored hash that cannot be read.
+    Hash(String),
+}
+
+impl std::fmt::Display for PasswordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PasswordError::TooShort(min) => write!(f, "password must be at least {} characters", min),
+            PasswordError::TooLong => write!(f, "password must be at most {} characters", MAX_PASSWORD_LENGTH),
+            PasswordError::Breached => write!(f, "password is too common; it appears in known data breaches"),
+            PasswordError::Hash(e) => write!(f, "password hash: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PasswordError {}
+
+impl From<argon2::Error> for PasswordError {
+    fn from(e: argon2::Error) -> Self {
+        PasswordError::Hash(e.to_string())
+    }
+}
+
+impl From<argon2::password_hash::Error> for PasswordError {
+    fn from(e: argon2::password_hash::Error) -> Self {
+        PasswordError::Hash(e.to_string())
+    }
+}
+
+/// Hashes new passwords with Argon2id and checks them against the password policy. Stored
+/// bcrypt hashes (from before Argon2id) still verify; `needs_rehash` tells the login to replace
+/// them, and Argon2id hashes made with other parameters, with a current hash.
+#[derive(Clone)]
+pub struct PasswordHasher {
+    params: Params,
+    min_length: usize,
+    breached: Arc<HashSet<String>>,
+}
+
+impl Default for PasswordHasher {
+    fn default() -> Self {
+        PasswordHasher::new(DEFAULT_ARGON2_MEMORY_KIB, DEFAULT_ARGON2_ITERATIONS, DEFAULT_ARGON2_PARALLELISM).expect("default Argon2 parameters")
+    }
+}
+
+fn breach_list(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')).map(str::to_lowercase)
+}
+
+impl PasswordHasher {
+    pub fn new(memory_kib: u32, iterations: u32, parallelism: u32) -> Result<Self, PasswordError> {
+        Ok(PasswordHasher {
+            params: Params::new(memory_kib, iterations, parallelism, None)?,
+            min_length: DEFAULT_PASSWORD_MIN_LENGTH,
+            breached: Arc::new(breach_list(BUNDLED_BREACH_LIST).collect()),
+        })
+    }
+
+    pub fn with_min_length(mut self, min_length: usize) -> Self {
+        self.min_length = min_length;
+        self
+    }
+
+    /// Adds passwords to refuse, one per line, to the bundled list.
+    pub fn with_breach_list(mut self, text: &str) -> Self {
+        Arc::make_mut(&mut self.breached).extend(breach_list(text));
+        self
+    }
+
+    pub fn from_config(config: &crate::config::Config) -> Result<Self, PasswordError> {
+        let mut hasher = PasswordHasher::new(config.argon2_memory_kib, config.argon2_iterations, config.argon2_parallelism)?
+            .with_min_length(config.password_min_length);
+        if let Some(path) = &config.password_breach_list {
+            let text = std::fs::read_to_string(path).map_err(|e| PasswordError::Hash(format!("{}: {}", path, e)))?;
+            hasher = hasher.with_breach_list(&text);
+        }
+        Ok(hasher)
+    }
+
+    fn argon2(&self) -> Argon2<'static> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+    }
+
+    /// Enforced when a password is chosen (sign-up, change, reset), not when one is used.
+    pub fn check_policy(&self, password: &str) -> Result<(), PasswordError> {
+        let length = password.chars().count();
+        if length < self.min_length {
+            return Err(PasswordError::TooShort(self.min_length));
+        }
+        if length > MAX_PASSWORD_LENGTH {
+            return Err(PasswordError::TooLong);
+        }
+        if self.breached.contains(&password.to_lowercase()) {
+            return Err(PasswordError::Breached);
+        }
+        Ok(())
+    }
+
+    /// A PHC string (`$argon2id$v=19$m=…,t=…,p=…$salt$hash`) with a random salt.
+    pub fn hash(&self, password: &str) -> Result<String, PasswordError> {
+        let mut salt = [0u8; 16];
+        getrandom::fill(&mut salt).expect("operating system random source");
+        let salt = SaltString::encode_b64(&salt)?;
+        Ok(self.argon2().hash_password(password.as_bytes(), &salt)?.to_string())
+    }
+
+    /// Checks `password` against an Argon2 or a legacy bcrypt hash.
+    pub fn verify(&self, password: &str, hash: &str) -> Result<bool, PasswordError> {
+        if hash == UNUSABLE_PASSWORD_HASH {
+            return Ok(false);
+        }
+        if !hash.starts_with("$argon2") {
+            return bcrypt::verify(password, hash).map_err(|e| PasswordError::Hash(e.to_string()));
+        }
+        // The stored hash names its own algorithm and parameters.
+        match Argon2::default().verify_password(password.as_bytes(), &PasswordHash::new(hash)?) {
+            Ok(()) => Ok(true),
+            Err(argon2::password_hash::Error::Password) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Does the work of a `verify` against a current hash without anything to compare with, for
+    /// logins naming no account.
+    pub fn verify_nothing(&self, password: &str) {
+        let _ = self.hash(password);
+    }
+
+    /// Whether `hash` was made by anything other than Argon2id with the current parameters.
+    pub fn needs_rehash(&self, hash: &str) -> bool {
+        let Ok(parsed) = PasswordHash::new(hash) else { return true };
+        let current = parsed.algorithm == Algorithm::Argon2id.ident()
+            && parsed.version == Some(Version::V0x13.into())
+            && Params::try_from(&parsed).is_ok_and(|p| p.m_cost() == self.params.m_cost() && p.t_cost() == self.params.t_cost() && p.p_cost() == self.params.p_cost());
+        !current
+    }
+}
@@ -0,0 +1,166 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use crate::services::{decode_text, parse_amount, HoldingChange, ImportIssue, ImportPreview, ImportRow, ServiceError};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// Parses QIF dates: `MM/DD/YYYY`, `M/D'YY`, `YYYY-MM-DD`. QIF is US-centric, so month comes first.
+fn parse_qif_date(raw: &str) -> Option<DateTime<Utc>> {
+    let cleaned: String = raw.trim().replace('\'', "/").chars().filter(|c| !c.is_whitespace()).collect();
+    let parts: Vec<&str> = cleaned.split(['/', '-', '.']).collect();
+    let [a, b, c] = parts[..] else { return None };
+    let (y, m, d) = if a.len() == 4 {
+        (a.parse::<i32>().ok()?, b.parse().ok()?, c.parse().ok()?)
+    } else {
+        let y: i32 = c.parse().ok()?;
+        let y = match y {
+            0..=69 => 2000 + y,
+            70..=99 => 1900 + y,
+            _ => y,
+        };
+        (y, a.parse().ok()?, b.parse().ok()?)
+    };
+    NaiveDate::from_ymd_opt(y, m, d)?.and_hms_opt(0, 0, 0).map(|dt| dt.and_utc())
+}
+
+/// One `^`-terminated record: field code -> value, plus the line it started on.
+struct Record {
+    line: usize,
+    fields: HashMap<char, String>,
+}
+
+impl Record {
+    fn get(&self, code: char) -> Option<&str> {
+        self.fields.get(&code).map(String::as_str).filter(|v| !v.is_empty())
+    }
+}
+
+/// Parses a QIF export (`!Type:Bank`, `Cash`, `CCard`, `Oth A/L` and `Invst` sections).
+/// QIF has no transaction ids, so rows get a stable id from date, amount and payee for dedupe.
+pub fn parse_qif(bytes: &[u8]) -> Result<ImportPreview, ServiceError> {
+    let text = decode_text(bytes, "utf-8").or_else(|_| decode_text(bytes, "windows-1252"))?;
+    let mut preview = ImportPreview::default();
+    let mut section = String::new();
+    let mut current: Option<Record> = None;
+    let mut occurrences: HashMap<String, usize> = HashMap::new();
+
+    for (i, raw) in text.lines().enumerate() {
+        let line = i + 1;
+        let raw = raw.trim_end();
+        if raw.is_empty() {
+            continue;
+        }
+        if let Some(header) = raw.strip_prefix('!') {
+            if let Some(t) = header.strip_prefix("Type:") {
+                section = t.trim().to_string();
+            }
+            continue;
+        }
+        if raw.starts_with('^') {
+            if let Some(record) = current.take() {
+                convert_record(&section, record, &mut occurrences, &mut preview);
+            }
+            continue;
+        }
+        let mut chars = raw.chars();
+        let code = chars.next().unwrap_or(' ');
+        let record = current.get_or_insert_with(|| Record { line, fields: HashMap::new() });
+        // Split lines (S/E/$) repeat; the first occurrence is enough for a transaction row.
+        record.fields.entry(code).or_insert_with(|| chars.as_str().trim().to_string());
+    }
+    if let Some(record) = current.take() {
+        convert_record(&section, record, &mut occurrences, &mut preview);
+    }
+    if preview.rows.is_empty() && preview.errors.is_empty() && preview.ignored.is_empty() {
+        return Err(ServiceError::Invalid("no QIF records found".into()));
+    }
+    Ok(preview)
+}
+
+fn convert_record(section: &str, r: Record, occurrences: &mut HashMap<String, usize>, preview: &mut ImportPreview) {
+    if matches!(section, "Class" | "Cat" | "Memorized" | "Security" | "Prices") || section.starts_with("Account") {
+        return;
+    }
+    let Some(date) = r.get('D').and_then(parse_qif_date) else {
+        preview.errors.push(ImportIssue { line: r.line, message: format!("invalid date: {:?}", r.get('D').unwrap_or("")) });
+        return;
+    };
+    let amount = r.get('T').or_else(|| r.get('U')).and_then(parse_amount);
+    let payee = r.get('P').map(str::to_string);
+    let memo = r.get('M').map(str::to_string);
+
+    // Same date/amount/payee can legitimately repeat; number the repeats. Hashed, since the
+    // payee is sealed in `counterparty` while `external_id` stays plaintext for deduping.
+    let key = format!("{}|{}|{}", date.format("%Y%m%d"), amount.unwrap_or_default().normalize(), payee.as_deref().unwrap_or(""));
+    let n = occurrences.entry(key.clone()).or_insert(0);
+    *n += 1;
+    let digest: String = Sha256::digest(format!("{}|{}", key, n)).iter().take(16).map(|b| format!("{:02x}", b)).collect();
+    let external_id = Some(format!("qif:{}", digest));
+
+    if section == "Invst" {
+        let action = r.get('N').unwrap_or("");
+        let security = r.get('Y').unwrap_or("").to_string();
+        let total = amount.unwrap_or_default();
+        let (transaction_type, category, signed) = match action {
+            "Buy" | "BuyX" => ("transfer", "investment", -total.abs()),
+            "Sell" | "SellX" => ("transfer", "investment", total.abs()),
+            "Div" | "DivX" => ("income", "dividend", total.abs()),
+            "IntInc" | "IntIncX" => ("income", "interest", total.abs()),
+            _ => {
+                preview.ignored.push(ImportIssue { line: r.line, message: format!("unsupported investment action: {}", action) });
+                return;
+            }
+        };
+        if matches!(action, "Buy" | "BuyX" | "Sell" | "SellX") {
+            let (Some(units), Some(price)) = (r.get('Q').and_then(parse_amount), r.get('I').and_then(parse_amount)) else {
+                preview.errors.push(ImportIssue { line: r.line, message: "trade without quantity (Q) or price (I)".into() });
+                return;
+            };
+            let units = if action.starts_with("Sell") { -units.abs() } else { units.abs() };
+            preview.holdings.push(HoldingChange {
+                line: r.line,
+                date,
+                symbol: security.clone(),
+                name: security.clone(),
+                asset_type: "stock".to_string(),
+                units,
+                unit_price: price.abs(),
+                external_id: external_id.clone(),
+            });
+        }
+        preview.rows.push(ImportRow {
+            line: r.line,
+            date,
+            transaction_type: transaction_type.to_string(),
+            amount: if transaction_type == "transfer" { signed } else { signed.abs() },
+            description: memo.unwrap_or_else(|| format!("{} {}", action, security)),
+            category: Some(category.to_string()),
+            counterparty: None,
+            external_id,
+            refund_of: None,
+            tags: Vec::new(),
+        });
+        return;
+    }
+
+    let Some(amount) = amount.filter(|a| !a.is_zero()) else {
+        preview.errors.push(ImportIssue { line: r.line, message: "missing or zero amount".into() });
+        return;
+    };
+    // `L[Savings]` names another account: a transfer.
+    let category = r.get('L');
+    let is_transfer = category.is_some_and(|c| c.starts_with('['));
+    let transaction_type = if is_transfer { "transfer" } else if amount.is_sign_negative() { "expense" } else { "income" };
+    preview.rows.push(ImportRow {
+        line: r.line,
+        date,
+        transaction_type: transaction_type.to_string(),
+        amount: if is_transfer { amount } else { amount.abs() },
+        description: memo.or_else(|| payee.clone()).unwrap_or_default(),
+        category: category.filter(|_| !is_transfer).map(str::to_string),
+        counterparty: payee,
+        external_id,
+        refund_of: None,
+        tags: Vec::new(),
+    });
+}
+
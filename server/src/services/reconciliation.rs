@@ -0,0 +1,142 @@
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set, TransactionTrait};
+use serde::Serialize;
+use crate::models::{reconciliation, transaction};
+use crate::services::{account_owner, audit_update, AuditContext, ServiceError, signed_amount, STATUS_CLEARED, STATUS_PENDING, STATUS_RECONCILED};
+use sea_orm::prelude::Decimal;
+
+pub const RECONCILIATION_OPEN: &str = "open";
+pub const RECONCILIATION_COMPLETED: &str = "completed";
+
+#[derive(Serialize)]
+pub struct ReconciliationSummary {
+    pub reconciliation: reconciliation::Model,
+    /// Opening balance plus every cleared item up to the statement date.
+    pub cleared_balance: Decimal,
+    /// Statement ending balance minus cleared balance; must be zero to complete.
+    pub difference: Decimal,
+    pub cleared: Vec<transaction::Model>,
+    pub uncleared: Vec<transaction::Model>,
+}
+
+pub async fn create_reconciliation(
+    db: &DatabaseConnection,
+    account_id: i32,
+    statement_date: chrono::NaiveDate,
+    ending_balance: Decimal,
+) -> Result<reconciliation::Model, ServiceError> {
+    let existing = find_reconciliations_by_account(db, account_id).await?;
+    if existing.iter().any(|r| r.status == RECONCILIATION_OPEN) {
+        return Err(ServiceError::Invalid("account already has an open reconciliation".into()));
+    }
+    // The previous statement's ending balance is where this one starts.
+    let opening_balance = existing
+        .iter()
+        .filter(|r| r.status == RECONCILIATION_COMPLETED)
+        .max_by_key(|r| r.statement_date)
+        .map(|r| r.ending_balance)
+        .unwrap_or(Decimal::ZERO);
+    let active = reconciliation::ActiveModel {
+        account_id: Set(account_id),
+        statement_date: Set(statement_date),
+        opening_balance: Set(opening_balance),
+        ending_balance: Set(ending_balance),
+        status: Set(RECONCILIATION_OPEN.to_string()),
+        ..Default::default()
+    };
+    Ok(active.insert(db).await?)
+}
+
+pub async fn get_reconciliation_by_id(
+    db: &DatabaseConnection,
+    id: i32,
+) -> Result<Option<reconciliation::Model>, sea_orm::DbErr> {
+    reconciliation::Entity::find_by_id(id).one(db).await
+}
+
+pub async fn find_reconciliations_by_account(
+    db: &DatabaseConnection,
+    account_id: i32,
+) -> Result<Vec<reconciliation::Model>, sea_orm::DbErr> {
+    reconciliation::Entity::find()
+        .filter(reconciliation::Column::AccountId.eq(account_id))
+        .order_by_asc(reconciliation::Column::StatementDate)
+        .all(db)
+        .await
+}
+
+/// For an open session lists the cleared and still-pending items up to the statement date;
+/// a completed session reports the items it reconciled.
+pub async fn reconciliation_summary(
+    db: &DatabaseConnection,
+    rec: reconciliation::Model,
+) -> Result<ReconciliationSummary, sea_orm::DbErr> {
+    let (cleared, uncleared) = if rec.status == RECONCILIATION_COMPLETED {
+        let reconciled = transaction::Entity::find()
+            .filter(transaction::Column::ReconciliationId.eq(rec.id))
+            .order_by_asc(transaction::Column::CreatedAt)
+            .all(db)
+            .await?;
+        (reconciled, Vec::new())
+    } else {
+        let items = transaction::Entity::find()
+            .filter(transaction::Column::AccountId.eq(rec.account_id))
+            .filter(transaction::Column::Status.is_in([STATUS_PENDING, STATUS_CLEARED]))
+            .order_by_asc(transaction::Column::CreatedAt)
+            .all(db)
+            .await?;
+        items
+            .into_iter()
+            .filter(|t| t.created_at.date_naive() <= rec.statement_date)
+            .partition(|t| t.status == STATUS_CLEARED)
+    };
+    let cleared_balance = rec.opening_balance + cleared.iter().map(signed_amount).sum::<Decimal>();
+    let difference = rec.ending_balance - cleared_balance;
+    Ok(ReconciliationSummary { reconciliation: rec, cleared_balance, difference, cleared, uncleared })
+}
+
+/// Locks every cleared item of the session as `reconciled`. Fails unless the difference is zero.
+pub async fn complete_reconciliation(
+    db: &DatabaseConnection,
+    audit: &AuditContext,
+    id: i32,
+) -> Result<Option<ReconciliationSummary>, ServiceError> {
+    let Some(rec) = get_reconciliation_by_id(db, id).await? else { return Ok(None) };
+    if rec.status != RECONCILIATION_OPEN {
+        return Err(ServiceError::Invalid("reconciliation is not open".into()));
+    }
+    let summary = reconciliation_summary(db, rec).await?;
+    if !summary.difference.is_zero() {
+        return Err(ServiceError::Invalid(format!("difference is {}, expected 0", summary.difference)));
+    }
+
+    let txn = db.begin().await?;
+    let user_id = account_owner(&txn, summary.reconciliation.account_id).await?;
+    for t in &summary.cleared {
+        let mut active: transaction::ActiveModel = t.clone().into();
+        active.status = Set(STATUS_RECONCILED.to_string());
+        active.reconciliation_id = Set(Some(id));
+        let updated = active.update(&txn).await?;
+        audit_update(&txn, audit, user_id, "transaction", t.id, t, &updated).await?;
+    }
+    let mut active: reconciliation::ActiveModel = summary.reconciliation.clone().into();
+    active.status = Set(RECONCILIATION_COMPLETED.to_string());
+    active.completed_at = Set(Some(chrono::Utc::now()));
+    let rec = active.update(&txn).await?;
+    txn.commit().await?;
+
+    let summary = reconciliation_summary(db, rec).await?;
+    Ok(Some(summary))
+}
+
+/// Discards an open session; completed sessions are kept as history.
+pub async fn delete_reconciliation(
+    db: &DatabaseConnection,
+    id: i32,
+) -> Result<u64, ServiceError> {
+    let Some(rec) = get_reconciliation_by_id(db, id).await? else { return Ok(0) };
+    if rec.status != RECONCILIATION_OPEN {
+        return Err(ServiceError::Locked("completed reconciliations cannot be deleted".into()));
+    }
+    let res = reconciliation::Entity::delete_by_id(id).exec(db).await?;
+    Ok(res.rows_affected)
+}
@@ -0,0 +1,123 @@
+use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveDate, TimeZone, Utc};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
+use serde::Serialize;
+use crate::models::{account, transaction};
+use crate::services::{created_before, find_accounts_by_user, signed_amount, ServiceError};
+use sea_orm::prelude::Decimal;
+use std::collections::BTreeMap;
+
+/// A calendar month in the reader's time zone, as a half-open UTC range.
+#[derive(Debug, Clone, Copy)]
+pub struct MonthPeriod {
+    pub year: i32,
+    pub month: u32,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+impl MonthPeriod {
+    /// Parses `YYYY-MM`; `utc_offset_minutes` places the month boundaries (China is 480).
+    pub fn parse(raw: &str, utc_offset_minutes: i32) -> Result<Self, ServiceError> {
+        let invalid = || ServiceError::Invalid(format!("invalid month {:?}, expected YYYY-MM", raw));
+        let first = NaiveDate::parse_from_str(&format!("{}-01", raw.trim()), "%Y-%m-%d").map_err(|_| invalid())?;
+        let next = first.checked_add_months(chrono::Months::new(1)).ok_or_else(invalid)?;
+        let tz = FixedOffset::east_opt(utc_offset_minutes * 60)
+            .ok_or_else(|| ServiceError::Invalid(format!("invalid utc offset {}", utc_offset_minutes)))?;
+        let at = |d: NaiveDate| tz.from_local_datetime(&d.and_hms_opt(0, 0, 0).expect("midnight")).unwrap().with_timezone(&Utc);
+        Ok(MonthPeriod { year: first.year(), month: first.month(), start: at(first), end: at(next) })
+    }
+
+    pub fn label(&self) -> String {
+        format!("{:04}-{:02}", self.year, self.month)
+    }
+
+    /// Last day of the month, for display.
+    pub fn last_day(&self, utc_offset_minutes: i32) -> NaiveDate {
+        (self.end - Duration::seconds(1) + Duration::minutes(utc_offset_minutes as i64)).date_naive()
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct StatementLine {
+    pub transaction: transaction::Model,
+    pub signed_amount: Decimal,
+    /// Balance after this line.
+    pub balance: Decimal,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CategoryTotal {
+    pub category: String,
+    pub income: Decimal,
+    pub expense: Decimal,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Statement {
+    pub account: account::Model,
+    pub opening_balance: Decimal,
+    pub closing_balance: Decimal,
+    pub total_income: Decimal,
+    pub total_expense: Decimal,
+    pub lines: Vec<StatementLine>,
+    pub categories: Vec<CategoryTotal>,
+}
+
+/// Account `balance` is the balance the account was opened with; every transaction moves it.
+fn build_statement(account: account::Model, mut transactions: Vec<transaction::Model>, period: &MonthPeriod) -> Statement {
+    transactions.sort_by_key(|t| (t.created_at, t.id));
+    let opening_balance = account.balance
+        + transactions.iter().filter(|t| t.created_at < period.start).map(signed_amount).sum::<Decimal>();
+
+    let mut balance = opening_balance;
+    let mut total_income = Decimal::ZERO;
+    let mut total_expense = Decimal::ZERO;
+    let mut categories: BTreeMap<String, (Decimal, Decimal)> = BTreeMap::new();
+    let mut lines = Vec::new();
+    for t in transactions.into_iter().filter(|t| t.created_at >= period.start && t.created_at < period.end) {
+        let signed = signed_amount(&t);
+        balance += signed;
+        let entry = categories.entry(t.category.clone().unwrap_or_default()).or_default();
+        match t.transaction_type.as_str() {
+            "income" => {
+                total_income += t.amount;
+                entry.0 += t.amount;
+            }
+            "expense" => {
+                total_expense += t.amount;
+                entry.1 += t.amount;
+            }
+            _ => {}
+        }
+        lines.push(StatementLine { transaction: t, signed_amount: signed, balance });
+    }
+    let categories = categories
+        .into_iter()
+        .filter(|(_, (i, e))| !i.is_zero() || !e.is_zero())
+        .map(|(category, (income, expense))| CategoryTotal { category, income, expense })
+        .collect();
+    Statement { account, opening_balance, closing_balance: balance, total_income, total_expense, lines, categories }
+}
+
+async fn account_transactions(db: &DatabaseConnection, account_id: i32, before: DateTime<Utc>) -> Result<Vec<transaction::Model>, sea_orm::DbErr> {
+    transaction::Entity::find()
+        .filter(transaction::Column::AccountId.eq(account_id))
+        .filter(created_before(before))
+        .order_by_asc(transaction::Column::Id)
+        .all(db)
+        .await
+}
+
+pub async fn account_statement(db: &DatabaseConnection, account: account::Model, period: &MonthPeriod) -> Result<Statement, sea_orm::DbErr> {
+    let transactions = account_transactions(db, account.id, period.end).await?;
+    Ok(build_statement(account, transactions, period))
+}
+
+/// Statements for every account the user owns, for the balances overview.
+pub async fn user_statements(db: &DatabaseConnection, user_id: i32, period: &MonthPeriod) -> Result<Vec<Statement>, sea_orm::DbErr> {
+    let mut out = Vec::new();
+    for account in find_accounts_by_user(db, user_id).await? {
+        out.push(account_statement(db, account, period).await?);
+    }
+    Ok(out)
+}
@@ -0,0 +1,230 @@
+use chrono::Duration;
+use pdf_writer::types::{CidFontType, FontFlags, SystemInfo};
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+use crate::services::{MonthPeriod, Statement};
+
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 40.0;
+const FONT: Name<'static> = Name(b"F1");
+const SIZE: f32 = 9.0;
+const LINE: f32 = 14.0;
+
+/// Adobe's standard Simplified Chinese font. It is not embedded: every PDF reader ships a
+/// substitute, which keeps the file small and needs no font files on the server.
+const CJK_FONT: Name<'static> = Name(b"STSong-Light");
+
+/// Advance widths (per 1000 em) of STSong-Light's proportional Latin glyphs, CIDs 1-95, which
+/// `UniGB-UCS2-H` maps from U+0020-U+007E. These are Adobe's published metrics for the font;
+/// every other CID, the CJK glyphs included, is full-width.
+const ASCII_WIDTHS: [f32; 95] = [
+    207.0, 270.0, 342.0, 467.0, 462.0, 797.0, 710.0, 239.0, 374.0, 374.0, 423.0, 605.0, 238.0, 375.0, 238.0, 334.0,
+    462.0, 462.0, 462.0, 462.0, 462.0, 462.0, 462.0, 462.0, 462.0, 462.0, 238.0, 238.0, 605.0, 605.0, 605.0, 344.0,
+    748.0, 684.0, 560.0, 695.0, 739.0, 563.0, 511.0, 729.0, 793.0, 318.0, 312.0, 666.0, 526.0, 896.0, 758.0, 772.0,
+    544.0, 772.0, 628.0, 465.0, 607.0, 753.0, 711.0, 972.0, 647.0, 620.0, 607.0, 374.0, 333.0, 374.0, 606.0, 500.0,
+    239.0, 417.0, 503.0, 427.0, 529.0, 415.0, 264.0, 444.0, 518.0, 241.0, 230.0, 495.0, 228.0, 793.0, 527.0, 524.0,
+    524.0, 504.0, 338.0, 336.0, 277.0, 517.0, 450.0, 652.0, 466.0, 452.0, 407.0, 370.0, 258.0, 370.0, 605.0,
+];
+const FULL_WIDTH: f32 = 1000.0;
+
+/// Text for a UCS-2 CMap; characters outside the BMP become `?`.
+fn encode(text: &str) -> Vec<u8> {
+    text.chars()
+        .flat_map(|c| u16::try_from(c as u32).unwrap_or(b'?' as u16).to_be_bytes())
+        .collect()
+}
+
+/// Rendered width of `text`, from the same metrics the font's `/W` table declares.
+fn width(text: &str, size: f32) -> f32 {
+    let units: f32 = text
+        .chars()
+        .map(|c| match c {
+            ' '..='~' => ASCII_WIDTHS[c as usize - 0x20],
+            _ => FULL_WIDTH,
+        })
+        .sum();
+    units * size / 1000.0
+}
+
+fn fit(text: &str, max: f32, size: f32) -> String {
+    if width(text, size) <= max {
+        return text.to_string();
+    }
+    let mut out = String::new();
+    for c in text.chars() {
+        if width(&out, size) + width(&c.to_string(), size) + width("...", size) > max {
+            break;
+        }
+        out.push(c);
+    }
+    out + "..."
+}
+
+struct Pages {
+    done: Vec<Content>,
+    current: Content,
+    y: f32,
+}
+
+impl Pages {
+    fn new() -> Self {
+        Pages { done: Vec::new(), current: Content::new(), y: PAGE_HEIGHT - MARGIN }
+    }
+
+    fn text(&mut self, x: f32, size: f32, s: &str) {
+        self.current.begin_text();
+        self.current.set_font(FONT, size);
+        self.current.next_line(x, self.y);
+        self.current.show(Str(&encode(s)));
+        self.current.end_text();
+    }
+
+    fn text_right(&mut self, right: f32, size: f32, s: &str) {
+        self.text(right - width(s, size), size, s);
+    }
+
+    fn rule(&mut self) {
+        let y = self.y - 4.0;
+        self.current.set_line_width(0.5);
+        self.current.move_to(MARGIN, y);
+        self.current.line_to(PAGE_WIDTH - MARGIN, y);
+        self.current.stroke();
+    }
+
+    fn break_page(&mut self) {
+        let page = std::mem::replace(&mut self.current, Content::new());
+        self.done.push(page);
+        self.y = PAGE_HEIGHT - MARGIN;
+    }
+
+    fn finish(mut self) -> Vec<Content> {
+        self.break_page();
+        self.done
+    }
+}
+
+const COL_DATE: f32 = MARGIN;
+const COL_DESC: f32 = 100.0;
+const COL_CATEGORY: f32 = 330.0;
+const COL_STATUS: f32 = 400.0;
+const COL_AMOUNT_RIGHT: f32 = 490.0;
+const COL_BALANCE_RIGHT: f32 = PAGE_WIDTH - MARGIN;
+
+fn table_header(pages: &mut Pages) {
+    pages.text(COL_DATE, SIZE, "Date");
+    pages.text(COL_DESC, SIZE, "Description");
+    pages.text(COL_CATEGORY, SIZE, "Category");
+    pages.text(COL_STATUS, SIZE, "Status");
+    pages.text_right(COL_AMOUNT_RIGHT, SIZE, "Amount");
+    pages.text_right(COL_BALANCE_RIGHT, SIZE, "Balance");
+    pages.rule();
+    pages.y -= LINE + 2.0;
+}
+
+/// Printable monthly statement for one account: balances, totals and every transaction.
+pub fn render_statement_pdf(statement: &Statement, period: &MonthPeriod, utc_offset_minutes: i32) -> Vec<u8> {
+    let account = &statement.account;
+    let currency = &account.currency;
+    let mut pages = Pages::new();
+
+    pages.text(MARGIN, 16.0, &format!("Account Statement {}", period.label()));
+    pages.y -= 24.0;
+    pages.text(MARGIN, 11.0, &format!("{} ({}, {})", account.name, account.account_type, currency));
+    pages.y -= 16.0;
+    pages.text(MARGIN, SIZE, &format!("Period: {}-01 to {}", period.label(), period.last_day(utc_offset_minutes)));
+    pages.y -= LINE * 1.5;
+    for (label, value) in [
+        ("Opening balance", statement.opening_balance),
+        ("Total income", statement.total_income),
+        ("Total expense", statement.total_expense),
+        ("Closing balance", statement.closing_balance),
+    ] {
+        pages.text(MARGIN, SIZE, label);
+        pages.text_right(250.0, SIZE, &format!("{:.2} {}", value, currency));
+        pages.y -= LINE;
+    }
+    pages.y -= LINE;
+    table_header(&mut pages);
+
+    if statement.lines.is_empty() {
+        pages.text(COL_DESC, SIZE, "No transactions in this period.");
+    }
+    for line in &statement.lines {
+        if pages.y < MARGIN + LINE {
+            pages.break_page();
+            table_header(&mut pages);
+        }
+        let t = &line.transaction;
+        let local = t.created_at + Duration::minutes(utc_offset_minutes as i64);
+        let description = match &t.counterparty {
+            Some(p) if !p.is_empty() => format!("{} - {}", p, t.description),
+            _ => t.description.to_string(),
+        };
+        pages.text(COL_DATE, SIZE, &local.format("%Y-%m-%d").to_string());
+        pages.text(COL_DESC, SIZE, &fit(&description, COL_CATEGORY - COL_DESC - 8.0, SIZE));
+        pages.text(COL_CATEGORY, SIZE, &fit(t.category.as_deref().unwrap_or(""), COL_STATUS - COL_CATEGORY - 8.0, SIZE));
+        pages.text(COL_STATUS, SIZE, &t.status);
+        pages.text_right(COL_AMOUNT_RIGHT, SIZE, &format!("{:.2}", line.signed_amount));
+        pages.text_right(COL_BALANCE_RIGHT, SIZE, &format!("{:.2}", line.balance));
+        pages.y -= LINE;
+    }
+
+    let contents = pages.finish();
+    let total = contents.len();
+    let catalog_id = Ref::new(1);
+    let tree_id = Ref::new(2);
+    let font_id = Ref::new(3);
+    let cid_font_id = Ref::new(4);
+    let descriptor_id = Ref::new(5);
+    let info_id = Ref::new(6);
+    let page_ids: Vec<Ref> = (0..total).map(|i| Ref::new(7 + 2 * i as i32)).collect();
+
+    let mut pdf = Pdf::new();
+    pdf.catalog(catalog_id).pages(tree_id);
+    pdf.pages(tree_id).kids(page_ids.iter().copied()).count(total as i32);
+    pdf.document_info(info_id)
+        .title(TextStr(&format!("Account Statement {} {}", account.name, period.label())))
+        .producer(TextStr("Your Wallet"));
+    pdf.type0_font(font_id)
+        .base_font(CJK_FONT)
+        .encoding_predefined(Name(b"UniGB-UCS2-H"))
+        .descendant_font(cid_font_id);
+    let mut cid = pdf.cid_font(cid_font_id);
+    cid.subtype(CidFontType::Type0)
+        .base_font(CJK_FONT)
+        .system_info(SystemInfo { registry: Str(b"Adobe"), ordering: Str(b"GB1"), supplement: 2 })
+        .font_descriptor(descriptor_id)
+        .default_width(FULL_WIDTH);
+    cid.widths().consecutive(1, ASCII_WIDTHS);
+    cid.finish();
+    pdf.font_descriptor(descriptor_id)
+        .name(CJK_FONT)
+        .flags(FontFlags::SYMBOLIC)
+        .bbox(Rect::new(-25.0, -254.0, 1000.0, 880.0))
+        .italic_angle(0.0)
+        .ascent(880.0)
+        .descent(-120.0)
+        .cap_height(880.0)
+        .stem_v(93.0);
+
+    for (i, mut content) in contents.into_iter().enumerate() {
+        // Page footer.
+        let footer = format!("Page {} of {}", i + 1, total);
+        content.begin_text();
+        content.set_font(FONT, 8.0);
+        content.next_line(PAGE_WIDTH - MARGIN - width(&footer, 8.0), MARGIN / 2.0);
+        content.show(Str(&encode(&footer)));
+        content.end_text();
+
+        let page_id = page_ids[i];
+        let content_id = Ref::new(page_id.get() + 1);
+        let mut page = pdf.page(page_id);
+        page.media_box(Rect::new(0.0, 0.0, PAGE_WIDTH, PAGE_HEIGHT));
+        page.parent(tree_id);
+        page.contents(content_id);
+        page.resources().fonts().pair(FONT, font_id);
+        page.finish();
+        pdf.stream(content_id, &content.finish());
+    }
+    pdf.finish()
+}
@@ -0,0 +1,86 @@
+use chrono::Duration;
+use rust_xlsxwriter::{Format, Workbook, Worksheet, XlsxError};
+use crate::services::{MonthPeriod, Statement};
+use sea_orm::prelude::Decimal;
+
+fn number(d: Decimal) -> f64 {
+    f64::try_from(d).unwrap_or_default()
+}
+
+fn header(sheet: &mut Worksheet, titles: &[&str], bold: &Format) -> Result<(), XlsxError> {
+    for (col, title) in titles.iter().enumerate() {
+        sheet.write_string_with_format(0, col as u16, *title, bold)?;
+    }
+    sheet.set_freeze_panes(1, 0)?;
+    Ok(())
+}
+
+/// Workbook for one account's month: itemised transactions, a category summary, and the
+/// balances of all the owner's accounts (`all`) for the same month.
+pub fn render_statement_xlsx(statement: &Statement, all: &[Statement], period: &MonthPeriod, utc_offset_minutes: i32) -> Result<Vec<u8>, XlsxError> {
+    let bold = Format::new().set_bold();
+    let money = Format::new().set_num_format("#,##0.00");
+    let money_bold = Format::new().set_bold().set_num_format("#,##0.00");
+    let mut workbook = Workbook::new();
+
+    let sheet = workbook.add_worksheet().set_name("Transactions")?;
+    header(sheet, &["Date", "Description", "Counterparty", "Category", "Type", "Status", "Amount", "Balance"], &bold)?;
+    sheet.set_column_width(0, 17)?;
+    sheet.set_column_width(1, 32)?;
+    sheet.set_column_width(2, 20)?;
+    sheet.set_column_width(3, 14)?;
+    sheet.set_column_width(6, 12)?;
+    sheet.set_column_width(7, 12)?;
+    sheet.write_string_with_format(1, 1, "Opening balance", &bold)?;
+    sheet.write_number_with_format(1, 7, number(statement.opening_balance), &money_bold)?;
+    let mut row = 2;
+    for line in &statement.lines {
+        let t = &line.transaction;
+        let local = t.created_at + Duration::minutes(utc_offset_minutes as i64);
+        sheet.write_string(row, 0, local.format("%Y-%m-%d %H:%M").to_string())?;
+        sheet.write_string(row, 1, t.description.as_str())?;
+        sheet.write_string(row, 2, t.counterparty.clone().unwrap_or_default())?;
+        sheet.write_string(row, 3, t.category.clone().unwrap_or_default())?;
+        sheet.write_string(row, 4, t.transaction_type.as_str())?;
+        sheet.write_string(row, 5, t.status.as_str())?;
+        sheet.write_number_with_format(row, 6, number(line.signed_amount), &money)?;
+        sheet.write_number_with_format(row, 7, number(line.balance), &money)?;
+        row += 1;
+    }
+    sheet.write_string_with_format(row, 1, "Closing balance", &bold)?;
+    sheet.write_number_with_format(row, 7, number(statement.closing_balance), &money_bold)?;
+
+    let sheet = workbook.add_worksheet().set_name("Categories")?;
+    header(sheet, &["Category", "Income", "Expense", "Net"], &bold)?;
+    sheet.set_column_width(0, 20)?;
+    let mut row = 1;
+    for c in &statement.categories {
+        let name = if c.category.is_empty() { "(uncategorized)" } else { c.category.as_str() };
+        sheet.write_string(row, 0, name)?;
+        sheet.write_number_with_format(row, 1, number(c.income), &money)?;
+        sheet.write_number_with_format(row, 2, number(c.expense), &money)?;
+        sheet.write_number_with_format(row, 3, number(c.income - c.expense), &money)?;
+        row += 1;
+    }
+    sheet.write_string_with_format(row, 0, "Total", &bold)?;
+    sheet.write_number_with_format(row, 1, number(statement.total_income), &money_bold)?;
+    sheet.write_number_with_format(row, 2, number(statement.total_expense), &money_bold)?;
+    sheet.write_number_with_format(row, 3, number(statement.total_income - statement.total_expense), &money_bold)?;
+
+    let sheet = workbook.add_worksheet().set_name("Accounts")?;
+    header(sheet, &["Account", "Type", "Currency", "Opening", "Income", "Expense", "Closing"], &bold)?;
+    sheet.set_column_width(0, 24)?;
+    for (i, s) in all.iter().enumerate() {
+        let row = i as u32 + 1;
+        sheet.write_string(row, 0, s.account.name.as_str())?;
+        sheet.write_string(row, 1, s.account.account_type.as_str())?;
+        sheet.write_string(row, 2, s.account.currency.as_str())?;
+        sheet.write_number_with_format(row, 3, number(s.opening_balance), &money)?;
+        sheet.write_number_with_format(row, 4, number(s.total_income), &money)?;
+        sheet.write_number_with_format(row, 5, number(s.total_expense), &money)?;
+        sheet.write_number_with_format(row, 6, number(s.closing_balance), &money)?;
+    }
+    sheet.write_string(all.len() as u32 + 2, 0, format!("Balances for {}", period.label()))?;
+
+    workbook.save_to_buffer()
+}
@@ -0,0 +1,326 @@
+use regex::Regex;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set, TransactionTrait};
+use serde::{Deserialize, Serialize};
+use crate::models::{rule, transaction};
+use crate::services::{audit_update, find_accounts_by_user, AuditContext, ImportRow, ServiceError, STATUS_RECONCILED};
+use sea_orm::prelude::Decimal;
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+/// Definition of a rule as sent by clients; amounts are compared without sign.
+#[derive(Deserialize, Debug, Clone)]
+pub struct RuleSpec {
+    pub name: String,
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+    pub description_contains: Option<String>,
+    pub description_regex: Option<String>,
+    pub counterparty_contains: Option<String>,
+    pub account_id: Option<i32>,
+    pub min_amount: Option<Decimal>,
+    pub max_amount: Option<Decimal>,
+    pub set_category: Option<String>,
+    #[serde(default)]
+    pub add_tags: Vec<String>,
+    pub rename_description: Option<String>,
+    #[serde(default)]
+    pub mark_transfer: bool,
+}
+
+impl RuleSpec {
+    fn validate(&self) -> Result<(), ServiceError> {
+        if self.name.trim().is_empty() {
+            return Err(ServiceError::Invalid("rule name is required".into()));
+        }
+        let has_condition = self.description_contains.is_some()
+            || self.description_regex.is_some()
+            || self.counterparty_contains.is_some()
+            || self.account_id.is_some()
+            || self.min_amount.is_some()
+            || self.max_amount.is_some();
+        if !has_condition {
+            return Err(ServiceError::Invalid("a rule needs at least one condition".into()));
+        }
+        let has_action = self.set_category.is_some() || !self.add_tags.is_empty() || self.rename_description.is_some() || self.mark_transfer;
+        if !has_action {
+            return Err(ServiceError::Invalid("a rule needs at least one action".into()));
+        }
+        if let (Some(min), Some(max)) = (self.min_amount, self.max_amount) {
+            if min > max {
+                return Err(ServiceError::Invalid("min_amount is greater than max_amount".into()));
+            }
+        }
+        if let Some(re) = &self.description_regex {
+            Regex::new(re).map_err(|e| ServiceError::Invalid(format!("invalid description_regex: {}", e)))?;
+        }
+        Ok(())
+    }
+}
+
+/// An enabled rule with its regex compiled once.
+pub struct CompiledRule {
+    pub rule: rule::Model,
+    regex: Option<Regex>,
+    tags: Vec<String>,
+}
+
+/// The fields rules read and write; built from a new transaction, an import row or a stored one.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RuleSubject {
+    #[serde(skip)]
+    pub account_id: i32,
+    pub transaction_type: String,
+    pub amount: Decimal,
+    pub description: String,
+    #[serde(skip)]
+    pub counterparty: Option<String>,
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+}
+
+fn contains(haystack: &str, needle: &str) -> bool {
+    haystack.to_lowercase().contains(&needle.to_lowercase())
+}
+
+impl CompiledRule {
+    fn matches(&self, s: &RuleSubject) -> bool {
+        let r = &self.rule;
+        let amount = s.amount.abs();
+        r.description_contains.as_deref().is_none_or(|n| contains(&s.description, n))
+            && self.regex.as_ref().is_none_or(|re| re.is_match(&s.description))
+            && r.counterparty_contains.as_deref().is_none_or(|n| s.counterparty.as_deref().is_some_and(|c| contains(c, n)))
+            && r.account_id.is_none_or(|id| id == s.account_id)
+            && r.min_amount.is_none_or(|min| amount >= min)
+            && r.max_amount.is_none_or(|max| amount <= max)
+    }
+}
+
+pub fn tags_from_json(value: Option<&sea_orm::prelude::Json>) -> Vec<String> {
+    value
+        .and_then(|v| v.as_array())
+        .map(|a| a.iter().filter_map(|t| t.as_str().map(str::to_string)).collect())
+        .unwrap_or_default()
+}
+
+pub fn tags_to_json(tags: &[String]) -> Option<sea_orm::prelude::Json> {
+    (!tags.is_empty()).then(|| serde_json::json!(tags))
+}
+
+/// Runs `rules` (already in priority order) against `subject` and returns the ids that matched.
+/// Conditions see the subject as it came in. For each field the first matching rule that sets it
+/// wins; tags accumulate. A category already present is kept unless `overwrite_category`.
+pub fn apply_rules(rules: &[CompiledRule], subject: &mut RuleSubject, overwrite_category: bool) -> Vec<i32> {
+    let original = subject.clone();
+    let mut matched = Vec::new();
+    let (mut category_set, mut renamed) = (!overwrite_category && subject.category.is_some(), false);
+    for c in rules.iter().filter(|c| c.matches(&original)) {
+        matched.push(c.rule.id);
+        if let Some(category) = &c.rule.set_category {
+            if !category_set {
+                subject.category = Some(category.clone());
+                category_set = true;
+            }
+        }
+        if let Some(description) = &c.rule.rename_description {
+            if !renamed {
+                subject.description = description.clone();
+                renamed = true;
+            }
+        }
+        for tag in &c.tags {
+            if !subject.tags.contains(tag) {
+                subject.tags.push(tag.clone());
+            }
+        }
+        // Keep the balance effect: an expense of 100 becomes a transfer of -100.
+        if c.rule.mark_transfer && subject.transaction_type != "transfer" {
+            if subject.transaction_type == "expense" {
+                subject.amount = -subject.amount;
+            }
+            subject.transaction_type = "transfer".to_string();
+        }
+    }
+    matched
+}
+
+pub async fn find_rules_by_user(db: &DatabaseConnection, user_id: i32) -> Result<Vec<rule::Model>, sea_orm::DbErr> {
+    rule::Entity::find()
+        .filter(rule::Column::UserId.eq(user_id))
+        .order_by_asc(rule::Column::Priority)
+        .order_by_asc(rule::Column::Id)
+        .all(db)
+        .await
+}
+
+/// The user's enabled rules, in the order they run.
+pub async fn load_rules<C: sea_orm::ConnectionTrait>(db: &C, user_id: i32) -> Result<Vec<CompiledRule>, sea_orm::DbErr> {
+    let rules = rule::Entity::find()
+        .filter(rule::Column::UserId.eq(user_id))
+        .filter(rule::Column::Enabled.eq(true))
+        .order_by_asc(rule::Column::Priority)
+        .order_by_asc(rule::Column::Id)
+        .all(db)
+        .await?;
+    // Patterns are validated on save; a rule whose pattern no longer compiles is skipped.
+    Ok(rules
+        .into_iter()
+        .filter_map(|rule| {
+            let regex = match rule.description_regex.as_deref() {
+                Some(re) => Some(Regex::new(re).ok()?),
+                None => None,
+            };
+            Some(CompiledRule { regex, tags: tags_from_json(rule.add_tags.as_ref()), rule })
+        })
+        .collect())
+}
+
+fn fill(active: &mut rule::ActiveModel, spec: RuleSpec) {
+    active.name = Set(spec.name.trim().to_string());
+    active.priority = Set(spec.priority);
+    active.enabled = Set(spec.enabled);
+    active.description_contains = Set(spec.description_contains);
+    active.description_regex = Set(spec.description_regex);
+    active.counterparty_contains = Set(spec.counterparty_contains);
+    active.account_id = Set(spec.account_id);
+    active.min_amount = Set(spec.min_amount);
+    active.max_amount = Set(spec.max_amount);
+    active.set_category = Set(spec.set_category);
+    active.add_tags = Set(tags_to_json(&spec.add_tags));
+    active.rename_description = Set(spec.rename_description);
+    active.mark_transfer = Set(spec.mark_transfer);
+}
+
+pub async fn create_rule(db: &DatabaseConnection, user_id: i32, spec: RuleSpec) -> Result<rule::Model, ServiceError> {
+    spec.validate()?;
+    let mut active = rule::ActiveModel {
+        user_id: Set(user_id),
+        ..Default::default()
+    };
+    fill(&mut active, spec);
+    Ok(active.insert(db).await?)
+}
+
+/// Replaces a rule's definition.
+pub async fn update_rule(db: &DatabaseConnection, id: i32, spec: RuleSpec) -> Result<Option<rule::Model>, ServiceError> {
+    spec.validate()?;
+    let Some(model) = rule::Entity::find_by_id(id).one(db).await? else {
+        return Ok(None);
+    };
+    let mut active: rule::ActiveModel = model.into();
+    fill(&mut active, spec);
+    Ok(Some(active.update(db).await?))
+}
+
+pub async fn delete_rule(db: &DatabaseConnection, id: i32) -> Result<u64, sea_orm::DbErr> {
+    let res = rule::Entity::delete_by_id(id).exec(db).await?;
+    Ok(res.rows_affected)
+}
+
+/// Applies the user's rules to parsed import rows. The user's rules take precedence over
+/// categories supplied by the statement.
+pub fn apply_rules_to_rows(rules: &[CompiledRule], account_id: i32, rows: &mut [ImportRow]) {
+    for row in rows {
+        let mut subject = RuleSubject {
+            account_id,
+            transaction_type: row.transaction_type.clone(),
+            amount: row.amount,
+            description: row.description.clone(),
+            counterparty: row.counterparty.clone(),
+            category: row.category.clone(),
+            tags: std::mem::take(&mut row.tags),
+        };
+        apply_rules(rules, &mut subject, true);
+        row.transaction_type = subject.transaction_type;
+        row.amount = subject.amount;
+        row.description = subject.description;
+        row.category = subject.category;
+        row.tags = subject.tags;
+    }
+}
+
+fn subject_of(t: &transaction::Model) -> RuleSubject {
+    RuleSubject {
+        account_id: t.account_id,
+        transaction_type: t.transaction_type.clone(),
+        amount: t.amount,
+        description: t.description.to_string(),
+        counterparty: t.counterparty.clone().map(Into::into),
+        category: t.category.clone(),
+        tags: tags_from_json(t.tags.as_ref()),
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct RuleChange {
+    pub transaction_id: i32,
+    pub rule_ids: Vec<i32>,
+    pub before: RuleSubject,
+    pub after: RuleSubject,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RuleApplication {
+    pub dry_run: bool,
+    /// Transactions that would change (or changed).
+    pub changes: Vec<RuleChange>,
+    /// Reconciled transactions that would change but are locked.
+    pub locked: Vec<i32>,
+}
+
+/// Runs rules over the user's existing transactions. `rule_ids` limits which rules run; with
+/// `dry_run` nothing is written and the diff is only reported.
+pub async fn apply_rules_retroactively(
+    db: &DatabaseConnection,
+    audit: &AuditContext,
+    user_id: i32,
+    rule_ids: Option<Vec<i32>>,
+    overwrite_category: bool,
+    dry_run: bool,
+) -> Result<RuleApplication, ServiceError> {
+    let mut rules = load_rules(db, user_id).await?;
+    if let Some(ids) = &rule_ids {
+        rules.retain(|c| ids.contains(&c.rule.id));
+    }
+    let account_ids: Vec<i32> = find_accounts_by_user(db, user_id).await?.into_iter().map(|a| a.id).collect();
+    let transactions = transaction::Entity::find()
+        .filter(transaction::Column::AccountId.is_in(account_ids))
+        .order_by_asc(transaction::Column::Id)
+        .all(db)
+        .await?;
+
+    let mut out = RuleApplication { dry_run, changes: Vec::new(), locked: Vec::new() };
+    let mut updates = Vec::new();
+    for t in transactions {
+        let before = subject_of(&t);
+        let mut after = before.clone();
+        let rule_ids = apply_rules(&rules, &mut after, overwrite_category);
+        if after == before {
+            continue;
+        }
+        if t.status == STATUS_RECONCILED {
+            out.locked.push(t.id);
+            continue;
+        }
+        out.changes.push(RuleChange { transaction_id: t.id, rule_ids, before, after: after.clone() });
+        updates.push((t, after));
+    }
+    if !dry_run {
+        let txn = db.begin().await?;
+        for (t, after) in updates {
+            let mut active: transaction::ActiveModel = t.clone().into();
+            active.transaction_type = Set(after.transaction_type);
+            active.amount = Set(after.amount);
+            active.description = Set(after.description.into());
+            active.category = Set(after.category);
+            active.tags = Set(tags_to_json(&after.tags));
+            let updated = active.update(&txn).await?;
+            audit_update(&txn, audit, user_id, "transaction", t.id, &t, &updated).await?;
+        }
+        txn.commit().await?;
+    }
+    Ok(out)
+}
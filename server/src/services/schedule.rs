@@ -0,0 +1,248 @@
+use chrono::{Datelike, Days, Months, NaiveDate};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set};
+use crate::models::{account, investment_plan, recurring_transaction};
+use crate::services::ServiceError;
+use sea_orm::prelude::Decimal;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+impl Frequency {
+    pub fn parse(raw: &str) -> Result<Self, ServiceError> {
+        match raw.trim().to_lowercase().as_str() {
+            "daily" => Ok(Frequency::Daily),
+            "weekly" => Ok(Frequency::Weekly),
+            "monthly" => Ok(Frequency::Monthly),
+            "yearly" => Ok(Frequency::Yearly),
+            other => Err(ServiceError::Invalid(format!("invalid frequency {:?}, expected daily, weekly, monthly or yearly", other))),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Frequency::Daily => "daily",
+            Frequency::Weekly => "weekly",
+            Frequency::Monthly => "monthly",
+            Frequency::Yearly => "yearly",
+        }
+    }
+
+    /// The `n`-th occurrence after `start`. Months are added to the anchor rather than chained,
+    /// so a schedule starting on the 31st lands on the last day of short months and comes back.
+    fn nth(&self, start: NaiveDate, every: u32, n: u32) -> Option<NaiveDate> {
+        let steps = every.checked_mul(n)?;
+        match self {
+            Frequency::Daily => start.checked_add_days(Days::new(steps as u64)),
+            Frequency::Weekly => start.checked_add_days(Days::new(steps as u64 * 7)),
+            Frequency::Monthly => start.checked_add_months(Months::new(steps)),
+            Frequency::Yearly => start.checked_add_months(Months::new(steps.checked_mul(12)?)),
+        }
+    }
+}
+
+/// Dates in `from..=to` on which a schedule fires; `end` is inclusive.
+pub fn occurrences(start: NaiveDate, frequency: Frequency, every: i32, end: Option<NaiveDate>, from: NaiveDate, to: NaiveDate) -> Vec<NaiveDate> {
+    let every = every.max(1) as u32;
+    let last = end.map_or(to, |e| e.min(to));
+    // Skip whole periods before `from` for the day-based frequencies.
+    let mut n = match frequency {
+        Frequency::Daily if from > start => ((from - start).num_days() as u32) / every,
+        Frequency::Weekly if from > start => ((from - start).num_days() as u32) / (every * 7),
+        _ => 0,
+    };
+    let mut out = Vec::new();
+    while let Some(date) = frequency.nth(start, every, n) {
+        if date > last {
+            break;
+        }
+        if date >= from {
+            out.push(date);
+        }
+        n += 1;
+    }
+    out
+}
+
+/// `day` of the given month, clamped to its last day (a statement day of 31 closes on Feb 28).
+pub fn day_of_month(year: i32, month: u32, day: u32) -> Option<NaiveDate> {
+    let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+    let last = first.checked_add_months(Months::new(1))?.pred_opt()?.day();
+    first.with_day(day.clamp(1, last))
+}
+
+async fn owned_account(db: &DatabaseConnection, user_id: i32, account_id: i32) -> Result<account::Model, ServiceError> {
+    match account::Entity::find_by_id(account_id).one(db).await? {
+        Some(a) if a.user_id == user_id => Ok(a),
+        _ => Err(ServiceError::Invalid(format!("account {} not found", account_id))),
+    }
+}
+
+fn check_schedule(amount: Decimal, interval: i32, start_date: NaiveDate, end_date: Option<NaiveDate>) -> Result<(), ServiceError> {
+    if amount <= Decimal::ZERO {
+        return Err(ServiceError::Invalid("amount must be positive".into()));
+    }
+    if interval < 1 {
+        return Err(ServiceError::Invalid("interval must be at least 1".into()));
+    }
+    if end_date.is_some_and(|e| e < start_date) {
+        return Err(ServiceError::Invalid("end_date is before start_date".into()));
+    }
+    Ok(())
+}
+
+pub struct NewRecurringTransaction {
+    pub account_id: i32,
+    pub transaction_type: String,
+    pub amount: Decimal,
+    pub description: String,
+    pub category: Option<String>,
+    pub frequency: Frequency,
+    pub interval: i32,
+    pub start_date: NaiveDate,
+    pub end_date: Option<NaiveDate>,
+}
+
+#[derive(Default)]
+pub struct RecurringTransactionChanges {
+    pub amount: Option<Decimal>,
+    pub description: Option<String>,
+    pub category: Option<String>,
+    pub frequency: Option<Frequency>,
+    pub interval: Option<i32>,
+    pub end_date: Option<NaiveDate>,
+    pub active: Option<bool>,
+}
+
+pub async fn create_recurring_transaction(db: &DatabaseConnection, user_id: i32, new: NewRecurringTransaction) -> Result<recurring_transaction::Model, ServiceError> {
+    if !matches!(new.transaction_type.as_str(), "income" | "expense" | "transfer") {
+        return Err(ServiceError::Invalid(format!("invalid transaction_type: {}", new.transaction_type)));
+    }
+    // Transfers carry their direction in the sign, like transactions do.
+    let magnitude = if new.transaction_type == "transfer" { new.amount.abs() } else { new.amount };
+    check_schedule(magnitude, new.interval, new.start_date, new.end_date)?;
+    owned_account(db, user_id, new.account_id).await?;
+    let active = recurring_transaction::ActiveModel {
+        user_id: Set(user_id),
+        account_id: Set(new.account_id),
+        transaction_type: Set(new.transaction_type),
+        amount: Set(new.amount),
+        description: Set(new.description.into()),
+        category: Set(new.category),
+        frequency: Set(new.frequency.as_str().to_string()),
+        interval: Set(new.interval),
+        start_date: Set(new.start_date),
+        end_date: Set(new.end_date),
+        active: Set(true),
+        ..Default::default()
+    };
+    Ok(active.insert(db).await?)
+}
+
+pub async fn find_recurring_transactions_by_user(db: &DatabaseConnection, user_id: i32) -> Result<Vec<recurring_transaction::Model>, sea_orm::DbErr> {
+    recurring_transaction::Entity::find()
+        .filter(recurring_transaction::Column::UserId.eq(user_id))
+        .order_by_asc(recurring_transaction::Column::Id)
+        .all(db)
+        .await
+}
+
+pub async fn update_recurring_transaction(db: &DatabaseConnection, id: i32, changes: RecurringTransactionChanges) -> Result<Option<recurring_transaction::Model>, ServiceError> {
+    let Some(model) = recurring_transaction::Entity::find_by_id(id).one(db).await? else {
+        return Ok(None);
+    };
+    let amount = changes.amount.unwrap_or(model.amount);
+    let interval = changes.interval.unwrap_or(model.interval);
+    let end_date = changes.end_date.or(model.end_date);
+    let magnitude = if model.transaction_type == "transfer" { amount.abs() } else { amount };
+    check_schedule(magnitude, interval, model.start_date, end_date)?;
+    let mut active: recurring_transaction::ActiveModel = model.into();
+    active.amount = Set(amount);
+    active.interval = Set(interval);
+    active.end_date = Set(end_date);
+    if let Some(v) = changes.description { active.description = Set(v.into()); }
+    if let Some(v) = changes.category { active.category = Set(Some(v)); }
+    if let Some(v) = changes.frequency { active.frequency = Set(v.as_str().to_string()); }
+    if let Some(v) = changes.active { active.active = Set(v); }
+    Ok(Some(active.update(db).await?))
+}
+
+pub async fn delete_recurring_transaction(db: &DatabaseConnection, id: i32) -> Result<u64, sea_orm::DbErr> {
+    let res = recurring_transaction::Entity::delete_by_id(id).exec(db).await?;
+    Ok(res.rows_affected)
+}
+
+pub struct NewInvestmentPlan {
+    pub account_id: i32,
+    pub name: String,
+    pub symbol: String,
+    pub amount: Decimal,
+    pub frequency: Frequency,
+    pub interval: i32,
+    pub start_date: NaiveDate,
+    pub end_date: Option<NaiveDate>,
+}
+
+#[derive(Default)]
+pub struct InvestmentPlanChanges {
+    pub name: Option<String>,
+    pub amount: Option<Decimal>,
+    pub frequency: Option<Frequency>,
+    pub interval: Option<i32>,
+    pub end_date: Option<NaiveDate>,
+    pub active: Option<bool>,
+}
+
+pub async fn create_investment_plan(db: &DatabaseConnection, user_id: i32, new: NewInvestmentPlan) -> Result<investment_plan::Model, ServiceError> {
+    check_schedule(new.amount, new.interval, new.start_date, new.end_date)?;
+    owned_account(db, user_id, new.account_id).await?;
+    let active = investment_plan::ActiveModel {
+        user_id: Set(user_id),
+        account_id: Set(new.account_id),
+        name: Set(new.name),
+        symbol: Set(new.symbol),
+        amount: Set(new.amount),
+        frequency: Set(new.frequency.as_str().to_string()),
+        interval: Set(new.interval),
+        start_date: Set(new.start_date),
+        end_date: Set(new.end_date),
+        active: Set(true),
+        ..Default::default()
+    };
+    Ok(active.insert(db).await?)
+}
+
+pub async fn find_investment_plans_by_user(db: &DatabaseConnection, user_id: i32) -> Result<Vec<investment_plan::Model>, sea_orm::DbErr> {
+    investment_plan::Entity::find()
+        .filter(investment_plan::Column::UserId.eq(user_id))
+        .order_by_asc(investment_plan::Column::Id)
+        .all(db)
+        .await
+}
+
+pub async fn update_investment_plan(db: &DatabaseConnection, id: i32, changes: InvestmentPlanChanges) -> Result<Option<investment_plan::Model>, ServiceError> {
+    let Some(model) = investment_plan::Entity::find_by_id(id).one(db).await? else {
+        return Ok(None);
+    };
+    let amount = changes.amount.unwrap_or(model.amount);
+    let interval = changes.interval.unwrap_or(model.interval);
+    let end_date = changes.end_date.or(model.end_date);
+    check_schedule(amount, interval, model.start_date, end_date)?;
+    let mut active: investment_plan::ActiveModel = model.into();
+    active.amount = Set(amount);
+    active.interval = Set(interval);
+    active.end_date = Set(end_date);
+    if let Some(v) = changes.name { active.name = Set(v); }
+    if let Some(v) = changes.frequency { active.frequency = Set(v.as_str().to_string()); }
+    if let Some(v) = changes.active { active.active = Set(v); }
+    Ok(Some(active.update(db).await?))
+}
+
+pub async fn delete_investment_plan(db: &DatabaseConnection, id: i32) -> Result<u64, sea_orm::DbErr> {
+    let res = investment_plan::Entity::delete_by_id(id).exec(db).await?;
+    Ok(res.rows_affected)
+}
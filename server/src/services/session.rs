@@ -0,0 +1,211 @@
+use chrono::{DateTime, Duration, Utc};
+use sea_orm::{ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set, TransactionTrait};
+use sea_orm::sea_query::Expr;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use crate::models::session;
+use std::collections::BTreeMap;
+
+/// A refresh token is good for this long after it was issued; using it issues a fresh one.
+pub const REFRESH_TOKEN_DAYS: i64 = 30;
+
+#[derive(Debug)]
+pub enum SessionError {
+    Db(sea_orm::DbErr),
+    /// Unknown, expired or revoked.
+    InvalidToken,
+    /// The token was already rotated; the whole family has been revoked.
+    Reused,
+}
+
+impl std::fmt::Display for SessionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionError::Db(e) => write!(f, "{}", e),
+            SessionError::InvalidToken => write!(f, "invalid or expired refresh token"),
+            SessionError::Reused => write!(f, "refresh token was already used; the session has been revoked"),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+impl From<sea_orm::DbErr> for SessionError {
+    fn from(e: sea_orm::DbErr) -> Self {
+        SessionError::Db(e)
+    }
+}
+
+/// A refresh token as handed to the client; only its hash is kept.
+pub struct IssuedToken {
+    pub user_id: i32,
+    pub family_id: String,
+    pub refresh_token: String,
+}
+
+/// An opaque random token (two v4 UUIDs, 244 random bits) for handing to clients.
+pub fn new_secret_token() -> String {
+    format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple())
+}
+
+/// What gets stored instead of a secret token.
+pub fn secret_token_hash(token: &str) -> String {
+    Sha256::digest(token.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+async fn issue<C: ConnectionTrait>(db: &C, user_id: i32, family_id: String, user_agent: Option<String>) -> Result<IssuedToken, sea_orm::DbErr> {
+    let refresh_token = new_secret_token();
+    let now = Utc::now();
+    let active = session::ActiveModel {
+        user_id: Set(user_id),
+        family_id: Set(family_id.clone()),
+        token_hash: Set(secret_token_hash(&refresh_token)),
+        user_agent: Set(user_agent),
+        expires_at: Set(now + Duration::days(REFRESH_TOKEN_DAYS)),
+        created_at: Set(now),
+        ..Default::default()
+    };
+    active.insert(db).await?;
+    Ok(IssuedToken { user_id, family_id, refresh_token })
+}
+
+async fn find_by_token<C: ConnectionTrait>(db: &C, token: &str) -> Result<Option<session::Model>, sea_orm::DbErr> {
+    session::Entity::find().filter(session::Column::TokenHash.eq(secret_token_hash(token))).one(db).await
+}
+
+/// Starts a new session family at login. Rows of the user's expired tokens are pruned.
+pub async fn start_session(db: &DatabaseConnection, user_id: i32, user_agent: Option<String>) -> Result<IssuedToken, sea_orm::DbErr> {
+    session::Entity::delete_many()
+        .filter(session::Column::UserId.eq(user_id))
+        .filter(session::Column::ExpiresAt.lt(Utc::now()))
+        .exec(db)
+        .await?;
+    issue(db, user_id, uuid::Uuid::new_v4().to_string(), user_agent).await
+}
+
+/// Exchanges a refresh token for a new one in the same family. Each token works once: a token
+/// that was already rotated means it leaked (or the client misbehaved), so the family is revoked.
+pub async fn rotate_refresh_token(db: &DatabaseConnection, token: &str) -> Result<IssuedToken, SessionError> {
+    let txn = db.begin().await?;
+    let Some(row) = find_by_token(&txn, token).await? else {
+        return Err(SessionError::InvalidToken);
+    };
+    if row.revoked_at.is_some() || row.expires_at <= Utc::now() {
+        return Err(SessionError::InvalidToken);
+    }
+    if row.used_at.is_some() {
+        revoke_family(&txn, row.user_id, &row.family_id).await?;
+        txn.commit().await?;
+        return Err(SessionError::Reused);
+    }
+    // Claiming with a conditional update, so two concurrent refreshes cannot both succeed.
+    let claimed = session::Entity::update_many()
+        .col_expr(session::Column::UsedAt, Expr::value(Utc::now()))
+        .filter(session::Column::Id.eq(row.id))
+        .filter(session::Column::UsedAt.is_null())
+        .exec(&txn)
+        .await?
+        .rows_affected
+        == 1;
+    if !claimed {
+        revoke_family(&txn, row.user_id, &row.family_id).await?;
+        txn.commit().await?;
+        return Err(SessionError::Reused);
+    }
+    let issued = issue(&txn, row.user_id, row.family_id, row.user_agent).await?;
+    txn.commit().await?;
+    Ok(issued)
+}
+
+async fn revoke_family<C: ConnectionTrait>(db: &C, user_id: i32, family_id: &str) -> Result<u64, sea_orm::DbErr> {
+    let res = session::Entity::update_many()
+        .col_expr(session::Column::RevokedAt, Expr::value(Utc::now()))
+        .filter(session::Column::UserId.eq(user_id))
+        .filter(session::Column::FamilyId.eq(family_id))
+        .filter(session::Column::RevokedAt.is_null())
+        .exec(db)
+        .await?;
+    Ok(res.rows_affected)
+}
+
+/// Logout: revokes the family the token belongs to. Returns false for unknown tokens.
+pub async fn end_session(db: &DatabaseConnection, token: &str) -> Result<bool, sea_orm::DbErr> {
+    let Some(row) = find_by_token(db, token).await? else { return Ok(false) };
+    revoke_family(db, row.user_id, &row.family_id).await?;
+    Ok(true)
+}
+
+/// Whether access tokens issued for the family may still be used: some token in it is neither
+/// revoked nor expired. Logout, signing a device out, refresh token reuse and password resets
+/// all revoke the family.
+pub async fn session_is_active(db: &DatabaseConnection, user_id: i32, family_id: &str) -> Result<bool, sea_orm::DbErr> {
+    let live = session::Entity::find()
+        .filter(session::Column::UserId.eq(user_id))
+        .filter(session::Column::FamilyId.eq(family_id))
+        .filter(session::Column::RevokedAt.is_null())
+        .filter(session::Column::ExpiresAt.gt(Utc::now()))
+        .one(db)
+        .await?;
+    Ok(live.is_some())
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SessionInfo {
+    /// The family id; pass it to `DELETE /api/auth/sessions/{id}`.
+    pub id: String,
+    pub user_agent: Option<String>,
+    /// When the user logged in.
+    pub created_at: DateTime<Utc>,
+    /// When the refresh token was last rotated (or the login, if never).
+    pub last_used_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// Whether the access token making the request belongs to this session.
+    pub current: bool,
+}
+
+/// The user's live sessions, most recently used first.
+pub async fn list_sessions(db: &DatabaseConnection, user_id: i32, current_family: Option<&str>) -> Result<Vec<SessionInfo>, sea_orm::DbErr> {
+    let rows = session::Entity::find()
+        .filter(session::Column::UserId.eq(user_id))
+        .filter(session::Column::RevokedAt.is_null())
+        .order_by_asc(session::Column::Id)
+        .all(db)
+        .await?;
+    let now = Utc::now();
+    let mut families: BTreeMap<&str, Vec<&session::Model>> = BTreeMap::new();
+    for row in &rows {
+        families.entry(row.family_id.as_str()).or_default().push(row);
+    }
+    let mut out: Vec<SessionInfo> = families
+        .into_iter()
+        .filter_map(|(family, rows)| {
+            let live = rows.iter().find(|r| r.used_at.is_none() && r.expires_at > now)?;
+            Some(SessionInfo {
+                id: family.to_string(),
+                user_agent: live.user_agent.clone(),
+                created_at: rows.iter().map(|r| r.created_at).min().unwrap_or(live.created_at),
+                last_used_at: live.created_at,
+                expires_at: live.expires_at,
+                current: current_family == Some(family),
+            })
+        })
+        .collect();
+    out.sort_by(|a, b| b.last_used_at.cmp(&a.last_used_at).then_with(|| a.id.cmp(&b.id)));
+    Ok(out)
+}
+
+/// Signs one device out. Returns how many tokens were revoked (0 when the session is unknown).
+pub async fn revoke_session(db: &DatabaseConnection, user_id: i32, family_id: &str) -> Result<u64, sea_orm::DbErr> {
+    revoke_family(db, user_id, family_id).await
+}
+
+/// Signs the user out everywhere, e.g. after a password change.
+pub async fn revoke_all_sessions(db: &DatabaseConnection, user_id: i32) -> Result<u64, sea_orm::DbErr> {
+    let res = session::Entity::update_many()
+        .col_expr(session::Column::RevokedAt, Expr::value(Utc::now()))
+        .filter(session::Column::UserId.eq(user_id))
+        .filter(session::Column::RevokedAt.is_null())
+        .exec(db)
+        .await?;
+    Ok(res.rows_affected)
+}
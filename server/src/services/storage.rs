@@ -0,0 +1,69 @@
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Where attachment bytes live. Keys are produced by the attachment service (lowercase hex
+/// digests with an optional `.suffix`), so implementations may map them onto paths directly.
+pub trait AttachmentStorage: Send + Sync {
+    fn put(&self, key: &str, bytes: &[u8]) -> io::Result<()>;
+    /// `ErrorKind::NotFound` when the key was never stored or has been deleted.
+    fn get(&self, key: &str) -> io::Result<Vec<u8>>;
+    fn exists(&self, key: &str) -> io::Result<bool>;
+    /// Deleting a missing key is not an error.
+    fn delete(&self, key: &str) -> io::Result<()>;
+}
+
+pub type SharedStorage = Arc<dyn AttachmentStorage>;
+
+/// Used when `ATTACHMENT_DIR` is not set.
+pub const DEFAULT_ATTACHMENT_DIR: &str = "./attachments";
+
+/// Files under a root directory, fanned out by the first two characters of the key
+/// (`<root>/ab/abcdef…`) so no single directory grows too large.
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LocalStorage { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> io::Result<PathBuf> {
+        let valid = key.len() > 2 && key.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '.') && !key.starts_with('.');
+        if !valid {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("invalid storage key: {:?}", key)));
+        }
+        Ok(self.root.join(&key[..2]).join(key))
+    }
+}
+
+impl AttachmentStorage for LocalStorage {
+    fn put(&self, key: &str, bytes: &[u8]) -> io::Result<()> {
+        let path = self.path(key)?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // Write then rename, so readers never see a half-written file.
+        let partial = path.with_extension(format!("{}.partial", uuid::Uuid::new_v4().simple()));
+        std::fs::write(&partial, bytes)?;
+        std::fs::rename(&partial, &path).inspect_err(|_| {
+            let _ = std::fs::remove_file(&partial);
+        })
+    }
+
+    fn get(&self, key: &str) -> io::Result<Vec<u8>> {
+        std::fs::read(self.path(key)?)
+    }
+
+    fn exists(&self, key: &str) -> io::Result<bool> {
+        self.path(key)?.try_exists()
+    }
+
+    fn delete(&self, key: &str) -> io::Result<()> {
+        match std::fs::remove_file(self.path(key)?) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            other => other,
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, FromQueryResult, QueryFilter, QuerySelect};
+use serde::Serialize;
+use crate::models::{sealed::SealedText, transaction};
+use crate::services::find_accounts_by_user;
+use sea_orm::prelude::Decimal;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+pub const DEFAULT_SUGGESTIONS: usize = 3;
+
+/// Words and, for Chinese and other scripts without spaces, single characters and bigrams.
+/// Pure numbers (order ids, dates) are dropped; the amount and type become tokens of their own.
+pub fn suggestion_tokens(description: &str, counterparty: Option<&str>, amount: Decimal, transaction_type: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    for text in [Some(description), counterparty].into_iter().flatten() {
+        let mut word = String::new();
+        let mut run: Vec<char> = Vec::new();
+        let flush_word = |word: &mut String, out: &mut Vec<String>| {
+            if word.chars().count() >= 2 && !word.chars().all(|c| c.is_ascii_digit()) {
+                out.push(std::mem::take(word));
+            }
+            word.clear();
+        };
+        let flush_run = |run: &mut Vec<char>, out: &mut Vec<String>| {
+            out.extend(run.iter().map(|c| c.to_string()));
+            out.extend(run.windows(2).map(|w| w.iter().collect::<String>()));
+            run.clear();
+        };
+        for c in text.to_lowercase().chars() {
+            if c.is_ascii_alphanumeric() {
+                flush_run(&mut run, &mut out);
+                word.push(c);
+            } else if c.is_alphabetic() {
+                flush_word(&mut word, &mut out);
+                run.push(c);
+            } else {
+                flush_word(&mut word, &mut out);
+                flush_run(&mut run, &mut out);
+            }
+        }
+        flush_word(&mut word, &mut out);
+        flush_run(&mut run, &mut out);
+    }
+    // Order of magnitude of the amount: coffee and rent rarely share a category.
+    let magnitude = f64::try_from(amount.abs()).unwrap_or_default().max(1.0).log10().floor() as i32;
+    out.push(format!("#amount:{}", magnitude));
+    out.push(format!("#type:{}", transaction_type));
+    out
+}
+
+#[derive(Default, Debug)]
+struct CategoryCounts {
+    documents: u32,
+    tokens: u32,
+    per_token: HashMap<String, u32>,
+}
+
+/// Multinomial naive Bayes over one user's categorized transactions. It remembers what each
+/// transaction contributed, so syncing with the database only touches rows that changed.
+#[derive(Default, Debug)]
+pub struct CategoryModel {
+    categories: HashMap<String, CategoryCounts>,
+    /// How often each token occurs across all categories; its size is the vocabulary.
+    vocabulary: HashMap<String, u32>,
+    trained: HashMap<i32, (String, Vec<String>)>,
+}
+
+impl CategoryModel {
+    fn add(&mut self, category: &str, tokens: &[String]) {
+        let counts = self.categories.entry(category.to_string()).or_default();
+        counts.documents += 1;
+        counts.tokens += tokens.len() as u32;
+        for t in tokens {
+            *counts.per_token.entry(t.clone()).or_default() += 1;
+            *self.vocabulary.entry(t.clone()).or_default() += 1;
+        }
+    }
+
+    fn remove(&mut self, category: &str, tokens: &[String]) {
+        let Some(counts) = self.categories.get_mut(category) else { return };
+        counts.documents -= 1;
+        counts.tokens -= tokens.len() as u32;
+        for t in tokens {
+            if let Some(n) = counts.per_token.get_mut(t) {
+                *n -= 1;
+                if *n == 0 {
+                    counts.per_token.remove(t);
+                }
+            }
+            if let Some(n) = self.vocabulary.get_mut(t) {
+                *n -= 1;
+                if *n == 0 {
+                    self.vocabulary.remove(t);
+                }
+            }
+        }
+        if counts.documents == 0 {
+            self.categories.remove(category);
+        }
+    }
+
+    /// Brings the model in line with `rows` (every categorized transaction the user has):
+    /// new and recategorized rows are learned, changed and deleted ones unlearned.
+    fn sync(&mut self, rows: Vec<TrainingRow>) {
+        let mut current: HashMap<i32, (String, Vec<String>)> = HashMap::with_capacity(rows.len());
+        for r in rows {
+            let tokens = suggestion_tokens(&r.description, r.counterparty.as_deref().map(String::as_str), r.amount, &r.transaction_type);
+            current.insert(r.id, (r.category, tokens));
+        }
+        let stale: Vec<i32> = self
+            .trained
+            .iter()
+            .filter(|(id, seen)| current.get(id) != Some(seen))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in stale {
+            if let Some((category, tokens)) = self.trained.remove(&id) {
+                self.remove(&category, &tokens);
+            }
+        }
+        for (id, (category, tokens)) in current {
+            if self.trained.contains_key(&id) {
+                continue;
+            }
+            self.add(&category, &tokens);
+            self.trained.insert(id, (category, tokens));
+        }
+    }
+
+    pub fn trained_on(&self) -> usize {
+        self.trained.len()
+    }
+
+    /// Categories ranked by posterior probability, with Laplace smoothing.
+    pub fn suggest(&self, tokens: &[String], limit: usize) -> Vec<CategorySuggestion> {
+        let total: u32 = self.categories.values().map(|c| c.documents).sum();
+        if total == 0 {
+            return Vec::new();
+        }
+        let vocabulary = self.vocabulary.len().max(1) as f64;
+        let mut scored: Vec<(&String, &CategoryCounts, f64)> = self
+            .categories
+            .iter()
+            .map(|(name, c)| {
+                let prior = (c.documents as f64 / total as f64).ln();
+                let denominator = c.tokens as f64 + vocabulary;
+                let likelihood: f64 = tokens
+                    .iter()
+                    .map(|t| ((c.per_token.get(t).copied().unwrap_or(0) as f64 + 1.0) / denominator).ln())
+                    .sum();
+                (name, c, prior + likelihood)
+            })
+            .collect();
+        // Normalise log scores into probabilities without underflow.
+        let best = scored.iter().map(|s| s.2).fold(f64::NEG_INFINITY, f64::max);
+        let sum: f64 = scored.iter().map(|s| (s.2 - best).exp()).sum();
+        for s in scored.iter_mut() {
+            s.2 = (s.2 - best).exp() / sum;
+        }
+        scored.sort_by(|a, b| b.2.total_cmp(&a.2).then_with(|| a.0.cmp(b.0)));
+        scored
+            .into_iter()
+            .take(limit)
+            .map(|(name, c, p)| CategorySuggestion { category: name.clone(), confidence: (p * 1000.0).round() / 1000.0, examples: c.documents })
+            .collect()
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CategorySuggestion {
+    pub category: String,
+    /// Posterior probability, 0 to 1.
+    pub confidence: f64,
+    /// Categorized transactions the model has seen in this category.
+    pub examples: u32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Suggestions {
+    pub suggestions: Vec<CategorySuggestion>,
+    pub trained_on: usize,
+}
+
+#[derive(FromQueryResult)]
+struct TrainingRow {
+    id: i32,
+    transaction_type: String,
+    amount: Decimal,
+    description: SealedText,
+    counterparty: Option<SealedText>,
+    category: String,
+}
+
+/// Per-user category models, kept in memory and shared by all requests.
+#[derive(Clone, Default)]
+pub struct SuggestionModels {
+    inner: Arc<Mutex<HashMap<i32, CategoryModel>>>,
+}
+
+pub struct SuggestionInput<'a> {
+    pub description: &'a str,
+    pub counterparty: Option<&'a str>,
+    pub amount: Decimal,
+    pub transaction_type: &'a str,
+}
+
+impl SuggestionModels {
+    /// Syncs the user's model with their categorized transactions, then ranks categories for `input`.
+    pub async fn suggest(&self, db: &DatabaseConnection, user_id: i32, input: SuggestionInput<'_>, limit: usize) -> Result<Suggestions, sea_orm::DbErr> {
+        let account_ids: Vec<i32> = find_accounts_by_user(db, user_id).await?.into_iter().map(|a| a.id).collect();
+        let rows = transaction::Entity::find()
+            .select_only()
+            .columns([
+                transaction::Column::Id,
+                transaction::Column::TransactionType,
+                transaction::Column::Amount,
+                transaction::Column::Description,
+                transaction::Column::Counterparty,
+                transaction::Column::Category,
+            ])
+            .filter(transaction::Column::AccountId.is_in(account_ids))
+            .filter(transaction::Column::Category.is_not_null())
+            .filter(transaction::Column::Category.ne(""))
+            .into_model::<TrainingRow>()
+            .all(db)
+            .await?;
+        let tokens = suggestion_tokens(input.description, input.counterparty, input.amount, input.transaction_type);
+        let mut models = self.inner.lock().expect("suggestion models poisoned");
+        let model = models.entry(user_id).or_default();
+        model.sync(rows);
+        Ok(Suggestions { suggestions: model.suggest(&tokens, limit), trained_on: model.trained_on() })
+    }
+}
@@ -0,0 +1,222 @@
+use chrono::Utc;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
+use sea_orm::sea_query::Expr;
+use crate::models::user;
+use crate::services::{send_unlock_email, SharedMailer};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Wrong passwords per email address before each further attempt has to wait.
+pub const LOGIN_FREE_ATTEMPTS_PER_EMAIL: u32 = 3;
+/// Per client address; higher, since many users can share one address behind NAT.
+pub const LOGIN_FREE_ATTEMPTS_PER_IP: u32 = 20;
+/// The first wait; it doubles with every further failure.
+const LOGIN_BACKOFF_BASE: Duration = Duration::from_secs(1);
+pub const LOGIN_BACKOFF_MAX: Duration = Duration::from_secs(15 * 60);
+/// Counters are forgotten after this long without a failure.
+const LOGIN_FAILURE_WINDOW: Duration = Duration::from_secs(60 * 60);
+/// Consecutive wrong passwords that lock the account.
+pub const LOCKOUT_THRESHOLD: i32 = 10;
+pub const LOCKOUT_MINUTES: i64 = 30;
+/// Counters and buckets are pruned once a map holds this many keys.
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+#[derive(Clone, Copy)]
+struct FailureCount {
+    failures: u32,
+    last: Instant,
+}
+
+/// In-memory failed-login counters per email address and per client address, with exponential
+/// backoff. They slow guessing down without touching the database; the persistent lockout
+/// below is what stops a sustained attack on one account.
+#[derive(Clone, Default)]
+pub struct LoginThrottle {
+    entries: Arc<Mutex<HashMap<String, FailureCount>>>,
+}
+
+fn email_key(email: &str) -> String {
+    format!("email:{}", email.trim().to_lowercase())
+}
+
+fn ip_key(ip: IpAddr) -> String {
+    format!("ip:{}", ip)
+}
+
+fn backoff(failures: u32, free: u32) -> Duration {
+    if failures < free {
+        return Duration::ZERO;
+    }
+    let doublings = (failures - free).min(20);
+    (LOGIN_BACKOFF_BASE * 2u32.pow(doublings)).min(LOGIN_BACKOFF_MAX)
+}
+
+impl LoginThrottle {
+    /// How long the caller has to wait before trying again, if at all.
+    pub fn retry_after(&self, email: &str, ip: Option<IpAddr>) -> Option<Duration> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let wait = |key: String, free: u32| {
+            entries.get(&key).map(|c| backoff(c.failures, free).saturating_sub(c.last.elapsed())).unwrap_or_default()
+        };
+        let mut longest = wait(email_key(email), LOGIN_FREE_ATTEMPTS_PER_EMAIL);
+        if let Some(ip) = ip {
+            longest = longest.max(wait(ip_key(ip), LOGIN_FREE_ATTEMPTS_PER_IP));
+        }
+        (!longest.is_zero()).then_some(longest)
+    }
+
+    pub fn record_failure(&self, email: &str, ip: Option<IpAddr>) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() >= MAX_TRACKED_KEYS {
+            entries.retain(|_, c| c.last.elapsed() < LOGIN_FAILURE_WINDOW);
+        }
+        let now = Instant::now();
+        for key in std::iter::once(email_key(email)).chain(ip.map(ip_key)) {
+            let count = entries.entry(key).or_insert(FailureCount { failures: 0, last: now });
+            if count.last.elapsed() >= LOGIN_FAILURE_WINDOW {
+                count.failures = 0;
+            }
+            count.failures += 1;
+            count.last = now;
+        }
+    }
+
+    /// A correct password clears the address's counter; the client's is left to expire.
+    pub fn record_success(&self, email: &str) {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).remove(&email_key(email));
+    }
+}
+
+/// Counts a wrong password against the account. At `LOCKOUT_THRESHOLD` the account is locked
+/// for `LOCKOUT_MINUTES` and the owner is mailed an unlock token. Returns the lock's end when
+/// this failure caused one.
+pub async fn record_failed_login(db: &DatabaseConnection, mailer: &SharedMailer, user: &user::Model) -> Result<Option<chrono::DateTime<Utc>>, sea_orm::DbErr> {
+    // Incremented in SQL, so parallel guesses are all counted.
+    user::Entity::update_many()
+        .col_expr(user::Column::FailedLogins, Expr::col(user::Column::FailedLogins).add(1))
+        .filter(user::Column::Id.eq(user.id))
+        .exec(db)
+        .await?;
+    let Some(current) = user::Entity::find_by_id(user.id).one(db).await? else { return Ok(None) };
+    if current.failed_logins < LOCKOUT_THRESHOLD {
+        return Ok(None);
+    }
+    let until = Utc::now() + chrono::Duration::minutes(LOCKOUT_MINUTES);
+    let mut active: user::ActiveModel = current.into();
+    active.failed_logins = Set(0);
+    active.locked_until = Set(Some(until));
+    let locked = active.update(db).await?;
+    send_unlock_email(db, mailer, &locked).await?;
+    Ok(Some(until))
+}
+
+/// After a successful login.
+pub async fn clear_failed_logins(db: &DatabaseConnection, user: &user::Model) -> Result<(), sea_orm::DbErr> {
+    if user.failed_logins == 0 && user.locked_until.is_none() {
+        return Ok(());
+    }
+    let mut active: user::ActiveModel = user.clone().into();
+    active.failed_logins = Set(0);
+    active.locked_until = Set(None);
+    active.update(db).await?;
+    Ok(())
+}
+
+/// Requests per window for one route group, e.g. `20/60` for 20 requests a minute.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub per: Duration,
+}
+
+impl RateLimit {
+    pub const fn per_minute(requests: u32) -> Self {
+        RateLimit { requests, per: Duration::from_secs(60) }
+    }
+
+    /// `<requests>/<seconds>`, or `off` for no limit.
+    pub fn parse(s: &str) -> Result<Option<Self>, String> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("off") {
+            return Ok(None);
+        }
+        let invalid = || format!("invalid rate limit {:?}; use <requests>/<seconds> such as 20/60, or off", s);
+        let (requests, seconds) = s.split_once('/').ok_or_else(invalid)?;
+        let requests: u32 = requests.trim().parse().map_err(|_| invalid())?;
+        let seconds: u64 = seconds.trim().parse().map_err(|_| invalid())?;
+        if requests == 0 || seconds == 0 {
+            return Err(invalid());
+        }
+        Ok(Some(RateLimit { requests, per: Duration::from_secs(seconds) }))
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// A token bucket per client address: `requests` tokens, refilled evenly over `per`, so a
+/// client may burst up to the full allowance and then continues at the average rate.
+#[derive(Clone)]
+pub struct RateLimiter {
+    limit: Option<RateLimit>,
+    buckets: Arc<Mutex<HashMap<IpAddr, Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: Option<RateLimit>) -> Self {
+        RateLimiter { limit, buckets: Arc::default() }
+    }
+
+    /// Takes a token for `client`, or says how long until one is available.
+    pub fn check(&self, client: IpAddr) -> Result<(), Duration> {
+        let Some(limit) = self.limit else { return Ok(()) };
+        let capacity = limit.requests as f64;
+        let per_second = capacity / limit.per.as_secs_f64();
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() >= MAX_TRACKED_KEYS {
+            // A bucket that would be full again carries no state.
+            buckets.retain(|_, b| now.duration_since(b.updated) < limit.per);
+        }
+        let bucket = buckets.entry(client).or_insert(Bucket { tokens: capacity, updated: now });
+        bucket.tokens = (bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * per_second).min(capacity);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_second))
+        }
+    }
+}
+
+pub const DEFAULT_RATE_LIMIT_AUTH: RateLimit = RateLimit::per_minute(20);
+pub const DEFAULT_RATE_LIMIT_UPLOADS: RateLimit = RateLimit::per_minute(30);
+pub const DEFAULT_RATE_LIMIT_API: RateLimit = RateLimit::per_minute(300);
+
+/// One limiter per route group (see `build_router`).
+#[derive(Clone)]
+pub struct RateLimits {
+    /// `/api/auth/*`.
+    pub auth: RateLimiter,
+    /// Imports and attachment uploads.
+    pub uploads: RateLimiter,
+    /// Everything else under `/api`.
+    pub api: RateLimiter,
+}
+
+impl RateLimits {
+    pub fn new(auth: Option<RateLimit>, uploads: Option<RateLimit>, api: Option<RateLimit>) -> Self {
+        RateLimits { auth: RateLimiter::new(auth), uploads: RateLimiter::new(uploads), api: RateLimiter::new(api) }
+    }
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        RateLimits::new(Some(DEFAULT_RATE_LIMIT_AUTH), Some(DEFAULT_RATE_LIMIT_UPLOADS), Some(DEFAULT_RATE_LIMIT_API))
+    }
+}
@@ -0,0 +1,326 @@
+use chrono::{DateTime, Duration, Utc};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use sea_orm::{ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter, Set, TransactionTrait};
+use sea_orm::sea_query::Expr;
+use serde::Serialize;
+use sha1::Sha1;
+use crate::models::{mfa_challenge, recovery_code, user};
+use crate::services::{audit_update, new_secret_token, secret_token_hash, AuditContext};
+
+/// Shown as the account's label in authenticator apps.
+pub const TOTP_ISSUER: &str = "Your Wallet";
+pub const TOTP_PERIOD: i64 = 30;
+pub const TOTP_DIGITS: u32 = 6;
+/// Codes from one step either side of now are accepted, for clock drift.
+const TOTP_SKEW: i64 = 1;
+pub const RECOVERY_CODE_COUNT: usize = 10;
+/// How long the second step of a login may take.
+pub const MFA_CHALLENGE_MINUTES: i64 = 5;
+/// Wrong codes allowed per challenge before the password has to be entered again.
+const MFA_MAX_ATTEMPTS: i32 = 5;
+
+#[derive(Debug)]
+pub enum MfaError {
+    Db(sea_orm::DbErr),
+    AlreadyEnabled,
+    NotEnabled,
+    /// Confirming without having started setup.
+    NoPendingSetup,
+    /// Wrong, expired or already used TOTP or recovery code.
+    InvalidCode,
+    /// Unknown, expired or exhausted login challenge.
+    InvalidChallenge,
+}
+
+impl std::fmt::Display for MfaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MfaError::Db(e) => write!(f, "{}", e),
+            MfaError::AlreadyEnabled => write!(f, "two-factor authentication is already enabled"),
+            MfaError::NotEnabled => write!(f, "two-factor authentication is not enabled"),
+            MfaError::NoPendingSetup => write!(f, "start two-factor setup first"),
+            MfaError::InvalidCode => write!(f, "invalid authentication code"),
+            MfaError::InvalidChallenge => write!(f, "invalid or expired login challenge; log in again"),
+        }
+    }
+}
+
+impl std::error::Error for MfaError {}
+
+impl From<sea_orm::DbErr> for MfaError {
+    fn from(e: sea_orm::DbErr) -> Self {
+        MfaError::Db(e)
+    }
+}
+
+/// RFC 6238 code (HMAC-SHA1, 6 digits) for a time step.
+pub fn totp_code(secret: &[u8], step: i64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(&(step as u64).to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes([digest[offset] & 0x7f, digest[offset + 1], digest[offset + 2], digest[offset + 3]]);
+    format!("{:0width$}", value % 10u32.pow(TOTP_DIGITS), width = TOTP_DIGITS as usize)
+}
+
+pub fn totp_step(at: DateTime<Utc>) -> i64 {
+    at.timestamp().div_euclid(TOTP_PERIOD)
+}
+
+/// Decodes a base32 secret as stored and shown to the user.
+pub fn decode_totp_secret(secret: &str) -> Option<Vec<u8>> {
+    BASE32_NOPAD.decode(secret.as_bytes()).ok()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// The time step near now whose code is `code`, if any.
+fn matching_step(secret: &str, code: &str) -> Option<i64> {
+    let key = decode_totp_secret(secret)?;
+    let current = totp_step(Utc::now());
+    (current - TOTP_SKEW..=current + TOTP_SKEW).find(|&step| constant_time_eq(totp_code(&key, step).as_bytes(), code.as_bytes()))
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut buf = [0u8; N];
+    getrandom::fill(&mut buf).expect("operating system random source");
+    buf
+}
+
+/// Spaces and dashes are ignored and case does not matter, so codes can be typed as shown.
+fn normalize_code(code: &str) -> String {
+    code.chars().filter(|c| !c.is_whitespace() && *c != '-').collect::<String>().to_ascii_lowercase()
+}
+
+/// `RFC 3986` unreserved characters pass, everything else is percent-encoded.
+fn uri_component(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TotpSetup {
+    /// Base32, for typing into an authenticator by hand.
+    pub secret: String,
+    /// `otpauth://` URI to render as a QR code.
+    pub otpauth_uri: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TotpStatus {
+    pub enabled: bool,
+    pub enabled_at: Option<DateTime<Utc>>,
+    /// Setup was started but not confirmed.
+    pub pending: bool,
+    pub recovery_codes_remaining: u64,
+}
+
+async fn find_user<C: ConnectionTrait>(db: &C, user_id: i32) -> Result<user::Model, MfaError> {
+    // Callers are authenticated; a missing row means the account was just deleted.
+    user::Entity::find_by_id(user_id).one(db).await?.ok_or(MfaError::NotEnabled)
+}
+
+pub async fn totp_status(db: &DatabaseConnection, user_id: i32) -> Result<Option<TotpStatus>, sea_orm::DbErr> {
+    let Some(user) = user::Entity::find_by_id(user_id).one(db).await? else { return Ok(None) };
+    let recovery_codes_remaining = recovery_code::Entity::find()
+        .filter(recovery_code::Column::UserId.eq(user_id))
+        .filter(recovery_code::Column::UsedAt.is_null())
+        .count(db)
+        .await?;
+    Ok(Some(TotpStatus {
+        enabled: user.totp_enabled_at.is_some(),
+        enabled_at: user.totp_enabled_at,
+        pending: user.totp_enabled_at.is_none() && user.totp_secret.is_some(),
+        recovery_codes_remaining,
+    }))
+}
+
+/// Generates a new secret. Nothing changes for login until it is confirmed with a code;
+/// starting again replaces an unconfirmed secret.
+pub async fn begin_totp_setup(db: &DatabaseConnection, user_id: i32) -> Result<TotpSetup, MfaError> {
+    let user = find_user(db, user_id).await?;
+    if user.totp_enabled_at.is_some() {
+        return Err(MfaError::AlreadyEnabled);
+    }
+    let secret = BASE32_NOPAD.encode(&random_bytes::<20>());
+    let otpauth_uri = format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        uri_component(TOTP_ISSUER),
+        uri_component(&user.email),
+        secret,
+        uri_component(TOTP_ISSUER),
+        TOTP_DIGITS,
+        TOTP_PERIOD
+    );
+    let mut active: user::ActiveModel = user.into();
+    active.totp_secret = Set(Some(secret.clone()));
+    active.totp_last_step = Set(None);
+    active.update(db).await?;
+    Ok(TotpSetup { secret, otpauth_uri })
+}
+
+/// Replaces the user's recovery codes and returns the new ones in the form shown to the user.
+async fn replace_recovery_codes<C: ConnectionTrait>(db: &C, user_id: i32) -> Result<Vec<String>, sea_orm::DbErr> {
+    recovery_code::Entity::delete_many().filter(recovery_code::Column::UserId.eq(user_id)).exec(db).await?;
+    let mut codes = Vec::with_capacity(RECOVERY_CODE_COUNT);
+    for _ in 0..RECOVERY_CODE_COUNT {
+        // 10 base32 characters, 50 random bits.
+        let raw = BASE32_NOPAD.encode(&random_bytes::<7>()).to_ascii_lowercase();
+        let code = format!("{}-{}", &raw[..5], &raw[5..10]);
+        let active = recovery_code::ActiveModel {
+            user_id: Set(user_id),
+            code_hash: Set(secret_token_hash(&normalize_code(&code))),
+            created_at: Set(Utc::now()),
+            ..Default::default()
+        };
+        active.insert(db).await?;
+        codes.push(code);
+    }
+    Ok(codes)
+}
+
+/// Turns two-factor login on once the user proves their authenticator produces the right
+/// codes. Returns the recovery codes; they are not retrievable later.
+pub async fn confirm_totp_setup(db: &DatabaseConnection, audit: &AuditContext, user_id: i32, code: &str) -> Result<Vec<String>, MfaError> {
+    let user = find_user(db, user_id).await?;
+    if user.totp_enabled_at.is_some() {
+        return Err(MfaError::AlreadyEnabled);
+    }
+    let Some(secret) = user.totp_secret.clone() else { return Err(MfaError::NoPendingSetup) };
+    let Some(step) = matching_step(&secret, &normalize_code(code)) else { return Err(MfaError::InvalidCode) };
+    let txn = db.begin().await?;
+    let mut active: user::ActiveModel = user.clone().into();
+    active.totp_enabled_at = Set(Some(Utc::now()));
+    active.totp_last_step = Set(Some(step));
+    let updated = active.update(&txn).await?;
+    audit_update(&txn, audit, user_id, "user", user_id, &user, &updated).await?;
+    let codes = replace_recovery_codes(&txn, user_id).await?;
+    txn.commit().await?;
+    Ok(codes)
+}
+
+/// Accepts a current TOTP code or an unused recovery code, spending it: a TOTP code works once
+/// per time step, a recovery code once ever.
+async fn accept_second_factor(db: &DatabaseConnection, user: &user::Model, code: &str) -> Result<bool, sea_orm::DbErr> {
+    let (Some(secret), Some(_)) = (user.totp_secret.as_deref(), user.totp_enabled_at) else { return Ok(false) };
+    let code = normalize_code(code);
+    if code.len() == TOTP_DIGITS as usize && code.chars().all(|c| c.is_ascii_digit()) {
+        let Some(step) = matching_step(secret, &code) else { return Ok(false) };
+        let res = user::Entity::update_many()
+            .col_expr(user::Column::TotpLastStep, Expr::value(step))
+            .filter(user::Column::Id.eq(user.id))
+            .filter(Condition::any().add(user::Column::TotpLastStep.is_null()).add(user::Column::TotpLastStep.lt(step)))
+            .exec(db)
+            .await?;
+        return Ok(res.rows_affected == 1);
+    }
+    let found = recovery_code::Entity::find()
+        .filter(recovery_code::Column::UserId.eq(user.id))
+        .filter(recovery_code::Column::CodeHash.eq(secret_token_hash(&code)))
+        .filter(recovery_code::Column::UsedAt.is_null())
+        .one(db)
+        .await?;
+    let Some(found) = found else { return Ok(false) };
+    let res = recovery_code::Entity::update_many()
+        .col_expr(recovery_code::Column::UsedAt, Expr::value(Utc::now()))
+        .filter(recovery_code::Column::Id.eq(found.id))
+        .filter(recovery_code::Column::UsedAt.is_null())
+        .exec(db)
+        .await?;
+    Ok(res.rows_affected == 1)
+}
+
+/// Turns two-factor login off; needs a current code (or a recovery code).
+pub async fn disable_totp(db: &DatabaseConnection, audit: &AuditContext, user_id: i32, code: &str) -> Result<(), MfaError> {
+    let user = find_user(db, user_id).await?;
+    if user.totp_enabled_at.is_none() {
+        return Err(MfaError::NotEnabled);
+    }
+    if !accept_second_factor(db, &user, code).await? {
+        return Err(MfaError::InvalidCode);
+    }
+    let txn = db.begin().await?;
+    let mut active: user::ActiveModel = user.clone().into();
+    active.totp_secret = Set(None);
+    active.totp_enabled_at = Set(None);
+    active.totp_last_step = Set(None);
+    let updated = active.update(&txn).await?;
+    audit_update(&txn, audit, user_id, "user", user_id, &user, &updated).await?;
+    recovery_code::Entity::delete_many().filter(recovery_code::Column::UserId.eq(user_id)).exec(&txn).await?;
+    mfa_challenge::Entity::delete_many().filter(mfa_challenge::Column::UserId.eq(user_id)).exec(&txn).await?;
+    txn.commit().await?;
+    Ok(())
+}
+
+/// Invalidates the remaining recovery codes and issues a fresh set.
+pub async fn regenerate_recovery_codes(db: &DatabaseConnection, user_id: i32, code: &str) -> Result<Vec<String>, MfaError> {
+    let user = find_user(db, user_id).await?;
+    if user.totp_enabled_at.is_none() {
+        return Err(MfaError::NotEnabled);
+    }
+    if !accept_second_factor(db, &user, code).await? {
+        return Err(MfaError::InvalidCode);
+    }
+    let txn = db.begin().await?;
+    let codes = replace_recovery_codes(&txn, user_id).await?;
+    txn.commit().await?;
+    Ok(codes)
+}
+
+/// First half of a two-factor login: the password was right, the code is still owed. Returns
+/// the challenge token for `complete_mfa_challenge`. Expired challenges of the user are pruned.
+pub async fn start_mfa_challenge(db: &DatabaseConnection, user_id: i32) -> Result<String, sea_orm::DbErr> {
+    mfa_challenge::Entity::delete_many()
+        .filter(mfa_challenge::Column::UserId.eq(user_id))
+        .filter(mfa_challenge::Column::ExpiresAt.lt(Utc::now()))
+        .exec(db)
+        .await?;
+    let token = new_secret_token();
+    let now = Utc::now();
+    let active = mfa_challenge::ActiveModel {
+        user_id: Set(user_id),
+        token_hash: Set(secret_token_hash(&token)),
+        attempts: Set(0),
+        expires_at: Set(now + Duration::minutes(MFA_CHALLENGE_MINUTES)),
+        created_at: Set(now),
+        ..Default::default()
+    };
+    active.insert(db).await?;
+    Ok(token)
+}
+
+/// Second half: checks the code and returns the user to issue tokens for. The challenge is used
+/// up on success and after too many wrong codes.
+pub async fn complete_mfa_challenge(db: &DatabaseConnection, token: &str, code: &str) -> Result<user::Model, MfaError> {
+    let row = mfa_challenge::Entity::find()
+        .filter(mfa_challenge::Column::TokenHash.eq(secret_token_hash(token)))
+        .one(db)
+        .await?;
+    let Some(row) = row else { return Err(MfaError::InvalidChallenge) };
+    if row.expires_at <= Utc::now() || row.attempts >= MFA_MAX_ATTEMPTS {
+        mfa_challenge::Entity::delete_by_id(row.id).exec(db).await?;
+        return Err(MfaError::InvalidChallenge);
+    }
+    let Some(user) = user::Entity::find_by_id(row.user_id).one(db).await? else { return Err(MfaError::InvalidChallenge) };
+    if accept_second_factor(db, &user, code).await? {
+        // Whoever deletes the row wins, so a challenge cannot be completed twice.
+        let res = mfa_challenge::Entity::delete_by_id(row.id).exec(db).await?;
+        if res.rows_affected == 0 {
+            return Err(MfaError::InvalidChallenge);
+        }
+        return Ok(user);
+    }
+    mfa_challenge::Entity::update_many()
+        .col_expr(mfa_challenge::Column::Attempts, Expr::col(mfa_challenge::Column::Attempts).add(1))
+        .filter(mfa_challenge::Column::Id.eq(row.id))
+        .exec(db)
+        .await?;
+    Err(MfaError::InvalidCode)
+}
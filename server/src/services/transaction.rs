@@ -1,24 +1,88 @@
-use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
-use crate::models::transaction;
+use chrono::{DateTime, Utc};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set, TransactionTrait};
+use sea_orm::sea_query::{Expr, SimpleExpr};
+use crate::models::{account, transaction};
+use crate::services::{account_owner, apply_rules, audit_create, audit_delete, audit_update, load_rules, tags_to_json, AuditContext, RuleSubject, ServiceError};
 use sea_orm::prelude::Decimal;
 
+pub const STATUS_PENDING: &str = "pending";
+pub const STATUS_CLEARED: &str = "cleared";
+pub const STATUS_RECONCILED: &str = "reconciled";
+
+/// `transactions.created_at` as comparable UTC text. Rows hold either SQLite's default format or
+/// RFC 3339 depending on how they were written, and `datetime()` normalises both.
+pub fn created_at_expr() -> SimpleExpr {
+    Expr::cust("datetime(transactions.created_at)")
+}
+
+/// Filter for transactions created strictly before `at`.
+pub fn created_before(at: DateTime<Utc>) -> SimpleExpr {
+    Expr::cust_with_values("datetime(transactions.created_at) < ?", [at.format("%Y-%m-%d %H:%M:%S").to_string()])
+}
+
+/// Decimal places kept by `transactions.amount`, a `DECIMAL(16, 8)`.
+pub const AMOUNT_SCALE: u32 = 8;
+
+/// SQL summing `amount` exactly. SQLite holds NUMERIC values as floating point, so each amount is
+/// scaled to an integer of `AMOUNT_SCALE` places before adding; read the sum with `scaled_amount`.
+pub fn scaled_sum_sql(amount: &str) -> String {
+    format!("SUM(CAST(ROUND({} * 1e{}) AS INTEGER))", amount, AMOUNT_SCALE)
+}
+
+/// A sum produced by `scaled_sum_sql` as a `Decimal`.
+pub fn scaled_amount(sum: i64) -> Decimal {
+    Decimal::new(sum, AMOUNT_SCALE).normalize()
+}
+
+/// Statuses a client may set directly; `reconciled` is only reachable by completing a reconciliation.
+fn is_settable_status(s: &str) -> bool {
+    s == STATUS_PENDING || s == STATUS_CLEARED
+}
+
+/// Amount as it affects the account balance: income adds, expense subtracts, transfers keep their sign.
+pub fn signed_amount(m: &transaction::Model) -> Decimal {
+    match m.transaction_type.as_str() {
+        "expense" => -m.amount,
+        _ => m.amount,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn create_transaction(
     db: &DatabaseConnection,
+    audit: &AuditContext,
     account_id: i32,
     transaction_type: String,
     amount: Decimal,
     description: String,
     category: Option<String>,
-) -> Result<transaction::Model, sea_orm::DbErr> {
+    status: Option<String>,
+) -> Result<transaction::Model, ServiceError> {
+    if let Some(ref s) = status {
+        if !is_settable_status(s) {
+            return Err(ServiceError::Invalid(format!("invalid status: {}", s)));
+        }
+    }
+    // The user's rules fill in what was not given explicitly; a missing account fails on insert.
+    let mut subject = RuleSubject { account_id, transaction_type, amount, description, counterparty: None, category, tags: Vec::new() };
+    if let Some(account) = account::Entity::find_by_id(account_id).one(db).await? {
+        apply_rules(&load_rules(db, account.user_id).await?, &mut subject, false);
+    }
     let active = transaction::ActiveModel {
         account_id: Set(account_id),
-        transaction_type: Set(transaction_type),
-        amount: Set(amount),
-        description: Set(description),
-        category: Set(category),
+        transaction_type: Set(subject.transaction_type),
+        amount: Set(subject.amount),
+        description: Set(subject.description.into()),
+        category: Set(subject.category),
+        tags: Set(tags_to_json(&subject.tags)),
+        status: Set(status.unwrap_or_else(|| STATUS_PENDING.to_string())),
         ..Default::default()
     };
-    active.insert(db).await
+    let txn = db.begin().await?;
+    let model = active.insert(&txn).await?;
+    audit_create(&txn, audit, account_owner(&txn, account_id).await?, "transaction", model.id, &model).await?;
+    txn.commit().await?;
+    Ok(model)
 }
 
 pub async fn get_transaction_by_id(
@@ -38,22 +102,88 @@ pub async fn find_transactions_by_account(
         .await
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn update_transaction(
     db: &DatabaseConnection,
+    audit: &AuditContext,
     id: i32,
     transaction_type: Option<String>,
     amount: Option<Decimal>,
     description: Option<String>,
     category: Option<String>,
-) -> Result<Option<transaction::Model>, sea_orm::DbErr> {
-    if let Some(model) = transaction::Entity::find_by_id(id).one(db).await? {
-        let mut active: transaction::ActiveModel = model.into();
+    status: Option<String>,
+) -> Result<Option<transaction::Model>, ServiceError> {
+    let txn = db.begin().await?;
+    if let Some(model) = transaction::Entity::find_by_id(id).one(&txn).await? {
+        if model.status == STATUS_RECONCILED {
+            return Err(ServiceError::Locked("transaction is reconciled; unlock it first".into()));
+        }
+        if let Some(ref s) = status {
+            if !is_settable_status(s) {
+                return Err(ServiceError::Invalid(format!("invalid status: {}", s)));
+            }
+        }
+        let mut active: transaction::ActiveModel = model.clone().into();
         if let Some(v) = transaction_type { active.transaction_type = Set(v); }
         if let Some(v) = amount { active.amount = Set(v); }
-        if let Some(v) = description { active.description = Set(v); }
+        if let Some(v) = description { active.description = Set(v.into()); }
         // Note: None means do not change category. To clear, set Some(String::new()) or add a dedicated clear function.
         if let Some(v) = category { active.category = Set(Some(v)); }
-        let updated = active.update(db).await?;
+        if let Some(v) = status { active.status = Set(v); }
+        let updated = active.update(&txn).await?;
+        audit_update(&txn, audit, account_owner(&txn, updated.account_id).await?, "transaction", id, &model, &updated).await?;
+        txn.commit().await?;
+        Ok(Some(updated))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Replaces a transaction's tags; an empty list clears them.
+pub async fn set_transaction_tags(
+    db: &DatabaseConnection,
+    audit: &AuditContext,
+    id: i32,
+    tags: Vec<String>,
+) -> Result<Option<transaction::Model>, ServiceError> {
+    let Some(model) = transaction::Entity::find_by_id(id).one(db).await? else {
+        return Ok(None);
+    };
+    if model.status == STATUS_RECONCILED {
+        return Err(ServiceError::Locked("transaction is reconciled; unlock it first".into()));
+    }
+    let mut unique: Vec<String> = Vec::new();
+    for tag in tags.into_iter().map(|t| t.trim().to_string()).filter(|t| !t.is_empty()) {
+        if !unique.contains(&tag) {
+            unique.push(tag);
+        }
+    }
+    let mut active: transaction::ActiveModel = model.clone().into();
+    active.tags = Set(tags_to_json(&unique));
+    let txn = db.begin().await?;
+    let updated = active.update(&txn).await?;
+    audit_update(&txn, audit, account_owner(&txn, updated.account_id).await?, "transaction", id, &model, &updated).await?;
+    txn.commit().await?;
+    Ok(Some(updated))
+}
+
+/// Moves a reconciled transaction back to `cleared` so it can be edited or deleted again.
+pub async fn unlock_transaction(
+    db: &DatabaseConnection,
+    audit: &AuditContext,
+    id: i32,
+) -> Result<Option<transaction::Model>, sea_orm::DbErr> {
+    if let Some(model) = transaction::Entity::find_by_id(id).one(db).await? {
+        if model.status != STATUS_RECONCILED {
+            return Ok(Some(model));
+        }
+        let mut active: transaction::ActiveModel = model.clone().into();
+        active.status = Set(STATUS_CLEARED.to_string());
+        active.reconciliation_id = Set(None);
+        let txn = db.begin().await?;
+        let updated = active.update(&txn).await?;
+        audit_update(&txn, audit, account_owner(&txn, updated.account_id).await?, "transaction", id, &model, &updated).await?;
+        txn.commit().await?;
         Ok(Some(updated))
     } else {
         Ok(None)
@@ -62,8 +192,16 @@ pub async fn update_transaction(
 
 pub async fn delete_transaction(
     db: &DatabaseConnection,
+    audit: &AuditContext,
     id: i32,
-) -> Result<u64, sea_orm::DbErr> {
-    let res = transaction::Entity::delete_by_id(id).exec(db).await?;
+) -> Result<u64, ServiceError> {
+    let txn = db.begin().await?;
+    let Some(model) = transaction::Entity::find_by_id(id).one(&txn).await? else { return Ok(0) };
+    if model.status == STATUS_RECONCILED {
+        return Err(ServiceError::Locked("transaction is reconciled; unlock it first".into()));
+    }
+    let res = transaction::Entity::delete_by_id(id).exec(&txn).await?;
+    audit_delete(&txn, audit, account_owner(&txn, model.account_id).await?, "transaction", id, &model).await?;
+    txn.commit().await?;
     Ok(res.rows_affected)
 }
@@ -1,8 +1,11 @@
-use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
+use sea_orm::sea_query::Expr;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set, TransactionTrait};
 use crate::models::user;
+use crate::services::{audit_create, audit_delete, audit_update, AuditContext};
 
 pub async fn create_user(
     db: &DatabaseConnection,
+    audit: &AuditContext,
     username: String,
     email: String,
     password_hash: String,
@@ -13,7 +16,13 @@ pub async fn create_user(
         password_hash: Set(password_hash),
         ..Default::default()
     };
-    active.insert(db).await
+    let txn = db.begin().await?;
+    let model = active.insert(&txn).await?;
+    // Signing up is done by the new user themselves.
+    let audit = AuditContext { actor_user_id: audit.actor_user_id.or(Some(model.id)), ..audit.clone() };
+    audit_create(&txn, &audit, model.id, "user", model.id, &model).await?;
+    txn.commit().await?;
+    Ok(model)
 }
 
 pub async fn get_user_by_id(
@@ -45,15 +54,22 @@ pub async fn get_user_by_email(
 
 pub async fn update_user(
     db: &DatabaseConnection,
+    audit: &AuditContext,
     id: i32,
     new_username: Option<String>,
     new_email: Option<String>,
 ) -> Result<Option<user::Model>, sea_orm::DbErr> {
-    if let Some(model) = user::Entity::find_by_id(id).one(db).await? {
-        let mut active: user::ActiveModel = model.into();
+    let txn = db.begin().await?;
+    if let Some(model) = user::Entity::find_by_id(id).one(&txn).await? {
+        let email_changed = new_email.as_ref().is_some_and(|v| *v != model.email);
+        let mut active: user::ActiveModel = model.clone().into();
         if let Some(v) = new_username { active.username = Set(v); }
         if let Some(v) = new_email { active.email = Set(v); }
-        let updated = active.update(db).await?;
+        // A new address has to be verified again.
+        if email_changed { active.email_verified_at = Set(None); }
+        let updated = active.update(&txn).await?;
+        audit_update(&txn, audit, id, "user", id, &model, &updated).await?;
+        txn.commit().await?;
         Ok(Some(updated))
     } else {
         Ok(None)
@@ -62,23 +78,46 @@ pub async fn update_user(
 
 pub async fn update_user_password(
     db: &DatabaseConnection,
+    audit: &AuditContext,
     id: i32,
     new_password_hash: String,
 ) -> Result<Option<user::Model>, sea_orm::DbErr> {
-    if let Some(model) = user::Entity::find_by_id(id).one(db).await? {
-        let mut active: user::ActiveModel = model.into();
+    let txn = db.begin().await?;
+    if let Some(model) = user::Entity::find_by_id(id).one(&txn).await? {
+        let mut active: user::ActiveModel = model.clone().into();
         active.password_hash = Set(new_password_hash);
-        let updated = active.update(db).await?;
+        let updated = active.update(&txn).await?;
+        audit_update(&txn, audit, id, "user", id, &model, &updated).await?;
+        txn.commit().await?;
         Ok(Some(updated))
     } else {
         Ok(None)
     }
 }
 
+/// Replaces the hash of the user's current password with one made with current parameters
+/// (after a login). The password is unchanged, so this is not audited and sessions are kept;
+/// nothing happens if the password was changed in the meantime.
+pub async fn upgrade_password_hash(db: &DatabaseConnection, user: &user::Model, password_hash: String) -> Result<(), sea_orm::DbErr> {
+    user::Entity::update_many()
+        .col_expr(user::Column::PasswordHash, Expr::value(password_hash))
+        .filter(user::Column::Id.eq(user.id))
+        .filter(user::Column::PasswordHash.eq(user.password_hash.clone()))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
 pub async fn delete_user(
     db: &DatabaseConnection,
+    audit: &AuditContext,
     id: i32,
 ) -> Result<u64, sea_orm::DbErr> {
-    let res = user::Entity::delete_by_id(id).exec(db).await?;
+    let txn = db.begin().await?;
+    let Some(model) = user::Entity::find_by_id(id).one(&txn).await? else { return Ok(0) };
+    let res = user::Entity::delete_by_id(id).exec(&txn).await?;
+    // The log outlives the user, as a record of the deletion.
+    audit_delete(&txn, audit, id, "user", id, &model).await?;
+    txn.commit().await?;
     Ok(res.rows_affected)
 }
@@ -0,0 +1,200 @@
+// Encryption keys are process-wide, so this runs apart from tests/api.rs.
+use axum::{http::{Request, StatusCode}, body::Body};
+use migration::MigratorTrait;
+use tower::ServiceExt; // for `oneshot`
+
+const MASTER_KEY_A: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+const MASTER_KEY_B: &str = "qrvM3e7/ABEiM0RVZneImaq7zN3u/wARIjNEVWZ3iJk=";
+
+#[tokio::test]
+async fn field_encryption() {
+    use http_body_util::BodyExt; // for collect
+    use sea_orm::{ConnectionTrait, DbBackend, Statement};
+    use serde_json::{json, Value};
+    use server::services::{init_encryption, rotate_encryption_keys, LocalStorage, MasterKeys};
+
+    let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+    migration::Migrator::up(&db, None).await.unwrap();
+    let dir = std::env::temp_dir().join(format!("wallet-attachments-{}", uuid::Uuid::new_v4()));
+    let storage = std::sync::Arc::new(LocalStorage::new(&dir));
+    let state = server::routes::AppState::new(db.clone()).with_attachment_storage(storage.clone());
+    let app = server::build_router(state);
+
+    let call = |method: &str, uri: String, token: Option<&str>, body: Value| {
+        let app = app.clone();
+        let mut req = Request::builder().method(method).uri(uri).header("content-type","application/json");
+        if let Some(token) = token {
+            req = req.header("authorization", format!("Bearer {}", token));
+        }
+        let req = req.body(Body::from(body.to_string())).unwrap();
+        async move {
+            let res = app.oneshot(req).await.unwrap();
+            let status = res.status();
+            let bytes = res.into_body().collect().await.unwrap().to_bytes();
+            (status, serde_json::from_slice::<Value>(&bytes).unwrap_or(Value::Null))
+        }
+    };
+    let download = |uri: String| {
+        let app = app.clone();
+        async move {
+            let res = app.oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap()).await.unwrap();
+            (res.status(), res.into_body().collect().await.unwrap().to_bytes().to_vec())
+        }
+    };
+    let column = |sql: &str| {
+        let db = db.clone();
+        let sql = sql.to_string();
+        async move {
+            let rows = db.query_all(Statement::from_string(DbBackend::Sqlite, sql)).await.unwrap();
+            rows.iter().map(|r| r.try_get_by_index::<String>(0).unwrap()).collect::<Vec<_>>()
+        }
+    };
+    let blob_path = |key: &str| dir.join(&key[..2]).join(key);
+    let blob = |key: &str| std::fs::read(blob_path(key)).unwrap();
+    let upload = |transaction_id: &Value, png: &[u8]| {
+        let app = app.clone();
+        let mut body = b"--XBOUNDARY\r\nContent-Disposition: form-data; name=\"file\"; filename=\"payslip.png\"\r\nContent-Type: image/png\r\n\r\n".to_vec();
+        body.extend_from_slice(png);
+        body.extend_from_slice(b"\r\n--XBOUNDARY--\r\n");
+        let req = Request::builder().method("POST").uri(format!("/api/transactions/{}/attachments", transaction_id))
+            .header("content-type", "multipart/form-data; boundary=XBOUNDARY")
+            .body(Body::from(body)).unwrap();
+        async move {
+            let res = app.oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::CREATED);
+            serde_json::from_slice::<Value>(&res.into_body().collect().await.unwrap().to_bytes()).unwrap()
+        }
+    };
+
+    // Written before a key is configured: stays readable, and stays plaintext until rotation.
+    let (_, user) = call("POST", "/api/users".into(), None, json!({"username":"u29","email":"u29@example.com","password":"secret-sauce-42"})).await;
+    let (_, old) = call("POST", "/api/accounts".into(), None, json!({"user_id": user["id"], "name":"Savings", "account_type":"bank", "balance":"0", "currency":"CNY"})).await;
+    // Plaintext that reads like a sealed value is escaped, not mistaken for one.
+    let lookalike = format!("enc1:{}:AAAA", "ab".repeat(16));
+    let (_, note) = call("POST", "/api/transactions".into(), None, json!({"account_id": old["id"], "transaction_type":"expense", "amount":"1", "description": lookalike})).await;
+    assert_eq!(note["description"], lookalike.as_str());
+    assert_eq!(column(&format!("SELECT description FROM transactions WHERE id = {}", note["id"])).await[0], format!("enc0:{}", lookalike));
+
+    init_encryption(&db, Some(MasterKeys::parse(MASTER_KEY_A).unwrap())).await.unwrap();
+    let (_, login) = call("POST", "/api/auth/login".into(), None, json!({"email":"u29@example.com","password":"secret-sauce-42"})).await;
+    let jwt = login["token"].as_str().unwrap().to_string();
+    let (status, bank) = call("POST", "/api/accounts".into(), Some(&jwt), json!({"user_id": user["id"], "name":"Swiss bank", "account_type":"bank", "balance":"0", "currency":"CHF"})).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(bank["name"], "Swiss bank");
+    let (_, txn) = call("POST", "/api/transactions".into(), Some(&jwt), json!({"account_id": bank["id"], "transaction_type":"income", "amount":"9000", "description":"salary from ACME"})).await;
+    assert_eq!(txn["description"], "salary from ACME");
+    call("PATCH", format!("/api/accounts/{}", bank["id"]), Some(&jwt), json!({"name":"Offshore"})).await;
+
+    let mut png = std::io::Cursor::new(Vec::new());
+    image::RgbImage::from_pixel(64, 48, image::Rgb([20, 90, 200])).write_to(&mut png, image::ImageFormat::Png).unwrap();
+    let png = png.into_inner();
+    let attachment = upload(&txn["id"], &png).await;
+    assert_eq!(attachment["file_name"], "payslip.png");
+    let sha = attachment["sha256"].as_str().unwrap().to_string();
+    let key = format!("{}.u{}", sha, user["id"]);
+
+    // The same file from another user is a separate blob, sealed with that user's key.
+    let (_, other) = call("POST", "/api/users".into(), None, json!({"username":"u29b","email":"u29b@example.com","password":"secret-sauce-42"})).await;
+    let (_, other_bank) = call("POST", "/api/accounts".into(), None, json!({"user_id": other["id"], "name":"Other bank", "account_type":"bank", "balance":"0", "currency":"CNY"})).await;
+    let (_, other_txn) = call("POST", "/api/transactions".into(), None, json!({"account_id": other_bank["id"], "transaction_type":"expense", "amount":"1", "description":"copy"})).await;
+    let other_attachment = upload(&other_txn["id"], &png).await;
+    let other_key = format!("{}.u{}", sha, other["id"]);
+    assert_ne!(blob(&key)[5..37], blob(&other_key)[5..37]);
+    let key_of = |user: &Value| {
+        let db = db.clone();
+        let user_id = user["id"].as_i64().unwrap();
+        async move {
+            let sql = format!("SELECT key_id FROM data_keys WHERE user_id = {} ORDER BY id DESC LIMIT 1", user_id);
+            let rows = db.query_all(Statement::from_string(DbBackend::Sqlite, sql)).await.unwrap();
+            rows[0].try_get_by_index::<String>(0).unwrap().into_bytes()
+        }
+    };
+    assert_eq!(blob(&key)[5..37], key_of(&user).await[..]);
+    assert_eq!(blob(&other_key)[5..37], key_of(&other).await[..]);
+
+    // On disk: ciphertext everywhere a copied database or attachment directory would leak.
+    let names = column("SELECT name FROM accounts ORDER BY id").await;
+    assert_eq!(names[0], "Savings");
+    assert!(names[1].starts_with("enc1:"));
+    let descriptions = column(&format!("SELECT description FROM transactions WHERE id = {}", txn["id"])).await;
+    assert!(descriptions[0].starts_with("enc1:") && !descriptions[0].contains("ACME"));
+    assert!(column("SELECT file_name FROM attachments").await[0].starts_with("enc1:"));
+    let audit = column("SELECT COALESCE(\"before\", '') || COALESCE(\"after\", '') FROM audit_log").await.concat();
+    assert!(!audit.contains("ACME") && !audit.contains("Offshore") && !audit.contains("Swiss"));
+    assert!(blob(&key).starts_with(b"WENC1"));
+
+    // Through the API: plaintext.
+    let (_, accounts) = call("GET", format!("/api/accounts?user_id={}", user["id"]), Some(&jwt), Value::Null).await;
+    let names: Vec<&str> = accounts.as_array().unwrap().iter().map(|a| a["name"].as_str().unwrap()).collect();
+    assert_eq!(names, vec!["Savings", "Offshore"]);
+    let (_, fetched) = call("GET", format!("/api/transactions/{}", txn["id"]), Some(&jwt), Value::Null).await;
+    assert_eq!(fetched["description"], "salary from ACME");
+    let (_, fetched) = call("GET", format!("/api/transactions/{}", note["id"]), Some(&jwt), Value::Null).await;
+    assert_eq!(fetched["description"], lookalike.as_str());
+    // Input that looks sealed is sealed like any other value, so a copied ciphertext stays text.
+    let (_, copied) = call("POST", "/api/transactions".into(), Some(&jwt), json!({"account_id": bank["id"], "transaction_type":"expense", "amount":"1", "description": descriptions[0]})).await;
+    let (_, fetched) = call("GET", format!("/api/transactions/{}", copied["id"]), Some(&jwt), Value::Null).await;
+    assert_eq!(fetched["description"], descriptions[0].as_str());
+
+    // Counterparties and recurring descriptions are sealed too, in the audit log as well.
+    let qif = "!Type:Bank\nD01/15/2025\nT-42.00\nPMEITUAN*Takeout\nMlunch\n^\n";
+    let req = Request::builder().method("POST").uri(format!("/api/import/qif/commit?account_id={}", bank["id"]))
+        .header("authorization", format!("Bearer {}", jwt))
+        .body(Body::from(qif)).unwrap();
+    assert_eq!(app.clone().oneshot(req).await.unwrap().status(), StatusCode::CREATED);
+    let (status, recurring) = call("POST", "/api/recurring".into(), Some(&jwt), json!({"user_id": user["id"], "account_id": bank["id"], "transaction_type":"expense", "amount":"60", "description":"therapy sessions", "frequency":"monthly", "start_date":"2030-01-01"})).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(recurring["description"], "therapy sessions");
+    let stored = column("SELECT COALESCE(counterparty, '') FROM transactions").await.concat()
+        + &column("SELECT description FROM recurring_transactions").await.concat()
+        + &column("SELECT COALESCE(\"after\", '') FROM audit_log").await.concat();
+    assert!(!stored.contains("MEITUAN") && !stored.contains("therapy"));
+    let (_, listed) = call("GET", format!("/api/transactions?account_id={}", bank["id"]), Some(&jwt), Value::Null).await;
+    assert!(listed.as_array().unwrap().iter().any(|t| t["counterparty"] == "MEITUAN*Takeout"));
+    let (_, entries) = call("GET", "/api/audit?entity=account".into(), Some(&jwt), Value::Null).await;
+    assert_eq!(entries[0]["before"], json!({"name": "Swiss bank"}));
+    assert_eq!(entries[0]["after"], json!({"name": "Offshore"}));
+    assert_eq!(download(format!("/api/attachments/{}", attachment["id"])).await, (StatusCode::OK, png.clone()));
+    assert_eq!(download(format!("/api/attachments/{}", other_attachment["id"])).await, (StatusCode::OK, png.clone()));
+
+    // A blob still shared under its bare hash, as stored before blobs were kept per user, is
+    // read from there until rotation gives each owner a copy.
+    std::fs::rename(blob_path(&other_key), blob_path(&sha)).unwrap();
+    assert_eq!(download(format!("/api/attachments/{}", other_attachment["id"])).await, (StatusCode::OK, png.clone()));
+
+    // Rotation to a new master key: every value and blob is re-encrypted, plaintext included,
+    // and every data key is rewrapped so the old master key can go.
+    let old_ciphertext = descriptions[0].clone();
+    init_encryption(&db, Some(MasterKeys::parse(MASTER_KEY_B).unwrap().with_previous_key(MASTER_KEY_A).unwrap())).await.unwrap();
+    let report = rotate_encryption_keys(&db, storage.as_ref()).await.unwrap();
+    assert_eq!((report.rows, report.blobs, report.data_keys), (11, 4, 4));
+    let master_b = MasterKeys::parse(MASTER_KEY_B).unwrap();
+    assert_eq!(report.master_key_id, master_b.key_id());
+    assert!(column("SELECT master_key_id FROM data_keys").await.iter().all(|id| id == master_b.key_id()));
+    assert!(column("SELECT name FROM accounts").await.iter().all(|n| n.starts_with("enc1:")));
+    assert!(column("SELECT description FROM transactions").await.iter().all(|d| d.starts_with("enc1:")));
+    let descriptions = column(&format!("SELECT description FROM transactions WHERE id = {}", txn["id"])).await;
+    assert_ne!(descriptions[0][..37], old_ciphertext[..37]);
+    assert_eq!(blob(&key)[5..37], key_of(&user).await[..]);
+    assert_eq!(blob(&other_key)[5..37], key_of(&other).await[..]);
+    assert!(!blob_path(&sha).exists());
+
+    let (_, fetched) = call("GET", format!("/api/transactions/{}", txn["id"]), Some(&jwt), Value::Null).await;
+    assert_eq!(fetched["description"], "salary from ACME");
+    let (_, fetched) = call("GET", format!("/api/transactions/{}", note["id"]), Some(&jwt), Value::Null).await;
+    assert_eq!(fetched["description"], lookalike.as_str());
+    let (_, fetched) = call("GET", format!("/api/accounts/{}", old["id"]), Some(&jwt), Value::Null).await;
+    assert_eq!(fetched["name"], "Savings");
+    assert_eq!(download(format!("/api/attachments/{}", attachment["id"])).await, (StatusCode::OK, png.clone()));
+    assert_eq!(download(format!("/api/attachments/{}", other_attachment["id"])).await, (StatusCode::OK, png));
+    let (_, entries) = call("GET", "/api/audit?entity=account".into(), Some(&jwt), Value::Null).await;
+    assert_eq!(entries[0]["after"], json!({"name": "Offshore"}));
+    let (_, listed) = call("GET", format!("/api/recurring?user_id={}", user["id"]), Some(&jwt), Value::Null).await;
+    assert_eq!(listed[0]["description"], "therapy sessions");
+    assert!(column("SELECT description FROM recurring_transactions").await[0].starts_with("enc1:"));
+
+    // Starting up needs only the new key; no key, or only the old one, is refused.
+    assert!(init_encryption(&db, None).await.is_err());
+    assert!(init_encryption(&db, Some(MasterKeys::parse(MASTER_KEY_A).unwrap())).await.is_err());
+    init_encryption(&db, Some(MasterKeys::parse(MASTER_KEY_B).unwrap())).await.unwrap();
+}